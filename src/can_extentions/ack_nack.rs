@@ -1,3 +1,19 @@
+use serde::{ Serialize, Deserialize };
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn serde_round_trip() {
+        for ack_nack in &[AckNack::Ack, AckNack::Nack, AckNack::UNKNOWN] {
+            let json = serde_json::to_string(ack_nack).unwrap();
+            assert_eq!(serde_json::from_str::<AckNack>(&json).unwrap(), *ack_nack);
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub enum AckNack {
     Ack,
     Nack,
@@ -12,4 +28,14 @@ impl From<u8> for AckNack {
             _ => AckNack::UNKNOWN
         }
     }
-}
\ No newline at end of file
+}
+
+impl AckNack {
+    pub fn to_byte(&self) -> u8 {
+        match self {
+            AckNack::Ack     => 0x00,
+            AckNack::Nack    => 0xFF,
+            AckNack::UNKNOWN => 0x01
+        }
+    }
+}
@@ -6,9 +6,36 @@
  */
 use crate::can_extentions::fault_reports::{ BmsFaultReport, MotorControllerFaultReport };
 use crate::can_extentions::ack_nack::AckNack;
+use serde::{ Serialize, Deserialize };
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::can_extentions::fault_reports::{ SeverityCode, BmsErrorCode };
+
+    #[test]
+    fn serde_round_trip() {
+        let commands = [
+            CanCommand::BmsHealthCheck { battery_pack_current: 12.5, cell_temperature: -3.25 },
+            CanCommand::BmsFaultReport(BmsFaultReport::new(SeverityCode::SEVERE, BmsErrorCode::BATTERY_OVERCURRENT)),
+            CanCommand::MotorControllerFaultReport(MotorControllerFaultReport::new(SeverityCode::WARNING)),
+            CanCommand::MotorControllerStateChange(AckNack::Nack),
+            CanCommand::PressureHigh(101.325),
+            CanCommand::Torchic1([Some(21.5), None]),
+            CanCommand::RoboteqMotorEncoderResult { motor_number: 2, speed: -1500 },
+        ];
+        for command in &commands {
+            let json = serde_json::to_string(command).unwrap();
+            assert_eq!(&serde_json::from_str::<CanCommand>(&json).unwrap(), command);
+        }
+        // A missing sensor reading is null, not a sentinel value
+        assert_eq!(serde_json::to_string(&CanCommand::Torchic2([None, Some(1.5)])).unwrap(), r#"{"Torchic2":[null,1.5]}"#);
+    }
+}
 
 // The full list that need to be supported
 // can be found here: (Can Communication Protocol) [https://docs.google.com/document/d/1pAAAPyWClxrq7MwrA0_AGxnqU6B5r5MHmvRERMY6hUo/edit]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum CanCommand {
     BmsHealthCheck { battery_pack_current: f32, cell_temperature: f32 },
     MotorControllerHealthCheck { igbt_temp: f32, motor_voltage: f32 },
@@ -34,5 +61,4 @@ pub enum CanCommand {
     RoboteqTemperatureResult{ sub_index: u8, temp: i8},
     RoboteqBatteryAmpsResult{ motor_number: u8, amps: i16},
     RoboteqMotorEncoderResult{ motor_number: u8, speed: i32},
-    Unknown(u32), // Arbitration ID provided for debugging Purposes
 }
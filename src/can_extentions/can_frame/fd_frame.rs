@@ -0,0 +1,111 @@
+/*!
+ * @Struct CanFdFrame
+ * @brief A CAN-FD frame, carrying up to 64 data bytes where a classic frame carries 8.
+ * socketcan's CANFrame only holds classic frames, so this is laid out like the kernel's canfd_frame.
+ * Only built with the canfd feature
+ */
+
+use super::super::frame::{ ConstructionError, EFF_FLAG, EFF_MASK, SFF_MASK };
+use super::frame_handler::{ decode_frame, encode_command, FrameHandler };
+use crate::can_extentions::can_command::CanCommand;
+use crate::can_extentions::error::{ CanError, FrameDecodeError };
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn payloads_are_padded_to_a_frame_length() {
+        let frame = CanFdFrame::new(0x020, &[1u8; 9]).unwrap();
+        assert_eq!(frame.data(), &[1, 1, 1, 1, 1, 1, 1, 1, 1, 0, 0, 0]);
+        assert_eq!(CanFdFrame::new(0x020, &[0u8; 64]).unwrap().data().len(), 64);
+        assert!(matches!(CanFdFrame::new(0x020, &[0u8; 65]), Err(ConstructionError::TooMuchData)));
+        assert!(matches!(CanFdFrame::new(EFF_MASK + 1, &[]), Err(ConstructionError::IDTooLarge)));
+    }
+
+    #[test]
+    fn commands_round_trip() {
+        let command = CanCommand::BmsHealthCheck{ battery_pack_current: 12.0, cell_temperature: 30.5 };
+        let frame = CanFdFrame::to_frame(&command).unwrap();
+        assert!(!frame.is_extended());
+        assert_eq!(frame.get_command(), Ok(command));
+    }
+}
+
+/**
+ * Payload lengths a CAN-FD frame can have. Anything between them is padded up to the next one
+ */
+const FD_LENGTHS: [usize; 16] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 12, 16, 20, 24, 32, 48, 64];
+
+const CANFD_MAX_DLEN: usize = 64;
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)] // The same layout as struct canfd_frame
+pub struct CanFdFrame {
+    can_id: u32, // The id, with EFF_FLAG set for an extended frame
+    len: u8,
+    flags: u8,
+    _res0: u8,
+    _res1: u8,
+    data: [u8; CANFD_MAX_DLEN]
+}
+
+impl CanFdFrame {
+    /**
+     * @brief new
+     * Build a frame, which is extended if the id does not fit in 11 bits. The payload is padded with
+     * zeros up to the next length a CAN-FD frame can have
+     */
+    pub fn new(id: u32, data: &[u8]) -> Result<CanFdFrame, ConstructionError> {
+        if id > EFF_MASK {
+            return Err(ConstructionError::IDTooLarge);
+        }
+        let len = *FD_LENGTHS.iter().find(|&&len| len >= data.len()).ok_or(ConstructionError::TooMuchData)?;
+        let mut frame = CanFdFrame {
+            can_id: if id > SFF_MASK { id | EFF_FLAG } else { id },
+            len: len as u8,
+            flags: 0,
+            _res0: 0,
+            _res1: 0,
+            data: [0u8; CANFD_MAX_DLEN]
+        };
+        frame.data[..data.len()].copy_from_slice(data);
+        Ok(frame)
+    }
+
+    pub fn id(&self) -> u32 {
+        if self.is_extended() {
+            self.can_id & EFF_MASK
+        } else {
+            self.can_id & SFF_MASK
+        }
+    }
+
+    pub fn is_extended(&self) -> bool {
+        self.can_id & EFF_FLAG != 0
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data[..self.len as usize]
+    }
+
+    /**
+     * Whether the length is one a CAN-FD frame can have. Always true of a frame built with new,
+     * checked for frames read from a socket
+     */
+    #[cfg(unix)]
+    pub(crate) fn has_valid_length(&self) -> bool {
+        FD_LENGTHS.contains(&(self.len as usize))
+    }
+}
+
+impl FrameHandler for CanFdFrame {
+    fn get_command(&self) -> Result<CanCommand, FrameDecodeError> {
+        decode_frame(self.id(), self.is_extended(), self.data())
+    }
+
+    fn to_frame(command: &CanCommand) -> Result<CanFdFrame, CanError> {
+        let (id, data) = encode_command(command)?;
+        Ok(CanFdFrame::new(id, &data)?)
+    }
+}
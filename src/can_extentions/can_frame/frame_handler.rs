@@ -1,81 +1,318 @@
 use super::super::can_command::CanCommand;
+use log::{ warn };
 use super::super::fault_reports::{ BmsFaultReport, MotorControllerFaultReport };
 use super::super::ack_nack::AckNack;
-use byteorder::{ LittleEndian, ByteOrder };
+use super::super::error::{ CanError, FrameDecodeError };
+use super::super::can_ids;
+use byteorder::{ LittleEndian, BigEndian, ByteOrder };
+use super::super::frame::CANFrame;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::super::frame::ConstructionError;
+
+    #[test]
+    fn short_pressure_high_frames_do_not_panic() {
+        let frame = CANFrame::new(0x020, &[], false, false).unwrap();
+        assert_eq!(frame.get_command(), Err(FrameDecodeError::TruncatedPayload{ id: 0x020, expected: 4, got: 0 }));
+        let frame = CANFrame::new(0x020, &[0u8; 3], false, false).unwrap();
+        assert_eq!(frame.get_command(), Err(FrameDecodeError::TruncatedPayload{ id: 0x020, expected: 4, got: 3 }));
+        // Pressure only uses the first float, so a 7 byte payload still decodes
+        let frame = CANFrame::new(0x020, &[0u8; 7], false, false).unwrap();
+        assert!(matches!(frame.get_command(), Ok(CanCommand::PressureHigh(_))));
+    }
+
+    #[test]
+    fn pressure_high_frame_decodes() {
+        let frame = CANFrame::new(0x020, &12.5f32.to_le_bytes(), false, false).unwrap();
+        match frame.get_command() {
+            Ok(CanCommand::PressureHigh(pressure)) => assert_eq!(pressure, 12.5),
+            _ => panic!("Expected a PressureHigh command")
+        }
+    }
+
+    #[test]
+    fn floats_decode_in_either_byte_order() {
+        let bytes = [0x41, 0x48, 0x00, 0x00];
+        assert_eq!(read_float(&bytes, Endianness::Big), 12.5);
+        assert_eq!(read_float(&bytes, Endianness::Little), f32::from_le_bytes(bytes));
+
+        let bytes = 12.5f32.to_le_bytes();
+        assert_eq!(read_float(&bytes, Endianness::Little), 12.5);
+        assert_eq!(read_float(&bytes, Endianness::Big), f32::from_be_bytes(bytes));
+        assert_eq!(payload_endianness(0x020), Endianness::Little);
+    }
+
+    #[test]
+    fn floats_past_the_eighth_byte_are_parsed() {
+        let data = encode_floats(can_ids::PRESSURE_HIGH, &[1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(parse_float(can_ids::PRESSURE_HIGH, &data, 3), Ok(4.0));
+        assert_eq!(parse_float(can_ids::PRESSURE_HIGH, &data, 4), Err(FrameDecodeError::TruncatedPayload{ id: can_ids::PRESSURE_HIGH, expected: 20, got: 16 }));
+        // A classic frame can not hold them
+        assert!(matches!(CANFrame::new(can_ids::PRESSURE_HIGH, &data, false, false), Err(ConstructionError::TooMuchData)));
+    }
+
+    #[test]
+    fn short_two_float_frames_are_truncated() {
+        let frame = CANFrame::new(0x001, &[0u8; 7], false, false).unwrap();
+        assert_eq!(frame.get_command(), Err(FrameDecodeError::TruncatedPayload{ id: 0x001, expected: 8, got: 7 }));
+        let frame = CANFrame::new(0x581, &[0x4f, 0x21, 0x0f], false, false).unwrap();
+        assert_eq!(frame.get_command(), Err(FrameDecodeError::TruncatedPayload{ id: 0x581, expected: 8, got: 3 }));
+    }
+
+    #[test]
+    fn disconnected_thermocouples_have_no_reading() {
+        let mut data = 21.5f32.to_le_bytes().to_vec();
+        data.extend_from_slice(&TORCHIC_DISCONNECTED_THRESHOLD.to_le_bytes());
+        let frame = CANFrame::new(can_ids::TORCHIC_1, &data, false, false).unwrap();
+        assert_eq!(frame.get_command(), Ok(CanCommand::Torchic1([Some(21.5), None])));
+
+        let frame = CANFrame::to_frame(&CanCommand::Torchic2([None, Some(30.0)])).unwrap();
+        assert_eq!(frame.get_command(), Ok(CanCommand::Torchic2([None, Some(30.0)])));
+    }
+
+    #[test]
+    fn extended_frames_decode_with_their_full_id() {
+        // The low 11 bits are BMS_HEALTH_CHECK, which must not be what this decodes as
+        let frame = CANFrame::new(0x18FF_0001, &[0u8; 8], false, false).unwrap();
+        assert!(frame.is_extended());
+        assert_eq!(frame.get_command(), Err(FrameDecodeError::UnknownId(0x18FF_0001)));
+
+        // An extended frame on an id in the standard range is a different message from the standard one
+        assert_eq!(decode_frame(can_ids::BMS_HEALTH_CHECK, true, &[0u8; 8]), Err(FrameDecodeError::UnknownId(can_ids::BMS_HEALTH_CHECK)));
+        assert!(decode_frame(can_ids::BMS_HEALTH_CHECK, false, &[0u8; 8]).is_ok());
+    }
+
+    #[test]
+    fn extended_frames_on_a_known_id_are_unknown() {
+        // No peripheral has an extended id yet, so a pressure reading is only decoded from the standard frame
+        let data = 101.5f32.to_le_bytes();
+        assert_eq!(decode_frame(can_ids::PRESSURE_HIGH, false, &data), Ok(CanCommand::PressureHigh(101.5)));
+        assert_eq!(decode_frame(can_ids::PRESSURE_HIGH, true, &data), Err(FrameDecodeError::UnknownId(can_ids::PRESSURE_HIGH)));
+
+        let extended_id = 0x18FF_0000 | can_ids::PRESSURE_HIGH;
+        let frame = CANFrame::new(extended_id, &data, false, false).unwrap();
+        assert!(frame.is_extended());
+        assert_eq!(frame.get_command(), Err(FrameDecodeError::UnknownId(extended_id)));
+    }
+
+    #[test]
+    fn unknown_ids_are_reported() {
+        let frame = CANFrame::new(0x123, &[0u8; 8], false, false).unwrap();
+        assert_eq!(frame.get_command(), Err(FrameDecodeError::UnknownId(0x123)));
+    }
+
+    #[test]
+    fn commands_round_trip() {
+        use crate::can_extentions::fault_reports::{ SeverityCode, BmsErrorCode };
+        let commands = vec![
+            CanCommand::BmsHealthCheck{ battery_pack_current: 12.0, cell_temperature: 30.5 },
+            CanCommand::MotorControllerHealthCheck{ igbt_temp: 40.0, motor_voltage: 32.0 },
+            CanCommand::BmsFaultReport(BmsFaultReport{ severity_code: SeverityCode::DANGER, error_code: BmsErrorCode::CELL_TEMPERATURE }),
+            CanCommand::BmsStateChange(AckNack::Ack),
+            CanCommand::BmsData1{ battery_pack_voltage: 48.0, state_of_charge: 90.0 },
+            CanCommand::BmsData2{ buck_temperature: 25.0, bms_current: 0.01 },
+            CanCommand::BmsData3{ link_cap_voltage: 47.5 },
+            CanCommand::MotorControllerFaultReport(MotorControllerFaultReport{ severity_code: SeverityCode::WARNING }),
+            CanCommand::MotorControllerStateChange(AckNack::Nack),
+            CanCommand::MotorControllerData1{ mc_pod_speed: 3.0, motor_current: 20.0 },
+            CanCommand::MotorControllerData2{ battery_current: 10.0, battery_voltage: 47.0 },
+            CanCommand::PodSpeed{ pod_speed: 5.5 },
+            CanCommand::PressureHigh(200.0),
+            CanCommand::PressureLow1(50.0),
+            CanCommand::PressureLow2(51.0),
+            CanCommand::PressureStateChange(AckNack::Ack),
+            CanCommand::Current5V(1.0),
+            CanCommand::Current12V(2.0),
+            CanCommand::Current24V(3.0),
+            CanCommand::Torchic1([Some(20.0), Some(21.0)]),
+            CanCommand::Torchic2([Some(22.0), Some(23.0)]),
+        ];
+        for command in commands {
+            let frame = CANFrame::to_frame(&command).unwrap();
+            assert_eq!(frame.get_command(), Ok(command));
+        }
+    }
+
+    #[test]
+    fn receive_only_commands_are_not_encoded() {
+        assert!(matches!(CANFrame::to_frame(&CanCommand::RoboteqTemperatureResult{ sub_index: 1, temp: 20 }), Err(CanError::UnencodableCommand)));
+    }
+}
 
 /**
  *
  * CAN FRAME HANDLER
  *
- * Functions for decoding a Can Frame
+ * Functions for decoding a Can Frame and encoding a CanCommand back into one.
+ * Implemented for classic frames, and for CAN-FD frames with the canfd feature
  */
 pub trait FrameHandler {
-    fn get_command(&self) -> CanCommand;
+    fn get_command(&self) -> Result<CanCommand, FrameDecodeError>;
+    fn to_frame(command: &CanCommand) -> Result<Self, CanError> where Self: Sized;
 }
 
 const ROBOTEQ_MSG_CSS: u8 = 0b11110000;
 
-impl FrameHandler for socketcan::CANFrame {
-    fn get_command(&self) -> CanCommand {
-        let id = self.id();
-        let data = self.data();
-
-        match id {
-            0x001 => CanCommand::BmsHealthCheck{battery_pack_current: parse_first_float(data), cell_temperature: parse_second_float(data)},
-            0x002 => CanCommand::MotorControllerHealthCheck{ igbt_temp: parse_first_float(data), motor_voltage: parse_second_float(data)},
-            0x00A => CanCommand::BmsFaultReport(BmsFaultReport::from(data)),
-            0x00B => CanCommand::BmsStateChange(get_state_change_ack(data)),
-            0x00C => CanCommand::BmsData1{ battery_pack_voltage: parse_first_float(data), state_of_charge: parse_second_float(data)},
-            0x00D => CanCommand::BmsData2{ buck_temperature: parse_first_float(data), bms_current: parse_second_float(data)},
-            0x00E => CanCommand::BmsData3{ link_cap_voltage: parse_first_float(data)},
-            0x014 => CanCommand::MotorControllerFaultReport(MotorControllerFaultReport::from(data)),
-            0x015 => CanCommand::MotorControllerStateChange(get_state_change_ack(data)),
-            0x016 => CanCommand::MotorControllerData1{ mc_pod_speed: parse_first_float(data), motor_current: parse_second_float(data) },
-            0x017 => CanCommand::MotorControllerData2{ battery_current: parse_first_float(data), battery_voltage: parse_second_float(data) },
-            0x01F => CanCommand::PodSpeed{ pod_speed: parse_first_float(data)},
-            0x020 => CanCommand::PressureHigh(parse_first_float(data)),
-            0x021 => CanCommand::PressureLow1(parse_first_float(data)),
-            0x022 => CanCommand::PressureLow2(parse_first_float(data)),
-            0x023 => CanCommand::PressureStateChange(get_state_change_ack(data)),
-            0x030 => CanCommand::Current5V(parse_first_float(data)),
-            0x031 => CanCommand::Current12V(parse_first_float(data)),
-            0x032 => CanCommand::Current24V(parse_first_float(data)),
-            0x040 => CanCommand::Torchic1([Some(parse_first_float(data)), Some(parse_second_float(data))]),
-            0x041 => CanCommand::Torchic2([Some(parse_first_float(data)), Some(parse_second_float(data))]),
-            0x581 => {
-                /* ROBOTEQ HANDLER */
-                let flags = data[0];
-                let index = (data[1] as u16) << 8 | (data[2] as u16);
-                let subindex = data[3];
-                match (flags | ROBOTEQ_MSG_CSS) >> 4 {
-                    0x4 => {
-                        match index {
-                            0x2103 => CanCommand::RoboteqMotorEncoderResult{
-                                motor_number: subindex,
-                                speed: (((data[4] as u32) << 24) | ((data[5] as u32) << 16) | ((data[6] as u32) << 8) | (data[7] as u32)) as i32
-                            },
-                            0x210C => CanCommand::RoboteqBatteryAmpsResult{
-                                motor_number: subindex,
-                                amps: (((data[4] as u32) << 8) | ((data[5] as u32))) as i16
-                            },
-                            0x210f => CanCommand::RoboteqTemperatureResult{
-                                sub_index: subindex,
-                                temp: data[4] as i8
-                            },
-                            _ => CanCommand::Unknown(id)
-                        }
-                    },
-                    0x6 => CanCommand::Unknown(id),
-                    0x8 => {
-                        println!("Message Error Roboteq: index:{:?}, subindex:{:?}", index, subindex);
-                        return CanCommand::Unknown(id);
+impl FrameHandler for CANFrame {
+    fn get_command(&self) -> Result<CanCommand, FrameDecodeError> {
+        decode_frame(self.id(), self.is_extended(), self.data())
+    }
+
+    fn to_frame(command: &CanCommand) -> Result<CANFrame, CanError> {
+        let (id, data) = encode_command(command)?;
+        // The flags are remote transmission request and error. The frame is extended if the
+        // command's id is, socketcan picks the format from the id
+        Ok(CANFrame::new(id, &data, false, false)?)
+    }
+}
+
+/**
+ * @func decode_frame
+ * @brief decode a frame given its id and whether it is an extended frame. Every id is only
+ * defined in one format, so a frame in the other format is an unknown message
+ */
+pub(super) fn decode_frame(id: u32, extended: bool, data: &[u8]) -> Result<CanCommand, FrameDecodeError> {
+    if extended != can_ids::is_extended(id) {
+        return Err(FrameDecodeError::UnknownId(id));
+    }
+    decode_command(id, data)
+}
+
+/**
+ * @func encode_command
+ * @brief the inverse of decode_command. Produces the id and payload for a command
+ */
+pub(super) fn encode_command(command: &CanCommand) -> Result<(u32, Vec<u8>), CanError> {
+    let encoded = match command {
+        CanCommand::BmsHealthCheck{ battery_pack_current, cell_temperature } => (can_ids::BMS_HEALTH_CHECK, encode_floats(can_ids::BMS_HEALTH_CHECK, &[*battery_pack_current, *cell_temperature])),
+        CanCommand::MotorControllerHealthCheck{ igbt_temp, motor_voltage } => (can_ids::MOTOR_CONTROLLER_HEALTH_CHECK, encode_floats(can_ids::MOTOR_CONTROLLER_HEALTH_CHECK, &[*igbt_temp, *motor_voltage])),
+        CanCommand::BmsFaultReport(report) => (can_ids::BMS_FAULT_REPORT, vec![report.severity_code.to_byte(), report.error_code.to_byte()]),
+        CanCommand::BmsStateChange(ack_nack) => (can_ids::BMS_STATE_CHANGE, encode_state_change_ack(ack_nack)),
+        CanCommand::BmsData1{ battery_pack_voltage, state_of_charge } => (can_ids::BMS_DATA_1, encode_floats(can_ids::BMS_DATA_1, &[*battery_pack_voltage, *state_of_charge])),
+        CanCommand::BmsData2{ buck_temperature, bms_current } => (can_ids::BMS_DATA_2, encode_floats(can_ids::BMS_DATA_2, &[*buck_temperature, *bms_current])),
+        CanCommand::BmsData3{ link_cap_voltage } => (can_ids::BMS_DATA_3, encode_floats(can_ids::BMS_DATA_3, &[*link_cap_voltage])),
+        CanCommand::MotorControllerFaultReport(report) => (can_ids::MOTOR_CONTROLLER_FAULT_REPORT, vec![report.severity_code.to_byte()]),
+        CanCommand::MotorControllerStateChange(ack_nack) => (can_ids::MOTOR_CONTROLLER_STATE_CHANGE, encode_state_change_ack(ack_nack)),
+        CanCommand::MotorControllerData1{ mc_pod_speed, motor_current } => (can_ids::MOTOR_CONTROLLER_DATA_1, encode_floats(can_ids::MOTOR_CONTROLLER_DATA_1, &[*mc_pod_speed, *motor_current])),
+        CanCommand::MotorControllerData2{ battery_current, battery_voltage } => (can_ids::MOTOR_CONTROLLER_DATA_2, encode_floats(can_ids::MOTOR_CONTROLLER_DATA_2, &[*battery_current, *battery_voltage])),
+        CanCommand::PodSpeed{ pod_speed } => (can_ids::POD_SPEED, encode_floats(can_ids::POD_SPEED, &[*pod_speed])),
+        CanCommand::PressureHigh(pressure) => (can_ids::PRESSURE_HIGH, encode_floats(can_ids::PRESSURE_HIGH, &[*pressure])),
+        CanCommand::PressureLow1(pressure) => (can_ids::PRESSURE_LOW_1, encode_floats(can_ids::PRESSURE_LOW_1, &[*pressure])),
+        CanCommand::PressureLow2(pressure) => (can_ids::PRESSURE_LOW_2, encode_floats(can_ids::PRESSURE_LOW_2, &[*pressure])),
+        CanCommand::PressureStateChange(ack_nack) => (can_ids::PRESSURE_STATE_CHANGE, encode_state_change_ack(ack_nack)),
+        CanCommand::Current5V(current) => (can_ids::CURRENT_5V, encode_floats(can_ids::CURRENT_5V, &[*current])),
+        CanCommand::Current12V(current) => (can_ids::CURRENT_12V, encode_floats(can_ids::CURRENT_12V, &[*current])),
+        CanCommand::Current24V(current) => (can_ids::CURRENT_24V, encode_floats(can_ids::CURRENT_24V, &[*current])),
+        CanCommand::Torchic1(temps) => (can_ids::TORCHIC_1, encode_floats(can_ids::TORCHIC_1, &[temps[0].unwrap_or(f32::NAN), temps[1].unwrap_or(f32::NAN)])),
+        CanCommand::Torchic2(temps) => (can_ids::TORCHIC_2, encode_floats(can_ids::TORCHIC_2, &[temps[0].unwrap_or(f32::NAN), temps[1].unwrap_or(f32::NAN)])),
+        CanCommand::RoboteqTemperatureResult{ .. }
+        | CanCommand::RoboteqBatteryAmpsResult{ .. }
+        | CanCommand::RoboteqMotorEncoderResult{ .. } => return Err(CanError::UnencodableCommand)
+    };
+    Ok(encoded)
+}
+
+/**
+ * @func decode_command
+ * @brief decode the payload of a frame with the given id.
+ * Returns TruncatedPayload if the payload is too short for the command it claims to be
+ */
+fn decode_command(id: u32, data: &[u8]) -> Result<CanCommand, FrameDecodeError> {
+    let command = match id {
+        can_ids::BMS_HEALTH_CHECK => CanCommand::BmsHealthCheck{battery_pack_current: parse_first_float(id, data)?, cell_temperature: parse_second_float(id, data)?},
+        can_ids::MOTOR_CONTROLLER_HEALTH_CHECK => CanCommand::MotorControllerHealthCheck{ igbt_temp: parse_first_float(id, data)?, motor_voltage: parse_second_float(id, data)?},
+        can_ids::BMS_FAULT_REPORT => {
+            check_length(id, data, 2)?;
+            CanCommand::BmsFaultReport(BmsFaultReport::from(data))
+        },
+        can_ids::BMS_STATE_CHANGE => CanCommand::BmsStateChange(get_state_change_ack(data)),
+        can_ids::BMS_DATA_1 => CanCommand::BmsData1{ battery_pack_voltage: parse_first_float(id, data)?, state_of_charge: parse_second_float(id, data)?},
+        can_ids::BMS_DATA_2 => CanCommand::BmsData2{ buck_temperature: parse_first_float(id, data)?, bms_current: parse_second_float(id, data)?},
+        can_ids::BMS_DATA_3 => CanCommand::BmsData3{ link_cap_voltage: parse_first_float(id, data)?},
+        can_ids::MOTOR_CONTROLLER_FAULT_REPORT => {
+            check_length(id, data, 1)?;
+            CanCommand::MotorControllerFaultReport(MotorControllerFaultReport::from(data))
+        },
+        can_ids::MOTOR_CONTROLLER_STATE_CHANGE => CanCommand::MotorControllerStateChange(get_state_change_ack(data)),
+        can_ids::MOTOR_CONTROLLER_DATA_1 => CanCommand::MotorControllerData1{ mc_pod_speed: parse_first_float(id, data)?, motor_current: parse_second_float(id, data)? },
+        can_ids::MOTOR_CONTROLLER_DATA_2 => CanCommand::MotorControllerData2{ battery_current: parse_first_float(id, data)?, battery_voltage: parse_second_float(id, data)? },
+        can_ids::POD_SPEED => CanCommand::PodSpeed{ pod_speed: parse_first_float(id, data)?},
+        can_ids::PRESSURE_HIGH => CanCommand::PressureHigh(parse_first_float(id, data)?),
+        can_ids::PRESSURE_LOW_1 => CanCommand::PressureLow1(parse_first_float(id, data)?),
+        can_ids::PRESSURE_LOW_2 => CanCommand::PressureLow2(parse_first_float(id, data)?),
+        can_ids::PRESSURE_STATE_CHANGE => CanCommand::PressureStateChange(get_state_change_ack(data)),
+        can_ids::CURRENT_5V => CanCommand::Current5V(parse_first_float(id, data)?),
+        can_ids::CURRENT_12V => CanCommand::Current12V(parse_first_float(id, data)?),
+        can_ids::CURRENT_24V => CanCommand::Current24V(parse_first_float(id, data)?),
+        can_ids::TORCHIC_1 => CanCommand::Torchic1([torchic_reading(parse_first_float(id, data)?), torchic_reading(parse_second_float(id, data)?)]),
+        can_ids::TORCHIC_2 => CanCommand::Torchic2([torchic_reading(parse_first_float(id, data)?), torchic_reading(parse_second_float(id, data)?)]),
+        can_ids::ROBOTEQ_RESPONSE => {
+            /* ROBOTEQ HANDLER */
+            check_length(id, data, 8)?;
+            let flags = data[0];
+            let index = (data[1] as u16) << 8 | (data[2] as u16);
+            let subindex = data[3];
+            match (flags | ROBOTEQ_MSG_CSS) >> 4 {
+                0x4 => {
+                    match index {
+                        0x2103 => CanCommand::RoboteqMotorEncoderResult{
+                            motor_number: subindex,
+                            speed: (((data[4] as u32) << 24) | ((data[5] as u32) << 16) | ((data[6] as u32) << 8) | (data[7] as u32)) as i32
+                        },
+                        0x210C => CanCommand::RoboteqBatteryAmpsResult{
+                            motor_number: subindex,
+                            amps: (((data[4] as u32) << 8) | ((data[5] as u32))) as i16
+                        },
+                        0x210f => CanCommand::RoboteqTemperatureResult{
+                            sub_index: subindex,
+                            temp: data[4] as i8
+                        },
+                        _ => return Err(FrameDecodeError::UnknownId(id))
                     }
-                    _ => CanCommand::Unknown(id)
+                },
+                0x6 => return Err(FrameDecodeError::UnknownId(id)),
+                0x8 => {
+                    warn!("Message Error Roboteq: index:{:?}, subindex:{:?}", index, subindex);
+                    return Err(FrameDecodeError::UnknownId(id))
                 }
+                _ => return Err(FrameDecodeError::UnknownId(id))
             }
-            id => CanCommand::Unknown(id)
         }
+        id => return Err(FrameDecodeError::UnknownId(id))
+    };
+    Ok(command)
+}
+
+/**
+ * The thermocouple amps report a temperature at or above this when their probe is disconnected.
+ * It is well above anything a type K thermocouple can measure, so no real reading is lost
+ */
+const TORCHIC_DISCONNECTED_THRESHOLD: f32 = 2000.0;
+
+/**
+ * @func torchic_reading
+ * @brief a thermocouple temperature, or None if the probe is disconnected.
+ * NaN is what a missing reading is encoded as, so it is also treated as disconnected
+ */
+fn torchic_reading(temperature: f32) -> Option<f32> {
+    if temperature.is_nan() || temperature >= TORCHIC_DISCONNECTED_THRESHOLD {
+        None
+    } else {
+        Some(temperature)
+    }
+}
+
+/**
+ * @func check_length
+ * @brief make sure a payload holds at least the expected number of bytes
+ */
+fn check_length(id: u32, data: &[u8], expected: usize) -> Result<(), FrameDecodeError> {
+    if data.len() < expected {
+        return Err(FrameDecodeError::TruncatedPayload{ id, expected, got: data.len() });
     }
+    Ok(())
 }
 
 /**
@@ -84,22 +321,96 @@ impl FrameHandler for socketcan::CANFrame {
  * An ack or a Nack of the state change request
  */
 fn get_state_change_ack(data: &[u8]) -> AckNack {
-    if data.len() == 0 { AckNack::UNKNOWN }
-    else { AckNack::from(data[1]) }
+    match data.get(1) {
+        Some(&byte) => AckNack::from(byte),
+        None => AckNack::UNKNOWN
+    }
+}
+
+/**
+ * @func encode_state_change_ack
+ * @brief the ack is read from the second byte of a state change frame
+ */
+fn encode_state_change_ack(ack_nack: &AckNack) -> Vec<u8> {
+    vec![0x00, ack_nack.to_byte()]
+}
+
+/**
+ * Byte order of the floats in a frame's payload
+ */
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Endianness {
+    Little,
+    Big
+}
+
+/**
+ * Ids sent by boards which transmit their floats big endian. Every other id is little endian
+ */
+const BIG_ENDIAN_IDS: &[u32] = &[];
+
+/**
+ * @func payload_endianness
+ * @brief the byte order of the floats in frames with the given id
+ */
+fn payload_endianness(id: u32) -> Endianness {
+    if BIG_ENDIAN_IDS.contains(&id) {
+        Endianness::Big
+    } else {
+        Endianness::Little
+    }
+}
+
+/**
+ * @func read_float
+ * @brief read a 4 byte float in the given byte order
+ */
+fn read_float(bytes: &[u8], endianness: Endianness) -> f32 {
+    match endianness {
+        Endianness::Little => LittleEndian::read_f32(bytes),
+        Endianness::Big => BigEndian::read_f32(bytes)
+    }
+}
+
+/**
+ * @func encode_floats
+ * @brief pack floats into the payload of a frame with the given id. More than 2 only fit in a CAN-FD frame
+ */
+fn encode_floats(id: u32, floats: &[f32]) -> Vec<u8> {
+    let mut data = vec![0u8; floats.len() * 4];
+    match payload_endianness(id) {
+        Endianness::Little => LittleEndian::write_f32_into(floats, &mut data),
+        Endianness::Big => BigEndian::write_f32_into(floats, &mut data)
+    }
+    data
+}
+
+/**
+ * @func parse_float
+ * @brief parse the float at index in frames consisting of 4-byte floats. A classic frame holds 2,
+ * a CAN-FD frame up to 16
+ * Returns TruncatedPayload if the frame is too short to hold the float
+ */
+fn parse_float(id: u32, data: &[u8], index: usize) -> Result<f32, FrameDecodeError> {
+    let start = index * 4;
+    check_length(id, data, start + 4)?;
+    Ok(read_float(&data[start..start + 4], payload_endianness(id)))
 }
 
 /**
- * @func parse_two_floats
+ * @func parse_first_float
  * @brief parse frames consisting of 2 4-byte floats
+ * Returns TruncatedPayload if the frame is too short to hold the float
  */
-fn parse_first_float(data: &[u8]) -> f32 {
-    LittleEndian::read_f32(&[data[0], data[1], data[2], data[3]])
+fn parse_first_float(id: u32, data: &[u8]) -> Result<f32, FrameDecodeError> {
+    parse_float(id, data, 0)
 }
 
 /**
  * @func parse_second_float
  * @brief parse second half of frames consisting of 2 4-byte floats
+ * Returns TruncatedPayload if the frame is too short to hold the float
  */
-fn parse_second_float(data: &[u8]) -> f32 {
-    LittleEndian::read_f32(&[data[4], data[5], data[6], data[7]])
+fn parse_second_float(id: u32, data: &[u8]) -> Result<f32, FrameDecodeError> {
+    parse_float(id, data, 1)
 }
@@ -1,3 +1,7 @@
 mod frame_handler;
+#[cfg(feature = "canfd")]
+mod fd_frame;
 
-pub use frame_handler::FrameHandler;
\ No newline at end of file
+pub use frame_handler::FrameHandler;
+#[cfg(feature = "canfd")]
+pub use fd_frame::CanFdFrame;
\ No newline at end of file
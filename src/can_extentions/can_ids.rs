@@ -0,0 +1,93 @@
+/*!
+ * CAN message identifiers as defined in the CAN Communication Protocol
+ * (https://docs.google.com/document/d/1pAAAPyWClxrq7MwrA0_AGxnqU6B5r5MHmvRERMY6hUo/edit)
+ *
+ * Every encoder and decoder should reference these instead of writing out the ids.
+ */
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ids_are_unique() {
+        let mut ids = ALL_IDS.to_vec();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), ALL_IDS.len());
+    }
+
+    #[test]
+    fn frame_format_follows_the_id() {
+        assert!(ALL_IDS.iter().all(|&id| !is_extended(id)));
+        assert!(is_extended(STANDARD_ID_MAX + 1));
+        assert!(is_extended(EXTENDED_ID_MAX));
+    }
+}
+
+/**
+ * Largest id of a standard (11-bit) frame. socketcan sends and receives any id above it as an
+ * extended (29-bit) frame, so a peripheral on extended ids must be assigned ids above this
+ */
+pub const STANDARD_ID_MAX: u32 = 0x7FF;
+pub const EXTENDED_ID_MAX: u32 = 0x1FFF_FFFF;
+
+/**
+ * @func is_extended
+ * True if frames with this id use the extended (29-bit) format
+ */
+pub const fn is_extended(id: u32) -> bool {
+    id > STANDARD_ID_MAX
+}
+
+pub const POD_STATE: u32 = 0x000;
+pub const BMS_HEALTH_CHECK: u32 = 0x001;
+pub const MOTOR_CONTROLLER_HEALTH_CHECK: u32 = 0x002;
+pub const BMS_FAULT_REPORT: u32 = 0x00A;
+pub const BMS_STATE_CHANGE: u32 = 0x00B;
+pub const BMS_DATA_1: u32 = 0x00C;
+pub const BMS_DATA_2: u32 = 0x00D;
+pub const BMS_DATA_3: u32 = 0x00E;
+pub const MOTOR_CONTROLLER_FAULT_REPORT: u32 = 0x014;
+pub const MOTOR_CONTROLLER_STATE_CHANGE: u32 = 0x015;
+pub const MOTOR_CONTROLLER_DATA_1: u32 = 0x016;
+pub const MOTOR_CONTROLLER_DATA_2: u32 = 0x017;
+pub const POD_SPEED: u32 = 0x01F;
+pub const PRESSURE_HIGH: u32 = 0x020;
+pub const PRESSURE_LOW_1: u32 = 0x021;
+pub const PRESSURE_LOW_2: u32 = 0x022;
+pub const PRESSURE_STATE_CHANGE: u32 = 0x023;
+pub const CURRENT_5V: u32 = 0x030;
+pub const CURRENT_12V: u32 = 0x031;
+pub const CURRENT_24V: u32 = 0x032;
+pub const TORCHIC_1: u32 = 0x040;
+pub const TORCHIC_2: u32 = 0x041;
+pub const ROBOTEQ_RESPONSE: u32 = 0x581; // 0x580 + roboteq node id
+pub const ROBOTEQ_REQUEST_BASE: u32 = 0x600; // Add the roboteq node id to get the request id
+
+pub const ALL_IDS: [u32; 24] = [
+    POD_STATE,
+    BMS_HEALTH_CHECK,
+    MOTOR_CONTROLLER_HEALTH_CHECK,
+    BMS_FAULT_REPORT,
+    BMS_STATE_CHANGE,
+    BMS_DATA_1,
+    BMS_DATA_2,
+    BMS_DATA_3,
+    MOTOR_CONTROLLER_FAULT_REPORT,
+    MOTOR_CONTROLLER_STATE_CHANGE,
+    MOTOR_CONTROLLER_DATA_1,
+    MOTOR_CONTROLLER_DATA_2,
+    POD_SPEED,
+    PRESSURE_HIGH,
+    PRESSURE_LOW_1,
+    PRESSURE_LOW_2,
+    PRESSURE_STATE_CHANGE,
+    CURRENT_5V,
+    CURRENT_12V,
+    CURRENT_24V,
+    TORCHIC_1,
+    TORCHIC_2,
+    ROBOTEQ_RESPONSE,
+    ROBOTEQ_REQUEST_BASE,
+];
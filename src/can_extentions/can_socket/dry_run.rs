@@ -0,0 +1,141 @@
+/*!
+ * @struct DryRunSocket
+ * @brief Wraps the socket the relay reads from so that nothing is ever written to the bus.
+ * Frames are still read and decoded as usual, every frame the relay would have sent is logged
+ * instead. Lets the relay's decisions be watched against a live bus without driving the pod
+ */
+
+use std::time::Duration;
+use log::{ debug, info };
+use super::super::frame::CANFrame;
+use crate::pod_states::PodState;
+use super::super::can_ids;
+use super::CanFrameSocket;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::{ RelayCanSocket, RoboteqCanSocket, pod_state_frame };
+    use std::sync::{ Arc, Mutex };
+
+    #[derive(Clone, Default)]
+    struct RecordingSocket {
+        written: Arc<Mutex<Vec<CANFrame>>>
+    }
+
+    impl CanFrameSocket for RecordingSocket {
+        fn read_frame(&self) -> std::io::Result<CANFrame> {
+            Ok(pod_state_frame(&PodState::Armed).unwrap())
+        }
+
+        fn write_frame(&self, frame: &CANFrame) -> std::io::Result<()> {
+            self.written.lock().unwrap().push(*frame);
+            Ok(())
+        }
+
+        fn write_frame_insist(&self, frame: &CANFrame) -> std::io::Result<()> {
+            self.write_frame(frame)
+        }
+
+        fn set_read_timeout(&self, _duration: Duration) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn writes_are_described_but_never_sent() {
+        let socket = RecordingSocket::default();
+        let dry_run = DryRunSocket::new(Box::new(socket.clone()));
+
+        assert!(dry_run.send_pod_state(&PodState::AutoPilot).is_ok());
+        assert!(dry_run.set_motor_throttle(1, 2, 40).is_ok());
+        assert!(dry_run.roboteq_emergency_stop(1).is_ok());
+        assert!(socket.written.lock().unwrap().is_empty());
+        assert!(dry_run.read_frame().is_ok());
+
+        assert_eq!(describe_frame(&pod_state_frame(&PodState::AutoPilot).unwrap()), "PodState::AutoPilot");
+        let throttle = CANFrame::new(can_ids::ROBOTEQ_REQUEST_BASE + 1, &[0x20, 0x00, 0x20, 0x02, 40, 0, 0, 0], false, false).unwrap();
+        assert_eq!(describe_frame(&throttle), "Roboteq node 1 throttle 40% on channel 2");
+    }
+}
+
+pub struct DryRunSocket {
+    socket: Box<dyn CanFrameSocket + Send>
+}
+
+impl DryRunSocket {
+    pub fn new(socket: Box<dyn CanFrameSocket + Send>) -> DryRunSocket {
+        DryRunSocket {
+            socket
+        }
+    }
+}
+
+impl CanFrameSocket for DryRunSocket {
+    fn read_frame(&self) -> std::io::Result<CANFrame> {
+        self.socket.read_frame()
+    }
+
+    fn write_frame(&self, frame: &CANFrame) -> std::io::Result<()> {
+        // The roboteq is polled for its readings several times a second, only commands are worth seeing by default
+        if is_roboteq_query(frame) {
+            debug!("[DRY RUN] would send {}", describe_frame(frame));
+        } else {
+            info!("[DRY RUN] would send {}", describe_frame(frame));
+        }
+        Ok(())
+    }
+
+    fn write_frame_insist(&self, frame: &CANFrame) -> std::io::Result<()> {
+        self.write_frame(frame)
+    }
+
+    fn set_read_timeout(&self, duration: Duration) -> std::io::Result<()> {
+        self.socket.set_read_timeout(duration)
+    }
+}
+
+const ROBOTEQ_THROTTLE_INDEX: u16 = 0x2000;
+const ROBOTEQ_EMERGENCY_STOP_INDEX: u16 = 0x200C;
+
+/**
+ * @func roboteq_request
+ * The node, index, subindex and value of a request to the roboteq, laid out as RoboteqCanSocket::send_msg writes them
+ */
+fn roboteq_request(frame: &CANFrame) -> Option<(u32, u16, u8, u32)> {
+    let node = frame.id().checked_sub(can_ids::ROBOTEQ_REQUEST_BASE).filter(|&node| node < 0x80)?;
+    match frame.data() {
+        &[_, index_low, index_high, subindex, v0, v1, v2, v3] => Some((
+            node,
+            u16::from_le_bytes([index_low, index_high]),
+            subindex,
+            u32::from_le_bytes([v0, v1, v2, v3])
+        )),
+        _ => None
+    }
+}
+
+fn is_roboteq_query(frame: &CANFrame) -> bool {
+    matches!(roboteq_request(frame), Some((_, index, _, _)) if index != ROBOTEQ_THROTTLE_INDEX && index != ROBOTEQ_EMERGENCY_STOP_INDEX)
+}
+
+/**
+ * @func describe_frame
+ * What a frame the relay sends commands, for the dry run log. Frames which aren't recognized are given in candump form
+ */
+fn describe_frame(frame: &CANFrame) -> String {
+    if frame.id() == can_ids::POD_STATE {
+        if let Some(state) = frame.data().first().and_then(|&byte| PodState::from_byte(byte)) {
+            return format!("PodState::{:?}", state);
+        }
+    }
+    if let Some((node, index, subindex, value)) = roboteq_request(frame) {
+        return match index {
+            ROBOTEQ_THROTTLE_INDEX => format!("Roboteq node {} throttle {}% on channel {}", node, value, subindex),
+            ROBOTEQ_EMERGENCY_STOP_INDEX => format!("Roboteq node {} emergency stop", node),
+            _ => format!("Roboteq node {} query {:#06x} subindex {}", node, index, subindex)
+        };
+    }
+    let data: String = frame.data().iter().map(|byte| format!("{:02X}", byte)).collect();
+    format!("{:03X}#{}", frame.id(), data)
+}
@@ -0,0 +1,93 @@
+/*!
+ * @Trait CanFdSocket
+ * @brief Reads and writes CAN-FD frames. socketcan's CANSocket only reads and writes classic frames,
+ * so these go straight to the socket's file descriptor. A socket only passes CAN-FD frames once
+ * enable_fd_frames has been called on it, and it still reads classic frames after that.
+ * Only built with the canfd feature
+ */
+
+use std::io;
+use std::os::unix::io::AsRawFd;
+use crate::can_extentions::can_frame::CanFdFrame;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::os::unix::net::UnixDatagram;
+
+    #[test]
+    fn frames_are_read_and_written_whole() {
+        assert_eq!(std::mem::size_of::<CanFdFrame>(), libc::CANFD_MTU);
+        // A datagram pair passes whole frames as a CAN socket does, without needing a CAN interface
+        let (relay, bus) = UnixDatagram::pair().unwrap();
+        let frame = CanFdFrame::new(0x1ABCDE, &[7u8; 20]).unwrap();
+        relay.write_fd_frame(&frame).unwrap();
+        let received = bus.read_fd_frame().unwrap();
+        assert_eq!((received.id(), received.is_extended(), received.data()), (0x1ABCDE, true, frame.data()));
+
+        // A classic frame is laid out like the start of a CAN-FD frame
+        let mut classic = [0u8; libc::CAN_MTU];
+        classic[..4].copy_from_slice(&0x020u32.to_ne_bytes());
+        classic[4] = 2;
+        classic[8..10].copy_from_slice(&[0xAB, 0xCD]);
+        bus.send(&classic).unwrap();
+        let received = relay.read_fd_frame().unwrap();
+        assert_eq!((received.id(), received.is_extended(), received.data()), (0x020, false, &[0xAB, 0xCD][..]));
+
+        bus.send(&[0u8; 3]).unwrap();
+        assert_eq!(relay.read_fd_frame().unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
+
+pub trait CanFdSocket {
+    fn enable_fd_frames(&self) -> io::Result<()>;
+    fn read_fd_frame(&self) -> io::Result<CanFdFrame>;
+    fn write_fd_frame(&self, frame: &CanFdFrame) -> io::Result<()>;
+}
+
+impl<S: AsRawFd> CanFdSocket for S {
+    fn enable_fd_frames(&self) -> io::Result<()> {
+        let enable: libc::c_int = 1;
+        let result = unsafe {
+            libc::setsockopt(
+                self.as_raw_fd(),
+                libc::SOL_CAN_RAW,
+                libc::CAN_RAW_FD_FRAMES,
+                &enable as *const libc::c_int as *const libc::c_void,
+                std::mem::size_of::<libc::c_int>() as libc::socklen_t
+            )
+        };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /**
+     * @brief read_fd_frame
+     * Read a CAN-FD frame, or a classic frame, which fills the first CAN_MTU bytes of one
+     */
+    fn read_fd_frame(&self) -> io::Result<CanFdFrame> {
+        let mut frame = CanFdFrame::new(0, &[]).expect("An empty standard frame is always valid");
+        let read = unsafe { libc::read(self.as_raw_fd(), &mut frame as *mut CanFdFrame as *mut libc::c_void, libc::CANFD_MTU) };
+        if read < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let read = read as usize;
+        if (read != libc::CAN_MTU && read != libc::CANFD_MTU) || !frame.has_valid_length() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("read {} bytes, which is not a CAN or CAN-FD frame", read)));
+        }
+        Ok(frame)
+    }
+
+    fn write_fd_frame(&self, frame: &CanFdFrame) -> io::Result<()> {
+        let written = unsafe { libc::write(self.as_raw_fd(), frame as *const CanFdFrame as *const libc::c_void, libc::CANFD_MTU) };
+        if written < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if written as usize != libc::CANFD_MTU {
+            return Err(io::Error::new(io::ErrorKind::WriteZero, "the CAN-FD frame was only partly written"));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,36 @@
+/*!
+ * @Trait CanFrameSocket
+ * @brief The socket operations the relay uses to talk on the bus. The can thread only
+ * sees this trait, so tests can stand in for the bus with a scripted socket
+ */
+
+use std::time::Duration;
+#[cfg(unix)]
+use socketcan::CANSocket;
+use super::super::frame::CANFrame;
+
+pub trait CanFrameSocket {
+    fn read_frame(&self) -> std::io::Result<CANFrame>;
+    fn write_frame(&self, frame: &CANFrame) -> std::io::Result<()>;
+    fn write_frame_insist(&self, frame: &CANFrame) -> std::io::Result<()>;
+    fn set_read_timeout(&self, duration: Duration) -> std::io::Result<()>;
+}
+
+#[cfg(unix)]
+impl CanFrameSocket for CANSocket {
+    fn read_frame(&self) -> std::io::Result<CANFrame> {
+        CANSocket::read_frame(self)
+    }
+
+    fn write_frame(&self, frame: &CANFrame) -> std::io::Result<()> {
+        CANSocket::write_frame(self, frame)
+    }
+
+    fn write_frame_insist(&self, frame: &CANFrame) -> std::io::Result<()> {
+        CANSocket::write_frame_insist(self, frame)
+    }
+
+    fn set_read_timeout(&self, duration: Duration) -> std::io::Result<()> {
+        CANSocket::set_read_timeout(self, duration)
+    }
+}
@@ -0,0 +1,169 @@
+/*!
+ * @Struct MockCanSocket
+ * @brief A bus with every board on it, for running the relay on a machine without a CAN interface.
+ * Reads cycle through plausible telemetry with a little noise on every value, each within the limits
+ * PodData::ok checks, and every pod state
+ * written is acked by the BMS, motor controller and pressure boards so the state machine can be
+ * driven from the controller. Only built with the mock-can feature, so it can never end up in a real build
+ */
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+use super::super::frame::CANFrame;
+use super::frame_socket::CanFrameSocket;
+use crate::can_extentions::{
+    ack_nack::AckNack,
+    can_command::CanCommand,
+    can_frame::FrameHandler,
+    can_ids
+};
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::can_extentions::can_socket::RelayCanSocket;
+    use crate::pod_states::PodState;
+
+    #[test]
+    fn telemetry_decodes_and_state_changes_are_acked() {
+        let socket = MockCanSocket::new();
+        for _ in 0..TELEMETRY_COMMANDS {
+            let command = socket.read_frame().unwrap().get_command().unwrap();
+            assert!(!matches!(command, CanCommand::BmsStateChange(_)));
+        }
+
+        assert!(matches!(read_command(None), Err(err) if err.kind() == std::io::ErrorKind::WouldBlock));
+
+        socket.send_pod_state(&PodState::Armed).unwrap();
+        let acks: Vec<CanCommand> = (0..3).map(|_| socket.read_frame().unwrap().get_command().unwrap()).collect();
+        assert!(acks.contains(&CanCommand::BmsStateChange(AckNack::Ack)));
+        assert!(acks.contains(&CanCommand::MotorControllerStateChange(AckNack::Ack)));
+        assert!(acks.contains(&CanCommand::PressureStateChange(AckNack::Ack)));
+    }
+}
+
+/**
+ * Time each read takes, roughly the pace of the real bus
+ */
+pub(super) const READ_INTERVAL: Duration = Duration::from_millis(10);
+/**
+ * Every watched device is heard from once per cycle, well inside the device watchdog's period
+ */
+const TELEMETRY_COMMANDS: usize = 15;
+
+pub struct MockCanSocket {
+    bus: Mutex<MockBus>
+}
+
+struct MockBus {
+    telemetry: MockTelemetry,
+    pending: VecDeque<CanCommand> // Acks waiting to be read, sent ahead of telemetry
+}
+
+/**
+ * The mock's cycle of plausible telemetry. Shared with SimulatedPod, which reads it unless scripted otherwise
+ */
+pub(super) struct MockTelemetry {
+    seed: u64,
+    next_command: usize
+}
+
+impl MockCanSocket {
+    pub fn new() -> MockCanSocket {
+        MockCanSocket {
+            bus: Mutex::new(MockBus {
+                telemetry: MockTelemetry::new(),
+                pending: VecDeque::new()
+            })
+        }
+    }
+}
+
+impl Default for MockCanSocket {
+    fn default() -> MockCanSocket {
+        MockCanSocket::new()
+    }
+}
+
+/**
+ * The frame a read returns for command. None is a bus with nothing to read, which a socket reports as WouldBlock
+ */
+pub(super) fn read_command(command: Option<CanCommand>) -> std::io::Result<CANFrame> {
+    match command {
+        Some(command) => CANFrame::to_frame(&command).map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{:?}", err))),
+        None => Err(std::io::Error::new(std::io::ErrorKind::WouldBlock, "Nothing on the simulated bus"))
+    }
+}
+
+impl MockTelemetry {
+    pub(super) fn new() -> MockTelemetry {
+        MockTelemetry {
+            seed: 0x2545_F491_4F6C_DD1D,
+            next_command: 0
+        }
+    }
+
+    /**
+     * Value between low and high. xorshift is plenty for noise on a dashboard
+     */
+    fn between(&mut self, low: f32, high: f32) -> f32 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 7;
+        self.seed ^= self.seed << 17;
+        low + (high - low) * ((self.seed >> 40) as f32 / (1u64 << 24) as f32)
+    }
+
+    pub(super) fn next_command(&mut self) -> CanCommand {
+        let index = self.next_command;
+        self.next_command = (self.next_command + 1) % TELEMETRY_COMMANDS;
+        match index {
+            0 => CanCommand::BmsHealthCheck{ battery_pack_current: self.between(10.0, 30.0), cell_temperature: self.between(25.0, 40.0) },
+            1 => CanCommand::MotorControllerHealthCheck{ igbt_temp: self.between(30.0, 55.0), motor_voltage: self.between(30.0, 35.0) },
+            2 => CanCommand::BmsData1{ battery_pack_voltage: self.between(46.0, 50.4), state_of_charge: self.between(80.0, 100.0) },
+            3 => CanCommand::BmsData2{ buck_temperature: self.between(25.0, 45.0), bms_current: self.between(0.01, 0.04) },
+            // No link cap voltage, PodData::ok expects none while there is no motor controller
+            4 => CanCommand::MotorControllerData1{ mc_pod_speed: self.between(0.0, 20.0), motor_current: self.between(5.0, 40.0) },
+            5 => CanCommand::MotorControllerData2{ battery_current: self.between(10.0, 30.0), battery_voltage: self.between(46.0, 50.4) },
+            6 => CanCommand::PodSpeed{ pod_speed: self.between(0.0, 20.0) },
+            7 => CanCommand::PressureHigh(self.between(200.0, 300.0)),
+            8 => CanCommand::PressureLow1(self.between(80.0, 95.0)),
+            9 => CanCommand::PressureLow2(self.between(80.0, 95.0)),
+            10 => CanCommand::Current5V(self.between(0.2, 0.8)),
+            11 => CanCommand::Current12V(self.between(0.5, 1.5)),
+            12 => CanCommand::Current24V(self.between(1.0, 3.0)),
+            13 => CanCommand::Torchic1([Some(self.between(20.0, 35.0)), Some(self.between(20.0, 35.0))]),
+            _ => CanCommand::Torchic2([Some(self.between(20.0, 35.0)), Some(self.between(20.0, 35.0))])
+        }
+    }
+}
+
+impl CanFrameSocket for MockCanSocket {
+    fn read_frame(&self) -> std::io::Result<CANFrame> {
+        std::thread::sleep(READ_INTERVAL);
+        let mut bus = self.bus.lock().expect("The mock bus is only locked for a read or write");
+        let command = match bus.pending.pop_front() {
+            Some(command) => command,
+            None => bus.telemetry.next_command()
+        };
+        read_command(Some(command))
+    }
+
+    fn write_frame(&self, frame: &CANFrame) -> std::io::Result<()> {
+        if frame.id() == can_ids::POD_STATE {
+            let mut bus = self.bus.lock().expect("The mock bus is only locked for a read or write");
+            bus.pending.push_back(CanCommand::BmsStateChange(AckNack::Ack));
+            bus.pending.push_back(CanCommand::MotorControllerStateChange(AckNack::Ack));
+            bus.pending.push_back(CanCommand::PressureStateChange(AckNack::Ack));
+        }
+        Ok(())
+    }
+
+    fn write_frame_insist(&self, frame: &CANFrame) -> std::io::Result<()> {
+        self.write_frame(frame)
+    }
+
+    fn set_read_timeout(&self, _duration: Duration) -> std::io::Result<()> {
+        Ok(())
+    }
+}
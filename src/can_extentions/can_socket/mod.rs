@@ -1,5 +1,23 @@
+mod frame_socket;
 mod relay_can;
 mod roboteq;
+mod replay;
+mod dry_run;
+#[cfg(all(unix, feature = "canfd"))]
+mod fd_socket;
+#[cfg(feature = "mock-can")]
+mod mock;
+#[cfg(feature = "mock-can")]
+mod simulated_pod;
 
-pub use relay_can::RelayCanSocket;
-pub use roboteq::RoboteqCanSocket;
\ No newline at end of file
+pub use frame_socket::CanFrameSocket;
+pub use relay_can::{ RelayCanSocket, pod_state_frame };
+pub use roboteq::RoboteqCanSocket;
+pub use replay::FileReplaySource;
+pub use dry_run::DryRunSocket;
+#[cfg(all(unix, feature = "canfd"))]
+pub use fd_socket::CanFdSocket;
+#[cfg(feature = "mock-can")]
+pub use mock::MockCanSocket;
+#[cfg(feature = "mock-can")]
+pub use simulated_pod::{ SimulatedPod, Board, StateChangeResponse };
\ No newline at end of file
@@ -9,16 +9,26 @@
 
 use crate::pod_states::PodState;
 use crate::can_extentions::prelude::CanError as Error;
-use socketcan::{ CANSocket, CANFrame };
+use crate::can_extentions::can_ids;
+use super::super::frame::CANFrame;
+use super::CanFrameSocket;
 
 pub trait RelayCanSocket {
     fn send_pod_state(&self, state: &PodState) -> Result<(), Error>;
 }
 
-impl RelayCanSocket for CANSocket {
+impl<T: CanFrameSocket + ?Sized> RelayCanSocket for T {
     fn send_pod_state(&self, state: &PodState) -> Result<(), Error> {
         self.write_frame_insist(
-            &CANFrame::new(0, &[state.into()], false, false)?
+            &pod_state_frame(state)?
         ).map_err(|e| Error::WriteError(e))
     }
+}
+
+/**
+ * @func pod_state_frame
+ * @brief build the frame which commands the rest of the pod into the given state
+ */
+pub fn pod_state_frame(state: &PodState) -> Result<CANFrame, Error> {
+    Ok(CANFrame::new(can_ids::POD_STATE, &[state.into()], false, false)?)
 }
\ No newline at end of file
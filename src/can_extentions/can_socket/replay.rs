@@ -0,0 +1,180 @@
+/*!
+ * @Struct FileReplaySource
+ * @brief Replays a recorded candump log as if the frames were arriving on the bus. Each frame is read
+ * at the same offset from the start of the replay as it was from the start of the log, divided by the
+ * speed multiplier. Frames written by the relay are dropped, and once the log runs out the bus goes silent,
+ * each read waiting out the read timeout and reporting WouldBlock as a socket with nothing to read would.
+ *
+ * The log is in the format written by `candump -l`, one frame per line:
+ *   (1436509052.249713) can0 01F#00002C41
+ */
+
+use std::collections::VecDeque;
+use std::io::BufRead;
+use std::sync::Mutex;
+use std::time::{ Duration, Instant };
+use log::{ info };
+use super::super::frame::CANFrame;
+use super::frame_socket::CanFrameSocket;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::super::frame::ShouldRetry;
+
+    #[test]
+    fn candump_lines_are_parsed() {
+        let (timestamp, frame) = parse_candump_line("(1436509052.249713) can0 01F#00002C41").unwrap();
+        assert_eq!(timestamp, 1436509052.249713);
+        assert_eq!(frame.id(), 0x01F);
+        assert_eq!(frame.data(), &[0x00, 0x00, 0x2C, 0x41]);
+
+        assert!(parse_candump_line("(1436509052.249713) can0 01F#00002C4").is_err());
+        assert!(parse_candump_line("can0 01F#00002C41").is_err());
+        // None of these can be replayed, and slicing through the multi-byte character would panic
+        assert!(parse_candump_line("(NaN) can0 01F#00002C41").is_err());
+        assert!(parse_candump_line("(inf) can0 01F#00002C41").is_err());
+        assert!(parse_candump_line("(10.0) can0 123#a\u{e9}b").is_err());
+
+        let log = "(10.0) can0 001#\n\nbad line\n";
+        let error = FileReplaySource::from_reader(log.as_bytes(), 1.0).err().unwrap();
+        assert!(error.to_string().starts_with("line 3"));
+
+        for speed in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            let error = FileReplaySource::from_reader("(10.0) can0 001#\n".as_bytes(), speed).err().unwrap();
+            assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+        }
+    }
+
+    #[test]
+    fn replay_keeps_frame_timing_at_speed() {
+        let log = "(100.000) can0 01F#00002C41\n(100.400) can0 020#0000C842\n";
+        let source = FileReplaySource::from_reader(log.as_bytes(), 10.0).unwrap();
+        let start = Instant::now();
+        assert_eq!(source.read_frame().unwrap().id(), 0x01F);
+        assert_eq!(source.read_frame().unwrap().id(), 0x020);
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(40) && elapsed < Duration::from_millis(400), "{:?}", elapsed);
+
+        source.set_read_timeout(Duration::from_millis(1)).unwrap();
+        assert!(source.read_frame().should_retry());
+    }
+}
+
+pub struct FileReplaySource {
+    replay: Mutex<Replay>
+}
+
+struct Replay {
+    frames: VecDeque<(f64, CANFrame)>, // Frames with their timestamp in the log, in seconds
+    first_timestamp: f64,
+    started: Option<Instant>, // Set by the first read, so time spent starting up the relay is not skipped
+    speed: f64,
+    read_timeout: Duration
+}
+
+impl FileReplaySource {
+    /**
+     * @brief open
+     * Load a candump log. Malformed lines are an InvalidData error naming the line, and a speed
+     * which is not a positive number is an InvalidInput error
+     */
+    pub fn open(path: &str, speed: f64) -> std::io::Result<FileReplaySource> {
+        let file = std::fs::File::open(path)?;
+        FileReplaySource::from_reader(std::io::BufReader::new(file), speed)
+    }
+
+    pub fn from_reader<R: BufRead>(reader: R, speed: f64) -> std::io::Result<FileReplaySource> {
+        if !(speed.is_finite() && speed > 0.0) {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("invalid replay speed {}", speed)));
+        }
+        let mut frames = VecDeque::new();
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let frame = parse_candump_line(&line).map_err(|reason| {
+                std::io::Error::new(std::io::ErrorKind::InvalidData, format!("line {}: {}", index + 1, reason))
+            })?;
+            frames.push_back(frame);
+        }
+        let first_timestamp = frames.front().map_or(0.0, |&(timestamp, _)| timestamp);
+        Ok(FileReplaySource {
+            replay: Mutex::new(Replay {
+                frames,
+                first_timestamp,
+                started: None,
+                speed,
+                read_timeout: Duration::from_millis(10000)
+            })
+        })
+    }
+}
+
+/**
+ * @func parse_candump_line
+ * Parse one line of a candump log into the frame's timestamp in seconds and the frame
+ */
+fn parse_candump_line(line: &str) -> Result<(f64, CANFrame), String> {
+    let mut fields = line.split_whitespace();
+    let timestamp = fields.next()
+        .and_then(|field| field.strip_prefix('('))
+        .and_then(|field| field.strip_suffix(')'))
+        .and_then(|field| field.parse::<f64>().ok())
+        .filter(|timestamp| timestamp.is_finite())
+        .ok_or_else(|| format!("expected a (seconds.micros) timestamp in {:?}", line))?;
+    let _interface = fields.next().ok_or_else(|| format!("missing interface in {:?}", line))?;
+    let (id, data) = fields.next()
+        .and_then(|field| field.split_once('#'))
+        .ok_or_else(|| format!("expected <id>#<data> in {:?}", line))?;
+    let id = u32::from_str_radix(id, 16).map_err(|_| format!("invalid id {:?}", id))?;
+    // The data is sliced two bytes at a time, which is only safe when every character is one byte
+    if !data.is_ascii() {
+        return Err(format!("invalid data {:?}", data));
+    }
+    if data.len() % 2 != 0 {
+        return Err(format!("odd number of hex digits in {:?}", data));
+    }
+    let data = (0..data.len()).step_by(2)
+        .map(|index| u8::from_str_radix(&data[index..index + 2], 16))
+        .collect::<Result<Vec<u8>, _>>()
+        .map_err(|_| format!("invalid data {:?}", data))?;
+    let frame = CANFrame::new(id, &data, false, false).map_err(|err| format!("{:?}", err))?;
+    Ok((timestamp, frame))
+}
+
+impl CanFrameSocket for FileReplaySource {
+    fn read_frame(&self) -> std::io::Result<CANFrame> {
+        let mut replay = self.replay.lock().expect("The replay is only locked for a read or write");
+        let started = *replay.started.get_or_insert_with(Instant::now);
+        let (timestamp, frame) = match replay.frames.pop_front() {
+            Some(next) => next,
+            None => {
+                std::thread::sleep(replay.read_timeout);
+                return Err(std::io::Error::from(std::io::ErrorKind::WouldBlock));
+            }
+        };
+        let offset = Duration::from_secs_f64(((timestamp - replay.first_timestamp) / replay.speed).max(0.0));
+        if let Some(wait) = (started + offset).checked_duration_since(Instant::now()) {
+            std::thread::sleep(wait);
+        }
+        if replay.frames.is_empty() {
+            info!("CAN REPLAY: Reached the end of the log");
+        }
+        Ok(frame)
+    }
+
+    fn write_frame(&self, _frame: &CANFrame) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    fn write_frame_insist(&self, frame: &CANFrame) -> std::io::Result<()> {
+        self.write_frame(frame)
+    }
+
+    fn set_read_timeout(&self, duration: Duration) -> std::io::Result<()> {
+        self.replay.lock().expect("The replay is only locked for a read or write").read_timeout = duration;
+        Ok(())
+    }
+}
@@ -8,8 +8,10 @@
  * Source DataSheet Outlineing CAN specification: https://drive.google.com/file/d/1ALK8BErG0tjE8fcfFuHN_62qg2OUG2fF/view?usp=sharing
  */
 
-use socketcan;
+use super::super::frame::CANFrame;
 use super::super::error::CanError as Error;
+use super::super::can_ids;
+use super::CanFrameSocket;
 
 pub trait RoboteqCanSocket {
     fn send_msg(&self, node_id: u32, is_query: bool, empty_bytes: u32, index: u16, subindex: u8, data: &[u8]) -> Result<(), Error>;
@@ -20,7 +22,7 @@ pub trait RoboteqCanSocket {
     fn roboteq_emergency_stop(&self, node_id: u32) -> Result<(), Error>;
 }
 
-impl RoboteqCanSocket for socketcan::CANSocket {
+impl<T: CanFrameSocket + ?Sized> RoboteqCanSocket for T {
     /**
      * Framework for sending messages to the roboteq controller
      */
@@ -36,7 +38,7 @@ impl RoboteqCanSocket for socketcan::CANSocket {
             data[2],
             data[3]
         ];
-        let message = socketcan::CANFrame::new(0x600 + node_id, &data, false, false).map_err(|e| Error::MessageError(e))?;
+        let message = CANFrame::new(can_ids::ROBOTEQ_REQUEST_BASE + node_id, &data, false, false)?;
         self.write_frame(&message).map_err(|e| Error::WriteError(e))?;
         Ok(())
     }
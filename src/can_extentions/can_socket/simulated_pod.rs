@@ -0,0 +1,249 @@
+/*!
+ * @Struct SimulatedPod
+ * @brief A scriptable MockCanSocket, for tests which run the relay end to end.
+ * Each board answers a pod state written by the relay the way it has been scripted to, acking anything
+ * not scripted, and the mock's telemetry is read between the answers unless a cycle of telemetry has
+ * been scripted in its place. Every frame the relay writes is
+ * kept so a test can check what was commanded. Clones share one pod, so a test can keep a clone to
+ * script and inspect while the can thread owns the socket. Only built with the mock-can feature
+ */
+
+use std::collections::{ HashMap, VecDeque };
+use std::sync::{ Arc, Mutex, MutexGuard };
+use std::time::Duration;
+use super::super::frame::CANFrame;
+use super::frame_socket::CanFrameSocket;
+use super::mock::{ read_command, MockTelemetry, READ_INTERVAL };
+use crate::can_extentions::{
+    ack_nack::AckNack,
+    can_command::CanCommand,
+    can_ids
+};
+use crate::pod_states::PodState;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::super::frame::ShouldRetry;
+    use crate::can_extentions::can_frame::FrameHandler;
+    use crate::can_extentions::can_socket::{ RelayCanSocket, RoboteqCanSocket };
+
+    #[test]
+    fn boards_answer_state_changes_as_scripted() {
+        let pod = SimulatedPod::new();
+        pod.respond(Board::MotorController, PodState::Armed, StateChangeResponse::Ignore);
+        pod.respond(Board::Pressure, PodState::Armed, StateChangeResponse::Nack);
+        pod.set_telemetry(vec![CanCommand::PressureHigh(250.0)]);
+
+        pod.send_pod_state(&PodState::Armed).unwrap();
+        let commands: Vec<CanCommand> = (0..3).map(|_| pod.read_frame().unwrap().get_command().unwrap()).collect();
+        assert_eq!(commands, vec![
+            CanCommand::BmsStateChange(AckNack::Ack),
+            CanCommand::PressureStateChange(AckNack::Nack),
+            CanCommand::PressureHigh(250.0)
+        ]);
+        assert_eq!(pod.board_state(Board::Bms), PodState::Armed);
+        assert_eq!(pod.board_state(Board::MotorController), PodState::LowVoltage);
+        assert_eq!(pod.commanded_state(), Some(PodState::Armed));
+
+        assert_eq!(pod.throttle(1, 2), None);
+        pod.set_motor_throttle(1, 2, 30).unwrap();
+        assert_eq!(pod.throttle(1, 2), Some(30));
+
+        pod.set_telemetry(Vec::new());
+        assert!(pod.read_frame().should_retry()); // A silent bus
+    }
+}
+
+/**
+ * The boards which acknowledge a change of pod state
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Board {
+    Bms,
+    MotorController,
+    Pressure
+}
+
+const BOARDS: [Board; 3] = [Board::Bms, Board::MotorController, Board::Pressure];
+
+impl Board {
+    fn state_change(&self, ack_nack: AckNack) -> CanCommand {
+        match self {
+            Board::Bms => CanCommand::BmsStateChange(ack_nack),
+            Board::MotorController => CanCommand::MotorControllerStateChange(ack_nack),
+            Board::Pressure => CanCommand::PressureStateChange(ack_nack)
+        }
+    }
+}
+
+/**
+ * How a board answers being commanded into a state it is not in
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StateChangeResponse {
+    Ack, // The board moves into the state
+    Nack, // The board refuses, and stays in its state
+    Ignore // Nothing is sent, as if the board had dropped off the bus
+}
+
+#[derive(Clone, Default)]
+pub struct SimulatedPod {
+    pod: Arc<Mutex<Pod>>
+}
+
+#[derive(Default)]
+struct Pod {
+    board_states: HashMap<Board, PodState>, // State each board has acked. Boards power on in LowVoltage
+    responses: HashMap<(Board, PodState), StateChangeResponse>, // Anything not scripted is acked
+    telemetry: Telemetry, // Read whenever nothing else is waiting
+    pending: VecDeque<CanCommand>, // Answers and sent commands waiting to be read, ahead of telemetry
+    written: Vec<CANFrame> // Every frame the relay wrote, oldest first
+}
+
+enum Telemetry {
+    Mock(MockTelemetry), // The same plausible telemetry as MockCanSocket
+    Scripted(Vec<CanCommand>, usize) // Read in a cycle from the index. Empty leaves the bus silent
+}
+
+impl Default for Telemetry {
+    fn default() -> Telemetry {
+        Telemetry::Mock(MockTelemetry::new())
+    }
+}
+
+impl SimulatedPod {
+    /**
+     * @brief new
+     * Every board acks every state, and the telemetry is the mock's, so every device the relay watches is heard from
+     */
+    pub fn new() -> SimulatedPod {
+        SimulatedPod::default()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Pod> {
+        self.pod.lock().expect("The simulated pod is only locked for a read, write or script")
+    }
+
+    /**
+     * @brief respond
+     * Script how board answers being commanded into state
+     */
+    pub fn respond(&self, board: Board, state: PodState, response: StateChangeResponse) {
+        self.lock().responses.insert((board, state), response);
+    }
+
+    /**
+     * @brief set_telemetry
+     * Replace the mock's telemetry with a cycle of commands. An empty cycle leaves the bus silent, so reads would block
+     */
+    pub fn set_telemetry(&self, telemetry: Vec<CanCommand>) {
+        self.lock().telemetry = Telemetry::Scripted(telemetry, 0);
+    }
+
+    /**
+     * @brief send
+     * Put command on the bus once, ahead of the telemetry. For fault reports and the like
+     */
+    pub fn send(&self, command: CanCommand) {
+        self.lock().pending.push_back(command);
+    }
+
+    /**
+     * @brief board_state
+     * The state board last acked
+     */
+    pub fn board_state(&self, board: Board) -> PodState {
+        self.lock().board_states.get(&board).copied().unwrap_or(PodState::LowVoltage)
+    }
+
+    /**
+     * @brief commanded_state
+     * The pod state the relay last wrote, if it has written one
+     */
+    pub fn commanded_state(&self) -> Option<PodState> {
+        self.lock().written.iter().rev()
+            .find(|frame| frame.id() == can_ids::POD_STATE && !frame.data().is_empty())
+            .map(|frame| PodState::from(frame.data()[0]))
+    }
+
+    /**
+     * @brief throttle
+     * The throttle the relay last commanded the roboteq at node to drive channel at, if it has
+     */
+    pub fn throttle(&self, node: u32, channel: u8) -> Option<u32> {
+        self.lock().written.iter().rev()
+            .filter(|frame| frame.id() == can_ids::ROBOTEQ_REQUEST_BASE + node)
+            .map(|frame| frame.data())
+            .find(|data| data.len() == 8 && data[..4] == [0x20, 0x00, 0x20, channel])
+            .map(|data| u32::from_le_bytes([data[4], data[5], data[6], data[7]]))
+    }
+
+    /**
+     * @brief written_frames
+     * A copy of every frame the relay has written, oldest first
+     */
+    pub fn written_frames(&self) -> Vec<CANFrame> {
+        self.lock().written.clone()
+    }
+}
+
+impl Pod {
+    /**
+     * Each board which is not already in state answers as it has been scripted to
+     */
+    fn command_state(&mut self, state: PodState) {
+        for board in BOARDS.iter() {
+            if self.board_states.get(board).copied().unwrap_or(PodState::LowVoltage) == state {
+                continue;
+            }
+            match self.responses.get(&(*board, state)).copied().unwrap_or(StateChangeResponse::Ack) {
+                StateChangeResponse::Ack => {
+                    self.board_states.insert(*board, state);
+                    self.pending.push_back(board.state_change(AckNack::Ack));
+                },
+                StateChangeResponse::Nack => self.pending.push_back(board.state_change(AckNack::Nack)),
+                StateChangeResponse::Ignore => {}
+            }
+        }
+    }
+
+    fn next_command(&mut self) -> Option<CanCommand> {
+        if let Some(command) = self.pending.pop_front() {
+            return Some(command);
+        }
+        match &mut self.telemetry {
+            Telemetry::Mock(telemetry) => Some(telemetry.next_command()),
+            Telemetry::Scripted(telemetry, next) => {
+                let command = telemetry.get(*next).cloned()?;
+                *next = (*next + 1) % telemetry.len();
+                Some(command)
+            }
+        }
+    }
+}
+
+impl CanFrameSocket for SimulatedPod {
+    fn read_frame(&self) -> std::io::Result<CANFrame> {
+        std::thread::sleep(READ_INTERVAL);
+        let command = self.lock().next_command();
+        read_command(command)
+    }
+
+    fn write_frame(&self, frame: &CANFrame) -> std::io::Result<()> {
+        let mut pod = self.lock();
+        pod.written.push(*frame);
+        if frame.id() == can_ids::POD_STATE && !frame.data().is_empty() {
+            pod.command_state(PodState::from(frame.data()[0]));
+        }
+        Ok(())
+    }
+
+    fn write_frame_insist(&self, frame: &CANFrame) -> std::io::Result<()> {
+        self.write_frame(frame)
+    }
+
+    fn set_read_timeout(&self, _duration: Duration) -> std::io::Result<()> {
+        Ok(())
+    }
+}
@@ -1,22 +1,72 @@
 use std::io;
+use super::frame::ConstructionError;
 
 #[derive(Debug)]
 pub enum CanError {
+    #[cfg(unix)]
     FailedToOpenSocket(socketcan::CANSocketOpenError),
-    MessageError(socketcan::ConstructionError),
+    MessageError(ConstructionError),
     UnableToSetNonBlocking(io::Error),
+    UnableToSetTimeout(io::Error),
+    #[cfg(all(unix, feature = "canfd"))]
+    UnableToEnableFdFrames(io::Error), // The interface or its driver does not support CAN-FD
     ReadError(io::Error),
     WriteError(io::Error),
+    UnencodableCommand, // The command is only ever received by the relay and has no outgoing frame
 }
 
+#[cfg(unix)]
 impl From<socketcan::CANSocketOpenError> for CanError {
     fn from(error: socketcan::CANSocketOpenError) -> CanError {
         CanError::FailedToOpenSocket(error)
     }
 }
 
-impl From<socketcan::ConstructionError> for CanError {
-    fn from(error: socketcan::ConstructionError) -> CanError {
+impl From<ConstructionError> for CanError {
+    fn from(error: ConstructionError) -> CanError {
         CanError::MessageError(error)
     }
 }
+
+impl std::fmt::Display for CanError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            #[cfg(unix)]
+            CanError::FailedToOpenSocket(error) => write!(f, "failed to open can socket: {}", error),
+            CanError::MessageError(error) => write!(f, "unable to build can frame: {}", error),
+            CanError::UnableToSetNonBlocking(error) => write!(f, "unable to set can socket non blocking: {}", error),
+            CanError::UnableToSetTimeout(error) => write!(f, "unable to set can socket read timeout: {}", error),
+            #[cfg(all(unix, feature = "canfd"))]
+            CanError::UnableToEnableFdFrames(error) => write!(f, "unable to enable CAN-FD frames on the can socket: {}", error),
+            CanError::ReadError(error) => write!(f, "can read error: {}", error),
+            CanError::WriteError(error) => write!(f, "can write error: {}", error),
+            CanError::UnencodableCommand => write!(f, "command has no outgoing can frame"),
+        }
+    }
+}
+
+impl std::error::Error for CanError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            #[cfg(unix)]
+            CanError::FailedToOpenSocket(error) => Some(error),
+            CanError::MessageError(error) => Some(error),
+            CanError::UnableToSetNonBlocking(error)
+            | CanError::UnableToSetTimeout(error)
+            | CanError::ReadError(error)
+            | CanError::WriteError(error) => Some(error),
+            #[cfg(all(unix, feature = "canfd"))]
+            CanError::UnableToEnableFdFrames(error) => Some(error),
+            CanError::UnencodableCommand => None
+        }
+    }
+}
+
+/**
+ * Reasons a received frame could not be decoded into a CanCommand
+ */
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum FrameDecodeError {
+    UnknownId(u32),
+    TruncatedPayload { id: u32, expected: usize, got: usize },
+}
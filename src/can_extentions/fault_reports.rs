@@ -1,3 +1,64 @@
+use serde::{ Serialize, Deserialize };
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn bms_fault_payloads_decode() {
+        let report = BmsFaultReport::from(&[0x0, 0x2][..]);
+        assert_eq!(report, BmsFaultReport::new(SeverityCode::SEVERE, BmsErrorCode::BATTERY_OVERCURRENT));
+        assert_eq!(report.active_faults(), vec![BmsErrorCode::BATTERY_OVERCURRENT]);
+
+        let report = BmsFaultReport::from(&[0x2, 0x8, 0xAA][..]);
+        assert_eq!(report, BmsFaultReport::new(SeverityCode::WARNING, BmsErrorCode::LOW_LAYER_EXCEPTION));
+
+        // Codes the relay doesn't know about are still faults
+        let report = BmsFaultReport::from(&[0x7, 0x20][..]);
+        assert_eq!(report, BmsFaultReport::new(SeverityCode::UNKNOWN, BmsErrorCode::UNKNOWN));
+        assert_eq!(report.active_faults(), vec![BmsErrorCode::UNKNOWN]);
+    }
+
+    #[test]
+    fn active_faults_across_reports_are_in_code_order() {
+        let reports = [
+            FaultReport::Bms(BmsFaultReport::new(SeverityCode::WARNING, BmsErrorCode::CELL_TEMPERATURE)),
+            FaultReport::MotorController(MotorControllerFaultReport::new(SeverityCode::SEVERE)),
+            FaultReport::Bms(BmsFaultReport::new(SeverityCode::SEVERE, BmsErrorCode::BATTERY_OVERCURRENT)),
+            FaultReport::Bms(BmsFaultReport::new(SeverityCode::DANGER, BmsErrorCode::CELL_TEMPERATURE)),
+        ];
+        let faults: Vec<BmsErrorCode> = active_bms_faults(&reports).collect();
+        assert_eq!(faults, vec![BmsErrorCode::BATTERY_OVERCURRENT, BmsErrorCode::CELL_TEMPERATURE]);
+
+        let names: Vec<String> = faults.iter().map(|fault| fault.to_string()).collect();
+        assert_eq!(names.join(", "), "Battery overcurrent, Cell temperature");
+
+        let report = BmsFaultReport::new(SeverityCode::SEVERE, BmsErrorCode::BUCK_TEMPERATURE);
+        assert_eq!(report.into_iter().collect::<Vec<_>>(), vec![BmsErrorCode::BUCK_TEMPERATURE]);
+    }
+
+    #[test]
+    fn short_fault_payloads_do_not_panic() {
+        assert_eq!(BmsFaultReport::from(&[0x1][..]), BmsFaultReport::new(SeverityCode::DANGER, BmsErrorCode::UNKNOWN));
+        assert_eq!(BmsFaultReport::from(&[][..]), BmsFaultReport::new(SeverityCode::UNKNOWN, BmsErrorCode::UNKNOWN));
+        assert_eq!(MotorControllerFaultReport::from(&[0x1][..]), MotorControllerFaultReport::new(SeverityCode::DANGER));
+        assert_eq!(MotorControllerFaultReport::from(&[][..]), MotorControllerFaultReport::new(SeverityCode::UNKNOWN));
+    }
+
+    #[test]
+    fn fault_reports_serde_round_trip() {
+        let faults = [
+            FaultReport::Bms(BmsFaultReport::new(SeverityCode::DANGER, BmsErrorCode::LOW_LAYER_EXCEPTION)),
+            FaultReport::MotorController(MotorControllerFaultReport::new(SeverityCode::UNKNOWN)),
+        ];
+        for fault in &faults {
+            let json = serde_json::to_string(fault).unwrap();
+            assert_eq!(serde_json::from_str::<FaultReport>(&json).unwrap(), *fault);
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub enum SeverityCode {
     SEVERE,
     DANGER,
@@ -14,8 +75,19 @@ impl From<u8> for SeverityCode {
         }
     }  
 }
+impl SeverityCode {
+    pub fn to_byte(&self) -> u8 {
+        match self {
+            SeverityCode::SEVERE  => 0x0,
+            SeverityCode::DANGER  => 0x1,
+            SeverityCode::WARNING => 0x2,
+            SeverityCode::UNKNOWN => 0xF,
+        }
+    }
+}
 
 #[allow(non_camel_case_types)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub enum BmsErrorCode {
     BATTERY_OVERVOLTAGE,
     BATTERY_UNDERVOLTAGE,
@@ -44,28 +116,139 @@ impl From<u8> for BmsErrorCode {
         }
     }
 }
+impl std::fmt::Display for BmsErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            BmsErrorCode::BATTERY_OVERVOLTAGE  => "Battery overvoltage",
+            BmsErrorCode::BATTERY_UNDERVOLTAGE => "Battery undervoltage",
+            BmsErrorCode::BATTERY_OVERCURRENT  => "Battery overcurrent",
+            BmsErrorCode::BATTERY_SOC          => "Battery state of charge",
+            BmsErrorCode::CELL_UNDERVOLTAGE    => "Cell undervoltage",
+            BmsErrorCode::CELL_OVERVOLTAGE     => "Cell overvoltage",
+            BmsErrorCode::CELL_TEMPERATURE     => "Cell temperature",
+            BmsErrorCode::BUCK_TEMPERATURE     => "Buck temperature",
+            BmsErrorCode::LOW_LAYER_EXCEPTION  => "Low layer exception",
+            BmsErrorCode::UNKNOWN              => "Unknown fault",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl BmsErrorCode {
+    pub fn to_byte(&self) -> u8 {
+        match self {
+            BmsErrorCode::BATTERY_OVERVOLTAGE  => 0x0,
+            BmsErrorCode::BATTERY_UNDERVOLTAGE => 0x1,
+            BmsErrorCode::BATTERY_OVERCURRENT  => 0x2,
+            BmsErrorCode::BATTERY_SOC          => 0x3,
+            BmsErrorCode::CELL_UNDERVOLTAGE    => 0x4,
+            BmsErrorCode::CELL_OVERVOLTAGE     => 0x5,
+            BmsErrorCode::CELL_TEMPERATURE     => 0x6,
+            BmsErrorCode::BUCK_TEMPERATURE     => 0x7,
+            BmsErrorCode::LOW_LAYER_EXCEPTION  => 0x8,
+            BmsErrorCode::UNKNOWN              => 0xFF,
+        }
+    }
+}
 
+/**
+ * Payload layout of a BMS fault report frame:
+ *   byte 0: SeverityCode
+ *   byte 1: BmsErrorCode
+ * The codes are whole bytes, not bit flags, so a frame carries exactly one fault.
+ * Missing bytes decode as UNKNOWN. The frame decoder rejects short payloads before they get here
+ */
 impl From<&[u8]> for BmsFaultReport {
     fn from(other: &[u8]) -> BmsFaultReport {
         BmsFaultReport {
-            severity_code: SeverityCode::from(other[0]),
-            error_code: BmsErrorCode::from(other[1])
+            severity_code: other.get(0).map_or(SeverityCode::UNKNOWN, |&byte| SeverityCode::from(byte)),
+            error_code: other.get(1).map_or(BmsErrorCode::UNKNOWN, |&byte| BmsErrorCode::from(byte))
         }
     }
 }
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub struct BmsFaultReport {
     pub severity_code: SeverityCode,
     pub error_code: BmsErrorCode,
 }
 
+impl BmsFaultReport {
+    pub fn new(severity_code: SeverityCode, error_code: BmsErrorCode) -> BmsFaultReport {
+        BmsFaultReport {
+            severity_code,
+            error_code
+        }
+    }
+
+    /**
+     * @brief active_faults
+     * The faults raised by this report. The BMS reports one fault per frame, including codes the relay does not recognize
+     */
+    pub fn active_faults(&self) -> Vec<BmsErrorCode> {
+        vec![self.error_code]
+    }
+}
+
+impl IntoIterator for BmsFaultReport {
+    type Item = BmsErrorCode;
+    type IntoIter = std::vec::IntoIter<BmsErrorCode>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.active_faults().into_iter()
+    }
+}
+
+/**
+ * Payload layout of a motor controller fault report frame:
+ *   byte 0: SeverityCode
+ * A missing byte decodes as UNKNOWN
+ */
 impl From<&[u8]> for MotorControllerFaultReport {
     fn from(other: &[u8]) -> MotorControllerFaultReport {
         MotorControllerFaultReport {
-            severity_code: SeverityCode::from(other[0]),
+            severity_code: other.get(0).map_or(SeverityCode::UNKNOWN, |&byte| SeverityCode::from(byte)),
         }
     }
 }
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub struct MotorControllerFaultReport {
     pub severity_code: SeverityCode
     // TODO Get the Error code values when they're available
-}
\ No newline at end of file
+}
+
+impl MotorControllerFaultReport {
+    pub fn new(severity_code: SeverityCode) -> MotorControllerFaultReport {
+        MotorControllerFaultReport {
+            severity_code
+        }
+    }
+}
+
+/**
+ * A fault reported by one of the boards. Forwarded to the controller on its own, separate from telemetry
+ */
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(tag = "board", content = "report")]
+pub enum FaultReport {
+    Bms(BmsFaultReport),
+    MotorController(MotorControllerFaultReport),
+}
+
+/**
+ * @func active_bms_faults
+ * Every distinct BMS fault raised across a set of reports, such as the faults queued for the controller.
+ * Each frame only carries one fault, so this is the set a bitfield would have held. Faults are given
+ * in the order of their codes, with unknown codes last
+ */
+pub fn active_bms_faults(reports: &[FaultReport]) -> impl Iterator<Item = BmsErrorCode> {
+    let mut faults: Vec<BmsErrorCode> = reports.iter()
+        .filter_map(|report| match report {
+            FaultReport::Bms(report) => Some(*report),
+            FaultReport::MotorController(_) => None
+        })
+        .flatten()
+        .collect();
+    faults.sort_by_key(|fault| fault.to_byte());
+    faults.dedup();
+    faults.into_iter()
+}
@@ -0,0 +1,142 @@
+/*!
+ * @brief The classic CAN frame and the socketcan types which come with it. On unix these are socketcan's own.
+ * socketcan only builds on unix, so elsewhere they are stood in for by types with the same interface.
+ * That lets the can and worker threads build on windows, where they run on the simulated bus of the mock-can feature
+ */
+
+#[cfg(unix)]
+pub use socketcan::{ CANFrame, ConstructionError, ShouldRetry, EFF_FLAG, EFF_MASK, SFF_MASK };
+
+#[cfg(not(unix))]
+pub use stand_in::{ CANFrame, ConstructionError, ShouldRetry, EFF_FLAG, EFF_MASK, SFF_MASK };
+
+#[cfg(not(unix))]
+mod stand_in {
+    use std::io;
+
+    #[cfg(test)]
+    mod test {
+        use super::*;
+
+        #[test]
+        fn frames_are_built_as_socketcan_builds_them() {
+            let frame = CANFrame::new(0x020, &[1, 2, 3], false, false).unwrap();
+            assert_eq!((frame.id(), frame.is_extended(), frame.data()), (0x020, false, &[1, 2, 3][..]));
+            let frame = CANFrame::new(0x18FF_0001, &[], true, false).unwrap();
+            assert_eq!((frame.id(), frame.is_extended(), frame.is_rtr()), (0x18FF_0001, true, true));
+            assert!(matches!(CANFrame::new(0x020, &[0u8; 9], false, false), Err(ConstructionError::TooMuchData)));
+            assert!(matches!(CANFrame::new(EFF_MASK + 1, &[], false, false), Err(ConstructionError::IDTooLarge)));
+            assert!(io::Error::from(io::ErrorKind::WouldBlock).should_retry());
+            assert!(!io::Error::from(io::ErrorKind::TimedOut).should_retry());
+        }
+    }
+
+    pub const EFF_FLAG: u32 = 0x8000_0000; // The frame is extended
+    pub const RTR_FLAG: u32 = 0x4000_0000; // The frame is a remote transmission request
+    pub const ERR_FLAG: u32 = 0x2000_0000; // The frame is an error frame
+    pub const SFF_MASK: u32 = 0x0000_07FF;
+    pub const EFF_MASK: u32 = 0x1FFF_FFFF;
+    pub const ERR_MASK: u32 = 0x1FFF_FFFF;
+
+    #[derive(Debug, Copy, Clone)]
+    pub enum ConstructionError {
+        IDTooLarge,
+        TooMuchData
+    }
+
+    impl std::fmt::Display for ConstructionError {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            match self {
+                ConstructionError::IDTooLarge => write!(f, "CAN ID too large"),
+                ConstructionError::TooMuchData => write!(f, "Payload is larger than CAN maximum of 8 bytes")
+            }
+        }
+    }
+
+    impl std::error::Error for ConstructionError {}
+
+    /**
+     * @Struct CANFrame
+     * @brief A classic frame of up to 8 bytes, laid out like socketcan's
+     */
+    #[derive(Debug, Copy, Clone)]
+    pub struct CANFrame {
+        id: u32, // The id, with the extended, remote transmission request and error flags
+        data_len: u8,
+        data: [u8; 8]
+    }
+
+    impl CANFrame {
+        pub fn new(id: u32, data: &[u8], rtr: bool, err: bool) -> Result<CANFrame, ConstructionError> {
+            if data.len() > 8 {
+                return Err(ConstructionError::TooMuchData);
+            }
+            if id > EFF_MASK {
+                return Err(ConstructionError::IDTooLarge);
+            }
+            let mut flagged_id = id;
+            if id > SFF_MASK {
+                flagged_id |= EFF_FLAG;
+            }
+            if rtr {
+                flagged_id |= RTR_FLAG;
+            }
+            if err {
+                flagged_id |= ERR_FLAG;
+            }
+            let mut frame_data = [0u8; 8];
+            frame_data[..data.len()].copy_from_slice(data);
+            Ok(CANFrame { id: flagged_id, data_len: data.len() as u8, data: frame_data })
+        }
+
+        pub fn id(&self) -> u32 {
+            if self.is_extended() {
+                self.id & EFF_MASK
+            } else {
+                self.id & SFF_MASK
+            }
+        }
+
+        pub fn err(&self) -> u32 {
+            self.id & ERR_MASK
+        }
+
+        pub fn is_extended(&self) -> bool {
+            self.id & EFF_FLAG != 0
+        }
+
+        pub fn is_error(&self) -> bool {
+            self.id & ERR_FLAG != 0
+        }
+
+        pub fn is_rtr(&self) -> bool {
+            self.id & RTR_FLAG != 0
+        }
+
+        pub fn data(&self) -> &[u8] {
+            &self.data[..self.data_len as usize]
+        }
+    }
+
+    /**
+     * Whether a failed read or write is only a timeout, and worth trying again
+     */
+    pub trait ShouldRetry {
+        fn should_retry(&self) -> bool;
+    }
+
+    impl ShouldRetry for io::Error {
+        fn should_retry(&self) -> bool {
+            self.kind() == io::ErrorKind::WouldBlock
+        }
+    }
+
+    impl<E: std::fmt::Debug> ShouldRetry for io::Result<E> {
+        fn should_retry(&self) -> bool {
+            match self {
+                Err(err) => err.should_retry(),
+                Ok(_) => false
+            }
+        }
+    }
+}
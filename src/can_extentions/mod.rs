@@ -1,24 +1,48 @@
 mod can_frame;
 mod can_socket;
 mod can_command;
+pub mod frame;
 mod error;
 pub mod fault_reports;
 pub mod ack_nack;
+pub mod can_ids;
+#[cfg(unix)]
 use error::CanError as Error;
 
 //* Helper function for opening a can socket
+#[cfg(unix)]
 pub fn open_socket<'a, S>(interface: S) 
 -> Result<socketcan::CANSocket, Error>
 where S: Into<&'a str> {
     Ok(socketcan::CANSocket::open(interface.into())?)
 }
 
+//* Helper function for opening a can socket which also reads and writes CAN-FD frames
+#[cfg(all(unix, feature = "canfd"))]
+pub fn open_fd_socket<'a, S>(interface: S)
+-> Result<socketcan::CANSocket, Error>
+where S: Into<&'a str> {
+    use can_socket::CanFdSocket;
+    let socket = open_socket(interface)?;
+    socket.enable_fd_frames().map_err(Error::UnableToEnableFdFrames)?;
+    Ok(socket)
+}
+
 /**
  * Traits and Error Types defined by can_extentions
  */
 pub mod prelude {
     pub use super::can_frame::FrameHandler;
-    pub use super::can_socket::{ RoboteqCanSocket, RelayCanSocket };
-    pub use super::error::CanError;
+    #[cfg(feature = "canfd")]
+    pub use super::can_frame::CanFdFrame;
+    #[cfg(all(unix, feature = "canfd"))]
+    pub use super::can_socket::CanFdSocket;
+    pub use super::can_socket::{ CanFrameSocket, RoboteqCanSocket, RelayCanSocket, FileReplaySource, DryRunSocket, pod_state_frame };
+    pub use super::error::{ CanError, FrameDecodeError };
     pub use super::can_command::CanCommand;
+    pub use super::frame::{ CANFrame, ShouldRetry };
+    #[cfg(feature = "mock-can")]
+    pub use super::can_socket::MockCanSocket;
+    #[cfg(feature = "mock-can")]
+    pub use super::can_socket::{ SimulatedPod, Board, StateChangeResponse };
 }
\ No newline at end of file
@@ -1,8 +1,14 @@
+use std::time::Duration;
+use std::path::PathBuf;
 use std::net::{
     IpAddr,
     Ipv4Addr,
+    Ipv6Addr,
     SocketAddr,
+    SocketAddrV6,
 };
+use crate::utils::sanity_ranges::{ self, OutOfRangeAction, SanityRanges };
+use crate::utils::bounded_channel::FullPolicy;
 
 #[cfg(test)]
 mod test {
@@ -12,18 +18,47 @@ mod test {
     fn config_from_args_address() {
         let args = vec!["test program", "-ta", "100.20.20.10:9090"];
         let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
-        let config_dut = Config::from_args(&args);
+        let config_dut = Config::from_args(&args).unwrap();
         let expected_address = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(100, 20, 20, 10)), 9090);
         assert_eq!(config_dut.tcp_address.ip(), expected_address.ip());
         assert_eq!(config_dut.tcp_address.port(), expected_address.port());
     }
 
+    #[test]
+    fn config_builder_overrides_defaults() {
+        let address = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 9090);
+        let config_dut = Config::builder()
+            .tcp_address(address)
+            .buffer_size(512)
+            .can_interface(String::from("vcan0"))
+            .build()
+            .unwrap();
+        assert_eq!(config_dut.tcp_address, address);
+        assert_eq!(config_dut.buffer_size, 512);
+        assert_eq!(config_dut.can_interface, "vcan0");
+        assert_eq!(config_dut.udp_port, Config::default().udp_port);
+    }
+
+    #[test]
+    fn config_builder_rejects_what_from_args_would() {
+        assert!(matches!(Config::builder().buffer_size(0).build(), Err(ConfigError::InvalidBufferSize(_))));
+        assert!(matches!(Config::builder().buffer_size(MAX_BUFFER_SIZE + 1).build(), Err(ConfigError::InvalidBufferSize(_))));
+        assert!(matches!(Config::builder().tcp_read_timeout(Duration::from_millis(0)).build(), Err(ConfigError::InvalidTimeout(_))));
+        assert!(matches!(Config::builder().disconnect_wait(DisconnectWait::Block(Duration::from_millis(0))).build(), Err(ConfigError::InvalidDisconnectWait(_))));
+        assert!(matches!(Config::builder().udp_max_number_timeouts(0).build(), Err(ConfigError::InvalidTimeoutCount(_))));
+        assert!(matches!(Config::builder().roboteq(RoboteqConfig { throttle_percent: 101, ..RoboteqConfig::default() }).build(), Err(ConfigError::ThrottleOutOfRange(101))));
+        assert!(matches!(Config::builder().telemetry_rate(0).build(), Err(ConfigError::InvalidTelemetryRate(_))));
+        assert!(matches!(Config::builder().can_replay(String::from("run.log"), f64::NAN).build(), Err(ConfigError::InvalidReplaySpeed(_))));
+        assert!(matches!(Config::builder().command_history_length(MAX_COMMAND_HISTORY_LENGTH + 1).build(), Err(ConfigError::InvalidHistoryLength(_))));
+        assert!(matches!(Config::builder().worker_channel_bound(0).build(), Err(ConfigError::InvalidChannelBound(_))));
+    }
+
     #[test]
     fn config_from_args_buffer_size() {
         let args = vec!["test program", "-b", "512"];
         let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
 
-        let config_dut = Config::from_args(&args);
+        let config_dut = Config::from_args(&args).unwrap();
         let expected_size: usize = 512;
 
         assert_eq!(config_dut.buffer_size, expected_size);
@@ -34,7 +69,7 @@ mod test {
         let args = vec!["test program", "-b", "1024", "-ta", "250.230.210.120:1000"];
         let args: Vec<String> = args.iter().map(|arg| String::from(*arg)).collect();
 
-        let config_dut = Config::from_args(&args);
+        let config_dut = Config::from_args(&args).unwrap();
 
         let expected_address = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(250, 230, 210, 120)), 1000);
         let expected_size: usize = 1024;
@@ -43,111 +78,1156 @@ mod test {
         assert_eq!(config_dut.tcp_address.port(), expected_address.port());
         assert_eq!(config_dut.buffer_size, expected_size);
     }
+
+    #[test]
+    fn config_from_args_long_address() {
+        let args = vec!["test program", "--address", "100.20.20.10:9090"];
+        let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+        let config_dut = Config::from_args(&args).unwrap();
+        let expected_address = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(100, 20, 20, 10)), 9090);
+        assert_eq!(config_dut.tcp_address, expected_address);
+
+        let args = vec!["test program", "--tcp-address", "100.20.20.10:9090"];
+        let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+        assert_eq!(Config::from_args(&args).unwrap().tcp_address, expected_address);
+    }
+
+    #[test]
+    fn config_from_args_long_buffer_size_and_address() {
+        let args = vec!["test program", "--buffer-size", "1024", "--address", "250.230.210.120:1000"];
+        let args: Vec<String> = args.iter().map(|arg| String::from(*arg)).collect();
+
+        let config_dut = Config::from_args(&args).unwrap();
+
+        let expected_address = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(250, 230, 210, 120)), 1000);
+        assert_eq!(config_dut.tcp_address, expected_address);
+        assert_eq!(config_dut.buffer_size, 1024);
+    }
+
+    #[test]
+    fn config_from_args_long_forms() {
+        let args = vec!["test program", "--udp-port", "9100", "--tcp-read-timeout", "250", "--can-max-timeouts", "5", "--throttle-percent", "40"];
+        let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+        let config_dut = Config::from_args(&args).unwrap();
+        assert_eq!(config_dut.udp_port, 9100);
+        assert_eq!(config_dut.tcp_read_timeout, Duration::from_millis(250));
+        assert_eq!(config_dut.can_max_number_timeouts, 5);
+        assert_eq!(config_dut.roboteq.throttle_percent, 40);
+    }
+
+    #[test]
+    fn config_from_args_base_port() {
+        let args = vec!["test program", "--base-port", "9000", "-ua", "10.0.0.2:8080"];
+        let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+        let config_dut = Config::from_args(&args).unwrap();
+        assert_eq!(config_dut.tcp_address, "0.0.0.0:9000".parse::<SocketAddr>().unwrap());
+        assert_eq!(config_dut.udp_address, "10.0.0.2:9001".parse::<SocketAddr>().unwrap());
+        assert_eq!(config_dut.udp_port, 9001);
+
+        let args = vec!["test program", "-upo", "10", "-bp", "9000"];
+        let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+        assert_eq!(Config::from_args(&args).unwrap().udp_address.port(), 9010);
+
+        let args = vec!["test program", "-bp", "65535"];
+        let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+        assert!(matches!(Config::from_args(&args), Err(ConfigError::PortOutOfRange(65536))));
+
+        let args = vec!["test program", "-bp", "65536"];
+        let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+        assert!(matches!(Config::from_args(&args), Err(ConfigError::InvalidPort(_))));
+    }
+
+    #[cfg(feature = "websocket")]
+    #[test]
+    fn config_from_args_websocket_address() {
+        let args = vec!["test program", "--websocket-address", "127.0.0.1:9002"];
+        let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+        assert_eq!(Config::from_args(&args).unwrap().websocket_address, "127.0.0.1:9002".parse::<SocketAddr>().unwrap());
+    }
+
+    #[cfg(feature = "health")]
+    #[test]
+    fn config_from_args_health_address() {
+        let args = vec!["test program", "-ha", "127.0.0.1:9003"];
+        let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+        assert_eq!(Config::from_args(&args).unwrap().health_address, "127.0.0.1:9003".parse::<SocketAddr>().unwrap());
+    }
+
+    #[test]
+    fn config_from_args_can_interface() {
+        let args = vec!["test program", "--can-interface", "vcan0"];
+        let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+        assert_eq!(Config::from_args(&args).unwrap().can_interface, "vcan0");
+
+        let args = vec!["test program", "-i", "can1", "-b", "512"];
+        let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+        assert_eq!(Config::from_args(&args).unwrap().can_interface, "can1");
+    }
+
+    #[test]
+    fn config_from_args_udp_port() {
+        let args = vec!["test program", "-up", "9100"];
+        let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+        assert_eq!(Config::from_args(&args).unwrap().udp_port, 9100);
+
+        let args = vec!["test program", "-up", "70000"];
+        let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+        assert!(matches!(Config::from_args(&args), Err(ConfigError::InvalidPort(_))));
+    }
+
+    #[test]
+    fn config_from_args_tcp_read_timeout() {
+        let args = vec!["test program", "-rt", "250"];
+        let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+        assert_eq!(Config::from_args(&args).unwrap().tcp_read_timeout, Duration::from_millis(250));
+
+        let args = vec!["test program", "-rt", "0"];
+        let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+        assert!(matches!(Config::from_args(&args), Err(ConfigError::InvalidTimeout(_))));
+    }
+
+    #[test]
+    fn config_from_args_can_read_timeout() {
+        let args = vec!["test program"];
+        let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+        assert_eq!(Config::from_args(&args).unwrap().can_read_timeout, Duration::from_secs(10));
+
+        let args = vec!["test program", "-crt", "100"];
+        let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+        assert_eq!(Config::from_args(&args).unwrap().can_read_timeout, Duration::from_millis(100));
+    }
+
+    #[test]
+    fn config_from_args_can_max_number_timeouts() {
+        let args = vec!["test program", "-cmt", "5"];
+        let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+        assert_eq!(Config::from_args(&args).unwrap().can_max_number_timeouts, 5);
+
+        let args = vec!["test program", "-cmt", "0"];
+        let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+        assert!(matches!(Config::from_args(&args), Err(ConfigError::InvalidTimeoutCount(_))));
+    }
+
+    #[test]
+    fn config_from_args_udp_backoff() {
+        let args = vec!["test program", "-ubb", "50", "-ubc", "2000"];
+        let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+        let config_dut = Config::from_args(&args).unwrap();
+        assert_eq!(config_dut.udp_backoff_base, Duration::from_millis(50));
+        assert_eq!(config_dut.udp_backoff_cap, Duration::from_millis(2000));
+
+        let args = vec!["test program", "-ubc", "0"];
+        let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+        assert!(matches!(Config::from_args(&args), Err(ConfigError::InvalidTimeout(_))));
+    }
+
+    #[test]
+    fn config_from_args_udp_idle_timeout() {
+        let args = vec!["test program", "--udp-read-timeout", "200", "-umt", "4"];
+        let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+        let config_dut = Config::from_args(&args).unwrap();
+        assert_eq!(config_dut.udp_read_timeout, Duration::from_millis(200));
+        assert_eq!(config_dut.udp_max_number_timeouts, 4);
+        assert_eq!(config_dut.udp_idle_timeout(), Duration::from_millis(800));
+
+        let args = vec!["test program", "--udp-max-timeouts", "0"];
+        let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+        assert!(matches!(Config::from_args(&args), Err(ConfigError::InvalidTimeoutCount(_))));
+    }
+
+    #[test]
+    fn config_from_args_can_replay() {
+        let args = vec!["test program", "--can-replay", "track_run.log", "-crs", "2.5"];
+        let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+        let config_dut = Config::from_args(&args).unwrap();
+        assert_eq!(config_dut.can_replay_file.as_deref(), Some("track_run.log"));
+        assert_eq!(config_dut.can_replay_speed, 2.5);
+
+        let args = vec!["test program", "--can-log", "/var/log/relay/can.log"];
+        let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+        assert_eq!(Config::from_args(&args).unwrap().can_log_path, Some(PathBuf::from("/var/log/relay/can.log")));
+
+        let args = vec!["test program", "-crs", "0"];
+        let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+        assert!(matches!(Config::from_args(&args), Err(ConfigError::InvalidReplaySpeed(_))));
+    }
+
+    #[test]
+    fn config_from_args_telemetry_rate() {
+        let args = vec!["test program", "--telemetry-rate", "5"];
+        let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+        assert_eq!(Config::from_args(&args).unwrap().telemetry_rate, 5);
+
+        let args = vec!["test program", "-tr", "0"];
+        let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+        assert!(matches!(Config::from_args(&args), Err(ConfigError::InvalidTelemetryRate(_))));
+    }
+
+    #[test]
+    fn config_from_args_telemetry_keepalive() {
+        let args: Vec<String> = vec![String::from("test program")];
+        assert_eq!(Config::from_args(&args).unwrap().telemetry_keepalive, None);
+
+        let args = vec!["test program", "--telemetry-keepalive", "2000"];
+        let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+        assert_eq!(Config::from_args(&args).unwrap().telemetry_keepalive, Some(Duration::from_millis(2000)));
+
+        let args = vec!["test program", "-tk", "0"];
+        let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+        assert_eq!(Config::from_args(&args).unwrap().telemetry_keepalive, None);
+        let args = vec!["test program", "-tk", "soon"];
+        let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+        assert!(matches!(Config::from_args(&args), Err(ConfigError::InvalidTimeout(_))));
+    }
+
+    #[test]
+    fn config_from_args_dry_run() {
+        assert!(!Config::default().dry_run);
+        // The flag takes no value, so it can go anywhere without breaking up the pairs around it
+        for args in &[vec!["test program", "--dry-run"], vec!["test program", "-up", "9100", "-dr", "-ci", "vcan0"], vec!["test program", "-ci", "vcan0", "--dry-run"]] {
+            let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+            assert!(Config::from_args(&args).unwrap().dry_run);
+        }
+        let args: Vec<String> = vec!["test program", "--no-dry-run", "-up", "9100", "--dry-run"].iter().map(|&arg| String::from(arg)).collect();
+        assert!(!Config::from_args(&args).unwrap().dry_run);
+        let args: Vec<String> = vec!["test program", "-up", "9100", "-dr", "-ci", "vcan0"].iter().map(|&arg| String::from(arg)).collect();
+        let config_dut = Config::from_args(&args).unwrap();
+        assert_eq!(config_dut.udp_port, 9100);
+        assert_eq!(config_dut.can_interface, "vcan0");
+        assert!(Config::builder().dry_run(true).build().unwrap().dry_run);
+    }
+
+    #[test]
+    fn config_from_args_disconnect_wait() {
+        assert_eq!(Config::default().disconnect_wait, DisconnectWait::Immediate);
+        for (param, expected) in &[("deferred", DisconnectWait::Deferred), ("immediate", DisconnectWait::Immediate), ("2000", DisconnectWait::Block(Duration::from_millis(2000)))] {
+            let args = vec!["test program", "--disconnect-wait", param];
+            let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+            assert_eq!(Config::from_args(&args).unwrap().disconnect_wait, *expected);
+        }
+
+        let args = vec!["test program", "-dw", "0"];
+        let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+        assert!(matches!(Config::from_args(&args), Err(ConfigError::InvalidDisconnectWait(_))));
+    }
+
+    #[test]
+    fn config_from_args_state_change_ack() {
+        let args = vec!["test program", "-sct", "250", "--state-change-retries", "0"];
+        let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+        let config_dut = Config::from_args(&args).unwrap();
+        assert_eq!(config_dut.state_change_timeout, Duration::from_millis(250));
+        assert_eq!(config_dut.state_change_retries, 0);
+
+        let args = vec!["test program", "-scr", "-1"];
+        let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+        assert!(matches!(Config::from_args(&args), Err(ConfigError::InvalidRetryCount(_))));
+    }
+
+    #[test]
+    fn config_from_args_roboteq() {
+        let args = vec!["test program", "-rn", "2", "-rc", "1", "-rp", "40"];
+        let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+        let roboteq = Config::from_args(&args).unwrap().roboteq;
+        assert_eq!(roboteq, RoboteqConfig::new(2, vec![1], 40).unwrap());
+
+        let args = vec!["test program", "-rp", "101"];
+        let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+        assert!(matches!(Config::from_args(&args), Err(ConfigError::ThrottleOutOfRange(101))));
+        assert!(matches!(RoboteqConfig::new(1, vec![1, 2], 150), Err(ConfigError::ThrottleOutOfRange(150))));
+    }
+
+    #[test]
+    fn config_from_args_allowlist() {
+        assert!(Config::default().connect_allowlist.is_empty());
+
+        let args = vec!["test program", "-al", "192.168.0.20,fe80::1"];
+        let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+        assert_eq!(Config::from_args(&args).unwrap().connect_allowlist, vec![
+            IpAddr::V4(Ipv4Addr::new(192, 168, 0, 20)),
+            "fe80::1".parse::<IpAddr>().unwrap()
+        ]);
+
+        let args = vec!["test program", "--allowlist", "192.168.0.20,controller"];
+        let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+        assert!(matches!(Config::from_args(&args), Err(ConfigError::InvalidAddress(_))));
+    }
+
+    #[test]
+    fn config_from_args_sanity_ranges() {
+        let args = vec!["test program", "-sr", "speed=0:44,igbt_temp=-20:120", "--out-of-range", "flag"];
+        let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+        let mut expected = SanityRanges::default();
+        expected.set_range("speed", 0.0, 44.0);
+        expected.set_range("igbt_temp", -20.0, 120.0);
+        expected.set_action(OutOfRangeAction::Flag);
+        assert_eq!(Config::from_args(&args).unwrap().sanity_ranges, expected);
+
+        for range in ["sped=0:44", "speed=44:0", "speed=0", "speed=0:inf"] {
+            let args = vec!["test program", "--sanity-range", range];
+            let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+            assert!(matches!(Config::from_args(&args), Err(ConfigError::InvalidSanityRange(param)) if param == range));
+        }
+        let args = vec!["test program", "-oor", "clamp"];
+        let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+        assert!(matches!(Config::from_args(&args), Err(ConfigError::InvalidOutOfRangeAction(_))));
+    }
+
+    #[test]
+    fn config_from_args_command_history() {
+        let args = vec!["test program", "-chl", "0"];
+        let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+        assert_eq!(Config::from_args(&args).unwrap().command_history_length, 0);
+
+        let args = vec![String::from("test program"), String::from("--command-history"), (MAX_COMMAND_HISTORY_LENGTH + 1).to_string()];
+        assert!(matches!(Config::from_args(&args), Err(ConfigError::InvalidHistoryLength(_))));
+    }
+
+    #[test]
+    fn config_from_args_worker_channel() {
+        let args = vec!["test program", "-wcb", "64", "--worker-channel-policy", "block"];
+        let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+        let config = Config::from_args(&args).unwrap();
+        assert_eq!((config.worker_channel_bound, config.worker_channel_policy), (64, FullPolicy::Block));
+
+        let args = vec!["test program", "--worker-channel-bound", "0"];
+        let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+        assert!(matches!(Config::from_args(&args), Err(ConfigError::InvalidChannelBound(_))));
+        let args = vec!["test program", "-wcp", "drop-newest"];
+        let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+        assert!(matches!(Config::from_args(&args), Err(ConfigError::InvalidChannelPolicy(_))));
+    }
+
+    #[test]
+    fn config_from_args_degenerate_buffer_size() {
+        for size in ["0", "999999999"] {
+            let args = vec!["test program", "-b", size];
+            let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+            assert!(matches!(Config::from_args(&args), Err(ConfigError::InvalidBufferSize(param)) if param == size));
+        }
+
+        let args = vec![String::from("test program"), String::from("--buffer-size"), MAX_BUFFER_SIZE.to_string()];
+        assert_eq!(Config::from_args(&args).unwrap().buffer_size, MAX_BUFFER_SIZE);
+    }
+
+    #[test]
+    fn config_from_args_odd_argument_count() {
+        let args = vec!["test program", "-b"];
+        let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+
+        assert!(matches!(Config::from_args(&args), Err(ConfigError::OddArgumentCount)));
+    }
+
+    #[test]
+    fn config_from_args_invalid_values() {
+        let args = vec!["test program", "-b", "lots"];
+        let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+        assert!(matches!(Config::from_args(&args), Err(ConfigError::InvalidBufferSize(_))));
+
+        let args = vec!["test program", "-ta", "10.0.0.1:port"];
+        let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+        assert!(matches!(Config::from_args(&args), Err(ConfigError::InvalidPort(_))));
+
+        let args = vec!["test program", "-ua", "10.0.300.1:8080"];
+        let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+        assert!(matches!(Config::from_args(&args), Err(ConfigError::InvalidAddress(_))));
+    }
+
+    #[test]
+    fn parse_args_help() {
+        for help in &["-h", "--help"] {
+            let args = vec!["test program", "-b", "512", help];
+            let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+            assert!(matches!(Config::parse_args(&args), Ok(ParseOutcome::HelpRequested)));
+        }
+
+        let args = vec!["test program", "-b", "512"];
+        let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+        assert!(matches!(Config::parse_args(&args), Ok(ParseOutcome::Config(config)) if config.buffer_size == 512));
+    }
+
+    #[test]
+    fn config_from_args_ipv6_address() {
+        let args = vec!["test program", "-ta", "[::1]:8080"];
+        let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+
+        let config_dut = Config::from_args(&args).unwrap();
+
+        assert_eq!(config_dut.tcp_address, "[::1]:8080".parse::<SocketAddr>().unwrap());
+        assert!(config_dut.tcp_address.is_ipv6());
+    }
+
+    #[test]
+    fn config_from_args_ipv6_address_with_scope() {
+        let args = vec!["test program", "-ua", "fe80::1%2:9090"];
+        let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+
+        let config_dut = Config::from_args(&args).unwrap();
+
+        match config_dut.udp_address {
+            SocketAddr::V6(addr) => {
+                assert_eq!(*addr.ip(), "fe80::1".parse::<std::net::Ipv6Addr>().unwrap());
+                assert_eq!(addr.scope_id(), 2);
+                assert_eq!(addr.port(), 9090);
+            },
+            SocketAddr::V4(_) => panic!("Expected an IPv6 address"),
+        }
+    }
+}
+
+/**
+ * Errors produced while building a Config from command line arguments
+ */
+#[derive(Debug)]
+pub enum ConfigError {
+    OddArgumentCount,
+    InvalidAddress(String),
+    InvalidPort(String),
+    InvalidBufferSize(String),
+    InvalidTimeout(String),
+    InvalidTimeoutCount(String),
+    InvalidRoboteqSetting(String),
+    ThrottleOutOfRange(u32), // Throttle is a percentage and can not be above 100
+    InvalidTelemetryRate(String),
+    InvalidReplaySpeed(String), // Replay speed must be a positive multiplier
+    InvalidDisconnectWait(String), // Expected immediate, deferred or a non zero timeout in milliseconds
+    InvalidRetryCount(String),
+    PortOutOfRange(u32), // A port derived from --base-port does not fit in a u16
+    InvalidSanityRange(String), // Expected field=min:max for a telemetry field, with min no greater than max
+    InvalidOutOfRangeAction(String), // Expected flag or drop
+    InvalidHistoryLength(String), // Expected a length no greater than MAX_COMMAND_HISTORY_LENGTH
+    InvalidChannelBound(String), // Expected a non zero number of messages
+    InvalidChannelPolicy(String), // Expected drop-oldest or block
+    InvalidDryRun(String), // RELAY_DRY_RUN must be true, false, 1 or 0
+    #[cfg(feature = "config-file")]
+    InvalidConfigFile(String), // The file could not be read, or is not TOML holding the keys a config file may
 }
 
+pub const USAGE: &'static str = "Usage: relay [-ta | --tcp-address | --address <host>:<port>] [-ua | --udp-address <host>:<port>] [-b | --buffer-size <buffer_size>] [-ci | -i | --can-interface <can_interface>] [-up | --udp-port <udp_port>] [-rt | --tcp-read-timeout <tcp_read_timeout_ms>] [-crt | --can-read-timeout <can_read_timeout_ms>] [-cmt | --can-max-timeouts <can_max_number_timeouts>] [-rn | --roboteq-node <roboteq_node>] [-rc | --roboteq-channels <roboteq_channel>[,<roboteq_channel>...]] [-rp | --throttle-percent <throttle_percent>] [-ubb | --udp-backoff-base <udp_backoff_base_ms>] [-ubc | --udp-backoff-cap <udp_backoff_cap_ms>] [-tr | --telemetry-rate <snapshots_per_second>] [-urt | --udp-read-timeout <udp_read_timeout_ms>] [-umt | --udp-max-timeouts <udp_max_number_timeouts>] [-cr | --can-replay <candump_log>] [-crs | --can-replay-speed <multiplier>] [-cl | --can-log <candump_log>] [-dw | --disconnect-wait immediate|deferred|<timeout_ms>] [-dr | --dry-run] [-ndr | --no-dry-run] [-sct | --state-change-timeout <state_change_timeout_ms>] [-scr | --state-change-retries <state_change_retries>] [-bp | --base-port <base_port>] [-upo | --udp-port-offset <udp_port_offset>] [-al | --allowlist <ip>[,<ip>...]] [-sr | --sanity-range <field>=<min>:<max>[,<field>=<min>:<max>...]] [-oor | --out-of-range flag|drop] [-chl | --command-history <length>] [-wcb | --worker-channel-bound <messages>] [-wcp | --worker-channel-policy drop-oldest|block] [-tk | --telemetry-keepalive <keepalive_ms>] [-wa | --websocket-address <host>:<port>] [-ha | --health-address <host>:<port>] [-c | --config <config_file>] [-h | --help]";
 
+
+/**
+ * Settings for every thread in the relay. The fields are public so that a binary which builds
+ * a Config, from arguments or by hand, can read back what it configured
+ */
 pub struct Config<A: std::net::ToSocketAddrs + std::fmt::Debug + Send + 'static> {
     pub tcp_address: A,
-    pub udp_address: A,
+    pub udp_address: A, // Local address the telemetry socket is bound to. Binding a specific ip picks the interface telemetry is sent from
     pub buffer_size: usize,
-    pub can_interface: String
+    pub can_interface: String,
+    pub udp_port: u16, // Port on the controller which the telemetry stream is sent to, unless CONNECT gives one. Advertised in the CONNECT response
+    pub tcp_read_timeout: Duration, // Amount of time a TCP client has to send its request before the connection is dropped
+    pub can_read_timeout: Duration, // Amount of time the CAN Socket will wait for a message from the rest of the POD
+    pub can_max_number_timeouts: u32, // Consecutive CAN read timeouts before the bus is considered silent and the pod is made safe
+    pub roboteq: RoboteqConfig,
+    pub udp_backoff_base: Duration, // Delay before the first udp socket re-bind after the controller link is lost. Doubles with every consecutive loss
+    pub udp_backoff_cap: Duration, // Longest delay between udp socket re-binds
+    pub telemetry_rate: u32, // Most telemetry snapshots sent to the controller per second. Updates in between are coalesced into the next snapshot
+    pub udp_read_timeout: Duration, // Amount of time the UDP Socket will wait for a message from the Controller
+    pub udp_max_number_timeouts: u32, // Consecutive UDP read timeouts before the controller is considered gone and the relay recovers
+    pub can_replay_file: Option<String>, // candump log to replay instead of reading from can_interface
+    pub can_replay_speed: f64, // Multiplier on the replay's timing, 2.0 replays twice as fast as the frames were recorded
+    pub can_log_path: Option<PathBuf>, // Every frame received on the bus is written here in candump format
+    pub disconnect_wait: DisconnectWait,
+    pub dry_run: bool, // Read and decode the bus as usual, but log the frames the relay would send instead of writing them
+    pub state_change_timeout: Duration, // Amount of time the boards have to acknowledge a state change before it is sent again
+    pub state_change_retries: u32, // Times an unacknowledged state change is sent again before the pod is put into SystemFailure
+    pub connect_allowlist: Vec<IpAddr>, // Peers allowed to CONNECT, HISTORY, MAINTENANCE and THROTTLE. Empty allows any peer
+    pub sanity_ranges: SanityRanges, // Telemetry readings outside of these are logged, and dropped unless they are only flagged
+    pub command_history_length: usize, // Most recently decoded can commands kept for HISTORY. Zero keeps none
+    pub worker_channel_bound: usize, // Most frames queued for the worker thread's telemetry. Commands to the boards are never dropped
+    pub worker_channel_policy: FullPolicy, // What the can thread does with a frame once the worker thread has fallen worker_channel_bound frames behind
+    pub telemetry_keepalive: Option<Duration>, // The last telemetry is sent again as a keepalive once nothing has changed for this long. None sends no keepalives
+    #[cfg(feature = "websocket")]
+    pub websocket_address: A, // Browser dashboards connect here for telemetry as json
+    #[cfg(feature = "health")]
+    pub health_address: A // Monitors GET /health here
+}
+
+/**
+ * When the reply to a DISCONNECT from the connected controller is sent. Until recovery has brought
+ * the pod down to LowVoltage it is not safe to cut power, so an operator should not be told it is
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DisconnectWait {
+    Immediate, // DISCONNECTED is sent as soon as recovery starts
+    Block(Duration), // Hold the reply until recovery completes. Falls back to Deferred if it takes longer. Other requests, such as ESTOP, are still handled while waiting
+    Deferred, // DISCONNECTING is sent straight away, then DISCONNECTED on the same connection once recovery completes
+}
+
+/**
+ * Settings used by the can thread when commanding the roboteq motor controller
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoboteqConfig {
+    pub node: u32, // CAN node id of the roboteq
+    pub channels: Vec<u8>, // Motor channels which are throttled in AutoPilot
+    pub throttle_percent: u32
+}
+
+impl RoboteqConfig {
+    pub fn new(node: u32, channels: Vec<u8>, throttle_percent: u32) -> Result<RoboteqConfig, ConfigError> {
+        Ok(RoboteqConfig {
+            node,
+            channels,
+            throttle_percent: check_throttle_percent(throttle_percent)?
+        })
+    }
+}
+
+impl Default for RoboteqConfig {
+    fn default() -> RoboteqConfig {
+        RoboteqConfig {
+            node: 1,
+            channels: vec![1, 2],
+            throttle_percent: 100
+        }
+    }
+}
+
+fn check_throttle_percent(throttle_percent: u32) -> Result<u32, ConfigError> {
+    if throttle_percent > 100 {
+        return Err(ConfigError::ThrottleOutOfRange(throttle_percent));
+    }
+    Ok(throttle_percent)
 }
 
 impl<A: std::net::ToSocketAddrs + std::fmt::Debug + Send + 'static> Config<A> {
-    #[cfg(windows)]
-    pub fn new(tcp_address: A, buffer_size: usize, can_interface: String, udp_address: A) -> Config<A> {
-        Config {
-            tcp_address,
-            buffer_size,
-            can_interface,
-            udp_address
+    /**
+     * @brief udp_idle_timeout
+     * How long a connected controller can go without sending anything before the udp thread
+     * gives up on it, brings the pod down through recovery and frees the relay for a new CONNECT
+     */
+    pub fn udp_idle_timeout(&self) -> Duration {
+        self.udp_read_timeout * self.udp_max_number_timeouts
+    }
+}
+
+/**
+ * Result of parsing the command line. Either the relay should run with a Config or only print its usage
+ */
+pub enum ParseOutcome {
+    Config(Config<SocketAddr>),
+    HelpRequested
+}
+
+/**
+ * Builds a Config starting from the defaults, so only the settings which differ need to be given.
+ * New settings can be added without changing how existing code constructs a Config. build checks
+ * the settings against the same limits as from_args
+ */
+pub struct ConfigBuilder {
+    config: Config<SocketAddr>
+}
+
+impl ConfigBuilder {
+    pub fn new() -> ConfigBuilder {
+        ConfigBuilder {
+            config: Config {
+                tcp_address: SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 8080),
+                udp_address: SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 8080),
+                buffer_size: 256,
+                can_interface: String::from("can0"),
+                udp_port: 8090,
+                tcp_read_timeout: Duration::from_millis(1000),
+                can_read_timeout: Duration::from_millis(10000),
+                can_max_number_timeouts: 3,
+                roboteq: RoboteqConfig::default(),
+                udp_backoff_base: Duration::from_millis(100),
+                udp_backoff_cap: Duration::from_millis(5000),
+                telemetry_rate: 20,
+                udp_read_timeout: Duration::from_millis(500),
+                udp_max_number_timeouts: 10,
+                can_replay_file: None,
+                can_replay_speed: 1.0,
+                can_log_path: None,
+                disconnect_wait: DisconnectWait::Immediate,
+                dry_run: false,
+                state_change_timeout: Duration::from_millis(1000),
+                state_change_retries: 3,
+                connect_allowlist: Vec::new(),
+                sanity_ranges: SanityRanges::default(),
+                command_history_length: 256,
+                worker_channel_bound: 1024,
+                worker_channel_policy: FullPolicy::DropOldest,
+                telemetry_keepalive: None,
+                #[cfg(feature = "websocket")]
+                websocket_address: SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 8082),
+                #[cfg(feature = "health")]
+                health_address: SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 8083)
+            }
+        }
+    }
+
+    pub fn tcp_address(mut self, tcp_address: SocketAddr) -> ConfigBuilder {
+        self.config.tcp_address = tcp_address;
+        self
+    }
+
+    pub fn udp_address(mut self, udp_address: SocketAddr) -> ConfigBuilder {
+        self.config.udp_address = udp_address;
+        self
+    }
+
+    pub fn buffer_size(mut self, buffer_size: usize) -> ConfigBuilder {
+        self.config.buffer_size = buffer_size;
+        self
+    }
+
+    pub fn can_interface(mut self, can_interface: String) -> ConfigBuilder {
+        self.config.can_interface = can_interface;
+        self
+    }
+
+    pub fn udp_port(mut self, udp_port: u16) -> ConfigBuilder {
+        self.config.udp_port = udp_port;
+        self
+    }
+
+    pub fn tcp_read_timeout(mut self, tcp_read_timeout: Duration) -> ConfigBuilder {
+        self.config.tcp_read_timeout = tcp_read_timeout;
+        self
+    }
+
+    pub fn can_read_timeout(mut self, can_read_timeout: Duration) -> ConfigBuilder {
+        self.config.can_read_timeout = can_read_timeout;
+        self
+    }
+
+    pub fn can_max_number_timeouts(mut self, can_max_number_timeouts: u32) -> ConfigBuilder {
+        self.config.can_max_number_timeouts = can_max_number_timeouts;
+        self
+    }
+
+    pub fn roboteq(mut self, roboteq: RoboteqConfig) -> ConfigBuilder {
+        self.config.roboteq = roboteq;
+        self
+    }
+
+    pub fn udp_backoff(mut self, udp_backoff_base: Duration, udp_backoff_cap: Duration) -> ConfigBuilder {
+        self.config.udp_backoff_base = udp_backoff_base;
+        self.config.udp_backoff_cap = udp_backoff_cap;
+        self
+    }
+
+    pub fn telemetry_rate(mut self, telemetry_rate: u32) -> ConfigBuilder {
+        self.config.telemetry_rate = telemetry_rate;
+        self
+    }
+
+    pub fn udp_read_timeout(mut self, udp_read_timeout: Duration) -> ConfigBuilder {
+        self.config.udp_read_timeout = udp_read_timeout;
+        self
+    }
+
+    pub fn udp_max_number_timeouts(mut self, udp_max_number_timeouts: u32) -> ConfigBuilder {
+        self.config.udp_max_number_timeouts = udp_max_number_timeouts;
+        self
+    }
+
+    pub fn can_replay(mut self, can_replay_file: String, can_replay_speed: f64) -> ConfigBuilder {
+        self.config.can_replay_file = Some(can_replay_file);
+        self.config.can_replay_speed = can_replay_speed;
+        self
+    }
+
+    pub fn can_log_path(mut self, can_log_path: PathBuf) -> ConfigBuilder {
+        self.config.can_log_path = Some(can_log_path);
+        self
+    }
+
+    pub fn disconnect_wait(mut self, disconnect_wait: DisconnectWait) -> ConfigBuilder {
+        self.config.disconnect_wait = disconnect_wait;
+        self
+    }
+
+    pub fn dry_run(mut self, dry_run: bool) -> ConfigBuilder {
+        self.config.dry_run = dry_run;
+        self
+    }
+
+    pub fn state_change_timeout(mut self, state_change_timeout: Duration) -> ConfigBuilder {
+        self.config.state_change_timeout = state_change_timeout;
+        self
+    }
+
+    pub fn state_change_retries(mut self, state_change_retries: u32) -> ConfigBuilder {
+        self.config.state_change_retries = state_change_retries;
+        self
+    }
+
+    pub fn connect_allowlist(mut self, connect_allowlist: Vec<IpAddr>) -> ConfigBuilder {
+        self.config.connect_allowlist = connect_allowlist;
+        self
+    }
+
+    pub fn sanity_ranges(mut self, sanity_ranges: SanityRanges) -> ConfigBuilder {
+        self.config.sanity_ranges = sanity_ranges;
+        self
+    }
+
+    pub fn command_history_length(mut self, command_history_length: usize) -> ConfigBuilder {
+        self.config.command_history_length = command_history_length;
+        self
+    }
+
+    pub fn worker_channel_bound(mut self, worker_channel_bound: usize) -> ConfigBuilder {
+        self.config.worker_channel_bound = worker_channel_bound;
+        self
+    }
+
+    pub fn worker_channel_policy(mut self, worker_channel_policy: FullPolicy) -> ConfigBuilder {
+        self.config.worker_channel_policy = worker_channel_policy;
+        self
+    }
+
+    pub fn telemetry_keepalive(mut self, telemetry_keepalive: Option<Duration>) -> ConfigBuilder {
+        self.config.telemetry_keepalive = telemetry_keepalive;
+        self
+    }
+
+    #[cfg(feature = "websocket")]
+    pub fn websocket_address(mut self, websocket_address: SocketAddr) -> ConfigBuilder {
+        self.config.websocket_address = websocket_address;
+        self
+    }
+
+    #[cfg(feature = "health")]
+    pub fn health_address(mut self, health_address: SocketAddr) -> ConfigBuilder {
+        self.config.health_address = health_address;
+        self
+    }
+
+    pub fn build(self) -> Result<Config<SocketAddr>, ConfigError> {
+        let config = self.config;
+        check_buffer_size(config.buffer_size)?;
+        for &timeout in &[config.tcp_read_timeout, config.can_read_timeout, config.udp_backoff_base, config.udp_backoff_cap, config.udp_read_timeout, config.state_change_timeout] {
+            check_timeout(timeout)?;
+        }
+        if let DisconnectWait::Block(timeout) = config.disconnect_wait {
+            check_timeout(timeout).map_err(|_| ConfigError::InvalidDisconnectWait(timeout.as_millis().to_string()))?;
+        }
+        for &count in &[config.can_max_number_timeouts, config.udp_max_number_timeouts] {
+            if count == 0 {
+                return Err(ConfigError::InvalidTimeoutCount(count.to_string()));
+            }
+        }
+        check_throttle_percent(config.roboteq.throttle_percent)?;
+        if config.telemetry_rate == 0 {
+            return Err(ConfigError::InvalidTelemetryRate(config.telemetry_rate.to_string()));
+        }
+        if !(config.can_replay_speed.is_finite() && config.can_replay_speed > 0.0) {
+            return Err(ConfigError::InvalidReplaySpeed(config.can_replay_speed.to_string()));
+        }
+        if config.command_history_length > MAX_COMMAND_HISTORY_LENGTH {
+            return Err(ConfigError::InvalidHistoryLength(config.command_history_length.to_string()));
         }
+        if config.worker_channel_bound == 0 {
+            return Err(ConfigError::InvalidChannelBound(config.worker_channel_bound.to_string()));
+        }
+        Ok(config)
+    }
+}
+
+impl Default for ConfigBuilder {
+    fn default() -> ConfigBuilder {
+        ConfigBuilder::new()
     }
 }
 
 impl Config<SocketAddr> {
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder::new()
+    }
+
     pub fn default() -> Config<SocketAddr> {
-        Config {
-            tcp_address: SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 8080),
-            udp_address: SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 8080),
-            buffer_size: 256,
-            can_interface: String::from("can0")
+        ConfigBuilder::new().build().expect("The default settings should be valid")
+    }
+
+    /**
+     * @brief parse_args
+     * Like from_args, but -h or --help anywhere in the arguments asks for the usage instead of a Config.
+     * Left to the caller to print USAGE and exit, so that it can be tested without exiting the process.
+     * Settings are also read from RELAY_* environment variables, see config_env. With the config-file feature,
+     * -c | --config config_file reads settings from a TOML file. The arguments override the environment,
+     * which overrides the file
+     */
+    pub fn parse_args(args: &Vec<String>) -> Result<ParseOutcome, ConfigError> {
+        if args.iter().skip(1).any(|arg| arg == "-h" || arg == "--help") {
+            return Ok(ParseOutcome::HelpRequested);
         }
+        let args = &crate::config_env::with_env_args(args, |name| std::env::var(name).ok())?;
+        #[cfg(feature = "config-file")]
+        let args = &crate::config_file::with_file_args(args)?;
+        Config::from_args(args).map(ParseOutcome::Config)
     }
 
     /**
      * @brief from_args
      * This builds a Config Item from a vector of command line arguments
      *
-     * If the args vector is malformed, the function will panic and exit
-     * TODO add proper error handling
+     * If the args vector is malformed, a ConfigError describing the bad argument is returned
      * TODO use an args crate instead
      *
      * Currently Accepted arguments:
-     * -ta hostIpv4:port
-     * -ua hostIpv4:port
-     * -b buffer_size
-     * -ci can_interface
+     * -ta | --tcp-address | --address hostIpv4:port | [hostIpv6]:port
+     * -ua | --udp-address hostIpv4:port | [hostIpv6]:port
+     * -b | --buffer-size buffer_size (1 to MAX_BUFFER_SIZE bytes)
+     * -ci | -i | --can-interface can_interface
+     * -up | --udp-port udp_port
+     * -rt | --tcp-read-timeout tcp_read_timeout (milliseconds, must be non zero)
+     * -crt | --can-read-timeout can_read_timeout (milliseconds, must be non zero)
+     * -cmt | --can-max-timeouts can_max_number_timeouts (must be non zero)
+     * -rn | --roboteq-node roboteq_node
+     * -rc | --roboteq-channels roboteq_channel[,roboteq_channel...]
+     * -rp | --throttle-percent throttle_percent (0 to 100)
+     * -ubb | --udp-backoff-base udp_backoff_base (milliseconds, must be non zero)
+     * -ubc | --udp-backoff-cap udp_backoff_cap (milliseconds, must be non zero)
+     * -tr | --telemetry-rate telemetry_rate (snapshots per second, must be non zero)
+     * -urt | --udp-read-timeout udp_read_timeout (milliseconds, must be non zero)
+     * -umt | --udp-max-timeouts udp_max_number_timeouts (must be non zero)
+     * -cr | --can-replay candump_log (replayed in place of the can interface)
+     * -crs | --can-replay-speed can_replay_speed (positive multiplier, defaults to 1)
+     * -cl | --can-log can_log_path (every received frame is written here in candump format)
+     * -dw | --disconnect-wait immediate | deferred | disconnect_timeout (milliseconds, must be non zero)
+     * -dr | --dry-run (takes no value, nothing is written to the can bus)
+     * -ndr | --no-dry-run (takes no value, undoes a --dry-run which comes after it, such as one from a config file)
+     * -sct | --state-change-timeout state_change_timeout (milliseconds, must be non zero)
+     * -scr | --state-change-retries state_change_retries (zero puts the pod into SystemFailure on the first timeout)
+     * -bp | --base-port base_port (the tcp address listens on base_port, and both the udp address and udp_port use base_port + udp_port_offset, keeping the addresses' ips)
+     * -upo | --udp-port-offset udp_port_offset (only used with --base-port, defaults to 1)
+     * -al | --allowlist ip[,ip...] (only these peers may CONNECT, HISTORY, MAINTENANCE and THROTTLE, any peer may when it is not given)
+     * -sr | --sanity-range field=min:max[,field=min:max...] (replaces the default range of each field given)
+     * -oor | --out-of-range flag | drop (defaults to drop)
+     * -chl | --command-history command_history_length (zero keeps no history, at most MAX_COMMAND_HISTORY_LENGTH)
+     * -wcb | --worker-channel-bound worker_channel_bound (frames, must be non zero)
+     * -wcp | --worker-channel-policy drop-oldest | block (defaults to drop-oldest, block waits at most 100ms before dropping the newest frame)
+     * -tk | --telemetry-keepalive telemetry_keepalive (ms, zero sends no keepalives, the default)
+     * -wa | --websocket-address hostIpv4:port | [hostIpv6]:port (only with the websocket feature)
+     * -ha | --health-address hostIpv4:port | [hostIpv6]:port (only with the health feature)
+     * -c | --config config_file (only through parse_args, with the config-file feature)
+     * -h | --help (only through parse_args)
      */
-    pub fn from_args(args: &Vec<String>) -> Config<SocketAddr> {
+    pub fn from_args(args: &Vec<String>) -> Result<Config<SocketAddr>, ConfigError> {
+        // Flags which take no value are taken out first, so the rest can be read in pairs.
+        // Like every other flag, the first one given wins
+        let dry_run_flag = |arg: &String| match arg.as_str() {
+            "-dr" | "--dry-run" => Some(true),
+            "-ndr" | "--no-dry-run" => Some(false),
+            _ => None
+        };
+        let dry_run = args.iter().skip(1).find_map(dry_run_flag).unwrap_or(false);
+        let args: Vec<String> = args.iter().enumerate()
+            .filter(|(index, arg)| *index == 0 || dry_run_flag(arg).is_none())
+            .map(|(_, arg)| arg.clone())
+            .collect();
         if args.len() % 2 == 0 {
-            panic!("invalid arguments");
+            return Err(ConfigError::OddArgumentCount);
         }
         let mut i = args.len() - 1;
         let mut config = Config::default();
+        config.dry_run = dry_run;
+        let mut base_port = None;
+        let mut udp_port_offset = DEFAULT_UDP_PORT_OFFSET;
 
         while i > 1 {
             let param = &args[i];
             let param_type: &str = &args[i-1];
 
             match param_type {
-                "-ta" => {
-                    let host_and_port: Vec<&str> = param.split(':').collect();
-                    if host_and_port.len() != 2 {
-                        panic!("Invalid address Argument, expected form -a <host>:<port>");
-                    }
-                    let host = host_and_port[0];
-                    let port = host_and_port[1].parse::<u16>().unwrap();
-                    let host: Vec<&str> = host.split('.').collect();
-
-                    if host.len() != 4 {
-                        panic!("Invalid host, expected form ##.##.##.##");
-                    }
-
-                    let host: Vec<u8> = host.iter().map(|val| val.parse::<u8>().unwrap()).collect();
-
-                    config.tcp_address = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(host[0], host[1], host[2], host[3])), port);
+                "-ta" | "--tcp-address" | "--address" => {
+                    config.tcp_address = parse_address(param)?;
                 },
-                "-ua" => {
-                    let host_and_port: Vec<&str> = param.split(':').collect();
-                    if host_and_port.len() != 2 {
-                        panic!("Invalid address Argument, expected form -a <host>:<port>");
-                    }
-                    let host = host_and_port[0];
-                    let port = host_and_port[1].parse::<u16>().unwrap();
-                    let host: Vec<&str> = host.split('.').collect();
-
-                    if host.len() != 4 {
-                        panic!("Invalid host, expected form ##.##.##.##");
-                    }
-
-                    let host: Vec<u8> = host.iter().map(|val| val.parse::<u8>().unwrap()).collect();
-
-                    config.udp_address = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(host[0], host[1], host[2], host[3])), port);
+                "-ua" | "--udp-address" => {
+                    config.udp_address = parse_address(param)?;
                 },
-                "-b" => {
-                    let size = param.parse::<usize>().unwrap();
-
-                    config.buffer_size = size;
+                "-b" | "--buffer-size" => {
+                    config.buffer_size = parse_buffer_size(param)?;
                 },
-                "-ci" => {
+                "-ci" | "-i" | "--can-interface" => {
                     let can_interface = String::from(param);
                     config.can_interface = can_interface;
-                }
+                },
+                "-up" | "--udp-port" => {
+                    config.udp_port = param.parse::<u16>().map_err(|_| ConfigError::InvalidPort(param.clone()))?;
+                },
+                "-rt" | "--tcp-read-timeout" => {
+                    config.tcp_read_timeout = parse_timeout(param)?;
+                },
+                "-crt" | "--can-read-timeout" => {
+                    config.can_read_timeout = parse_timeout(param)?;
+                },
+                "-cmt" | "--can-max-timeouts" => {
+                    config.can_max_number_timeouts = param.parse::<u32>().ok().filter(|&count| count > 0).ok_or_else(|| ConfigError::InvalidTimeoutCount(param.clone()))?;
+                },
+                "-rn" | "--roboteq-node" => {
+                    config.roboteq.node = param.parse::<u32>().map_err(|_| ConfigError::InvalidRoboteqSetting(param.clone()))?;
+                },
+                "-rc" | "--roboteq-channels" => {
+                    config.roboteq.channels = param.split(',')
+                        .map(|channel| channel.parse::<u8>())
+                        .collect::<Result<Vec<u8>, _>>()
+                        .map_err(|_| ConfigError::InvalidRoboteqSetting(param.clone()))?;
+                },
+                "-rp" | "--throttle-percent" => {
+                    let throttle_percent = param.parse::<u32>().map_err(|_| ConfigError::InvalidRoboteqSetting(param.clone()))?;
+                    config.roboteq.throttle_percent = check_throttle_percent(throttle_percent)?;
+                },
+                "-ubb" | "--udp-backoff-base" => {
+                    config.udp_backoff_base = parse_timeout(param)?;
+                },
+                "-ubc" | "--udp-backoff-cap" => {
+                    config.udp_backoff_cap = parse_timeout(param)?;
+                },
+                "-urt" | "--udp-read-timeout" => {
+                    config.udp_read_timeout = parse_timeout(param)?;
+                },
+                "-umt" | "--udp-max-timeouts" => {
+                    config.udp_max_number_timeouts = param.parse::<u32>().ok().filter(|&count| count > 0).ok_or_else(|| ConfigError::InvalidTimeoutCount(param.clone()))?;
+                },
+                "-cr" | "--can-replay" => {
+                    config.can_replay_file = Some(String::from(param));
+                },
+                "-crs" | "--can-replay-speed" => {
+                    config.can_replay_speed = param.parse::<f64>().ok().filter(|&speed| speed.is_finite() && speed > 0.0).ok_or_else(|| ConfigError::InvalidReplaySpeed(param.clone()))?;
+                },
+                "-cl" | "--can-log" => {
+                    config.can_log_path = Some(PathBuf::from(param));
+                },
+                "-dw" | "--disconnect-wait" => {
+                    config.disconnect_wait = parse_disconnect_wait(param)?;
+                },
+                "-sct" | "--state-change-timeout" => {
+                    config.state_change_timeout = parse_timeout(param)?;
+                },
+                "-scr" | "--state-change-retries" => {
+                    config.state_change_retries = param.parse::<u32>().map_err(|_| ConfigError::InvalidRetryCount(param.clone()))?;
+                },
+                #[cfg(feature = "websocket")]
+                "-wa" | "--websocket-address" => {
+                    config.websocket_address = parse_address(param)?;
+                },
+                #[cfg(feature = "health")]
+                "-ha" | "--health-address" => {
+                    config.health_address = parse_address(param)?;
+                },
+                "-bp" | "--base-port" => {
+                    base_port = Some(param.parse::<u16>().map_err(|_| ConfigError::InvalidPort(param.clone()))?);
+                },
+                "-al" | "--allowlist" => {
+                    config.connect_allowlist = param.split(',')
+                        .map(|ip| ip.parse::<IpAddr>())
+                        .collect::<Result<Vec<IpAddr>, _>>()
+                        .map_err(|_| ConfigError::InvalidAddress(param.clone()))?;
+                },
+                "-sr" | "--sanity-range" => {
+                    for range in param.split(',') {
+                        let (field, min, max) = parse_sanity_range(range).ok_or_else(|| ConfigError::InvalidSanityRange(String::from(range)))?;
+                        config.sanity_ranges.set_range(field, min, max);
+                    }
+                },
+                "-oor" | "--out-of-range" => {
+                    config.sanity_ranges.set_action(match param.as_str() {
+                        "flag" => OutOfRangeAction::Flag,
+                        "drop" => OutOfRangeAction::Drop,
+                        _ => return Err(ConfigError::InvalidOutOfRangeAction(param.clone()))
+                    });
+                },
+                "-chl" | "--command-history" => {
+                    config.command_history_length = param.parse::<usize>().ok().filter(|&length| length <= MAX_COMMAND_HISTORY_LENGTH).ok_or_else(|| ConfigError::InvalidHistoryLength(param.clone()))?;
+                },
+                "-wcb" | "--worker-channel-bound" => {
+                    config.worker_channel_bound = param.parse::<usize>().ok().filter(|&bound| bound > 0).ok_or_else(|| ConfigError::InvalidChannelBound(param.clone()))?;
+                },
+                "-wcp" | "--worker-channel-policy" => {
+                    config.worker_channel_policy = match param.as_str() {
+                        "drop-oldest" => FullPolicy::DropOldest,
+                        "block" => FullPolicy::Block,
+                        _ => return Err(ConfigError::InvalidChannelPolicy(param.clone()))
+                    };
+                },
+                "-upo" | "--udp-port-offset" => {
+                    udp_port_offset = param.parse::<u16>().map_err(|_| ConfigError::InvalidPort(param.clone()))?;
+                },
+                "-tr" | "--telemetry-rate" => {
+                    config.telemetry_rate = param.parse::<u32>().ok().filter(|&rate| rate > 0).ok_or_else(|| ConfigError::InvalidTelemetryRate(param.clone()))?;
+                },
+                "-tk" | "--telemetry-keepalive" => {
+                    config.telemetry_keepalive = match param.parse::<u64>() {
+                        Ok(0) => None,
+                        Ok(ms) => Some(Duration::from_millis(ms)),
+                        Err(_) => return Err(ConfigError::InvalidTimeout(param.clone()))
+                    };
+                },
                 _ => (),
             }
             i -= 2; // read arguments in pairs
         }
-        config
+        // Applied once every argument is read, so the base port wins over the ports in -ta, -ua and -up wherever it is given
+        if let Some(base_port) = base_port {
+            let (tcp_port, udp_port) = ports_from_base(base_port, udp_port_offset)?;
+            config.tcp_address.set_port(tcp_port);
+            config.udp_address.set_port(udp_port);
+            config.udp_port = udp_port;
+        }
+        Ok(config)
+    }
+}
+
+/**
+ * Default distance from --base-port to the udp port, so the two ports are adjacent
+ */
+const DEFAULT_UDP_PORT_OFFSET: u16 = 1;
+
+/**
+ * @brief ports_from_base
+ * The tcp and udp ports derived from a base port. The tcp port is the base and the udp port, used on both the relay and
+ * the controller, sits udp_port_offset above it
+ */
+fn ports_from_base(base_port: u16, udp_port_offset: u16) -> Result<(u16, u16), ConfigError> {
+    let udp_port = base_port.checked_add(udp_port_offset)
+        .ok_or(ConfigError::PortOutOfRange(u32::from(base_port) + u32::from(udp_port_offset)))?;
+    Ok((base_port, udp_port))
+}
+
+/**
+ * Largest buffer tcp requests may be read with. A request can be at most 4KiB, so anything much
+ * larger only allocates memory which is never filled
+ */
+pub const MAX_BUFFER_SIZE: usize = 65536;
+
+/**
+ * Longest command history. The whole history is allocated up front and written out in one HISTORY response
+ */
+pub const MAX_COMMAND_HISTORY_LENGTH: usize = 65536;
+
+/**
+ * @brief parse_buffer_size
+ * Parse the size of the buffer tcp requests are read in. A zero sized buffer reads nothing, which
+ * would turn every request into @@Failed@@, and one above MAX_BUFFER_SIZE is an absurd allocation
+ */
+fn parse_buffer_size(param: &str) -> Result<usize, ConfigError> {
+    let buffer_size = param.parse::<usize>().map_err(|_| ConfigError::InvalidBufferSize(String::from(param)))?;
+    check_buffer_size(buffer_size)
+}
+
+fn check_buffer_size(buffer_size: usize) -> Result<usize, ConfigError> {
+    if buffer_size == 0 || buffer_size > MAX_BUFFER_SIZE {
+        return Err(ConfigError::InvalidBufferSize(buffer_size.to_string()));
+    }
+    Ok(buffer_size)
+}
+
+/**
+ * @brief parse_timeout
+ * Parse a timeout given in milliseconds. A zero duration is rejected by set_read_timeout, so it is not a valid timeout
+ */
+fn parse_timeout(param: &str) -> Result<Duration, ConfigError> {
+    let timeout = param.parse::<u64>().map_err(|_| ConfigError::InvalidTimeout(String::from(param)))?;
+    check_timeout(Duration::from_millis(timeout))
+}
+
+fn check_timeout(timeout: Duration) -> Result<Duration, ConfigError> {
+    if timeout.is_zero() {
+        return Err(ConfigError::InvalidTimeout(timeout.as_millis().to_string()));
+    }
+    Ok(timeout)
+}
+
+fn parse_disconnect_wait(param: &str) -> Result<DisconnectWait, ConfigError> {
+    match param {
+        "immediate" => Ok(DisconnectWait::Immediate),
+        "deferred" => Ok(DisconnectWait::Deferred),
+        timeout => parse_timeout(timeout)
+            .map(DisconnectWait::Block)
+            .map_err(|_| ConfigError::InvalidDisconnectWait(String::from(param)))
+    }
+}
+
+/**
+ * @brief parse_sanity_range
+ * Parse one field=min:max. The field must be a reading in the telemetry, so a typo is not silently never checked
+ */
+fn parse_sanity_range(param: &str) -> Option<(&str, f64, f64)> {
+    let (field, range) = param.split_once('=')?;
+    let (min, max) = range.split_once(':')?;
+    let (min, max) = (min.parse::<f64>().ok()?, max.parse::<f64>().ok()?);
+    if !sanity_ranges::is_pod_data_field(field) || !min.is_finite() || !max.is_finite() || min > max {
+        return None;
+    }
+    Some((field, min, max))
+}
+
+/**
+ * @brief parse_address
+ * Parse an address argument of the form hostIpv4:port, [hostIpv6]:port or hostIpv6%scope:port
+ */
+fn parse_address(param: &str) -> Result<SocketAddr, ConfigError> {
+    if let Ok(addr) = param.parse::<SocketAddr>() {
+        return Ok(addr);
+    }
+    if param.matches(':').count() > 1 {
+        parse_ipv6_address(param)
+    } else {
+        parse_ipv4_address(param)
+    }
+}
+
+fn parse_ipv4_address(param: &str) -> Result<SocketAddr, ConfigError> {
+    let host_and_port: Vec<&str> = param.split(':').collect();
+    if host_and_port.len() != 2 {
+        return Err(ConfigError::InvalidAddress(String::from(param)));
+    }
+    let host = host_and_port[0];
+    let port = host_and_port[1].parse::<u16>().map_err(|_| ConfigError::InvalidPort(String::from(host_and_port[1])))?;
+    let host: Vec<&str> = host.split('.').collect();
+
+    if host.len() != 4 {
+        return Err(ConfigError::InvalidAddress(String::from(param)));
+    }
+
+    let host: Vec<u8> = host.iter()
+        .map(|val| val.parse::<u8>())
+        .collect::<Result<Vec<u8>, _>>()
+        .map_err(|_| ConfigError::InvalidAddress(String::from(param)))?;
+
+    Ok(SocketAddr::new(IpAddr::V4(Ipv4Addr::new(host[0], host[1], host[2], host[3])), port))
+}
+
+/**
+ * @brief parse_ipv6_address
+ * Handles the IPv6 forms that std does not parse, such as an unbracketed host
+ * or a named scope (fe80::1%eth0:9090). The port is always taken after the last ':'
+ */
+fn parse_ipv6_address(param: &str) -> Result<SocketAddr, ConfigError> {
+    let (host, port) = param.rsplit_once(':').ok_or_else(|| ConfigError::InvalidAddress(String::from(param)))?;
+    let port = port.parse::<u16>().map_err(|_| ConfigError::InvalidPort(String::from(port)))?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+
+    let (host, scope_id) = match host.split_once('%') {
+        Some((host, scope)) => (host, parse_scope_id(scope).ok_or_else(|| ConfigError::InvalidAddress(String::from(param)))?),
+        None => (host, 0)
+    };
+    let host = host.parse::<Ipv6Addr>().map_err(|_| ConfigError::InvalidAddress(String::from(param)))?;
+
+    Ok(SocketAddr::V6(SocketAddrV6::new(host, port, 0, scope_id)))
+}
+
+/**
+ * @brief parse_scope_id
+ * A scope can either be given as the numeric interface index or as the interface name
+ */
+fn parse_scope_id(scope: &str) -> Option<u32> {
+    if let Ok(scope_id) = scope.parse::<u32>() {
+        return Some(scope_id);
+    }
+    #[cfg(unix)]
+    {
+        let name = std::ffi::CString::new(scope).ok()?;
+        let index = unsafe { libc::if_nametoindex(name.as_ptr()) };
+        if index != 0 {
+            return Some(index);
+        }
     }
+    None
 }
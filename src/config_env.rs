@@ -0,0 +1,143 @@
+/*!
+ * @brief Environment variable overrides, for deployments where setting the environment is easier than
+ * the command line. Each variable stands in for one flag, and is turned into that flag so it is
+ * validated exactly as the command line is. The command line overrides the environment, which
+ * overrides a config file, which overrides the defaults
+ */
+
+use crate::config::ConfigError;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::Config;
+    use std::collections::HashMap;
+
+    #[test]
+    fn command_line_overrides_environment_overrides_default() {
+        let env: HashMap<&str, &str> = [(RELAY_BUFFER_SIZE, "512"), (RELAY_CAN_INTERFACE, "vcan0"), (RELAY_UDP_PORT, ""), (RELAY_DRY_RUN, "true")].iter().cloned().collect();
+        let lookup = |name: &str| env.get(name).map(|value| value.to_string());
+
+        let args = vec![String::from("relay")];
+        let config = Config::from_args(&with_env_args(&args, lookup).unwrap()).unwrap();
+        assert_eq!(config.buffer_size, 512);
+        assert_eq!(config.can_interface, "vcan0");
+        assert_eq!(config.udp_port, Config::default().udp_port); // Empty variables are ignored
+        assert!(config.dry_run);
+
+        let args: Vec<String> = vec!["relay", "-b", "1024"].iter().map(|&arg| String::from(arg)).collect();
+        let config = Config::from_args(&with_env_args(&args, lookup).unwrap()).unwrap();
+        assert_eq!(config.buffer_size, 1024);
+        assert_eq!(config.can_interface, "vcan0");
+
+        let bad_buffer_size = |name: &str| Some(String::from(if name == RELAY_BUFFER_SIZE { "0" } else { "" }));
+        assert!(matches!(Config::from_args(&with_env_args(&args[..1].to_vec(), bad_buffer_size).unwrap()), Err(ConfigError::InvalidBufferSize(_))));
+        assert!(matches!(with_env_args(&args, |name| (name == RELAY_DRY_RUN).then(|| String::from("yes"))), Err(ConfigError::InvalidDryRun(_))));
+
+        // false overrides a dry run which comes later, as one from a config file would
+        let dry_run_off = |name: &str| (name == RELAY_DRY_RUN).then(|| String::from("false"));
+        let mut combined = with_env_args(&args[..1].to_vec(), dry_run_off).unwrap();
+        combined.push(String::from("--dry-run"));
+        assert!(!Config::from_args(&combined).unwrap().dry_run);
+    }
+}
+
+pub const RELAY_ADDRESS: &str = "RELAY_ADDRESS";
+pub const RELAY_UDP_ADDRESS: &str = "RELAY_UDP_ADDRESS";
+pub const RELAY_BUFFER_SIZE: &str = "RELAY_BUFFER_SIZE";
+pub const RELAY_CAN_INTERFACE: &str = "RELAY_CAN_INTERFACE";
+pub const RELAY_UDP_PORT: &str = "RELAY_UDP_PORT";
+pub const RELAY_TCP_READ_TIMEOUT: &str = "RELAY_TCP_READ_TIMEOUT";
+pub const RELAY_CAN_READ_TIMEOUT: &str = "RELAY_CAN_READ_TIMEOUT";
+pub const RELAY_CAN_MAX_TIMEOUTS: &str = "RELAY_CAN_MAX_TIMEOUTS";
+pub const RELAY_ROBOTEQ_NODE: &str = "RELAY_ROBOTEQ_NODE";
+pub const RELAY_ROBOTEQ_CHANNELS: &str = "RELAY_ROBOTEQ_CHANNELS";
+pub const RELAY_THROTTLE_PERCENT: &str = "RELAY_THROTTLE_PERCENT";
+pub const RELAY_UDP_BACKOFF_BASE: &str = "RELAY_UDP_BACKOFF_BASE";
+pub const RELAY_UDP_BACKOFF_CAP: &str = "RELAY_UDP_BACKOFF_CAP";
+pub const RELAY_TELEMETRY_RATE: &str = "RELAY_TELEMETRY_RATE";
+pub const RELAY_UDP_READ_TIMEOUT: &str = "RELAY_UDP_READ_TIMEOUT";
+pub const RELAY_UDP_MAX_TIMEOUTS: &str = "RELAY_UDP_MAX_TIMEOUTS";
+pub const RELAY_CAN_REPLAY: &str = "RELAY_CAN_REPLAY";
+pub const RELAY_CAN_REPLAY_SPEED: &str = "RELAY_CAN_REPLAY_SPEED";
+pub const RELAY_CAN_LOG: &str = "RELAY_CAN_LOG";
+pub const RELAY_DISCONNECT_WAIT: &str = "RELAY_DISCONNECT_WAIT";
+pub const RELAY_STATE_CHANGE_TIMEOUT: &str = "RELAY_STATE_CHANGE_TIMEOUT";
+pub const RELAY_STATE_CHANGE_RETRIES: &str = "RELAY_STATE_CHANGE_RETRIES";
+pub const RELAY_BASE_PORT: &str = "RELAY_BASE_PORT";
+pub const RELAY_UDP_PORT_OFFSET: &str = "RELAY_UDP_PORT_OFFSET";
+pub const RELAY_ALLOWLIST: &str = "RELAY_ALLOWLIST";
+pub const RELAY_SANITY_RANGE: &str = "RELAY_SANITY_RANGE";
+pub const RELAY_OUT_OF_RANGE: &str = "RELAY_OUT_OF_RANGE";
+pub const RELAY_COMMAND_HISTORY: &str = "RELAY_COMMAND_HISTORY";
+pub const RELAY_WORKER_CHANNEL_BOUND: &str = "RELAY_WORKER_CHANNEL_BOUND";
+pub const RELAY_WORKER_CHANNEL_POLICY: &str = "RELAY_WORKER_CHANNEL_POLICY";
+pub const RELAY_TELEMETRY_KEEPALIVE: &str = "RELAY_TELEMETRY_KEEPALIVE";
+pub const RELAY_WEBSOCKET_ADDRESS: &str = "RELAY_WEBSOCKET_ADDRESS";
+pub const RELAY_HEALTH_ADDRESS: &str = "RELAY_HEALTH_ADDRESS";
+pub const RELAY_CONFIG: &str = "RELAY_CONFIG";
+pub const RELAY_DRY_RUN: &str = "RELAY_DRY_RUN"; // true or 1 for --dry-run, false or 0 for --no-dry-run
+
+/**
+ * Each variable which takes a value, with the flag it stands in for. Flags for features which are not
+ * built in are ignored, as they are on the command line
+ */
+const ENV_FLAGS: [(&str, &str); 34] = [
+    (RELAY_ADDRESS, "--address"),
+    (RELAY_UDP_ADDRESS, "--udp-address"),
+    (RELAY_BUFFER_SIZE, "--buffer-size"),
+    (RELAY_CAN_INTERFACE, "--can-interface"),
+    (RELAY_UDP_PORT, "--udp-port"),
+    (RELAY_TCP_READ_TIMEOUT, "--tcp-read-timeout"),
+    (RELAY_CAN_READ_TIMEOUT, "--can-read-timeout"),
+    (RELAY_CAN_MAX_TIMEOUTS, "--can-max-timeouts"),
+    (RELAY_ROBOTEQ_NODE, "--roboteq-node"),
+    (RELAY_ROBOTEQ_CHANNELS, "--roboteq-channels"),
+    (RELAY_THROTTLE_PERCENT, "--throttle-percent"),
+    (RELAY_UDP_BACKOFF_BASE, "--udp-backoff-base"),
+    (RELAY_UDP_BACKOFF_CAP, "--udp-backoff-cap"),
+    (RELAY_TELEMETRY_RATE, "--telemetry-rate"),
+    (RELAY_UDP_READ_TIMEOUT, "--udp-read-timeout"),
+    (RELAY_UDP_MAX_TIMEOUTS, "--udp-max-timeouts"),
+    (RELAY_CAN_REPLAY, "--can-replay"),
+    (RELAY_CAN_REPLAY_SPEED, "--can-replay-speed"),
+    (RELAY_CAN_LOG, "--can-log"),
+    (RELAY_DISCONNECT_WAIT, "--disconnect-wait"),
+    (RELAY_STATE_CHANGE_TIMEOUT, "--state-change-timeout"),
+    (RELAY_STATE_CHANGE_RETRIES, "--state-change-retries"),
+    (RELAY_BASE_PORT, "--base-port"),
+    (RELAY_UDP_PORT_OFFSET, "--udp-port-offset"),
+    (RELAY_ALLOWLIST, "--allowlist"),
+    (RELAY_SANITY_RANGE, "--sanity-range"),
+    (RELAY_OUT_OF_RANGE, "--out-of-range"),
+    (RELAY_COMMAND_HISTORY, "--command-history"),
+    (RELAY_WORKER_CHANNEL_BOUND, "--worker-channel-bound"),
+    (RELAY_WORKER_CHANNEL_POLICY, "--worker-channel-policy"),
+    (RELAY_TELEMETRY_KEEPALIVE, "--telemetry-keepalive"),
+    (RELAY_WEBSOCKET_ADDRESS, "--websocket-address"),
+    (RELAY_HEALTH_ADDRESS, "--health-address"),
+    (RELAY_CONFIG, "--config")
+];
+
+/**
+ * @brief with_env_args
+ * The arguments followed by the flags set in the environment. lookup reads a variable, std::env::var
+ * outside of tests. Earlier arguments take precedence, so the command line overrides the environment.
+ * Variables which are set but empty are ignored
+ */
+pub fn with_env_args<F: Fn(&str) -> Option<String>>(args: &[String], lookup: F) -> Result<Vec<String>, ConfigError> {
+    let mut combined = args.to_vec();
+    for (name, flag) in ENV_FLAGS.iter() {
+        if let Some(value) = lookup(name).filter(|value| !value.is_empty()) {
+            combined.push(String::from(*flag));
+            combined.push(value);
+        }
+    }
+    match lookup(RELAY_DRY_RUN).as_deref() {
+        Some("true") | Some("1") => combined.push(String::from("--dry-run")),
+        Some("false") | Some("0") => combined.push(String::from("--no-dry-run")),
+        None | Some("") => {},
+        Some(value) => return Err(ConfigError::InvalidDryRun(String::from(value)))
+    }
+    Ok(combined)
+}
@@ -0,0 +1,225 @@
+/*!
+ * @brief Reads the relay's settings from a TOML file, so a deployment can be kept in version control
+ * rather than in a long command line. Each key is named after the flag which sets it, and the file is
+ * turned into those flags so that it is validated exactly as the command line is. Flags given on the
+ * command line are read first, and so override the file
+ */
+
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use serde::Deserialize;
+use crate::config::{ Config, ConfigError };
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::DisconnectWait;
+    use std::time::Duration;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|&arg| String::from(arg)).collect()
+    }
+
+    #[test]
+    fn sample_config_is_parsed() {
+        let mut file_args = args(&["relay"]);
+        file_args.extend(parse(include_str!("../relay.example.toml")).unwrap());
+        let config = Config::from_args(&file_args).unwrap();
+
+        assert_eq!(config.buffer_size, 512);
+        assert_eq!(config.udp_address, "0.0.0.0:8081".parse::<SocketAddr>().unwrap());
+        assert_eq!(config.disconnect_wait, DisconnectWait::Deferred);
+        assert_eq!(config.connect_allowlist, vec!["192.168.0.20".parse::<std::net::IpAddr>().unwrap()]);
+        assert_eq!(config.sanity_ranges.range("speed"), Some(&(0.0..=44.0)));
+        assert_eq!(config.command_history_length, 1000);
+        assert_eq!(config.roboteq.channels, vec![1, 2]);
+        assert_eq!(config.roboteq.throttle_percent, 30);
+        #[cfg(unix)]
+        assert_eq!(config.can_read_timeout, Duration::from_millis(10000));
+        assert!(!config.dry_run);
+    }
+
+    #[test]
+    fn command_line_overrides_the_file() {
+        let path = std::env::temp_dir().join(format!("relay_config_test_{}.toml", std::process::id()));
+        std::fs::write(&path, "buffer-size = 512\nudp-port = 9100\n[can]\ndry-run = true\n").unwrap();
+        let config = Config::from_file(&path).unwrap();
+        assert_eq!((config.buffer_size, config.udp_port), (512, 9100));
+
+        let combined = with_file_args(&args(&["relay", "-b", "1024", "--config", path.to_str().unwrap()])).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let config = Config::from_args(&combined).unwrap();
+        assert_eq!((config.buffer_size, config.udp_port), (1024, 9100));
+        #[cfg(unix)]
+        assert!(config.dry_run);
+
+        assert!(matches!(parse("bufer-size = 512"), Err(ConfigError::InvalidConfigFile(_))));
+        assert!(matches!(with_file_args(&args(&["relay", "--config"])), Err(ConfigError::OddArgumentCount)));
+    }
+}
+
+/**
+ * The keys a config file may hold. Unknown keys are an error, so a misspelt setting is not silently ignored
+ */
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct ConfigFile {
+    tcp_address: Option<String>,
+    udp_address: Option<String>,
+    buffer_size: Option<usize>,
+    udp_port: Option<u16>,
+    tcp_read_timeout: Option<u64>,
+    udp_backoff_base: Option<u64>,
+    udp_backoff_cap: Option<u64>,
+    telemetry_rate: Option<u32>,
+    udp_read_timeout: Option<u64>,
+    udp_max_timeouts: Option<u32>,
+    disconnect_wait: Option<toml::Value>, // "immediate", "deferred" or a timeout in milliseconds
+    state_change_timeout: Option<u64>,
+    state_change_retries: Option<u32>,
+    base_port: Option<u16>,
+    udp_port_offset: Option<u16>,
+    allowlist: Option<Vec<String>>,
+    sanity_range: Option<BTreeMap<String, [f64; 2]>>, // field = [min, max]
+    out_of_range: Option<String>,
+    command_history: Option<usize>,
+    worker_channel_bound: Option<usize>,
+    worker_channel_policy: Option<String>,
+    telemetry_keepalive: Option<u64>,
+    #[cfg(feature = "websocket")]
+    websocket_address: Option<String>,
+    #[cfg(feature = "health")]
+    health_address: Option<String>,
+    roboteq: Option<RoboteqSection>,
+    #[cfg(unix)]
+    can: Option<CanSection>,
+    #[cfg(not(unix))]
+    #[allow(dead_code)]
+    can: Option<toml::Value> // Accepted so one file can be shared, but there is no can bus to configure
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct RoboteqSection {
+    node: Option<u32>,
+    channels: Option<Vec<u8>>,
+    throttle_percent: Option<u32>
+}
+
+#[cfg(unix)]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct CanSection {
+    interface: Option<String>,
+    read_timeout: Option<u64>,
+    max_timeouts: Option<u32>,
+    replay: Option<String>,
+    replay_speed: Option<f64>,
+    log: Option<String>,
+    dry_run: Option<bool>
+}
+
+impl Config<SocketAddr> {
+    /**
+     * @brief from_file
+     * Build a Config from a TOML file. Settings the file leaves out keep their defaults
+     */
+    pub fn from_file<P: AsRef<std::path::Path>>(path: P) -> Result<Config<SocketAddr>, ConfigError> {
+        let mut args = vec![String::from("relay")];
+        args.extend(read(path.as_ref())?);
+        Config::from_args(&args)
+    }
+}
+
+/**
+ * @brief with_file_args
+ * If the arguments hold -c | --config path, replace it with the flags the file sets. They are placed
+ * after the rest of the arguments, so that anything also given on the command line takes precedence
+ */
+pub fn with_file_args(args: &[String]) -> Result<Vec<String>, ConfigError> {
+    let index = match args.iter().skip(1).position(|arg| arg == "-c" || arg == "--config") {
+        Some(index) => index + 1,
+        None => return Ok(args.to_vec())
+    };
+    let path = args.get(index + 1).ok_or(ConfigError::OddArgumentCount)?;
+    let mut combined: Vec<String> = args.iter().enumerate()
+        .filter(|&(i, _)| i != index && i != index + 1)
+        .map(|(_, arg)| arg.clone())
+        .collect();
+    combined.extend(read(std::path::Path::new(path))?);
+    Ok(combined)
+}
+
+fn read(path: &std::path::Path) -> Result<Vec<String>, ConfigError> {
+    let contents = std::fs::read_to_string(path).map_err(|err| ConfigError::InvalidConfigFile(format!("{}: {}", path.display(), err)))?;
+    parse(&contents).map_err(|err| match err {
+        ConfigError::InvalidConfigFile(err) => ConfigError::InvalidConfigFile(format!("{}: {}", path.display(), err)),
+        err => err
+    })
+}
+
+/**
+ * @brief parse
+ * The flags set by the contents of a config file, as they would be given on the command line
+ */
+fn parse(contents: &str) -> Result<Vec<String>, ConfigError> {
+    let file: ConfigFile = toml::from_str(contents).map_err(|err| ConfigError::InvalidConfigFile(err.to_string()))?;
+    let mut args = Vec::new();
+    let mut set = |flag: &str, value: Option<String>| {
+        if let Some(value) = value {
+            args.push(String::from(flag));
+            args.push(value);
+        }
+    };
+    let join = |values: Vec<String>| values.join(",");
+
+    set("--tcp-address", file.tcp_address);
+    set("--udp-address", file.udp_address);
+    set("--buffer-size", file.buffer_size.map(|size| size.to_string()));
+    set("--udp-port", file.udp_port.map(|port| port.to_string()));
+    set("--tcp-read-timeout", file.tcp_read_timeout.map(|ms| ms.to_string()));
+    set("--udp-backoff-base", file.udp_backoff_base.map(|ms| ms.to_string()));
+    set("--udp-backoff-cap", file.udp_backoff_cap.map(|ms| ms.to_string()));
+    set("--telemetry-rate", file.telemetry_rate.map(|rate| rate.to_string()));
+    set("--udp-read-timeout", file.udp_read_timeout.map(|ms| ms.to_string()));
+    set("--udp-max-timeouts", file.udp_max_timeouts.map(|count| count.to_string()));
+    set("--disconnect-wait", file.disconnect_wait.map(|wait| match wait {
+        toml::Value::String(wait) => wait,
+        wait => wait.to_string()
+    }));
+    set("--state-change-timeout", file.state_change_timeout.map(|ms| ms.to_string()));
+    set("--state-change-retries", file.state_change_retries.map(|retries| retries.to_string()));
+    set("--base-port", file.base_port.map(|port| port.to_string()));
+    set("--udp-port-offset", file.udp_port_offset.map(|offset| offset.to_string()));
+    set("--allowlist", file.allowlist.map(join));
+    set("--sanity-range", file.sanity_range.map(|ranges| join(ranges.iter().map(|(field, [min, max])| format!("{}={}:{}", field, min, max)).collect())));
+    set("--out-of-range", file.out_of_range);
+    set("--command-history", file.command_history.map(|length| length.to_string()));
+    set("--worker-channel-bound", file.worker_channel_bound.map(|bound| bound.to_string()));
+    set("--worker-channel-policy", file.worker_channel_policy);
+    set("--telemetry-keepalive", file.telemetry_keepalive.map(|ms| ms.to_string()));
+    #[cfg(feature = "websocket")]
+    set("--websocket-address", file.websocket_address);
+    #[cfg(feature = "health")]
+    set("--health-address", file.health_address);
+    if let Some(roboteq) = file.roboteq {
+        set("--roboteq-node", roboteq.node.map(|node| node.to_string()));
+        set("--roboteq-channels", roboteq.channels.map(|channels| join(channels.iter().map(|channel| channel.to_string()).collect())));
+        set("--throttle-percent", roboteq.throttle_percent.map(|percent| percent.to_string()));
+    }
+    #[cfg(unix)]
+    if let Some(can) = file.can {
+        set("--can-interface", can.interface);
+        set("--can-read-timeout", can.read_timeout.map(|ms| ms.to_string()));
+        set("--can-max-timeouts", can.max_timeouts.map(|count| count.to_string()));
+        set("--can-replay", can.replay);
+        set("--can-replay-speed", can.replay_speed.map(|speed| speed.to_string()));
+        set("--can-log", can.log);
+        match can.dry_run {
+            Some(true) => args.push(String::from("--dry-run")),
+            Some(false) => args.push(String::from("--no-dry-run")),
+            None => {}
+        }
+    }
+    Ok(args)
+}
@@ -1,13 +1,107 @@
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::error::Error as StdError;
+
+    #[test]
+    fn socket_errors_keep_their_source() {
+        let error = Error::TcpSocketError(std::io::Error::from(std::io::ErrorKind::ConnectionReset));
+        assert!(error.to_string().starts_with("tcp socket error: "));
+        assert_eq!(error.source().unwrap().to_string(), std::io::Error::from(std::io::ErrorKind::ConnectionReset).to_string());
+        assert!(Error::UnableToHandleTcpMessage.source().is_none());
+    }
+
+    #[test]
+    fn io_errors_are_tagged_with_their_socket() {
+        let result: std::io::Result<()> = Err(std::io::Error::from(std::io::ErrorKind::TimedOut));
+        assert!(matches!(result.udp_socket(), Err(Error::UdpSocketError(_))));
+    }
+
+    #[test]
+    fn can_errors_convert() {
+        fn send() -> Result<(), Error> {
+            Err(crate::can_extentions::prelude::CanError::UnencodableCommand)?
+        }
+        assert!(matches!(send(), Err(Error::CanSocketError(_))));
+    }
+}
+
+/**
+ * @brief Error
+ * The error type shared by the tcp, udp and can threads. Errors which only one protocol can produce
+ * are still variants here rather than a second enum, so the threads never have to convert between them
+ */
 #[derive(Debug)]
 pub enum Error {
     InvalidState(&'static str),
     TcpSocketError(std::io::Error),
     UdpSocketError(std::io::Error),
-    #[cfg(unix)]
     CanSocketError(crate::can_extentions::prelude::CanError),
     InvalidAddr(std::io::Error),
     UninitializedUdpSocket,
     UninitializedCanSocket,
     AddrParseError,
     UnableToHandleTcpMessage,
-}
\ No newline at end of file
+    ThreadUnavailable(&'static str), // The named relay thread has exited, so a message to it could not be sent
+    UnauthorizedPeer(std::net::IpAddr), // A peer which is not on the allowlist made a restricted request, e.g. CONNECT
+    InMaintenance, // CONNECT was refused because the relay is in maintenance
+    StartingUp, // CONNECT was refused because the udp thread has not finished startup
+    UnknownTelemetryField, // CONNECT asked for a telemetry field which PodData does not have
+}
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::InvalidState(reason) => write!(f, "invalid state: {}", reason),
+            Error::TcpSocketError(error) => write!(f, "tcp socket error: {}", error),
+            Error::UdpSocketError(error) => write!(f, "udp socket error: {}", error),
+            Error::CanSocketError(error) => write!(f, "can socket error: {}", error),
+            Error::InvalidAddr(error) => write!(f, "invalid address: {}", error),
+            Error::UninitializedUdpSocket => write!(f, "the udp socket has not been initialized"),
+            Error::UninitializedCanSocket => write!(f, "the can socket has not been initialized"),
+            Error::AddrParseError => write!(f, "unable to parse address"),
+            Error::UnableToHandleTcpMessage => write!(f, "unable to handle tcp message"),
+            Error::ThreadUnavailable(thread) => write!(f, "the {} thread is not running", thread),
+            Error::UnauthorizedPeer(peer) => write!(f, "{} is not on the allowlist", peer),
+            Error::InMaintenance => write!(f, "the relay is in maintenance"),
+            Error::StartingUp => write!(f, "the relay is starting up"),
+            Error::UnknownTelemetryField => write!(f, "unknown telemetry field"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::TcpSocketError(error)
+            | Error::UdpSocketError(error)
+            | Error::InvalidAddr(error) => Some(error),
+            Error::CanSocketError(error) => Some(error),
+            _ => None
+        }
+    }
+}
+
+impl From<crate::can_extentions::prelude::CanError> for Error {
+    fn from(error: crate::can_extentions::prelude::CanError) -> Error {
+        Error::CanSocketError(error)
+    }
+}
+
+/**
+ * An io::Error can come from either the tcp or udp socket, so there is no From<io::Error>.
+ * Instead the result is tagged with the socket it came from, which keeps ? short without losing that
+ */
+pub trait SocketContext<T> {
+    fn tcp_socket(self) -> Result<T, Error>;
+    fn udp_socket(self) -> Result<T, Error>;
+}
+
+impl<T> SocketContext<T> for std::io::Result<T> {
+    fn tcp_socket(self) -> Result<T, Error> {
+        self.map_err(Error::TcpSocketError)
+    }
+
+    fn udp_socket(self) -> Result<T, Error> {
+        self.map_err(Error::UdpSocketError)
+    }
+}
@@ -6,13 +6,13 @@
  */
 
 pub mod run_threads;
-#[cfg(unix)]
 pub mod can_extentions;
 
 pub mod utils;
 pub use utils::stream_utils;
 pub use utils::requests;
 pub use utils::device_watchdog;
+pub use utils::metrics;
 pub mod project_butterfree;
 pub mod pod_states;
 pub mod board_states;
@@ -20,3 +20,6 @@ pub mod pod_data;
 pub mod thread_managers;
 pub mod error;
 pub mod config;
+pub mod config_env;
+#[cfg(feature = "config-file")]
+pub mod config_file;
@@ -1,17 +1,30 @@
 use std::env;
 
 fn main() -> Result<(), std::io::Error> {
+    // Defaults to info so the relay is as chatty as it was with println!, RUST_LOG overrides it
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
     let args: Vec<String> = env::args().collect();
 
     // if cfg!(feature = "socketcan") {
-    let config;
-    if args.len() > 1 {
-        config = relay::config::Config::from_args(&args);
-    } else {
-        config = relay::config::Config::default();
-    }
+    // Parsed even without arguments, as settings may come from the environment
+    let config = match relay::config::Config::parse_args(&args) {
+        Ok(relay::config::ParseOutcome::Config(config)) => config,
+        Ok(relay::config::ParseOutcome::HelpRequested) => {
+            println!("{}", relay::config::USAGE);
+            std::process::exit(0);
+        },
+        Err(err) => {
+            eprintln!("Invalid arguments: {:?}", err);
+            eprintln!("{}", relay::config::USAGE);
+            std::process::exit(1);
+        }
+    };
     // }
     // let mut server = relay::tcp_server::Server::new(config);
-    relay::run_threads::run_threads(config).expect("Shutting down");
+    let handles = relay::run_threads::run_threads(config).expect("Shutting down");
+    if !handles.supervise() {
+        // A thread died. Exit with an error so whatever runs the relay can restart it
+        std::process::exit(1);
+    }
     Ok(())
 }
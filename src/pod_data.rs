@@ -1,10 +1,143 @@
 use json::{ JsonValue, object, array }; // TODO Reimplement with serde json
+use chrono::NaiveDateTime;
+use serde::{ Serialize, Deserialize };
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rail_currents_are_forwarded() {
+        let mut pod_data = PodData::new();
+        pod_data.current_5v = Some(1.5);
+        pod_data.current_12v = Some(2.5);
+        pod_data.current_24v = Some(3.5);
+
+        assert!(pod_data.ok());
+        let jv: JsonValue = pod_data.into();
+        assert_eq!(jv["current_5v"].as_f32(), Some(1.5));
+        assert_eq!(jv["current_12v"].as_f32(), Some(2.5));
+        assert_eq!(jv["current_24v"].as_f32(), Some(3.5));
+    }
+
+    #[test]
+    fn motor_controller_data_is_forwarded() {
+        let mut pod_data = PodData::new();
+        pod_data.mc_pod_speed = Some(12.0);
+        pod_data.motor_current = Some(80.0);
+        pod_data.battery_current = Some(60.0);
+        pod_data.battery_voltage = Some(46.0);
+
+        assert!(pod_data.ok());
+        let jv: JsonValue = pod_data.clone().into();
+        assert_eq!(jv["mc_pod_speed"].as_f32(), Some(12.0));
+        assert_eq!(jv["motor_current"].as_f32(), Some(80.0));
+        assert_eq!(jv["battery_current"].as_f32(), Some(60.0));
+        assert_eq!(jv["battery_voltage"].as_f32(), Some(46.0));
+
+        pod_data.mc_pod_speed = Some(50.0);
+        assert!(!pod_data.ok());
+    }
+
+    #[test]
+    fn wire_format_has_every_field() {
+        let mut pod_data = PodData::new();
+        pod_data.speed = Some(4.5);
+        pod_data.torchic_1 = [Some(20.0), None];
+        let timestamp = NaiveDateTime::from_timestamp(1_600_000_000, 250_000_000);
+
+        let jv = json::parse(&pod_data.to_json(&timestamp)).unwrap();
+        assert_eq!(jv["timestamp"].as_i64(), Some(1_600_000_000_250));
+        assert_eq!(jv["speed"].as_f32(), Some(4.5));
+        assert_eq!(jv["torchic_1"][0].as_f32(), Some(20.0));
+        assert!(jv["torchic_1"][1].is_null());
+        assert!(jv["pressure_high"].is_null());
+        // Every field is present, even if it has not been received
+        let expected: JsonValue = pod_data.into();
+        for (key, _) in expected.entries() {
+            assert!(jv.has_key(key), "missing {}", key);
+        }
+    }
+
+    #[test]
+    fn telemetry_is_cut_down_to_the_selected_fields() {
+        assert_eq!(TelemetryFields::parse("speed,warp_factor"), Err(UnknownTelemetryField(String::from("warp_factor"))));
+
+        let mut pod_data = PodData::new();
+        pod_data.speed = Some(4.5);
+        pod_data.pressure_low_1 = Some(100.0);
+        let time = NaiveDateTime::from_timestamp(1_600_000_000, 0);
+        pod_data.mark_updated("speed", &time);
+        pod_data.mark_updated("pressure_low_1", &time);
+        let mut jv: JsonValue = pod_data.into();
+        TelemetryFields::parse("speed,pressure_high").unwrap().retain(&mut jv);
+        assert_eq!(jv["speed"].as_f32(), Some(4.5));
+        assert!(jv.has_key("pressure_high"));
+        assert!(!jv.has_key("pressure_low_1"));
+        assert!(jv["updated"].has_key("speed"));
+        assert!(!jv["updated"].has_key("pressure_low_1"));
+
+        // Every field is sent unless some are asked for
+        let expected: JsonValue = PodData::new().into();
+        assert_eq!(TELEMETRY_FIELDS.len(), expected.len() - 1); // All but updated
+        assert!(TELEMETRY_FIELDS.iter().all(|field| TelemetryFields::default().contains(field) && expected.has_key(field)));
+    }
+
+    #[test]
+    fn wire_bytes_round_trip() {
+        let mut pod_data = PodData::new();
+        pod_data.speed = Some(4.5);
+        pod_data.current_12v = Some(1.25);
+        pod_data.torchic_2 = [None, Some(30.0)];
+        pod_data.roboteq_motor_1_battery_amps = Some(-12);
+        let timestamp = NaiveDateTime::from_timestamp(1_600_000_000, 250_000_000);
+
+        let bytes = pod_data.to_wire_bytes(&timestamp);
+        assert_eq!(bytes[0], TELEMETRY_SCHEMA_VERSION);
+        let (decoded, decoded_timestamp) = PodData::from_wire_bytes(&bytes).unwrap();
+        assert_eq!(decoded_timestamp, timestamp);
+        assert_eq!(decoded.speed, Some(4.5));
+        assert_eq!(decoded.current_12v, Some(1.25));
+        assert_eq!(decoded.torchic_2, [None, Some(30.0)]);
+        assert_eq!(decoded.roboteq_motor_1_battery_amps, Some(-12));
+        assert_eq!(decoded.pressure_high, None);
+    }
+
+    #[test]
+    fn wire_bytes_version_is_checked() {
+        assert!(matches!(PodData::from_wire_bytes(&[]), Err(WireFormatError::Empty)));
+        let mut bytes = PodData::new().to_wire_bytes(&NaiveDateTime::from_timestamp(0, 0));
+        bytes[0] = TELEMETRY_SCHEMA_VERSION + 1;
+        assert!(matches!(PodData::from_wire_bytes(&bytes), Err(WireFormatError::UnsupportedVersion(v)) if v == TELEMETRY_SCHEMA_VERSION + 1));
+    }
+
+    #[test]
+    fn fields_go_stale_once_they_stop_updating() {
+        let mut pod_data = PodData::new();
+        let updated = NaiveDateTime::from_timestamp(1_600_000_000, 0);
+        pod_data.pressure_high = Some(2.3);
+        pod_data.mark_updated("pressure_high", &updated);
+
+        let max_age = Duration::from_millis(500);
+        assert!(!pod_data.is_stale("pressure_high", &(updated + chrono::Duration::milliseconds(500)), max_age));
+        assert!(pod_data.is_stale("pressure_high", &(updated + chrono::Duration::milliseconds(501)), max_age));
+        // A field which has never been received has nothing current to show
+        assert!(pod_data.is_stale("pressure_low_1", &updated, max_age));
+
+        // The controller gets the same answer from the telemetry it receives
+        let (decoded, _) = PodData::from_wire_bytes(&pod_data.to_wire_bytes(&updated)).unwrap();
+        assert_eq!(decoded.last_updated("pressure_high"), Some(updated));
+        assert_eq!(decoded.last_updated("pressure_low_1"), None);
+    }
+}
 type Float2 = [Option<f32>; 2];
 type Float1 = Option<f32>;
 
 // All Pod data will be optional. None values will be converted to null in the JSON that is sent to the
 // Desktop
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct PodData {
     pub battery_pack_current: Float1,
     pub average_cell_temperature: Float1,
@@ -35,8 +168,90 @@ pub struct PodData {
     pub roboteq_mcu_temp: Option<i8>,
     pub roboteq_sensor_1_temp: Option<i8>,
     pub roboteq_sensor_2_temp: Option<i8>,
+    #[serde(default)]
+    pub updated: FieldTimestamps, // When each field was last received. Fields which have never been received are absent
+
+
+}
+
+/**
+ * The last time each PodData field was received, in milliseconds since the unix epoch, keyed by the
+ * field's name in the telemetry. Values in PodData are kept until they are replaced, so this is
+ * what tells a live reading apart from one whose sensor has stopped reporting
+ */
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct FieldTimestamps(BTreeMap<String, i64>);
 
+/**
+ * Names of the PodData fields as they appear in the telemetry
+ */
+pub const TELEMETRY_FIELDS: [&str; 29] = [
+    "battery_pack_current", "average_cell_temperature", "igbt_temp", "motor_voltage", "battery_pack_voltage",
+    "state_of_charge", "buck_temperature", "bms_current", "link_cap_voltage", "mc_pod_speed", "motor_current",
+    "battery_current", "battery_voltage", "speed", "current_5v", "current_12v", "current_24v", "torchic_1",
+    "torchic_2", "pressure_high", "pressure_low_1", "pressure_low_2", "roboteq_motor_1_speed", "roboteq_motor_2_speed",
+    "roboteq_motor_1_battery_amps", "roboteq_motor_2_battery_amps", "roboteq_mcu_temp", "roboteq_sensor_1_temp",
+    "roboteq_sensor_2_temp"
+];
 
+/**
+ * The PodData fields a controller wants sent, so one on a slow link can leave out what it doesn't use.
+ * Bit n is set if TELEMETRY_FIELDS[n] is sent
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TelemetryFields(u32);
+
+/**
+ * A field name given to TelemetryFields::parse which PodData does not have
+ */
+#[derive(Debug, PartialEq)]
+pub struct UnknownTelemetryField(pub String);
+
+/**
+ * Leading byte of the telemetry wire format. Bump this whenever a field is added to, removed from,
+ * or changes meaning in PodData so that controllers can detect a mismatch instead of breaking silently
+ *
+ * Version 1: timestamp and every field of PodData, including the rail currents (current_5v/12v/24v),
+ * the motor controller data (mc_pod_speed, motor_current, battery_current, battery_voltage),
+ * speed and the roboteq readings
+ * Version 2: adds updated, an object with the time each field was last received in milliseconds since the unix epoch
+ */
+pub const TELEMETRY_SCHEMA_VERSION: u8 = 2;
+
+/**
+ * Errors produced while decoding telemetry from the wire format
+ */
+#[derive(Debug)]
+pub enum WireFormatError {
+    Empty,
+    UnsupportedVersion(u8),
+    InvalidJson(serde_json::Error),
+}
+
+/**
+ * Telemetry as it is written out by the relay, the readings plus the time they were taken
+ * in milliseconds since the unix epoch
+ */
+#[derive(Serialize, Deserialize)]
+struct TimestampedPodData<D> {
+    timestamp: i64,
+    #[serde(flatten)]
+    data: D
+}
+
+/**
+ * Inverse of NaiveDateTime::timestamp_millis, the unit times are written in
+ */
+fn from_millis(millis: i64) -> NaiveDateTime {
+    NaiveDateTime::from_timestamp(millis.div_euclid(1000), (millis.rem_euclid(1000) * 1_000_000) as u32)
+}
+
+impl std::fmt::Display for PodData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let json = serde_json::to_string(self).map_err(|_| std::fmt::Error)?;
+        write!(f, "{}", json)
+    }
 }
 
 trait JsonHelper {
@@ -49,6 +264,16 @@ impl JsonHelper for Float2 {
     }
 }
 
+impl JsonHelper for FieldTimestamps {
+    fn to_json(&self) -> JsonValue {
+        let mut updated = JsonValue::new_object();
+        for (field, &millis) in &self.0 {
+            updated[field.as_str()] = millis.into();
+        }
+        updated
+    }
+}
+
 impl Into<JsonValue> for PodData {
     fn into(self) -> JsonValue {
         object!{
@@ -81,6 +306,7 @@ impl Into<JsonValue> for PodData {
             roboteq_mcu_temp: self.roboteq_mcu_temp,
             roboteq_sensor_1_temp: self.roboteq_sensor_1_temp,
             roboteq_sensor_2_temp: self.roboteq_sensor_2_temp,
+            updated: self.updated.to_json(),
         }
     }
 }
@@ -167,7 +393,79 @@ impl From<JsonValue> for PodData {
     }
 }
 
+impl Default for TelemetryFields {
+    fn default() -> TelemetryFields {
+        TelemetryFields::all()
+    }
+}
+
+impl TelemetryFields {
+    pub fn all() -> TelemetryFields {
+        TelemetryFields((1 << TELEMETRY_FIELDS.len()) - 1)
+    }
+
+    /**
+     * @brief parse
+     * Select the fields in a comma separated list of names, e.g. speed,pressure_high
+     */
+    pub fn parse(list: &str) -> Result<TelemetryFields, UnknownTelemetryField> {
+        let mut fields = 0;
+        for name in list.split(',') {
+            let index = TELEMETRY_FIELDS.iter().position(|&field| field == name)
+                .ok_or_else(|| UnknownTelemetryField(name.to_string()))?;
+            fields |= 1 << index;
+        }
+        Ok(TelemetryFields(fields))
+    }
+
+    pub fn contains(&self, field: &str) -> bool {
+        matches!(TELEMETRY_FIELDS.iter().position(|&name| name == field), Some(index) if self.0 & (1 << index) != 0)
+    }
+
+    /**
+     * @brief retain
+     * Remove each field which is not selected from telemetry, along with the time it was updated
+     */
+    pub fn retain(&self, telemetry: &mut JsonValue) {
+        for field in TELEMETRY_FIELDS.iter().filter(|&&field| !self.contains(field)) {
+            telemetry.remove(field);
+            telemetry["updated"].remove(field);
+        }
+    }
+}
+
 impl PodData {
+    /**
+     * @brief to_json
+     * The telemetry with its timestamp as a json object. None values are written as null
+     */
+    pub fn to_json(&self, timestamp: &NaiveDateTime) -> String {
+        serde_json::to_string(&TimestampedPodData{ timestamp: timestamp.timestamp_millis(), data: self }).expect("PodData should always serialize")
+    }
+
+    /**
+     * @brief to_wire_bytes
+     * TELEMETRY_SCHEMA_VERSION followed by to_json, ready to be written to a socket
+     */
+    pub fn to_wire_bytes(&self, timestamp: &NaiveDateTime) -> Vec<u8> {
+        let mut bytes = vec![TELEMETRY_SCHEMA_VERSION];
+        bytes.extend_from_slice(self.to_json(timestamp).as_bytes());
+        bytes
+    }
+
+    /**
+     * @brief from_wire_bytes
+     * The inverse of to_wire_bytes. Telemetry from any other schema version is rejected
+     */
+    pub fn from_wire_bytes(bytes: &[u8]) -> Result<(PodData, NaiveDateTime), WireFormatError> {
+        let (&version, json) = bytes.split_first().ok_or(WireFormatError::Empty)?;
+        if version != TELEMETRY_SCHEMA_VERSION {
+            return Err(WireFormatError::UnsupportedVersion(version));
+        }
+        let decoded: TimestampedPodData<PodData> = serde_json::from_slice(json).map_err(|e| WireFormatError::InvalidJson(e))?;
+        Ok((decoded.data, from_millis(decoded.timestamp)))
+    }
+
     pub fn new() -> PodData {
         PodData {
             battery_pack_current: None,
@@ -199,6 +497,35 @@ impl PodData {
             roboteq_mcu_temp: None,
             roboteq_sensor_1_temp: None,
             roboteq_sensor_2_temp: None,
+            updated: FieldTimestamps::default(),
+        }
+    }
+
+    /**
+     * @brief mark_updated
+     * Record that field was received at time. field is the name of the field as it appears in the telemetry
+     */
+    pub fn mark_updated(&mut self, field: &str, time: &NaiveDateTime) {
+        self.updated.0.insert(field.to_string(), time.timestamp_millis());
+    }
+
+    /**
+     * @brief last_updated
+     * When field was last received, or None if it never has been
+     */
+    pub fn last_updated(&self, field: &str) -> Option<NaiveDateTime> {
+        self.updated.0.get(field).map(|&millis| from_millis(millis))
+    }
+
+    /**
+     * @brief is_stale
+     * True if field has not been received within max_age of now, including if it has never been received.
+     * A stale value must not be shown as a current reading
+     */
+    pub fn is_stale(&self, field: &str, now: &NaiveDateTime, max_age: Duration) -> bool {
+        match self.updated.0.get(field) {
+            Some(&millis) => now.timestamp_millis() - millis > max_age.as_millis() as i64,
+            None => true
         }
     }
 
@@ -215,15 +542,11 @@ impl PodData {
     &&  (self.state_of_charge.is_none() ||  { self.state_of_charge.unwrap() > 10.0 })
     &&  (self.buck_temperature.is_none() || true) // We will be using an off the shelf buck because Elekid does not provide enough current. It will monitor the temp itself.__rust_force_expr!
     &&  (self.bms_current.is_none() ||  {self.bms_current.unwrap() < 0.05 }) // 50 miliamps
-    &&  (self.link_cap_voltage.is_none()) // !! NO MC RIGHT NOW!!!
-    &&  (self.mc_pod_speed.is_none()) // !! NO MC RIGHT NOW !!
-    &&  (self.motor_current.is_none()) // !! NO MC RIGHT NOW
-    &&  (self.battery_current.is_none()) // !! NO MC RIGHT NOW
-    &&  (self.battery_voltage.is_none()) // !! NO MC RIGHT NOW
+    // link_cap_voltage is forwarded as telemetry. TODO add limits once the MC ratings are known, it sits at zero until the link cap precharges
+    &&  (self.mc_pod_speed.is_none() ||  { self.mc_pod_speed.unwrap() >= -1.0 && self.mc_pod_speed.unwrap() < 44.0}) // Same limits as the pod speed
+    // motor_current, battery_current and battery_voltage are forwarded as telemetry. TODO add limits once the MC ratings are known
     &&  (self.speed.is_none() ||  { self.speed.unwrap() >= -1.0 && self.speed.unwrap() < 44.0})
-    &&  (self.current_5v.is_none()) // OFF THE SHELF BUCK. IF WE NEED TO BE CHECKING THIS, IT WILL BE UPDATED
-    &&  (self.current_12v.is_none()) // OFF THE SHELF BUCK. IF WE NEED TO BE CHECKING THIS, IT WILL BE UPDATED
-    &&  (self.current_24v.is_none()) // OFF THE SHELF BUCK. IF WE NEED TO BE CHECKING THIS, IT WILL BE UPDATED
+    // current_5v, current_12v and current_24v: OFF THE SHELF BUCK. Rail currents are forwarded as telemetry but not checked. IF WE NEED TO BE CHECKING THIS, IT WILL BE UPDATED
     &&  (self.torchic_1[0].is_none() ||  { self.torchic_1[0].unwrap() < 100.0})
     &&  (self.torchic_1[1].is_none() ||  { self.torchic_1[1].unwrap() < 100.0})
     &&  (self.torchic_2[0].is_none() ||  { self.torchic_2[0].unwrap() < 100.0})
@@ -1,11 +1,12 @@
 use std::collections::HashMap;
+use serde::{ Serialize, Deserialize };
 
-#[derive(PartialEq, Hash, Eq, Debug, Copy, Clone)]
+#[derive(PartialEq, Hash, Eq, Debug, Copy, Clone, Serialize, Deserialize)]
 pub enum PodState {
     LowVoltage,
     Armed,
     AutoPilot,
-    Braking,
+    Braking, // Throttle is commanded to zero and the pod decelerates, it is not safe until it reaches LowVoltage
     EmergencyBrake,
     SystemFailure,
     Resting,
@@ -22,11 +23,35 @@ mod test {
     use super::{ PodState, HashMap };
     #[test]
     fn test_states() {
-        for i in 0u8..0x0Bu8 {
-            assert_eq!(PodState::from_byte(i).to_byte(), i);
+        for i in 0u8..=0x0Bu8 {
+            assert_eq!(PodState::from_byte(i).unwrap().to_byte(), i);
         }
     }
 
+    #[test]
+    fn test_state_byte_round_trip() {
+        let all_states = [
+            PodState::Resting,
+            PodState::LowVoltage,
+            PodState::Armed,
+            PodState::AutoPilot,
+            PodState::Braking,
+            PodState::EmergencyBrake,
+            PodState::SystemFailure,
+            PodState::ManualOperationWaiting,
+            PodState::Accelerating,
+            PodState::AtSpeed,
+            PodState::Decelerating,
+            PodState::Invalid
+        ];
+        for state in all_states {
+            assert_eq!(PodState::from_byte(state.to_byte()), Some(state));
+        }
+        assert_eq!(PodState::from_byte(0x0C), None);
+        assert_eq!(PodState::from_byte(0xFF), None);
+        assert_eq!(PodState::from(0xFF), PodState::Invalid);
+    }
+
     #[test]
     fn test_transitions() {
         let all_states = vec![
@@ -49,9 +74,9 @@ mod test {
         valid_transitions.insert(PodState::Resting, vec![PodState::LowVoltage]);
         valid_transitions.insert(PodState::LowVoltage, vec![PodState::Resting, PodState::Armed]);
         valid_transitions.insert(PodState::Armed, vec![PodState::LowVoltage, PodState::AutoPilot, PodState::EmergencyBrake]);
-        valid_transitions.insert(PodState::AutoPilot, vec![PodState::Braking, PodState::EmergencyBrake]);
+        valid_transitions.insert(PodState::AutoPilot, vec![PodState::Braking, PodState::EmergencyBrake, PodState::LowVoltage]);
         valid_transitions.insert(PodState::Braking, vec![PodState::LowVoltage]);
-        valid_transitions.insert(PodState::EmergencyBrake, vec![PodState::SystemFailure]);
+        valid_transitions.insert(PodState::EmergencyBrake, vec![PodState::SystemFailure, PodState::LowVoltage]);
         valid_transitions.insert(PodState::SystemFailure, vec![]);
 
         for state in all_states {
@@ -64,6 +89,38 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn test_illegal_transitions() {
+        assert!(!PodState::LowVoltage.can_transition_to(&PodState::AutoPilot));
+        assert!(!PodState::Resting.can_transition_to(&PodState::Armed));
+        assert!(!PodState::AutoPilot.can_transition_to(&PodState::Armed));
+        assert!(!PodState::SystemFailure.can_transition_to(&PodState::LowVoltage));
+        assert!(!PodState::Invalid.can_transition_to(&PodState::LowVoltage));
+        // Any state can fail
+        assert!(PodState::Invalid.can_transition_to(&PodState::SystemFailure));
+    }
+
+    #[test]
+    fn test_emergency_transitions() {
+        // A moving or emergency braking pod can always be commanded into the safe state
+        assert!(PodState::AutoPilot.can_transition_to(&PodState::LowVoltage));
+        assert!(PodState::EmergencyBrake.can_transition_to(&PodState::LowVoltage));
+        // but the emergency edges do not lead anywhere else
+        assert!(!PodState::EmergencyBrake.can_transition_to(&PodState::Armed));
+        assert!(!PodState::AutoPilot.can_transition_to(&PodState::Resting));
+        assert!(!PodState::ManualOperationWaiting.can_transition_to(&PodState::LowVoltage));
+    }
+
+    #[test]
+    fn test_state_serde_round_trip() {
+        for i in 0u8..=0x0Bu8 {
+            let state = PodState::from_byte(i).unwrap();
+            let json = serde_json::to_string(&state).unwrap();
+            assert_eq!(serde_json::from_str::<PodState>(&json).unwrap(), state);
+        }
+        assert_eq!(serde_json::to_string(&PodState::AutoPilot).unwrap(), "\"AutoPilot\"");
+    }
 }
 
 /**
@@ -88,34 +145,50 @@ impl PodState {
         }
     }
 
-    pub fn from_byte(byte: u8) -> Self {
+    /**
+     * @brief from_byte
+     * The inverse of to_byte. Bytes which are not assigned to a state give None
+     */
+    pub fn from_byte(byte: u8) -> Option<Self> {
         match byte {
-            0x00 => PodState::Resting,
-            0x01 => PodState::LowVoltage,
-            0x02 => PodState::Armed,
-            0x03 => PodState::AutoPilot,
-            0x04 => PodState::Braking,
-            0x05 => PodState::EmergencyBrake,
-            0x06 => PodState::SystemFailure,
-            0x07 => PodState::ManualOperationWaiting,
-            0x08 => PodState::Accelerating,
-            0x09 => PodState::AtSpeed,
-            0x0A => PodState::Decelerating,
-            _ => PodState::Invalid
+            0x00 => Some(PodState::Resting),
+            0x01 => Some(PodState::LowVoltage),
+            0x02 => Some(PodState::Armed),
+            0x03 => Some(PodState::AutoPilot),
+            0x04 => Some(PodState::Braking),
+            0x05 => Some(PodState::EmergencyBrake),
+            0x06 => Some(PodState::SystemFailure),
+            0x07 => Some(PodState::ManualOperationWaiting),
+            0x08 => Some(PodState::Accelerating),
+            0x09 => Some(PodState::AtSpeed),
+            0x0A => Some(PodState::Decelerating),
+            0x0B => Some(PodState::Invalid),
+            _ => None
         }
     }
 
     /**
      * @brief validates state transitions
+     * The legal edges of the state graph are:
+     *   Resting -> LowVoltage
+     *   LowVoltage -> Resting | Armed
+     *   Armed -> LowVoltage | AutoPilot | EmergencyBrake
+     *   AutoPilot -> Braking | EmergencyBrake | LowVoltage
+     *   Braking -> LowVoltage
+     *   EmergencyBrake -> SystemFailure | LowVoltage
+     *   any state -> SystemFailure
+     * AutoPilot -> LowVoltage and EmergencyBrake -> LowVoltage are emergency edges, so that a DISCONNECT
+     * or a relay thread dying can always command a moving pod into the safe state
+     * The manual operation states have no edges until manual controls are implemented
      */
     pub fn can_transition_to(&self, new_state: &PodState) -> bool {
         (match self {
             PodState::Resting => matches!(new_state, PodState::LowVoltage),
             PodState::LowVoltage =>  matches!(new_state, PodState::Resting | PodState::Armed),
             PodState::Armed => matches!(new_state, PodState::LowVoltage | PodState::AutoPilot | PodState::EmergencyBrake),
-            PodState::AutoPilot => matches!(new_state, PodState::Braking | PodState::EmergencyBrake),
+            PodState::AutoPilot => matches!(new_state, PodState::Braking | PodState::EmergencyBrake | PodState::LowVoltage),
             PodState::Braking => matches!(new_state, PodState::LowVoltage),
-            PodState::EmergencyBrake => matches!(new_state, PodState::SystemFailure),
+            PodState::EmergencyBrake => matches!(new_state, PodState::SystemFailure | PodState::LowVoltage),
             PodState::SystemFailure => false,
             PodState::ManualOperationWaiting => false, // TODO Implement manual controls
             PodState::Accelerating => false,
@@ -131,8 +204,11 @@ impl PodState {
 }
 
 impl From<u8> for PodState {
+    /**
+     * Unknown bytes become PodState::Invalid, which can not be transitioned to
+     */
     fn from(byte: u8) -> PodState {
-        return PodState::from_byte(byte);
+        return PodState::from_byte(byte).unwrap_or(PodState::Invalid);
     }
 }
 
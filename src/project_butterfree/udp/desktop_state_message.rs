@@ -34,7 +34,7 @@ impl DesktopStateMessage {
                         let requested_state_byte = (requested_state & 0xff) as u8;
                         if let Some(timestamp) = timestamp.as_fixed_point_i64(0) {
                             return Ok(DesktopStateMessage {
-                                requested_state: PodState::from_byte(requested_state_byte),
+                                requested_state: PodState::from(requested_state_byte),
                                 most_recent_timestamp: NaiveDateTime::from_timestamp(timestamp, 0)
                             });
                         }
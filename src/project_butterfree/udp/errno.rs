@@ -4,7 +4,10 @@ pub enum UdpErrno {
     InvalidTransitionRequest,
     ArmingFault,
     ControllerTimeout,
-    GeneralPodFailure
+    GeneralPodFailure,
+    TransitionRejected, // A board refused the requested state
+    StateChangeTimeout, // The boards never acknowledged the requested state
+    CanBusFault // The can bus degraded, the relay may not be hearing from or commanding the pod
 }
 
 impl UdpErrno {
@@ -14,7 +17,10 @@ impl UdpErrno {
             UdpErrno::InvalidTransitionRequest => 0x1,
             UdpErrno::ArmingFault              => 0x2,
             UdpErrno::ControllerTimeout        => 0x3,
-            UdpErrno::GeneralPodFailure        => 0x4
+            UdpErrno::GeneralPodFailure        => 0x4,
+            UdpErrno::TransitionRejected       => 0x5,
+            UdpErrno::StateChangeTimeout       => 0x6,
+            UdpErrno::CanBusFault              => 0x7
         }
     }
 }
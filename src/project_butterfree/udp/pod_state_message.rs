@@ -3,7 +3,8 @@ use json::{
     object
 };
 use crate:: {
-    pod_data::PodData,
+    can_extentions::fault_reports::FaultReport,
+    pod_data::{ PodData, TelemetryFields, TELEMETRY_SCHEMA_VERSION },
     pod_states::PodState
 };
 use super::{
@@ -16,27 +17,40 @@ pub struct PodStateMessage {
     errno: UdpErrno,
     telemetry: Option<PodData>,
     telemetry_timestamp: NaiveDateTime,
-    recovering: bool
+    telemetry_sequence: Option<u64>, // Sequence number of the telemetry snapshot, sent only with telemetry
+    telemetry_keepalive: bool, // The telemetry is the last snapshot sent again because nothing has changed, not fresh data
+    recovering: bool,
+    faults: Vec<FaultReport>, // Faults the boards reported since the last message
+    fields: TelemetryFields // The telemetry fields the controller asked for when it connected
 }
 
 impl PodStateMessage {
     pub fn to_json_bytes(&self) -> Vec<u8> {
-        let telemetry: json::JsonValue = match self.telemetry {
-            Some(data) => data.into(),
+        let telemetry: json::JsonValue = match &self.telemetry {
+            Some(data) => {
+                let mut telemetry = data.clone().into();
+                self.fields.retain(&mut telemetry);
+                telemetry
+            },
             _ => json::JsonValue::Null
         };
+        let faults = serde_json::to_string(&self.faults).expect("Fault reports should always serialize");
         let json_data = object!{
             current_state: self.current_state.to_byte(),
             pending_next_state: self.pending_next_state.to_byte(),
             errno: self.errno.to_byte(),
+            telemetry_schema_version: TELEMETRY_SCHEMA_VERSION, // Lets the controller reject telemetry it doesn't know how to read
             telemetry: telemetry,
             telemetry_timestamp: self.telemetry_timestamp.timestamp(),
-            recovering: self.recovering
+            telemetry_sequence: self.telemetry_sequence,
+            telemetry_keepalive: self.telemetry_keepalive,
+            recovering: self.recovering,
+            faults: json::parse(&faults).expect("serde_json output should always parse")
         };
         json_data.dump().into_bytes()
     }
 
-    pub fn new(current_state: PodState, pending_next_state: PodState, errno: UdpErrno, telemetry: &PodData, telemetry_timestamp: NaiveDateTime, recovering: bool) -> PodStateMessage {
+    pub fn new(current_state: PodState, pending_next_state: PodState, errno: UdpErrno, telemetry: &PodData, telemetry_timestamp: NaiveDateTime, telemetry_sequence: u64, recovering: bool) -> PodStateMessage {
         PodStateMessage {
             current_state,
             errno,
@@ -44,9 +58,38 @@ impl PodStateMessage {
             recovering,
             telemetry: Some((*telemetry).clone()),
             telemetry_timestamp,
+            telemetry_sequence: Some(telemetry_sequence),
+            telemetry_keepalive: false,
+            faults: Vec::new(),
+            fields: TelemetryFields::all(),
         }
     }
 
+    /**
+     * Attach the faults reported by the boards. They are sent whether or not the message carries telemetry
+     */
+    pub fn with_faults(mut self, faults: &[FaultReport]) -> PodStateMessage {
+        self.faults = faults.to_vec();
+        self
+    }
+
+    /**
+     * Flag the telemetry as a keepalive, the last snapshot sent again rather than fresh data.
+     * Ignored without telemetry
+     */
+    pub fn with_keepalive(mut self, keepalive: bool) -> PodStateMessage {
+        self.telemetry_keepalive = keepalive && self.telemetry.is_some();
+        self
+    }
+
+    /**
+     * Only send the selected telemetry fields. Every field is sent by default
+     */
+    pub fn with_fields(mut self, fields: TelemetryFields) -> PodStateMessage {
+        self.fields = fields;
+        self
+    }
+
     pub fn new_no_telemetry(current_state: PodState, pending_next_state: PodState, errno: UdpErrno, telemetry_timestamp: NaiveDateTime, recovering: bool) -> PodStateMessage {
         PodStateMessage {
             current_state,
@@ -55,6 +98,10 @@ impl PodStateMessage {
             recovering,
             telemetry: None,
             telemetry_timestamp,
+            telemetry_sequence: None,
+            telemetry_keepalive: false,
+            faults: Vec::new(),
+            fields: TelemetryFields::all(),
         }
     }
 }
\ No newline at end of file
@@ -1,250 +1,459 @@
 #[allow(unused_doc_comments)]
 
 use std::time::Duration;
-use std::{sync::{
-    mpsc::{
-        channel,
-        Receiver,
-        Sender
-    }
-}, convert::TryInto};
+use log::{ info, error };
+use std::sync::mpsc::{
+    channel,
+    Receiver,
+    Sender
+};
+use std::thread::JoinHandle;
+use std::sync::Arc;
 use std::fs::OpenOptions;
 use std::io::prelude::*;
 
-#[cfg(unix)]
-use crate::can_extentions::prelude::*;
-
 use crate::{thread_managers::messages::{
     TcpMessage,
     UDPMessage,
     CanMessage as CANMessage,
     WorkerMessage
-}, utils::rpm_integrator, pod_data::{ PodData }};
-use json::JsonValue;
-use crate::thread_managers;
+}, pod_data::{ PodData }};
+#[cfg(feature = "websocket")]
+use crate::thread_managers::messages::WebsocketMessage;
+use crate::thread_managers::{ self, SAFE_POD_STATE };
 use crate::error::Error;
+use crate::metrics::Metrics;
+use crate::utils::command_history::CommandHistory;
+#[cfg(any(unix, feature = "mock-can"))]
+use crate::utils::bounded_channel::{ bounded_channel, BoundedReceiver, BoundedSender };
 
-use crate::device_watchdog::{
-    DeviceWatchdogMapFuncs,
-    Device
-};
+#[cfg(all(test, unix))]
+mod test {
+    use super::*;
+    use crate::pod_states::PodState;
+
+    /**
+     * Stands in for a relay thread. Runs until it is told to shut down
+     */
+    fn until_shutdown<T: Send + 'static>(receiver: Receiver<T>, is_shutdown: fn(&T) -> bool) -> JoinHandle<()> {
+        std::thread::spawn(move || {
+            while let Ok(message) = receiver.recv() {
+                if is_shutdown(&message) {
+                    break;
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn dead_worker_shuts_the_relay_down_safely() {
+        let (tcp_sender, tcp_receiver) = channel();
+        let (udp_message_sender, udp_message_receiver) = channel();
+        let (can_message_sender, can_message_receiver) = channel();
+        let (safe_state_sender, safe_state_receiver) = channel();
+        #[cfg(feature = "health")]
+        let liveness = Arc::new(crate::utils::health::ThreadLiveness::default());
+        let handles = RelayHandles {
+            tcp: until_shutdown(tcp_receiver, |message| matches!(message, TcpMessage::Shutdown)),
+            udp: until_shutdown(udp_message_receiver, |message| matches!(message, UDPMessage::Shutdown)),
+            can: std::thread::spawn(move || {
+                while let Ok(message) = can_message_receiver.recv() {
+                    match message {
+                        CANMessage::ChangeState(state) => safe_state_sender.send(state).unwrap(),
+                        CANMessage::Shutdown => break,
+                        _ => {}
+                    }
+                }
+            }),
+            worker: std::thread::spawn(|| panic!("Worker Thread died")),
+            logger: std::thread::spawn(|| {}),
+            #[cfg(feature = "websocket")]
+            websocket: std::thread::spawn(|| {}),
+            tcp_sender,
+            udp_message_sender,
+            can_message_sender,
+            #[cfg(feature = "websocket")]
+            websocket_message_sender: channel().0,
+            safe_state_bus: SafeStateBus::Interface(String::from("vcan0")),
+            #[cfg(feature = "health")]
+            health: Arc::clone(&liveness),
+        };
+
+        assert!(!handles.supervise());
+        assert_eq!(safe_state_receiver.try_recv(), Ok(PodState::LowVoltage));
+        // The dead thread was published for the health endpoint before the relay shut down
+        #[cfg(feature = "health")]
+        assert!(liveness.threads().contains(&("Worker Thread", false)));
+    }
 
-use crate::utils::rpm_integrator::RpmIntegrator;
+    #[test]
+    fn safe_state_is_only_written_to_a_live_bus() {
+        assert_eq!(SafeStateBus::new(String::from("can0"), true, false), SafeStateBus::DryRun);
+        assert_eq!(SafeStateBus::new(String::from("can0"), false, true), SafeStateBus::NoBus);
+        let live = SafeStateBus::new(String::from("can0"), false, false);
+        if cfg!(feature = "mock-can") {
+            assert_eq!(live, SafeStateBus::NoBus);
+        } else {
+            assert_eq!(live, SafeStateBus::Interface(String::from("can0")));
+        }
+    }
+}
 
-pub fn run_threads<A: std::net::ToSocketAddrs +std::fmt::Debug + Send + 'static>(config: crate::config::Config<A>) -> Result<(), Error> {
+/**
+ * Handles to every thread started by run_threads. The signal thread is left out,
+ * it only exits after a shutdown signal and is never waited on
+ */
+pub struct RelayHandles {
+    pub tcp: JoinHandle<()>,
+    pub udp: JoinHandle<()>,
+    #[cfg(any(unix, feature = "mock-can"))]
+    pub can: JoinHandle<()>,
+    #[cfg(any(unix, feature = "mock-can"))]
+    pub worker: JoinHandle<()>,
+    pub logger: JoinHandle<()>,
+    #[cfg(feature = "websocket")]
+    pub websocket: JoinHandle<()>,
+    tcp_sender: Sender<TcpMessage>,
+    udp_message_sender: Sender<UDPMessage>,
+    can_message_sender: Sender<CANMessage>,
+    #[cfg(feature = "websocket")]
+    websocket_message_sender: Sender<WebsocketMessage>,
+    #[cfg(unix)]
+    safe_state_bus: SafeStateBus,
+    #[cfg(feature = "health")]
+    health: Arc<crate::utils::health::ThreadLiveness>, // Published each time the threads are checked, for the Health Thread to serve
+}
+
+/**
+ * Where the safe state is commanded if the can thread has died
+ */
+#[cfg(unix)]
+#[derive(Debug, PartialEq)]
+enum SafeStateBus {
+    Interface(String), // The live bus, written on a socket of our own
+    DryRun, // Only logged, the relay never writes to the bus in a dry run
+    NoBus // Replaying a log or on the simulated bus, there is no bus to write to
+}
+
+#[cfg(unix)]
+impl SafeStateBus {
+    fn new(can_interface: String, dry_run: bool, replaying: bool) -> SafeStateBus {
+        if dry_run {
+            SafeStateBus::DryRun
+        } else if replaying || cfg!(feature = "mock-can") {
+            SafeStateBus::NoBus
+        } else {
+            SafeStateBus::Interface(can_interface)
+        }
+    }
+}
+
+impl RelayHandles {
+    /**
+     * @brief supervise
+     * Watch the threads until they have all exited. If one exits without a shutdown being requested,
+     * the pod is commanded into LowVoltage and the rest of the relay is shut down, so that it can be
+     * restarted from a clean state. Returns false if a thread died
+     */
+    pub fn supervise(self) -> bool {
+        let exited_thread = loop {
+            let threads = self.thread_liveness();
+            #[cfg(feature = "health")]
+            self.health.publish(threads.clone());
+            if let Some((name, _)) = threads.into_iter().find(|&(_, alive)| !alive) {
+                break name;
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        };
+        let clean_exit = crate::utils::signals::shutdown_requested();
+        if !clean_exit {
+            error!("{} exited unexpectedly, commanding {:?} and shutting down", exited_thread, SAFE_POD_STATE);
+            self.command_safe_state();
+            self.udp_message_sender.send(UDPMessage::SystemFault).ok();
+            // Threads may already be gone, so send failures are ignored
+            self.tcp_sender.send(TcpMessage::Shutdown).ok();
+            self.udp_message_sender.send(UDPMessage::Shutdown).ok();
+            self.can_message_sender.send(CANMessage::Shutdown).ok();
+            #[cfg(feature = "websocket")]
+            self.websocket_message_sender.send(WebsocketMessage::Shutdown).ok();
+        }
+        self.join();
+        clean_exit
+    }
+
+    /**
+     * Each thread's name and whether it is still running
+     */
+    fn thread_liveness(&self) -> Vec<(&'static str, bool)> {
+        let mut threads = Vec::new();
+        #[cfg(any(unix, feature = "mock-can"))]
+        {
+            threads.push(("CAN Thread", !self.can.is_finished()));
+            threads.push(("Worker Thread", !self.worker.is_finished()));
+        }
+        threads.push(("TCP Thread", !self.tcp.is_finished()));
+        threads.push(("UDP Thread", !self.udp.is_finished()));
+        threads.push(("Logger Thread", !self.logger.is_finished()));
+        #[cfg(feature = "websocket")]
+        threads.push(("Websocket Thread", !self.websocket.is_finished()));
+        threads
+    }
+
+    /**
+     * While the can thread is alive it commands the safe state itself as it shuts down.
+     * If it has died, the safe state is written on a socket of our own, unless there is no live bus to write to
+     */
+    fn command_safe_state(&self) {
+        #[cfg(unix)]
+        if self.can.is_finished() {
+            use crate::can_extentions::prelude::*;
+            match &self.safe_state_bus {
+                SafeStateBus::Interface(can_interface) => {
+                    let message_result = crate::can_extentions::open_socket(can_interface.as_str())
+                        .and_then(|socket| socket.send_pod_state(&SAFE_POD_STATE));
+                    if let Err(err) = message_result {
+                        error!("Unable to command {:?} after the CAN Thread exited: {:?}", SAFE_POD_STATE, err);
+                    }
+                },
+                SafeStateBus::DryRun => info!("[DRY RUN] would send PodState::{:?}", SAFE_POD_STATE),
+                SafeStateBus::NoBus => info!("No CAN bus to command {:?} on after the CAN Thread exited", SAFE_POD_STATE)
+            }
+            return;
+        }
+        #[cfg(all(not(unix), feature = "mock-can"))]
+        if self.can.is_finished() {
+            info!("No CAN bus to command {:?} on after the CAN Thread exited", SAFE_POD_STATE);
+            return;
+        }
+        self.can_message_sender.send(CANMessage::ChangeState(SAFE_POD_STATE)).ok();
+    }
+
+    /**
+     * @brief join
+     * Wait for every thread to exit. A thread can panic while shutting down if the thread
+     * it was messaging has already exited, this should not stop the others from being joined
+     */
+    pub fn join(self) {
+        #[cfg(any(unix, feature = "mock-can"))]
+        if self.worker.join().is_err() {
+            error!("Worker Thread panicked before it could be joined");
+        }
+        if self.udp.join().is_err() {
+            error!("UDP Thread panicked before it could be joined");
+        }
+        if self.tcp.join().is_err() {
+            error!("TCP Thread panicked before it could be joined");
+        }
+        #[cfg(any(unix, feature = "mock-can"))]
+        if self.can.join().is_err() {
+            error!("CAN Thread panicked before it could be joined");
+        }
+        if self.logger.join().is_err() {
+            error!("Logger Thread panicked before it could be joined");
+        }
+        #[cfg(feature = "websocket")]
+        if self.websocket.join().is_err() {
+            error!("Websocket Thread panicked before it could be joined");
+        }
+    }
+}
+
+pub fn run_threads<A: std::net::ToSocketAddrs +std::fmt::Debug + Send + 'static>(config: crate::config::Config<A>) -> Result<RelayHandles, Error> {
     let (udp_message_sender, udp_message_receiver): (Sender<UDPMessage>, Receiver<UDPMessage>) = channel();
-    #[allow(unused_variables)] // can_message_receiver is only used in unix, but needs to exist so that other parts of the code can send messages without crashing
+    #[allow(unused_variables)] // can_message_receiver is only used with a can thread, but needs to exist so that other parts of the code can send messages without crashing
     let (can_message_sender, can_message_receiver): (Sender<CANMessage>, Receiver<CANMessage>) = channel();
-    #[cfg(unix)] // Worker does not need to be created if running outside of unix
-    let (worker_message_sender, worker_message_receiver): (Sender<WorkerMessage>, Receiver<WorkerMessage>) = channel();
+    #[cfg(any(unix, feature = "mock-can"))] // Outside of unix there is no can or worker thread unless the bus is simulated
+    let (worker_message_sender, worker_message_receiver): (BoundedSender<WorkerMessage>, BoundedReceiver<WorkerMessage>) = bounded_channel(config.worker_channel_bound, config.worker_channel_policy);
     let (tcp_sender, tcp_receiver): (Sender<TcpMessage>, Receiver<TcpMessage>) = channel();
 
     // Configuration Values
-    let tcp_message_buffer_size = 128;
-    // TODO - Figure out what these value should be
-    let udp_socket_read_timeout = Duration::from_millis(500); // Amount of time the UDP Socket will wait for a message from the Controller
-    let udp_max_number_timeouts = 10;
+    let tcp_message_buffer_size = config.buffer_size;
+    let metrics = Arc::new(Metrics::new()); // Shared by every thread, served over TCP with METRICS
+    let command_history = Arc::new(CommandHistory::new(config.command_history_length)); // Written by the worker thread, served over TCP with HISTORY
+    info!("A controller which sends nothing for {:?} will be disconnected", config.udp_idle_timeout());
     // End Configuration Values
 
     // CAN Configuration
-    #[cfg(unix)]
-    let can_socket_read_timeout = Duration::from_millis(10000); // Amount of time the CAN Socket will wait for a message from the rest of the POD
+    #[cfg(any(unix, feature = "mock-can"))]
+    let can_socket_read_timeout = config.can_read_timeout;
+    // A bad replay log is found before any thread is started
+    #[cfg(any(unix, feature = "mock-can"))]
+    let can_replay = match &config.can_replay_file {
+        Some(path) => {
+            let replay = crate::can_extentions::prelude::FileReplaySource::open(path, config.can_replay_speed)
+                .map_err(|err| Error::CanSocketError(crate::can_extentions::prelude::CanError::ReadError(err)))?;
+            info!("Replaying CAN frames from {} at {}x speed", path, config.can_replay_speed);
+            Some(replay)
+        },
+        None => None
+    };
     // End CAN Configuration
 
+    // Websocket Configuration
+    // Bound up front so that an address in use is found before any thread is started
+    #[cfg(feature = "websocket")]
+    let websocket_listener = std::net::TcpListener::bind(&config.websocket_address).map_err(Error::TcpSocketError)?;
+    #[cfg(feature = "websocket")]
+    let (websocket_message_sender, websocket_message_receiver) = channel::<WebsocketMessage>();
+    // End Websocket Configuration
+
+    // Health Configuration
+    #[cfg(feature = "health")]
+    let health_listener = std::net::TcpListener::bind(&config.health_address).map_err(Error::TcpSocketError)?;
+    // End Health Configuration
+
     // Thread Handles
     let tcp_handle = thread_managers::TcpManager::run(
         config.tcp_address,
         udp_message_sender.clone(),
+        can_message_sender.clone(),
         tcp_receiver,
-        tcp_message_buffer_size
+        tcp_message_buffer_size,
+        config.udp_port,
+        config.tcp_read_timeout,
+        config.disconnect_wait,
+        config.connect_allowlist,
+        Arc::clone(&command_history),
+        Arc::clone(&metrics)
     );
     let udp_handle = thread_managers::UdpManager::run(
         can_message_sender.clone(),
         tcp_sender.clone(),
         udp_message_receiver,
-        udp_max_number_timeouts,
-        udp_socket_read_timeout,
-        config.udp_address
+        config.udp_max_number_timeouts,
+        config.udp_read_timeout,
+        config.udp_backoff_base,
+        config.udp_backoff_cap,
+        config.udp_address,
+        Arc::clone(&metrics)
     );
 
+    #[cfg(any(unix, feature = "mock-can"))]
+    let can_initializer = thread_managers::CanWorkerInitializer {
+        can_interface: config.can_interface.clone(),
+        worker_message_sender,
+        can_message_receiver,
+        can_socket_read_timeout,
+        can_max_number_timeouts: config.can_max_number_timeouts,
+        udp_message_sender: udp_message_sender.clone(),
+        roboteq: config.roboteq,
+        log_path: config.can_log_path,
+        dry_run: config.dry_run,
+        state_change_timeout: config.state_change_timeout,
+        state_change_retries: config.state_change_retries,
+        metrics: Arc::clone(&metrics),
+    };
     #[cfg(unix)]
-    let can_handle = thread_managers::CanManager::run(
-        thread_managers::CanWorkerInitializer {
-            can_interface: config.can_interface,
-            worker_message_sender,
-            can_message_receiver,
-            can_socket_read_timeout,
-            udp_message_sender: udp_message_sender.clone(),
+    let safe_state_bus = SafeStateBus::new(config.can_interface.clone(), config.dry_run, can_replay.is_some());
+    #[cfg(any(unix, feature = "mock-can"))]
+    let can_handle = match can_replay {
+        Some(replay) => thread_managers::CanManager::run_with_socket(Box::new(replay), can_initializer),
+        #[cfg(not(feature = "mock-can"))]
+        None => thread_managers::CanManager::run(can_initializer),
+        #[cfg(feature = "mock-can")]
+        None => {
+            info!("Running on a simulated CAN bus instead of {}", config.can_interface);
+            thread_managers::CanManager::run_with_socket(Box::new(crate::can_extentions::prelude::MockCanSocket::new()), can_initializer)
         }
-    );
+    };
 
-    udp_message_sender.send(UDPMessage::StartupComplete).expect("To be able to complete startup");
-
-    let (send_data_to_logger, data_logger_receiver) = channel::<PodData>();
-    std::thread::spawn(move || {
-        let mut out_file = OpenOptions::new()
-            .write(true)
-            .append(true)
-            .open("Logs.txt")
-            .unwrap();
-        loop {
-            if let Ok(data) = data_logger_receiver.recv() {
-                let jv: JsonValue = data.into();
-                out_file.write(&jv.dump().as_bytes()).unwrap();
-            }
-        }
-    });
+    // A udp thread which has already exited is found by supervise, which shuts the relay down safely
+    if udp_message_sender.send(UDPMessage::StartupComplete).is_err() {
+        error!("UDP Thread exited before startup completed");
+    }
 
+    // Shutdown on SIGINT/SIGTERM (Ctrl-C on windows)
+    match crate::utils::signals::install_shutdown_handler() {
+        Ok(()) => {
+            let udp_message_sender = udp_message_sender.clone();
+            let can_message_sender = can_message_sender.clone();
+            let tcp_sender = tcp_sender.clone();
+            #[cfg(feature = "websocket")]
+            let websocket_message_sender = websocket_message_sender.clone();
+            std::thread::Builder::new().name("Signal Thread".to_string()).spawn(move || {
+                while !crate::utils::signals::shutdown_requested() {
+                    std::thread::sleep(Duration::from_millis(100));
+                }
+                info!("Shutdown requested, stopping threads");
+                // The can thread is stopped last so that the final command on the bus puts the pod in LowVoltage.
+                // Threads may already be gone, so send failures are ignored
+                tcp_sender.send(TcpMessage::Shutdown).ok();
+                udp_message_sender.send(UDPMessage::Shutdown).ok();
+                can_message_sender.send(CANMessage::Shutdown).ok();
+                #[cfg(feature = "websocket")]
+                websocket_message_sender.send(WebsocketMessage::Shutdown).ok();
+            }).expect("Should be able to create Thread");
+        },
+        Err(err) => error!("Unable to install shutdown handler: {:?}", err)
+    }
 
-    // Worker Thread
-    // Initialization
-    #[cfg(unix)]
-    {
-        let mut pod_data = crate::pod_data::PodData::new();
-        let mut watchdog = crate::device_watchdog::DeviceWatchdogMap::with_all_devices(can_message_sender.clone(), CANMessage::DeviceLost, 400);
-        let mut rpm_integrator = RpmIntegrator::default();
-        loop {
-            match worker_message_receiver.recv() {
-                Ok(message) => {
-                    match message {
-                        WorkerMessage::CanFrameAndTimeStamp(frame, time) => {
-                            // Handle CAN Frame in here
-                            let mut new_data = true;
-                            match frame.get_command() {
-                                CanCommand::BmsHealthCheck{ battery_pack_current, cell_temperature } => {
-                                    pod_data.battery_pack_current = Some(battery_pack_current);
-                                    pod_data.average_cell_temperature = Some(cell_temperature);
-                                    watchdog.update_device_timestamp(Device::BMS, crate::device_watchdog::get_now());
-                                },
-                                CanCommand::MotorControllerHealthCheck{ igbt_temp, motor_voltage } => {
-                                    pod_data.motor_voltage = Some(motor_voltage);
-                                    pod_data.igbt_temp = Some(igbt_temp);
-                                    watchdog.update_device_timestamp(Device::MC, crate::device_watchdog::get_now());
-                                },
-                                CanCommand::BmsData1{ battery_pack_voltage, state_of_charge } => {
-                                    pod_data.battery_pack_voltage = Some(battery_pack_voltage);
-                                    pod_data.state_of_charge = Some(state_of_charge);
-                                    watchdog.update_device_timestamp(Device::BMS, crate::device_watchdog::get_now());
-                                },
-                                CanCommand::BmsData2{ buck_temperature, bms_current } => {
-                                    pod_data.buck_temperature = Some(buck_temperature);
-                                    pod_data.bms_current = Some(bms_current);
-                                    watchdog.update_device_timestamp(Device::BMS, crate::device_watchdog::get_now());
-
-                                },
-                                CanCommand::BmsData3{ link_cap_voltage } => {
-                                    pod_data.link_cap_voltage = Some(link_cap_voltage);
-                                    watchdog.update_device_timestamp(Device::BMS, crate::device_watchdog::get_now());
-
-                                },
-                                CanCommand::MotorControllerData1{ mc_pod_speed, motor_current } => {
-                                    pod_data.mc_pod_speed = Some(mc_pod_speed);
-                                    pod_data.motor_current = Some(motor_current);
-                                    watchdog.update_device_timestamp(Device::MC, crate::device_watchdog::get_now());
-
-                                },
-                                CanCommand::MotorControllerData2{ battery_current, battery_voltage } => {
-                                    pod_data.battery_current = Some(battery_current);
-                                    pod_data.battery_voltage = Some(battery_voltage);
-                                    watchdog.update_device_timestamp(Device::MC, crate::device_watchdog::get_now());
-
-                                },
-                                CanCommand::PodSpeed{ pod_speed } => {
-                                    pod_data.speed = Some(pod_speed);
-                                    watchdog.update_device_timestamp(Device::MC, crate::device_watchdog::get_now());
-
-                                },
-                                CanCommand::PressureHigh(pressure) => {
-                                    pod_data.pressure_high = Some(pressure);
-                                    watchdog.update_device_timestamp(Device::PRESSURE_HIGH, crate::device_watchdog::get_now());
-                                },
-                                CanCommand::PressureLow1(pressure) => {
-                                    pod_data.pressure_low_1 = Some(pressure);
-                                    watchdog.update_device_timestamp(Device::PRESSURE_LOW_1, crate::device_watchdog::get_now());
-                                },
-                                CanCommand::PressureLow2(pressure) => {
-                                    pod_data.pressure_low_2 = Some(pressure);
-                                    watchdog.update_device_timestamp(Device::PRESSURE_LOW_2, crate::device_watchdog::get_now());
-                                },
-                                CanCommand::Current5V(current) => {
-                                    pod_data.current_5v = Some(current);
-                                    watchdog.update_device_timestamp(Device::ELEKID, crate::device_watchdog::get_now());
-                                },
-                                CanCommand::Current12V(current) => {
-                                    pod_data.current_12v = Some(current);
-                                    watchdog.update_device_timestamp(Device::ELEKID, crate::device_watchdog::get_now());
-                                },
-                                CanCommand::Current24V(current) => {
-                                    pod_data.current_24v = Some(current);
-                                    watchdog.update_device_timestamp(Device::ELEKID, crate::device_watchdog::get_now());
-                                },
-                                CanCommand::Torchic1(data) => {
-                                    println!("TORCHIC1 DATA: {:?}", data);
-                                    pod_data.torchic_1 = data;
-                                    watchdog.update_device_timestamp(Device::TORCHIC_1, crate::device_watchdog::get_now());
-                                },
-                                CanCommand::Torchic2(data) => {
-                                    pod_data.torchic_2 = data;
-                                    watchdog.update_device_timestamp(Device::TORCHIC_2, crate::device_watchdog::get_now());
-                                },
-                                CanCommand::RoboteqBatteryAmpsResult{ motor_number, amps } => {
-                                    if motor_number == 1 {
-                                        pod_data.roboteq_motor_1_battery_amps = Some(amps);
-                                    } else if motor_number == 2 {
-                                        pod_data.roboteq_motor_1_battery_amps = Some(amps);
-                                    } else {
-                                        new_data = false;
-                                    }
-                                },
-                                CanCommand::RoboteqMotorEncoderResult{ motor_number, speed } => {
-                                    match motor_number {
-                                        1 => { pod_data.roboteq_motor_1_speed = Some(RpmIntegrator::calc_speed(speed)); },
-                                        2 => { pod_data.roboteq_motor_2_speed = Some(RpmIntegrator::calc_speed(speed));},
-                                        _ => { new_data = false; }
-                                    }
-                                },
-                                CanCommand::RoboteqTemperatureResult{ sub_index, temp } => {
-                                    match sub_index {
-                                        1 => { pod_data.roboteq_mcu_temp = Some(temp);},
-                                        2 => { pod_data.roboteq_sensor_1_temp = Some(temp); },
-                                        3 => { pod_data.roboteq_sensor_2_temp = Some(temp); },
-                                        _ => { new_data = false; },
-                                    }
-                                }
-                                _ => {
-                                    new_data = false;
-                                }
-                            }
-                            let devices = watchdog.check_devices();
-                            for device in &devices {
-                                println!("DEBUG: WATCHDOG DETECTED DEVICE LOST: {:?}", device);
-                            }
-                            if new_data {
-                                // println!("NEW DATA Parsed: {:?}", pod_data);
-                                if pod_data.ok() {
-                                    send_data_to_logger.send(pod_data.clone()).unwrap();
-                                    udp_message_sender.send(UDPMessage::TelemetryDataAvailable(pod_data, time)).expect("To be able to send telemetry data to udp from worker");
-                                } else {
-                                    //udp_message_sender.send(UDPMessage::SystemFault).expect("TO BE ABLE TO SEND MESSAGE");
-                                }
-                            }
-                        }
-                    }
-                },
-                Err(err) => {
-                    println!("Worker Receiver Error: {:?}", err);
-                    println!("Exiting");
-                    return Ok(());
+    let (send_data_to_logger, data_logger_receiver) = channel::<(PodData, chrono::NaiveDateTime)>();
+    let logger_handle = std::thread::Builder::new().name("Logger Thread".to_string()).spawn(move || {
+        // Telemetry is still drained without a log file, so the worker thread is never stopped by it
+        let mut out_file = match OpenOptions::new().append(true).create(true).open("Logs.txt") {
+            Ok(out_file) => Some(out_file),
+            Err(err) => {
+                error!("Unable to open Logs.txt, telemetry will not be logged: {:?}", err);
+                None
+            }
+        };
+        // Ends once the worker thread exits and drops its sender
+        while let Ok((data, time)) = data_logger_receiver.recv() {
+            if let Some(out_file) = &mut out_file {
+                let line = format!("{}\n", data.to_json(&time));
+                if let Err(err) = out_file.write_all(line.as_bytes()) {
+                    error!("Unable to write telemetry to Logs.txt: {:?}", err);
                 }
             }
         }
-    }
+    }).expect("Should be able to create Thread");
 
-    udp_handle.join().expect("Should be able to join at the end of the Program");
-    tcp_handle.join().expect("Should be able to join at the end of the Program");
-    #[cfg(unix)]
-    can_handle.join().expect("Should be able to join at the end of the Program");
 
-    Ok(())
-}
+    // Worker Thread
+    #[cfg(any(unix, feature = "mock-can"))]
+    let worker_handle = thread_managers::WorkerManager::run(
+        worker_message_receiver,
+        udp_message_sender.clone(),
+        can_message_sender.clone(),
+        send_data_to_logger,
+        config.telemetry_rate,
+        config.telemetry_keepalive,
+        config.sanity_ranges.clone(),
+        command_history,
+        Arc::clone(&metrics),
+        #[cfg(feature = "websocket")]
+        websocket_message_sender.clone()
+    );
 
+    #[cfg(feature = "websocket")]
+    let websocket_handle = {
+        info!("Serving telemetry to websocket clients on {:?}", config.websocket_address);
+        thread_managers::WebsocketManager::run(websocket_listener, websocket_message_receiver)
+    };
+
+    Ok(RelayHandles {
+        tcp: tcp_handle,
+        udp: udp_handle,
+        #[cfg(any(unix, feature = "mock-can"))]
+        can: can_handle,
+        #[cfg(any(unix, feature = "mock-can"))]
+        worker: worker_handle,
+        logger: logger_handle,
+        #[cfg(feature = "websocket")]
+        websocket: websocket_handle,
+        tcp_sender,
+        udp_message_sender,
+        can_message_sender,
+        #[cfg(feature = "websocket")]
+        websocket_message_sender,
+        #[cfg(unix)]
+        safe_state_bus,
+        #[cfg(feature = "health")]
+        health: {
+            info!("Serving GET /health on {:?}", config.health_address);
+            let liveness = Arc::new(crate::utils::health::ThreadLiveness::default());
+            crate::utils::health::HealthServer::new(health_listener, metrics, Arc::clone(&liveness)).run();
+            liveness
+        },
+    })
+}
@@ -0,0 +1,25 @@
+use std::time::Duration;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_cap() {
+        let base = Duration::from_millis(100);
+        let cap = Duration::from_millis(1000);
+        assert_eq!(backoff_delay(base, cap, 0), Duration::from_millis(100));
+        assert_eq!(backoff_delay(base, cap, 1), Duration::from_millis(200));
+        assert_eq!(backoff_delay(base, cap, 3), Duration::from_millis(800));
+        assert_eq!(backoff_delay(base, cap, 4), cap);
+        assert_eq!(backoff_delay(base, cap, u32::MAX), cap);
+    }
+}
+
+/**
+ * @brief backoff_delay
+ * Delay before the next attempt to re-open a socket. Doubles with every consecutive failure, up to the cap
+ */
+pub fn backoff_delay(base: Duration, cap: Duration, attempt: u32) -> Duration {
+    base.checked_mul(2u32.saturating_pow(attempt)).map_or(cap, |delay| delay.min(cap))
+}
@@ -0,0 +1,95 @@
+use std::fs::File;
+use std::io::{ BufWriter, Write };
+use std::path::Path;
+use std::sync::mpsc::{ channel, Sender };
+use std::thread::JoinHandle;
+use chrono::{ DateTime, Utc };
+use log::{ error };
+use crate::can_extentions::prelude::CANFrame;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn frames_are_written_in_candump_format() {
+        let time = DateTime::<Utc>::from_utc(chrono::NaiveDateTime::from_timestamp(1436509052, 249_713_000), Utc);
+        let frame = CANFrame::new(0x01F, &[0x00, 0x00, 0x2C, 0x41], false, false).unwrap();
+        assert_eq!(candump_line(&frame, &time, "can0"), "(1436509052.249713) can0 01F#00002C41");
+
+        let path = std::env::temp_dir().join(format!("relay_frame_logger_{}.log", std::process::id()));
+        let logger = CanFrameLogger::start(&path, String::from("vcan0")).unwrap();
+        logger.log(frame, time);
+        logger.log(CANFrame::new(0x000, &[0x02], false, false).unwrap(), time);
+        // Dropping the logger waits for everything to be flushed
+        drop(logger);
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(contents, "(1436509052.249713) vcan0 01F#00002C41\n(1436509052.249713) vcan0 000#02\n");
+    }
+}
+
+/**
+ * @brief CanFrameLogger
+ * Writes every frame the can thread receives to a file in the format of `candump -l`, so that a run
+ * can be analysed afterwards or replayed with --can-replay. Writing happens on its own thread so the
+ * can thread's read loop never waits on the disk. Dropping the logger flushes the file
+ */
+pub struct CanFrameLogger {
+    frame_sender: Option<Sender<(CANFrame, DateTime<Utc>)>>,
+    handle: Option<JoinHandle<()>>
+}
+
+impl CanFrameLogger {
+    pub fn start(path: &Path, can_interface: String) -> std::io::Result<CanFrameLogger> {
+        let mut out_file = BufWriter::new(File::create(path)?);
+        let (frame_sender, frame_receiver) = channel::<(CANFrame, DateTime<Utc>)>();
+        let handle = std::thread::Builder::new().name("CAN Log Thread".to_string()).spawn(move || {
+            // Ends once the logger is dropped and its sender closes
+            while let Ok((frame, time)) = frame_receiver.recv() {
+                if let Err(err) = writeln!(out_file, "{}", candump_line(&frame, &time, &can_interface)) {
+                    error!("CAN LOG THREAD: Unable to write frame: {:?}", err);
+                }
+            }
+            if let Err(err) = out_file.flush() {
+                error!("CAN LOG THREAD: Unable to flush the frame log: {:?}", err);
+            }
+        })?;
+        Ok(CanFrameLogger {
+            frame_sender: Some(frame_sender),
+            handle: Some(handle)
+        })
+    }
+
+    pub fn log(&self, frame: CANFrame, time: DateTime<Utc>) {
+        if let Some(frame_sender) = &self.frame_sender {
+            // The log thread only exits once the sender is dropped, unless a write panicked
+            frame_sender.send((frame, time)).ok();
+        }
+    }
+}
+
+impl Drop for CanFrameLogger {
+    fn drop(&mut self) {
+        drop(self.frame_sender.take());
+        if let Some(handle) = self.handle.take() {
+            if handle.join().is_err() {
+                error!("CAN Log Thread panicked before the frame log was flushed");
+            }
+        }
+    }
+}
+
+/**
+ * @func candump_line
+ * A frame as `candump -l` writes it: (seconds.micros) interface id#data, with seconds since the unix epoch
+ */
+fn candump_line(frame: &CANFrame, time: &DateTime<Utc>, can_interface: &str) -> String {
+    let id = if frame.is_extended() {
+        format!("{:08X}", frame.id())
+    } else {
+        format!("{:03X}", frame.id())
+    };
+    let data: String = frame.data().iter().map(|byte| format!("{:02X}", byte)).collect();
+    format!("({}.{:06}) {} {}#{}", time.timestamp(), time.timestamp_subsec_micros(), can_interface, id, data)
+}
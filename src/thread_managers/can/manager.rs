@@ -1,18 +1,80 @@
 use super::worker::{ CanWorkerState, CanWorkerInitializer };
+use log::{ info };
 use super::super::main_loop::WorkerStateTrait;
+use crate::can_extentions::prelude::CanFrameSocket;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::worker::test::ScriptedSocket;
+    use crate::config::RoboteqConfig;
+    use crate::pod_states::PodState;
+    use crate::thread_managers::messages::CanMessage;
+    use std::sync::{ Arc, mpsc::channel };
+    use crate::utils::bounded_channel::{ bounded_channel, FullPolicy };
+    use std::time::Duration;
+
+    #[test]
+    fn shutdown_joins_can_thread_in_low_voltage() {
+        let socket = ScriptedSocket::default(); // Nothing is scripted, so nobody else is talking on the bus
+        let (udp_message_sender, _udp_message_receiver) = channel();
+        let (worker_message_sender, _worker_message_receiver) = bounded_channel(16, FullPolicy::DropOldest);
+        let (can_message_sender, can_message_receiver) = channel();
+        let handle = CanManager::run_with_socket(Box::new(socket.clone()), CanWorkerInitializer {
+            can_interface: String::from("silent"),
+            udp_message_sender,
+            worker_message_sender,
+            can_message_receiver,
+            can_socket_read_timeout: Duration::from_millis(10),
+            can_max_number_timeouts: 1000,
+            roboteq: RoboteqConfig::default(),
+            log_path: None,
+            dry_run: false,
+            state_change_timeout: Duration::from_secs(60),
+            state_change_retries: 3,
+            metrics: Arc::new(crate::metrics::Metrics::new())
+        });
+
+        can_message_sender.send(CanMessage::ChangeState(PodState::Armed)).unwrap();
+        can_message_sender.send(CanMessage::Shutdown).unwrap();
+        assert!(handle.join().is_ok());
+        assert_eq!(socket.written.lock().unwrap().last().unwrap().data(), &[PodState::LowVoltage.to_byte()]);
+    }
+}
+
 pub struct CanManager {
 }
 
 impl CanManager {
+    #[cfg(unix)]
     pub fn run(
         initializer: CanWorkerInitializer
     ) -> std::thread::JoinHandle<()> {
         std::thread::Builder::new().name("CAN Thread".to_string()).spawn(move || {
             // Setup
-            let mut can_worker = CanWorkerState::new(initializer);
-            loop {
-                can_worker = can_worker.main_loop();
-            }
+            can_thread_main(CanWorkerState::new(initializer));
         }).expect("Should be able to create Thread")
     }
+
+    /**
+     * @brief run_with_socket
+     * Run the can thread on a socket which has already been opened, rather than opening can_interface
+     */
+    pub fn run_with_socket(
+        can_handle: Box<dyn CanFrameSocket + Send>,
+        initializer: CanWorkerInitializer
+    ) -> std::thread::JoinHandle<()> {
+        std::thread::Builder::new().name("CAN Thread".to_string()).spawn(move || {
+            can_thread_main(CanWorkerState::with_socket(can_handle, initializer));
+        }).expect("Should be able to create Thread")
+    }
+}
+
+fn can_thread_main(mut can_worker: CanWorkerState) {
+    while !can_worker.is_shutdown() {
+        can_worker = can_worker.main_loop();
+    }
+    // Dropping the worker commands the pod into a safe state and closes the can socket
+    drop(can_worker);
+    info!("CAN THREAD: Shut down");
 }
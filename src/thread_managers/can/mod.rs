@@ -1,5 +1,8 @@
 mod worker;
 mod manager;
+mod frame_logger;
+mod unknown_ids;
 
 pub use manager::CanManager;
 pub use worker::CanWorkerInitializer;
+pub(crate) use worker::SAFE_POD_STATE;
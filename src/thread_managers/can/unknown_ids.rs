@@ -0,0 +1,89 @@
+use std::collections::BTreeMap;
+use std::time::{ Duration, Instant };
+use crate::can_extentions::can_ids;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn summary_counts_each_id_and_what_did_not_fit() {
+        let mut unknown_ids = UnknownIdCounter::new(Duration::from_secs(10), 2);
+        for _ in 0..42 {
+            unknown_ids.record(0x055);
+        }
+        unknown_ids.record(0x1ABC_DEF0);
+        unknown_ids.record(0x056);
+        unknown_ids.record(0x057);
+        unknown_ids.record(0x055);
+
+        assert_eq!(unknown_ids.take_summary(), vec![
+            String::from("id 0x055 seen 43 times"),
+            String::from("id 0x1abcdef0 seen 1 times"),
+            String::from("2 frames from other unknown ids were not counted by id")
+        ]);
+        // Every summary covers the frames since the last one
+        assert!(unknown_ids.take_summary().is_empty());
+    }
+}
+
+/**
+ * @struct UnknownIdCounter
+ * @brief Counts the frames received with ids the relay does not decode, so that the ids actually
+ * on the bus can be found when a new board is integrated. Only max_ids distinct ids are counted
+ * at a time, frames from any more are added to a single total so a noisy bus can't grow the map
+ */
+pub struct UnknownIdCounter {
+    counts: BTreeMap<u32, u64>,
+    untracked: u64, // Frames from ids which arrived once the map was full
+    max_ids: usize,
+    summary_interval: Duration,
+    last_summary: Instant
+}
+
+impl UnknownIdCounter {
+    pub fn new(summary_interval: Duration, max_ids: usize) -> UnknownIdCounter {
+        UnknownIdCounter {
+            counts: BTreeMap::new(),
+            untracked: 0,
+            max_ids,
+            summary_interval,
+            last_summary: Instant::now()
+        }
+    }
+
+    pub fn record(&mut self, id: u32) {
+        if let Some(count) = self.counts.get_mut(&id) {
+            *count += 1;
+        } else if self.counts.len() < self.max_ids {
+            self.counts.insert(id, 1);
+        } else {
+            self.untracked += 1;
+        }
+    }
+
+    pub fn summary_due(&self) -> bool {
+        self.last_summary.elapsed() >= self.summary_interval
+    }
+
+    /**
+     * @brief take_summary
+     * One line per unknown id seen since the last summary, then the counts are cleared
+     */
+    pub fn take_summary(&mut self) -> Vec<String> {
+        self.last_summary = Instant::now();
+        let mut summary: Vec<String> = self.counts.iter().map(|(&id, count)| {
+            if can_ids::is_extended(id) {
+                format!("id {:#010x} seen {} times", id, count)
+            } else {
+                format!("id {:#05x} seen {} times", id, count)
+            }
+        }).collect();
+        if self.untracked > 0 {
+            summary.push(format!("{} frames from other unknown ids were not counted by id", self.untracked));
+        }
+        self.counts.clear();
+        self.untracked = 0;
+        summary
+    }
+}
@@ -1,44 +1,629 @@
 use super::super::worker_states::*;
+use log::{ debug, info, warn, error };
 use super::super::messages::*;
 use super::super::main_loop::*;
 use crate::board_states::{BoardStates};
 use crate::pod_states::PodState;
+use crate::config::RoboteqConfig;
+use crate::metrics::Metrics;
+use crate::utils::bounded_channel::BoundedSender;
+use std::sync::Arc;
 use std::sync::mpsc::{ Receiver, Sender };
 use std::time::{Duration, Instant};
 use std::convert::TryInto;
-use socketcan::ShouldRetry;
 use crate::can_extentions::prelude::*;
 use crate::can_extentions::ack_nack::AckNack;
+use crate::can_extentions::fault_reports::FaultReport;
+use super::frame_logger::CanFrameLogger;
+use super::unknown_ids::UnknownIdCounter;
+use super::super::backoff::backoff_delay;
 
+#[cfg(test)]
+pub(super) mod test {
+    use super::*;
+    use crate::can_extentions::can_ids;
+    use crate::can_extentions::fault_reports::*;
+    use std::collections::VecDeque;
+    use std::sync::{ Arc, Mutex, mpsc::channel };
+    use crate::utils::bounded_channel::{ bounded_channel, BoundedReceiver, FullPolicy };
+
+    /**
+     * Stands in for the bus. Reads replay the scripted frames, then find nothing to read as a timed out
+     * socket would, and every write is recorded
+     */
+    #[derive(Clone, Default)]
+    pub(crate) struct ScriptedSocket {
+        frames: Arc<Mutex<VecDeque<CANFrame>>>,
+        pub(crate) written: Arc<Mutex<Vec<CANFrame>>>,
+        bus_off: Arc<Mutex<bool>> // Every write fails, as it would on an interface which has gone bus-off
+    }
+
+    impl ScriptedSocket {
+        fn push(&self, command: CanCommand) {
+            self.frames.lock().unwrap().push_back(CANFrame::to_frame(&command).unwrap());
+        }
+
+        fn throttle_frames(&self) -> usize {
+            self.throttle_values().len()
+        }
+
+        /**
+         * The throttle percent of every throttle command written, in order
+         */
+        fn throttle_values(&self) -> Vec<u32> {
+            self.written.lock().unwrap().iter()
+                .filter(|frame| frame.id() == can_ids::ROBOTEQ_REQUEST_BASE + 1 && frame.data()[1..3] == [0x00, 0x20])
+                .map(|frame| u32::from_le_bytes([frame.data()[4], frame.data()[5], frame.data()[6], frame.data()[7]]))
+                .collect()
+        }
+    }
+
+    impl CanFrameSocket for ScriptedSocket {
+        fn read_frame(&self) -> std::io::Result<CANFrame> {
+            self.frames.lock().unwrap().pop_front().ok_or_else(|| std::io::Error::from(std::io::ErrorKind::WouldBlock))
+        }
+
+        fn write_frame(&self, frame: &CANFrame) -> std::io::Result<()> {
+            if *self.bus_off.lock().unwrap() {
+                return Err(std::io::Error::from(std::io::ErrorKind::Other));
+            }
+            self.written.lock().unwrap().push(*frame);
+            Ok(())
+        }
+
+        fn write_frame_insist(&self, frame: &CANFrame) -> std::io::Result<()> {
+            self.write_frame(frame)
+        }
+
+        fn set_read_timeout(&self, _duration: Duration) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn scripted_worker() -> (CanWorker<Disconnected>, ScriptedSocket, Sender<CanMessage>, Receiver<UDPMessage>, BoundedReceiver<WorkerMessage>) {
+        scripted_worker_with_dry_run(false)
+    }
+
+    fn scripted_worker_with_dry_run(dry_run: bool) -> (CanWorker<Disconnected>, ScriptedSocket, Sender<CanMessage>, Receiver<UDPMessage>, BoundedReceiver<WorkerMessage>) {
+        let socket = ScriptedSocket::default();
+        let (udp_message_sender, udp_message_receiver) = channel();
+        let (worker_message_sender, worker_message_receiver) = bounded_channel(16, FullPolicy::DropOldest);
+        let (can_message_sender, can_message_receiver) = channel();
+        let worker = CanWorker::with_socket(Box::new(socket.clone()), CanWorkerInitializer {
+            can_interface: String::from("scripted"),
+            udp_message_sender,
+            worker_message_sender,
+            can_message_receiver,
+            can_socket_read_timeout: Duration::from_millis(10),
+            can_max_number_timeouts: 3,
+            roboteq: RoboteqConfig::default(),
+            log_path: None,
+            dry_run,
+            state_change_timeout: Duration::from_secs(60),
+            state_change_retries: 3,
+            metrics: Arc::new(Metrics::new())
+        });
+        (worker, socket, can_message_sender, udp_message_receiver, worker_message_receiver)
+    }
+
+    /**
+     * Run one iteration of the main loop with the periodic messages due
+     */
+    fn step(mut worker: CanWorker<Disconnected>) -> CanWorker<Disconnected> {
+        worker.last_send = Instant::now() - Duration::from_millis(400);
+        run(worker)
+    }
+
+    /**
+     * Run one iteration of the main loop, without forcing the periodic messages
+     */
+    fn run(worker: CanWorker<Disconnected>) -> CanWorker<Disconnected> {
+        match worker.main_loop() {
+            CanWorkerState::Disconnected(worker) => worker,
+            _ => panic!("The can worker only runs while Disconnected")
+        }
+    }
+
+    #[test]
+    fn final_frame_commands_low_voltage() {
+        let frame = final_pod_state_frame().unwrap();
+        assert_eq!(frame.id(), can_ids::POD_STATE);
+        assert_eq!(frame.data(), &[PodState::LowVoltage.to_byte()]);
+    }
+
+    #[test]
+    fn throttle_waits_for_motor_controller() {
+        let mut board_state = BoardStates::default();
+        board_state.set_bms_state(&PodState::AutoPilot);
+        assert!(!throttle_allowed(&PodState::AutoPilot, &board_state));
+
+        board_state.set_motor_controller_state(&PodState::AutoPilot);
+        assert!(throttle_allowed(&PodState::AutoPilot, &board_state));
+        assert!(!throttle_allowed(&PodState::Braking, &board_state));
+    }
+
+    #[test]
+    fn low_voltage_frames_ack_the_return_to_low_voltage() {
+        let (worker, socket, can_message_sender, udp_message_receiver, _worker_message_receiver) = scripted_worker();
+        can_message_sender.send(CanMessage::ChangeState(PodState::Armed)).unwrap();
+        let worker = step(worker);
+        socket.push(CanCommand::BmsStateChange(AckNack::Ack));
+        socket.push(CanCommand::PressureStateChange(AckNack::Ack));
+        socket.push(CanCommand::MotorControllerStateChange(AckNack::Ack));
+        let worker = step(step(step(worker)));
+        assert!(matches!(udp_message_receiver.try_recv(), Ok(UDPMessage::PodStateChangeAck)));
+
+        // Recovery in the udp thread completes on this ack, so it must wait for the BMS along with every other board
+        can_message_sender.send(CanMessage::ChangeState(PodState::LowVoltage)).unwrap();
+        let worker = step(worker);
+        socket.push(CanCommand::PressureStateChange(AckNack::Ack));
+        socket.push(CanCommand::MotorControllerStateChange(AckNack::Ack));
+        let worker = step(step(worker));
+        assert!(udp_message_receiver.try_recv().is_err());
+        socket.push(CanCommand::BmsStateChange(AckNack::Ack));
+        let worker = step(worker);
+        assert!(matches!(udp_message_receiver.try_recv(), Ok(UDPMessage::PodStateChangeAck)));
+        assert_eq!(worker.current_pod_state, PodState::LowVoltage);
+    }
+
+    #[test]
+    fn dry_run_decodes_but_never_writes() {
+        let (worker, socket, can_message_sender, udp_message_receiver, worker_message_receiver) = scripted_worker_with_dry_run(true);
+        can_message_sender.send(CanMessage::ChangeState(PodState::Armed)).unwrap();
+        let worker = step(worker);
+        socket.push(CanCommand::BmsStateChange(AckNack::Ack));
+        socket.push(CanCommand::PressureStateChange(AckNack::Ack));
+        socket.push(CanCommand::MotorControllerStateChange(AckNack::Ack));
+        let worker = step(step(step(worker)));
+        assert!(matches!(udp_message_receiver.try_recv(), Ok(UDPMessage::PodStateChangeAck)));
+        assert!(worker_message_receiver.try_recv().is_ok());
+
+        // The throttle is still gated on every board reaching AutoPilot, the dry run only logs it
+        can_message_sender.send(CanMessage::ChangeState(PodState::AutoPilot)).unwrap();
+        let worker = step(worker);
+        socket.push(CanCommand::BmsStateChange(AckNack::Ack));
+        socket.push(CanCommand::PressureStateChange(AckNack::Ack));
+        socket.push(CanCommand::MotorControllerStateChange(AckNack::Ack));
+        let worker = step(step(step(worker)));
+        assert_eq!(worker.current_pod_state, PodState::AutoPilot);
+        assert!(throttle_allowed(&worker.current_pod_state, &worker.board_state));
+
+        // Not even the safe state on exit is written
+        drop(worker);
+        assert!(socket.written.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn unacknowledged_state_change_is_retried_then_escalated() {
+        let (mut worker, socket, can_message_sender, udp_message_receiver, _worker_message_receiver) = scripted_worker();
+        worker.state_change_timeout = Duration::from_millis(0);
+        worker.state_change_retries = 2;
+        worker.can_max_number_timeouts = 100;
+        can_message_sender.send(CanMessage::ChangeState(PodState::Armed)).unwrap();
+        // The request is taken, the wait for its ack starts, then it is sent again twice
+        let worker = run(run(run(run(worker))));
+        let armed_frames = socket.written.lock().unwrap().iter()
+            .filter(|frame| frame.id() == can_ids::POD_STATE && frame.data() == [PodState::Armed.to_byte()])
+            .count();
+        assert_eq!(armed_frames, 2);
+        assert!(udp_message_receiver.try_recv().is_err());
+
+        let worker = run(worker);
+        assert!(matches!(udp_message_receiver.try_recv(), Ok(UDPMessage::StateChangeUnacknowledged(PodState::Armed))));
+        assert!(matches!(udp_message_receiver.try_recv(), Ok(UDPMessage::SystemFault)));
+        assert_eq!(worker.requested_pod_state, PodState::SystemFailure);
+    }
+
+    #[test]
+    fn acknowledged_state_change_is_not_retried() {
+        let (mut worker, socket, can_message_sender, udp_message_receiver, _worker_message_receiver) = scripted_worker();
+        worker.state_change_retries = 0;
+        worker.can_max_number_timeouts = 100;
+        can_message_sender.send(CanMessage::ChangeState(PodState::Armed)).unwrap();
+        let worker = run(worker);
+        socket.push(CanCommand::BmsStateChange(AckNack::Ack));
+        socket.push(CanCommand::PressureStateChange(AckNack::Ack));
+        socket.push(CanCommand::MotorControllerStateChange(AckNack::Ack));
+        let mut worker = run(run(run(worker)));
+        assert_eq!(worker.current_pod_state, PodState::Armed);
+        assert!(worker.pending_state_change.is_none());
+        // Once acked, the state change can no longer time out
+        worker.state_change_timeout = Duration::from_millis(0);
+        run(run(worker));
+        assert!(matches!(udp_message_receiver.try_recv(), Ok(UDPMessage::PodStateChangeAck)));
+        assert!(udp_message_receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn failing_writes_reopen_the_socket() {
+        let (mut worker, socket, _can_message_sender, udp_message_receiver, _worker_message_receiver) = scripted_worker();
+        worker.can_max_number_timeouts = 100;
+        *socket.bus_off.lock().unwrap() = true;
+        let reopened = ScriptedSocket::default();
+        let opener_socket = reopened.clone();
+        let open_attempts = Arc::new(Mutex::new(0));
+        let opener_attempts = Arc::clone(&open_attempts);
+        worker.socket_opener = Some(Box::new(move || {
+            *opener_attempts.lock().unwrap() += 1;
+            if *opener_attempts.lock().unwrap() == 1 {
+                return Err(CanError::UnableToSetTimeout(std::io::Error::from(std::io::ErrorKind::Other)));
+            }
+            let can_handle: Box<dyn CanFrameSocket + Send> = Box::new(opener_socket.clone());
+            Ok(can_handle)
+        }));
+
+        // Every periodic send fails, so the bus degrades on the second and the first re-open is tried straight away
+        let mut worker = step(step(worker));
+        assert!(matches!(udp_message_receiver.try_recv(), Ok(UDPMessage::CanBusDegraded)));
+        assert_eq!(*open_attempts.lock().unwrap(), 1);
+        assert!(worker.next_reopen_at > Instant::now());
+
+        worker.next_reopen_at = Instant::now();
+        let worker = step(worker);
+        assert_eq!(*open_attempts.lock().unwrap(), 2);
+        let worker = step(worker);
+        assert!(!reopened.written.lock().unwrap().is_empty());
+        assert!(!worker.bus_degraded);
+        assert!(matches!(udp_message_receiver.try_recv(), Ok(UDPMessage::CanBusRecovered)));
+        assert!(udp_message_receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn braking_commands_zero_throttle() {
+        let mut board_state = BoardStates::default();
+        board_state.set_bms_state(&PodState::AutoPilot);
+        board_state.set_motor_controller_state(&PodState::AutoPilot);
+        assert_eq!(roboteq_throttle(&PodState::AutoPilot, &PodState::AutoPilot, &board_state, 40), Some(40));
+        // Braking is commanded as soon as it is requested, before the boards have acked it
+        assert_eq!(roboteq_throttle(&PodState::AutoPilot, &PodState::Braking, &board_state, 40), Some(0));
+        assert_eq!(roboteq_throttle(&PodState::LowVoltage, &PodState::LowVoltage, &BoardStates::default(), 40), None);
+
+        let (worker, socket, can_message_sender, _udp_message_receiver, _worker_message_receiver) = scripted_worker();
+        can_message_sender.send(CanMessage::ChangeState(PodState::Armed)).unwrap();
+        let worker = step(worker);
+        socket.push(CanCommand::BmsStateChange(AckNack::Ack));
+        socket.push(CanCommand::PressureStateChange(AckNack::Ack));
+        socket.push(CanCommand::MotorControllerStateChange(AckNack::Ack));
+        let worker = step(step(step(worker)));
+        can_message_sender.send(CanMessage::ChangeState(PodState::AutoPilot)).unwrap();
+        let worker = step(worker);
+        socket.push(CanCommand::BmsStateChange(AckNack::Ack));
+        socket.push(CanCommand::PressureStateChange(AckNack::Ack));
+        socket.push(CanCommand::MotorControllerStateChange(AckNack::Ack));
+        let worker = step(step(step(worker)));
+        let throttle_percent = RoboteqConfig::default().throttle_percent;
+        assert!(socket.throttle_values().iter().all(|&throttle| throttle == throttle_percent));
+
+        socket.written.lock().unwrap().clear();
+        can_message_sender.send(CanMessage::BrakingTimerTimeout).unwrap();
+        let worker = step(step(worker));
+        assert_eq!(worker.requested_pod_state, PodState::Braking);
+        assert!(socket.written.lock().unwrap().iter().any(|frame| frame.id() == can_ids::POD_STATE && frame.data() == [PodState::Braking.to_byte()]));
+        let throttle_values = socket.throttle_values();
+        assert!(!throttle_values.is_empty());
+        assert!(throttle_values.iter().all(|&throttle| throttle == 0));
+    }
+
+    #[test]
+    fn throttle_gating_for_every_board_state() {
+        let states = [PodState::LowVoltage, PodState::Armed, PodState::AutoPilot, PodState::Braking, PodState::SystemFailure];
+        let roboteq = RoboteqConfig::default();
+        for &requested in &states {
+            for &bms in &states {
+                for &mc in &states {
+                    let (mut worker, socket, _can_message_sender, _udp_message_receiver, _worker_message_receiver) = scripted_worker();
+                    worker.requested_pod_state = requested;
+                    worker.current_pod_state = requested;
+                    worker.board_state.set_bms_state(&bms);
+                    worker.board_state.set_motor_controller_state(&mc);
+                    let worker = step(worker);
+
+                    let expected_throttle = match requested {
+                        PodState::AutoPilot if bms == PodState::AutoPilot && mc == PodState::AutoPilot => vec![roboteq.throttle_percent; roboteq.channels.len()],
+                        PodState::Braking => vec![0; roboteq.channels.len()],
+                        _ => vec![]
+                    };
+                    assert_eq!(socket.throttle_values(), expected_throttle, "requested {:?}, bms {:?}, mc {:?}", requested, bms, mc);
+                    // The pod state is sent every time, whether or not the roboteq is throttled
+                    let pod_state_frames: Vec<Vec<u8>> = socket.written.lock().unwrap().iter()
+                        .filter(|frame| frame.id() == can_ids::POD_STATE)
+                        .map(|frame| frame.data().to_vec())
+                        .collect();
+                    assert_eq!(pod_state_frames, vec![vec![requested.to_byte()]], "requested {:?}, bms {:?}, mc {:?}", requested, bms, mc);
+                    drop(worker);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn throttle_is_sent_once_every_board_acks() {
+        let (worker, socket, can_message_sender, udp_message_receiver, _worker_message_receiver) = scripted_worker();
+        can_message_sender.send(CanMessage::ChangeState(PodState::Armed)).unwrap();
+        let worker = step(worker);
+        socket.push(CanCommand::BmsStateChange(AckNack::Ack));
+        socket.push(CanCommand::PressureStateChange(AckNack::Ack));
+        socket.push(CanCommand::MotorControllerStateChange(AckNack::Ack));
+        let worker = step(step(step(worker)));
+        assert!(matches!(udp_message_receiver.try_recv(), Ok(UDPMessage::PodStateChangeAck)));
+
+        can_message_sender.send(CanMessage::ChangeState(PodState::AutoPilot)).unwrap();
+        let worker = step(worker);
+
+        // There is no motor controller on the pod yet, so the transition completes without it, but the roboteq waits for it
+        socket.push(CanCommand::BmsStateChange(AckNack::Ack));
+        socket.push(CanCommand::PressureStateChange(AckNack::Ack));
+        let worker = step(step(worker));
+        assert!(matches!(udp_message_receiver.try_recv(), Ok(UDPMessage::PodStateChangeAck)));
+        assert_eq!(worker.current_pod_state, PodState::AutoPilot);
+        assert_eq!(socket.throttle_frames(), 0);
+
+        socket.push(CanCommand::MotorControllerStateChange(AckNack::Ack));
+        let worker = step(worker);
+        assert_eq!(socket.throttle_frames(), RoboteqConfig::default().channels.len());
+
+        // Tuning the throttle takes effect on the next throttle command
+        socket.written.lock().unwrap().clear();
+        can_message_sender.send(CanMessage::SetThrottle(55)).unwrap();
+        let worker = step(worker);
+        assert_eq!(socket.throttle_values(), vec![55; RoboteqConfig::default().channels.len()]);
+
+        // Leaving AutoPilot, e.g. to recover after a DISCONNECT, goes back to the configured throttle
+        can_message_sender.send(CanMessage::ChangeState(PodState::Braking)).unwrap();
+        let worker = step(worker);
+        socket.push(CanCommand::BmsStateChange(AckNack::Ack));
+        socket.push(CanCommand::PressureStateChange(AckNack::Ack));
+        socket.push(CanCommand::MotorControllerStateChange(AckNack::Ack));
+        let worker = step(step(step(worker)));
+        assert_eq!(worker.current_pod_state, PodState::Braking);
+        assert_eq!(worker.tuned_throttle_percent, None);
+    }
+
+    #[test]
+    fn illegal_transition_is_rejected() {
+        let (worker, socket, can_message_sender, udp_message_receiver, _worker_message_receiver) = scripted_worker();
+        can_message_sender.send(CanMessage::ChangeState(PodState::AutoPilot)).unwrap();
+        let worker = step(worker);
+        assert!(matches!(udp_message_receiver.try_recv(), Ok(UDPMessage::StateTransitionRejected(PodState::AutoPilot))));
+        assert_eq!(worker.requested_pod_state, PodState::LowVoltage);
+
+        can_message_sender.send(CanMessage::ChangeState(PodState::Armed)).unwrap();
+        let worker = step(worker);
+        assert!(udp_message_receiver.try_recv().is_err());
+        assert_eq!(worker.requested_pod_state, PodState::Armed);
+        assert!(socket.written.lock().unwrap().iter().any(|frame| frame.id() == can_ids::POD_STATE && frame.data() == [PodState::Armed.to_byte()]));
+    }
+
+    #[test]
+    fn dead_udp_thread_does_not_stop_the_can_thread() {
+        let (worker, socket, can_message_sender, udp_message_receiver, _worker_message_receiver) = scripted_worker();
+        drop(udp_message_receiver);
+        can_message_sender.send(CanMessage::ChangeState(PodState::Armed)).unwrap();
+        let worker = step(worker);
+        socket.push(CanCommand::BmsStateChange(AckNack::Nack));
+        let worker = step(worker);
+        can_message_sender.send(CanMessage::DeviceLost).unwrap();
+        let worker = step(worker);
+        assert_eq!(worker.requested_pod_state, PodState::SystemFailure);
+    }
+
+    #[test]
+    fn fault_frames_reach_the_udp_thread() {
+        let (worker, socket, _can_message_sender, udp_message_receiver, _worker_message_receiver) = scripted_worker();
+        socket.push(CanCommand::MotorControllerFaultReport(MotorControllerFaultReport{ severity_code: SeverityCode::SEVERE }));
+        let worker = step(worker);
+        assert!(matches!(udp_message_receiver.try_recv(), Ok(UDPMessage::FaultReported(FaultReport::MotorController(_)))));
+        drop(worker);
+        // The worker commands the safe state as it is dropped
+        assert_eq!(socket.written.lock().unwrap().last().unwrap().data(), &[SAFE_POD_STATE.to_byte()]);
+    }
+
+    #[test]
+    fn fault_frames_are_reported() {
+        let frame = CANFrame::new(can_ids::BMS_FAULT_REPORT, &[0x1, 0x6], false, false).unwrap();
+        let fault = fault_report(&frame.get_command().unwrap()).unwrap();
+        assert_eq!(fault, FaultReport::Bms(BmsFaultReport{ severity_code: SeverityCode::DANGER, error_code: BmsErrorCode::CELL_TEMPERATURE }));
+
+        let json = serde_json::to_value(&fault).unwrap();
+        assert_eq!(json["board"], "Bms");
+        assert_eq!(json["report"]["error_code"], "CELL_TEMPERATURE");
+
+        let frame = CANFrame::new(can_ids::POD_SPEED, &[0x00, 0x00, 0x2C, 0x41], false, false).unwrap();
+        assert_eq!(fault_report(&frame.get_command().unwrap()), None);
+    }
+}
+
+/**
+ * State commanded on the bus when the can thread exits, or by the supervisor when any thread dies,
+ * so the pod is never left in whatever state it was last told to be in
+ */
+pub(crate) const SAFE_POD_STATE: PodState = PodState::LowVoltage;
+
+/**
+ * Consecutive failed reads and writes before the bus is considered degraded. An interface which has
+ * gone bus-off fails every write, so this is reached within a few periodic sends
+ */
+const MAX_CONSECUTIVE_BUS_ERRORS: u32 = 10;
+/**
+ * Unknown ids are summarized this often. At most MAX_TRACKED_UNKNOWN_IDS distinct ids are counted between summaries
+ */
+const UNKNOWN_ID_SUMMARY_INTERVAL: Duration = Duration::from_secs(10);
+const MAX_TRACKED_UNKNOWN_IDS: usize = 64;
+const SOCKET_REOPEN_BACKOFF_BASE: Duration = Duration::from_millis(100);
+const SOCKET_REOPEN_BACKOFF_CAP: Duration = Duration::from_millis(5000);
+
+/**
+ * Opens a fresh socket on the interface the worker was started on
+ */
+type SocketOpener = Box<dyn Fn() -> Result<Box<dyn CanFrameSocket + Send>, CanError> + Send>;
+
+fn final_pod_state_frame() -> Result<CANFrame, CanError> {
+    pod_state_frame(&SAFE_POD_STATE)
+}
+
+/**
+ * @brief transition_allowed
+ * Whether a ChangeState request may be sent on the bus. Repeating the pending request is harmless,
+ * anything else has to be an edge of the state graph, which includes the emergency edges to the safe state
+ */
+fn transition_allowed(current_pod_state: &PodState, requested_pod_state: &PodState, new_state: &PodState) -> bool {
+    new_state == requested_pod_state
+    || current_pod_state.can_transition_to(new_state)
+}
+
+/**
+ * @brief throttle_allowed
+ * The roboteq is only throttled once both the BMS and the motor controller have acknowledged AutoPilot
+ */
+fn throttle_allowed(requested_pod_state: &PodState, board_state: &BoardStates) -> bool {
+    *requested_pod_state == PodState::AutoPilot
+    && *board_state.get_bms_state() == *requested_pod_state
+    && *board_state.get_motor_controller_state() == *requested_pod_state
+}
+
+/**
+ * @brief roboteq_throttle
+ * The throttle to command the roboteq with, if any. The configured throttle once AutoPilot has been
+ * reached, and zero while braking so that the motors stop driving the pod as it decelerates
+ */
+fn roboteq_throttle(current_pod_state: &PodState, requested_pod_state: &PodState, board_state: &BoardStates, throttle_percent: u32) -> Option<u32> {
+    if *requested_pod_state == PodState::Braking {
+        Some(0)
+    } else if current_pod_state == requested_pod_state && throttle_allowed(requested_pod_state, board_state) {
+        Some(throttle_percent)
+    } else {
+        None
+    }
+}
+
+/**
+ * A state change which the boards have not all acknowledged yet
+ */
+#[derive(Clone, Copy)]
+struct PendingStateChange {
+    state: PodState,
+    sent_at: Instant, // Start of the current wait for the acks
+    retries: u32,
+    escalated: bool // Every retry went unacknowledged and the controller has been told
+}
+
+impl PendingStateChange {
+    fn new(state: PodState) -> PendingStateChange {
+        PendingStateChange {
+            state,
+            sent_at: Instant::now(),
+            retries: 0,
+            escalated: false
+        }
+    }
+}
+
+/**
+ * @brief fault_report
+ * The fault carried by a command, if it is one of the boards' fault reports
+ */
+fn fault_report(command: &CanCommand) -> Option<FaultReport> {
+    match *command {
+        CanCommand::BmsFaultReport(report) => Some(FaultReport::Bms(report)),
+        CanCommand::MotorControllerFaultReport(report) => Some(FaultReport::MotorController(report)),
+        _ => None
+    }
+}
 
 #[repr(C)] //* Required for type transmutations
 pub struct CanWorker<State = Startup> {
     // can_interface: String,
-    can_handle: socketcan::CANSocket,
+    can_handle: Box<dyn CanFrameSocket + Send>,
     udp_sender: Sender<UDPMessage>,
-    worker_sender: Sender<WorkerMessage>,
+    worker_sender: BoundedSender<WorkerMessage>,
     can_receiver: Receiver<CanMessage>,
     requested_pod_state: PodState,
     current_pod_state: PodState,
     board_state: BoardStates,
     last_send: Instant,
+    timeout_counter: u32,
+    can_max_number_timeouts: u32,
+    roboteq: RoboteqConfig,
+    tuned_throttle_percent: Option<u32>, // Set by THROTTLE in place of roboteq.throttle_percent, until the pod leaves AutoPilot
+    frame_logger: Option<CanFrameLogger>,
+    unknown_ids: UnknownIdCounter,
+    metrics: Arc<Metrics>,
+    pending_state_change: Option<PendingStateChange>,
+    state_change_timeout: Duration,
+    state_change_retries: u32,
+    socket_opener: Option<SocketOpener>, // None when the worker was given a socket it can't re-open, such as a replay
+    can_socket_read_timeout: Duration,
+    dry_run: bool,
+    bus_error_counter: u32, // Consecutive failed reads and writes, reset by any that succeeds
+    bus_degraded: bool,
+    reopen_attempts: u32, // Socket re-opens since the bus degraded. Drives the re-open backoff
+    next_reopen_at: Instant,
+    shutdown_requested: bool,
     state: std::marker::PhantomData<State>
 }
 
 pub struct CanWorkerInitializer {
     pub can_interface: String,
     pub udp_message_sender: Sender<UDPMessage>,
-    pub worker_message_sender: Sender<WorkerMessage>,
+    pub worker_message_sender: BoundedSender<WorkerMessage>,
     pub can_message_receiver: Receiver<CanMessage>,
-    pub can_socket_read_timeout: Duration
+    pub can_socket_read_timeout: Duration,
+    pub can_max_number_timeouts: u32,
+    pub roboteq: RoboteqConfig,
+    pub log_path: Option<std::path::PathBuf>, // Every frame received is also written here in candump format
+    pub dry_run: bool, // Log the frames the relay would send instead of writing them to the bus
+    pub state_change_timeout: Duration, // Amount of time the boards have to acknowledge a state change before it is sent again
+    pub state_change_retries: u32, // Times a state change is sent again before the pod is put into SystemFailure
+    pub metrics: Arc<Metrics>
+}
+
+/**
+ * @brief prepare_socket
+ * In a dry run the socket is wrapped so that nothing is ever written to the bus
+ */
+fn prepare_socket(can_handle: Box<dyn CanFrameSocket + Send>, dry_run: bool) -> Box<dyn CanFrameSocket + Send> {
+    if dry_run {
+        Box::new(DryRunSocket::new(can_handle))
+    } else {
+        can_handle
+    }
 }
 
 impl CanWorker {
+    #[cfg(unix)] // Elsewhere there is no can interface to open, only the simulated bus through with_socket
     pub fn new(
         initializer: CanWorkerInitializer
     ) -> CanWorker<Disconnected> {
         let can_handle = socketcan::CANSocket::open(&initializer.can_interface).expect(&format!("Unable to Connect to CAN interface: {}", initializer.can_interface));
+        let can_interface = initializer.can_interface.clone();
+        let mut worker = CanWorker::with_socket(Box::new(can_handle), initializer);
+        worker.socket_opener = Some(Box::new(move || {
+            let can_handle: Box<dyn CanFrameSocket + Send> = Box::new(crate::can_extentions::open_socket(can_interface.as_str())?);
+            Ok(can_handle)
+        }));
+        worker
+    }
+
+    /**
+     * @brief with_socket
+     * Build a worker around an already open socket. The initializer's can_interface is not used,
+     * so the socket can not be re-opened if the bus degrades
+     */
+    pub fn with_socket(
+        can_handle: Box<dyn CanFrameSocket + Send>,
+        initializer: CanWorkerInitializer
+    ) -> CanWorker<Disconnected> {
+        if initializer.dry_run {
+            warn!("CAN THREAD: Dry run, nothing will be written to the bus");
+        }
+        let can_handle = prepare_socket(can_handle, initializer.dry_run);
         can_handle.set_read_timeout(initializer.can_socket_read_timeout).expect("Unable to Set Timeout on CAN Socket");
+        // The relay can run without the frame log, so failing to open it is not fatal
+        let frame_logger = initializer.log_path.as_ref().and_then(|path| {
+            CanFrameLogger::start(path, initializer.can_interface.clone())
+                .map_err(|err| error!("CAN THREAD: Unable to open the frame log {:?}: {:?}", path, err))
+                .ok()
+        });
         CanWorker {
             can_handle,
             udp_sender: initializer.udp_message_sender,
@@ -48,19 +633,75 @@ impl CanWorker {
             current_pod_state: PodState::LowVoltage,
             board_state: BoardStates::default(),
             last_send: Instant::now(),
+            timeout_counter: 0,
+            can_max_number_timeouts: initializer.can_max_number_timeouts,
+            roboteq: initializer.roboteq,
+            tuned_throttle_percent: None,
+            frame_logger,
+            unknown_ids: UnknownIdCounter::new(UNKNOWN_ID_SUMMARY_INTERVAL, MAX_TRACKED_UNKNOWN_IDS),
+            metrics: initializer.metrics,
+            pending_state_change: None,
+            state_change_timeout: initializer.state_change_timeout,
+            state_change_retries: initializer.state_change_retries,
+            socket_opener: None,
+            can_socket_read_timeout: initializer.can_socket_read_timeout,
+            dry_run: initializer.dry_run,
+            bus_error_counter: 0,
+            bus_degraded: false,
+            reopen_attempts: 0,
+            next_reopen_at: Instant::now(),
+            shutdown_requested: false,
             state: std::marker::PhantomData
         }
     }
 }
 
+/**
+ * Whichever way the can thread exits, whether it was asked to shutdown or it panicked,
+ * make a best effort attempt to leave the pod in a safe state before the socket is closed
+ */
+impl<State> Drop for CanWorker<State> {
+    fn drop(&mut self) {
+        let message_result = final_pod_state_frame()
+            .and_then(|frame| self.can_handle.write_frame_insist(&frame).map_err(|e| CanError::WriteError(e)));
+        match message_result {
+            Ok(()) => info!("CAN THREAD: Commanded {:?} before exiting", SAFE_POD_STATE),
+            Err(err) => {
+                error!("Error Sending Message on CAN bus: {:?}",  err);
+            }
+        }
+    }
+}
+
 pub type CanWorkerState = WorkerState<CanWorker<Startup>, CanWorker<Recovery>, CanWorker<Connected>, CanWorker<Disconnected>>;
 
 impl CanWorkerState {
+    #[cfg(unix)]
     pub fn new(
         initializer: CanWorkerInitializer
     ) -> CanWorkerState {
         CanWorkerState::Disconnected(CanWorker::new(initializer))
     }
+
+    pub fn with_socket(
+        can_handle: Box<dyn CanFrameSocket + Send>,
+        initializer: CanWorkerInitializer
+    ) -> CanWorkerState {
+        CanWorkerState::Disconnected(CanWorker::with_socket(can_handle, initializer))
+    }
+
+    /**
+     * @brief is_shutdown
+     * True once the worker has received CanMessage::Shutdown and should no longer be run
+     */
+    pub fn is_shutdown(&self) -> bool {
+        match self {
+            CanWorkerState::Startup(worker) => worker.shutdown_requested,
+            CanWorkerState::Disconnected(worker) => worker.shutdown_requested,
+            CanWorkerState::Connected(worker) => worker.shutdown_requested,
+            CanWorkerState::Recovery(worker) => worker.shutdown_requested,
+        }
+    }
 }
 
 /**
@@ -84,61 +725,273 @@ impl MainLoop<CanWorkerState> for CanWorker<Connected> {
         panic!("CanWorker does not implement connected");
     }
 }
+impl CanWorker<Disconnected> {
+    /**
+     * Message the udp thread. The can thread keeps commanding the pod if the udp thread has exited,
+     * so the message is logged and dropped rather than taking this thread down with it
+     */
+    fn notify_udp(&self, message: UDPMessage) {
+        if let Err(err) = self.udp_sender.send(message) {
+            error!("CAN THREAD: UDP Thread is not running, dropped {:?}", err.0);
+        }
+    }
+
+    /**
+     * @brief bus_silent
+     * Nothing has been heard on the bus for can_max_number_timeouts reads. The boards can no longer
+     * be trusted to be doing what we last asked, so command LowVoltage and let the controller know
+     */
+    fn bus_silent(&mut self) {
+        error!("CAN THREAD: No message received in {} reads, commanding {:?}", self.timeout_counter, SAFE_POD_STATE);
+        if self.requested_pod_state != PodState::SystemFailure {
+            self.requested_pod_state = SAFE_POD_STATE;
+        }
+        let message_result = self.can_handle.send_pod_state(&self.requested_pod_state);
+        self.record_write(message_result);
+        self.notify_udp(UDPMessage::SystemFault);
+    }
+
+    /**
+     * @brief record_write
+     * Log a failed write. A socket on an interface which has gone bus-off fails every write,
+     * so write errors count towards re-opening the socket
+     */
+    fn record_write(&mut self, result: Result<(), CanError>) {
+        match result {
+            Ok(()) => self.bus_ok(),
+            Err(err) => {
+                error!("Error Sending Message on CAN bus: {:?}",  err);
+                if let CanError::WriteError(_) = err {
+                    self.bus_error();
+                }
+            }
+        }
+    }
+
+    /**
+     * @brief bus_error
+     * Count a failed read or write. The controller is told once when the bus degrades, not on every error after
+     */
+    fn bus_error(&mut self) {
+        self.bus_error_counter = self.bus_error_counter.saturating_add(1);
+        if self.bus_error_counter == MAX_CONSECUTIVE_BUS_ERRORS && !self.bus_degraded {
+            error!("CAN THREAD: {} consecutive CAN bus errors, the bus is degraded", self.bus_error_counter);
+            if self.socket_opener.is_none() {
+                // Nothing else can be done for a socket the worker didn't open
+                warn!("CAN THREAD: The CAN socket can not be re-opened");
+            }
+            self.bus_degraded = true;
+            self.notify_udp(UDPMessage::CanBusDegraded);
+        }
+    }
+
+    /**
+     * @brief bus_ok
+     * A read or write succeeded. If the bus had degraded, it has now recovered
+     */
+    fn bus_ok(&mut self) {
+        self.bus_error_counter = 0;
+        if self.bus_degraded {
+            info!("CAN THREAD: CAN bus recovered after {} socket re-opens", self.reopen_attempts);
+            self.bus_degraded = false;
+            self.reopen_attempts = 0;
+            self.notify_udp(UDPMessage::CanBusRecovered);
+        }
+    }
+
+    /**
+     * @brief reopen_socket
+     * Replace the socket with a freshly opened one on the same interface. A failed attempt is retried
+     * after a backoff, which doubles with every attempt until the bus recovers
+     */
+    fn reopen_socket(&mut self) {
+        let socket_opener = match &self.socket_opener {
+            Some(socket_opener) => socket_opener,
+            None => return
+        };
+        let attempt = self.reopen_attempts.saturating_add(1);
+        info!("CAN THREAD: Re-opening the CAN socket, attempt {}", attempt);
+        let result = socket_opener().and_then(|can_handle| {
+            let can_handle = prepare_socket(can_handle, self.dry_run);
+            can_handle.set_read_timeout(self.can_socket_read_timeout).map_err(CanError::UnableToSetTimeout)?;
+            Ok(can_handle)
+        });
+        self.reopen_attempts = attempt;
+        match result {
+            Ok(can_handle) => {
+                info!("CAN THREAD: Re-opened the CAN socket on attempt {}", self.reopen_attempts);
+                self.can_handle = can_handle;
+                // The new socket gets a full run of errors before it is replaced in turn
+                self.bus_error_counter = 0;
+            },
+            Err(err) => {
+                let delay = backoff_delay(SOCKET_REOPEN_BACKOFF_BASE, SOCKET_REOPEN_BACKOFF_CAP, self.reopen_attempts - 1);
+                warn!("CAN THREAD: Unable to re-open the CAN socket on attempt {}: {}, trying again in {:?}", self.reopen_attempts, err, delay);
+                self.next_reopen_at = Instant::now() + delay;
+            }
+        }
+    }
+
+    /**
+     * @brief check_state_change_ack
+     * Every board the transition waits for has state_change_timeout to acknowledge the requested state. If one has not, the state
+     * is sent again, up to state_change_retries times. After that the controller is told, and anything
+     * short of SystemFailure is escalated to it, since a safe state which was never acked can't be relied on
+     */
+    fn check_state_change_ack(&mut self) {
+        if self.requested_pod_state == self.current_pod_state {
+            self.pending_state_change = None;
+            return;
+        }
+        let mut pending = match self.pending_state_change {
+            Some(pending) if pending.state == self.requested_pod_state => pending,
+            _ => {
+                self.pending_state_change = Some(PendingStateChange::new(self.requested_pod_state));
+                return;
+            }
+        };
+        if pending.escalated || pending.sent_at.elapsed() < self.state_change_timeout {
+            return;
+        }
+        let unacknowledged: Vec<&str> = [
+            ("BMS", self.board_state.get_bms_state()),
+            ("Pressure", self.board_state.get_pressure_state())
+        ].iter().filter(|(_, state)| **state != pending.state).map(|(board, _)| *board).collect();
+
+        if pending.retries < self.state_change_retries {
+            pending.retries += 1;
+            pending.sent_at = Instant::now();
+            warn!("CAN THREAD: {:?} has not acked {:?}, sending it again ({}/{})", unacknowledged, pending.state, pending.retries, self.state_change_retries);
+            let message_result = self.can_handle.send_pod_state(&pending.state);
+            self.record_write(message_result);
+        } else {
+            pending.escalated = true;
+            error!("CAN THREAD: {:?} never acked {:?} after {} retries", unacknowledged, pending.state, pending.retries);
+            self.notify_udp(UDPMessage::StateChangeUnacknowledged(pending.state));
+            if pending.state != PodState::SystemFailure {
+                self.requested_pod_state = PodState::SystemFailure;
+                self.notify_udp(UDPMessage::SystemFault);
+            }
+        }
+        self.pending_state_change = Some(pending);
+    }
+
+    /**
+     * @brief state_change_accepted
+     * Returns true if the board accepted the requested pod state. On a Nack the request is dropped
+     * and the udp thread is told, so that a board refusing to leave a state is never mistaken for success
+     */
+    fn state_change_accepted(&mut self, board: &str, ack_nack: AckNack) -> bool {
+        match ack_nack {
+            AckNack::Ack => true,
+            AckNack::Nack => {
+                warn!("CAN THREAD: {} rejected the transition to {:?}", board, self.requested_pod_state);
+                self.notify_udp(UDPMessage::StateTransitionRejected(self.requested_pod_state));
+                // A SystemFailure request must stand, the same as in CanMessage::ChangeState
+                if self.requested_pod_state != PodState::SystemFailure {
+                    self.requested_pod_state = self.current_pod_state;
+                }
+                false
+            },
+            AckNack::UNKNOWN => {
+                warn!("CAN THREAD: {} sent an unrecognized response to the transition to {:?}", board, self.requested_pod_state);
+                false
+            }
+        }
+    }
+}
+
 impl MainLoop<CanWorkerState> for CanWorker<Disconnected> {
  fn main_loop(mut self) -> CanWorkerState {
     let response = self.can_handle.read_frame(); // with timeout
     if response.should_retry() {
         // Timeout with no message
-        println!("CAN SOCKET: Read timeout no message Received");
+        debug!("CAN SOCKET: Read timeout no message Received");
+        self.timeout_counter += 1;
+        self.metrics.set_can_timeouts(self.timeout_counter);
+        // Only fault once per silent period, the counter is reset when the bus comes back
+        if self.timeout_counter == self.can_max_number_timeouts {
+            self.bus_silent();
+        }
     } else if let Ok(frame) = response {
         // Frame Received
+        self.timeout_counter = 0;
+        self.metrics.set_can_timeouts(0);
+        self.bus_ok();
+        self.metrics.can_frame_received();
+        let received = chrono::Utc::now();
+        let received_at = received.naive_local();
+        if let Some(frame_logger) = &self.frame_logger {
+            frame_logger.log(frame, received);
+        }
         // Check for state messages before passing the frame on to the worker
         match frame.get_command() {
-            CanCommand::BmsStateChange(ack_nack) => {
-                // println!("BMS STATE CHANGE ACC, {:?}", self.requested_pod_state);
-                match ack_nack {
-                    AckNack::Ack => {
-                        self.board_state.set_bms_state(&self.requested_pod_state);
-                    }
-                    _ => panic!("Received A NACK FROM BMS State Change. Don't know what to do!")
+            Ok(command) => {
+                if let Some(fault) = fault_report(&command) {
+                    warn!("CAN THREAD: Fault reported: {:?}", fault);
+                    self.notify_udp(UDPMessage::FaultReported(fault));
                 }
-            },
-            CanCommand::MotorControllerStateChange(ack_nack) => {
-                // println!("MC STATE CHANGE ACC, {:?}", self.requested_pod_state);
-                match ack_nack {
-                    AckNack::Ack => {
-                        self.board_state.set_motor_controller_state(&self.requested_pod_state);
+                match command {
+                    CanCommand::BmsStateChange(ack_nack) => {
+                        // println!("BMS STATE CHANGE ACC, {:?}", self.requested_pod_state);
+                        if self.state_change_accepted("BMS", ack_nack) {
+                            self.board_state.set_bms_state(&self.requested_pod_state);
+                        }
+                    },
+                    CanCommand::MotorControllerStateChange(ack_nack) => {
+                        // println!("MC STATE CHANGE ACC, {:?}", self.requested_pod_state);
+                        if self.state_change_accepted("MotorController", ack_nack) {
+                            self.board_state.set_motor_controller_state(&self.requested_pod_state);
+                        }
+                    },
+                    CanCommand::PressureStateChange(ack_nack) => {
+                        if self.state_change_accepted("Pressure", ack_nack) {
+                            self.board_state.set_pressure_state(&self.requested_pod_state);
+                        }
                     }
-                    _ => panic!("Received A NACK FROM MotorController State Change. Don't know what to do!")
+                    _ => {}
                 }
-            },
-            CanCommand::PressureStateChange(ack_nack) => {
-                match ack_nack {
-                    AckNack::Ack => {
-                        self.board_state.set_pressure_state(&self.requested_pod_state);
-                    }
-                    _ => panic!("Received A NACK FROM MotorController State Change. Don't know what to do!")
+                match self.worker_sender.send(WorkerMessage::CanFrameAndTimeStamp(frame, received_at)) {
+                    Ok(None) => {},
+                    Ok(Some(_)) => {
+                        self.metrics.worker_frame_dropped();
+                        debug!("CAN THREAD: Worker Thread has fallen behind, dropped a frame's telemetry");
+                    },
+                    Err(_) => error!("CAN THREAD: Worker Thread is not running, dropped the frame's telemetry")
                 }
+            },
+            Err(FrameDecodeError::UnknownId(id)) => {
+                self.metrics.can_frame_undecoded();
+                self.unknown_ids.record(id);
+                debug!("CAN SOCKET: Received frame with unknown id: {:#x}", id);
+            },
+            Err(FrameDecodeError::TruncatedPayload{ id, expected, got }) => {
+                self.metrics.can_frame_undecoded();
+                warn!("CAN SOCKET: Dropping corrupt frame {:#x}, expected {} bytes but got {}", id, expected, got);
             }
-            _ => {}
         }
-        self.worker_sender.send(WorkerMessage::CanFrameAndTimeStamp(frame, chrono::Utc::now().naive_local())).expect("Unable to send message from CAN Thread on Worker Channel");
     } else {
         // ERROR Reading from Can socket
-        println!("Error Reading from CAN Socket");
+        error!("Error Reading from CAN Socket");
+        self.bus_error();
     }
 
     // Check for Transition Complete
     if *self.board_state.get_bms_state() == self.requested_pod_state
     && *self.board_state.get_pressure_state() == self.requested_pod_state
-    // && *self.board_state.get_motor_controller_state() == self.requested_pod_state // NO MOTOR CONTROLLER
+    // && *self.board_state.get_motor_controller_state() == self.requested_pod_state // NO MOTOR CONTROLLER, it is only waited for before throttling
     && self.requested_pod_state != self.current_pod_state {
-        println!("Sending Ack to UDP for state change");
+        info!("Sending Ack to UDP for state change");
         self.current_pod_state = self.requested_pod_state;
-        self.udp_sender.send(UDPMessage::PodStateChangeAck).expect("unable to message UDP thread");
+        // A tuned throttle only lasts for the run it was set in, the next AutoPilot starts from the configured one
+        if self.current_pod_state != PodState::AutoPilot && self.tuned_throttle_percent.take().is_some() {
+            info!("CAN THREAD: Left AutoPilot, the throttle is back to the configured {}%", self.roboteq.throttle_percent);
+        }
+        self.notify_udp(UDPMessage::PodStateChangeAck);
     } else {
-        println!("CURRENT {:?}, BMS: {:?}, PYSDUCK: {:?}, REQUESTED: {:?}", self.current_pod_state, self.board_state.get_bms_state(), self.board_state.get_pressure_state(), self.requested_pod_state);
+        debug!("CURRENT {:?}, BMS: {:?}, MC: {:?}, PYSDUCK: {:?}, REQUESTED: {:?}", self.current_pod_state, self.board_state.get_bms_state(), self.board_state.get_motor_controller_state(), self.board_state.get_pressure_state(), self.requested_pod_state);
     }
+    self.check_state_change_ack();
 
     // check for state message from udp or timeout from worker
     if let Ok(message) = self.can_receiver.try_recv() {
@@ -146,104 +999,78 @@ impl MainLoop<CanWorkerState> for CanWorker<Disconnected> {
             CanMessage::ChangeState(new_state) => {
                 /* This check is just for safety. Since we deal with multiple workers, there could be race conditions. If DeviceLost is received, that needs to be the final state. */
                 if self.requested_pod_state != PodState::SystemFailure {
-                    self.requested_pod_state = new_state;
+                    if transition_allowed(&self.current_pod_state, &self.requested_pod_state, &new_state) {
+                        self.requested_pod_state = new_state;
+                    } else {
+                        warn!("CAN THREAD: Rejected the illegal transition from {:?} to {:?}", self.current_pod_state, new_state);
+                        self.notify_udp(UDPMessage::StateTransitionRejected(new_state));
+                    }
                 }
             }
             CanMessage::DeviceLost => {
                 self.requested_pod_state = PodState::SystemFailure;
-                self.udp_sender.send(UDPMessage::SystemFault).unwrap();
+                self.notify_udp(UDPMessage::SystemFault);
             },
             CanMessage::BrakingTimerTimeout => {
                 if self.current_pod_state == PodState::AutoPilot {
                     self.requested_pod_state = PodState::Braking;
                 }
             }
+            CanMessage::SetThrottle(throttle_percent) => {
+                info!("CAN THREAD: AutoPilot throttle changed from {}% to {}%", self.tuned_throttle_percent.unwrap_or(self.roboteq.throttle_percent), throttle_percent);
+                self.tuned_throttle_percent = Some(u32::from(throttle_percent));
+            }
+            CanMessage::Shutdown => {
+                // The safe pod state is sent when the worker is dropped
+                self.requested_pod_state = SAFE_POD_STATE;
+                self.shutdown_requested = true;
+                return CanWorkerState::Disconnected(self);
+            }
         }
     }
 
     if self.last_send.elapsed().as_millis() >= 400 {
         self.last_send = Instant::now();
         let message_result = self.can_handle.send_pod_state(&self.requested_pod_state);
-
-        match message_result {
-            Ok(()) => {},
-            Err(err) => {
-                println!("Error Sending Message on CAN bus: {:?}",  err);
-            }
-        }
+        self.record_write(message_result);
 
         /* ROBOT EQ Data queries */
-        let message_result = self.can_handle.roboteq_read_battery_amps(1, 1);
-        match message_result {
-            Ok(()) => {},
-            Err(err) => {
-                println!("Error Sending Message on CAN bus: {:?}",  err);
-            }
-        }
-        let message_result = self.can_handle.roboteq_read_battery_amps(1, 2);
-        match message_result {
-            Ok(()) => {},
-            Err(err) => {
-                println!("Error Sending Message on CAN bus: {:?}",  err);
-            }
-        }
-        let message_result = self.can_handle.roboteq_read_encoder_motor_speed(1, 1);
-        match message_result {
-            Ok(()) => {},
-            Err(err) => {
-                println!("Error Sending Message on CAN bus: {:?}",  err);
-            }
-        }
-        let message_result = self.can_handle.roboteq_read_encoder_motor_speed(1, 2);
-        match message_result {
-            Ok(()) => {},
-            Err(err) => {
-                println!("Error Sending Message on CAN bus: {:?}",  err);
-            }
+        let message_results: Vec<_> = self.roboteq.channels.iter().flat_map(|&channel| [
+            self.can_handle.roboteq_read_battery_amps(self.roboteq.node, channel),
+            self.can_handle.roboteq_read_encoder_motor_speed(self.roboteq.node, channel)
+        ]).collect();
+        for message_result in message_results {
+            self.record_write(message_result);
         }
-        let message_result = self.can_handle.roboteq_read_temps(1);
-        match message_result {
-            Ok(()) => {},
-            Err(err) => {
-                println!("Error Sending Message on CAN bus: {:?}",  err);
-            }
-        }
-
-        /* SEND GO MESSAGE TO ROBOTEQ */
-        if self.current_pod_state == self.requested_pod_state && self.current_pod_state == PodState::AutoPilot {
-            let node_id = 1;
-            let max_motors = 1;
-            let throttle_percent = 100;
-            let message_result = self.can_handle.set_motor_throttle(node_id, 1, throttle_percent);
-
-            match message_result {
-                Ok(()) => {},
-                Err(err) => {
-                    println!("Error Sending Message on CAN bus: {:?}",  err);
-                }
-            }
-            let message_result = self.can_handle.set_motor_throttle(node_id, 2, throttle_percent);
+        let message_result = self.can_handle.roboteq_read_temps(self.roboteq.node);
+        self.record_write(message_result);
 
-            match message_result {
-                Ok(()) => {},
-                Err(err) => {
-                    println!("Error Sending Message on CAN bus: {:?}",  err);
-                }
+        /* SEND THROTTLE TO ROBOTEQ, ZERO WHILE BRAKING */
+        if let Some(throttle_percent) = roboteq_throttle(&self.current_pod_state, &self.requested_pod_state, &self.board_state, self.tuned_throttle_percent.unwrap_or(self.roboteq.throttle_percent)) {
+            let message_results: Vec<_> = self.roboteq.channels.iter()
+                .map(|&channel| self.can_handle.set_motor_throttle(self.roboteq.node, channel, throttle_percent))
+                .collect();
+            for message_result in message_results {
+                self.record_write(message_result);
             }
         }
 
         /* TURN OFF ROBOTEQ with EBREAK */
         if self.requested_pod_state == PodState::SystemFailure {
-            let message_result = self.can_handle.roboteq_emergency_stop(1);
-            match message_result {
-                Ok(()) => {},
-                Err(err) => {
-                    println!("Error Sending Message on CAN bus: {:?}",  err);
-                }
-            }
+            let message_result = self.can_handle.roboteq_emergency_stop(self.roboteq.node);
+            self.record_write(message_result);
         }
+    }
 
+    if self.unknown_ids.summary_due() {
+        for line in self.unknown_ids.take_summary() {
+            info!("CAN THREAD: Unknown {}", line);
+        }
+    }
 
+    // Re-open the socket once the bus has been failing long enough, waiting out the backoff between attempts
+    if self.bus_error_counter >= MAX_CONSECUTIVE_BUS_ERRORS && self.socket_opener.is_some() && Instant::now() >= self.next_reopen_at {
+        self.reopen_socket();
     }
     CanWorkerState::Disconnected(self)
  }
@@ -1,37 +1,63 @@
 use std::net::SocketAddr;
-#[cfg(unix)]
-use socketcan::CANFrame;
+use std::sync::mpsc::Sender;
+use crate::can_extentions::prelude::CANFrame;
+use crate::can_extentions::fault_reports::FaultReport;
 use crate::{
     pod_data,
     pod_states,
 };
 
+#[derive(Debug)]
 pub enum TcpMessage {
+    StartupComplete, // Sent by the udp thread once it has finished startup. CONNECT is refused until then
     EnteringRecovery,
-    #[allow(dead_code)] // Not Dead, but it's only constructed when running in unix
-    RecoveryComplete,
-    UdpFailedToConnect,
+    RecoveryComplete, // Sent by the udp thread once the can thread has acked LowVoltage, i.e. every board has reported it is safe
+    UdpLinkLost, // The controller stopped responding on the udp socket. The pod is brought down as in recovery
+    PodStateChanged(pod_states::PodState),
+    Shutdown,
 }
 
 #[derive(Debug)]
 pub enum UDPMessage {
-    ConnectToDesktop(SocketAddr),
+    ConnectToDesktop(SocketAddr, pod_data::TelemetryFields, Sender<std::io::Result<u16>>), // Answered with the port the socket is bound to once it is connected to the controller, or has failed to be
     DisconnectFromHost,
+    FastDisconnectFromHost, // Disconnect without recovery, commanding LowVoltage straight away
     StartupComplete,
     #[allow(dead_code)] // Not Dead, only constructed when running in unix, but the udp socket needs to be able to check it in all cases
     PodStateChangeAck,
     #[allow(dead_code)]
-    TelemetryDataAvailable(pod_data::PodData, chrono::NaiveDateTime),
-    SystemFault
+    TelemetryDataAvailable(pod_data::PodData, chrono::NaiveDateTime, u64), // The snapshot's sequence number lets the controller detect lost or reordered datagrams
+    #[allow(dead_code)]
+    TelemetryKeepalive(pod_data::PodData, chrono::NaiveDateTime, u64), // The last snapshot again, numbered like a snapshot, when nothing has changed for the keepalive interval
+    SystemFault,
+    #[allow(dead_code)] // Only constructed when running in unix
+    StateTransitionRejected(pod_states::PodState), // The transition is not in the state graph, or a board on the can bus Nacked it
+    #[allow(dead_code)] // Only constructed when running in unix
+    CanBusDegraded, // Reads and writes on the can bus keep failing, the can thread is re-opening its socket
+    #[allow(dead_code)] // Only constructed when running in unix
+    CanBusRecovered, // A read or write succeeded again after the can bus degraded
+    #[allow(dead_code)] // Only constructed when running in unix
+    StateChangeUnacknowledged(pod_states::PodState), // The boards never acked the state, even after every retry
+    FaultReported(FaultReport), // Sent to the controller with the next pod state message, whether or not there is new telemetry
+    Shutdown, // Stop the udp thread. The worker exits its main loop and the socket is closed
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub enum CanMessage {
     ChangeState(pod_states::PodState),
     BrakingTimerTimeout,
-    DeviceLost
+    DeviceLost,
+    SetThrottle(u8), // Throttle percent the roboteq is driven at in AutoPilot, validated by the sender to be at most 100
+    Shutdown, // Command the pod to LowVoltage and stop the can thread
 }
 
 pub enum WorkerMessage {
     CanFrameAndTimeStamp(CANFrame, chrono::NaiveDateTime)
 }
+
+#[cfg(feature = "websocket")]
+#[derive(Debug)]
+pub enum WebsocketMessage {
+    TelemetryDataAvailable(pod_data::PodData, chrono::NaiveDateTime, u64), // The same snapshot sent to the udp thread
+    Shutdown, // Close every client and stop the websocket thread
+}
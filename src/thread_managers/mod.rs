@@ -3,13 +3,32 @@ pub mod worker_states {
     pub struct Disconnected;
     pub struct Connected;
     pub struct Recovery;
+
+    /**
+     * Name of each worker state as it is reported to the controller
+     */
+    pub trait StateName {
+        const NAME: &'static str;
+    }
+    impl StateName for Startup { const NAME: &'static str = "Startup"; }
+    impl StateName for Disconnected { const NAME: &'static str = "Disconnected"; }
+    impl StateName for Connected { const NAME: &'static str = "Connected"; }
+    impl StateName for Recovery { const NAME: &'static str = "Recovery"; }
 }
 pub mod messages;
 mod main_loop;
+mod backoff;
 mod udp;
 mod tcp;
 mod can;
+mod telemetry;
+#[cfg(feature = "websocket")]
+mod websocket;
 
 pub use udp::UdpManager;
 pub use tcp::TcpManager;
-pub use can::{ CanManager, CanWorkerInitializer };
\ No newline at end of file
+pub use can::{ CanManager, CanWorkerInitializer };
+pub(crate) use can::SAFE_POD_STATE;
+pub use telemetry::WorkerManager;
+#[cfg(feature = "websocket")]
+pub use websocket::WebsocketManager;
\ No newline at end of file
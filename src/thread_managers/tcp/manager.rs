@@ -1,4 +1,6 @@
 use super::worker::TcpWorkerState;
+use log::{ info };
+use std::net::TcpListener;
 use std::sync::mpsc::{
     Sender,
     Receiver
@@ -9,18 +11,58 @@ pub struct TcpManager {
 }
 
 impl TcpManager {
-    pub fn run<A: std::net::ToSocketAddrs + Send + 'static>(
+    pub fn run<A: std::net::ToSocketAddrs + std::fmt::Debug + Send + 'static>(
         address: A,
         udp_message_sender: Sender<UDPMessage>,
+        can_message_sender: Sender<CanMessage>,
         tcp_message_receiver: Receiver<TcpMessage>,
-        tcp_message_buffer_size: usize
+        tcp_message_buffer_size: usize,
+        udp_port: u16,
+        tcp_read_timeout: std::time::Duration,
+        disconnect_wait: crate::config::DisconnectWait,
+        connect_allowlist: Vec<std::net::IpAddr>,
+        command_history: std::sync::Arc<crate::utils::command_history::CommandHistory>,
+        metrics: std::sync::Arc<crate::metrics::Metrics>
     ) -> std::thread::JoinHandle<()> {
+        TcpManager::spawn(move || {
+            TcpWorkerState::new(address, udp_message_sender, can_message_sender, tcp_message_receiver, tcp_message_buffer_size, udp_port, tcp_read_timeout, disconnect_wait, connect_allowlist, command_history, metrics)
+        })
+    }
+
+    /**
+     * @brief run_with_listener
+     * Same as run, but serves a listener the caller has already bound. Binding to port 0 and
+     * reading the listener's local_addr lets tests run the handler without a fixed port
+     */
+    pub fn run_with_listener(
+        listener: TcpListener,
+        udp_message_sender: Sender<UDPMessage>,
+        can_message_sender: Sender<CanMessage>,
+        tcp_message_receiver: Receiver<TcpMessage>,
+        tcp_message_buffer_size: usize,
+        udp_port: u16,
+        tcp_read_timeout: std::time::Duration,
+        disconnect_wait: crate::config::DisconnectWait,
+        connect_allowlist: Vec<std::net::IpAddr>,
+        command_history: std::sync::Arc<crate::utils::command_history::CommandHistory>,
+        metrics: std::sync::Arc<crate::metrics::Metrics>
+    ) -> std::thread::JoinHandle<()> {
+        TcpManager::spawn(move || {
+            TcpWorkerState::with_listener(listener, udp_message_sender, can_message_sender, tcp_message_receiver, tcp_message_buffer_size, udp_port, tcp_read_timeout, disconnect_wait, connect_allowlist, command_history, metrics)
+        })
+    }
+
+    fn spawn<F: FnOnce() -> TcpWorkerState + Send + 'static>(setup: F) -> std::thread::JoinHandle<()> {
         std::thread::Builder::new().name("TCP Thread".to_string()).spawn(move || {
             // Setup
-            let mut tcp_worker = TcpWorkerState::new(address, udp_message_sender, tcp_message_receiver, tcp_message_buffer_size);
-            loop {
+            let mut tcp_worker = setup();
+            while !tcp_worker.is_shutdown() {
+                tcp_worker.report_state();
                 tcp_worker = tcp_worker.main_loop();
             }
+            // Dropping the worker closes the listener
+            drop(tcp_worker);
+            info!("TCP THREAD: Shut down");
         }).expect("Should be able to create Thread")
     }
 }
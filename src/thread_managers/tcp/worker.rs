@@ -1,4 +1,530 @@
-use crate::error::Error;
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn udp_handshake_advertises_forwarded_port() {
+        let peer: SocketAddr = "192.168.0.20:51234".parse().unwrap();
+        let desktop_addr = telemetry_address(peer, 9100);
+
+        assert_eq!(desktop_addr.ip(), peer.ip());
+        assert_eq!(desktop_addr.port(), 9100);
+        assert_eq!(connect_response(desktop_addr, 8081), "OK 9100 8081\r\n");
+    }
+
+    #[test]
+    fn status_response_for_each_state() {
+        assert_eq!(status_response::<Startup>(&PodState::LowVoltage), "STATUS Startup LowVoltage\r\n");
+        assert_eq!(status_response::<Disconnected>(&PodState::LowVoltage), "STATUS Disconnected LowVoltage\r\n");
+        assert_eq!(status_response::<Connected>(&PodState::AutoPilot), "STATUS Connected AutoPilot\r\n");
+        assert_eq!(status_response::<Recovery>(&PodState::Braking), "STATUS Recovery Braking\r\n");
+    }
+
+    fn test_worker() -> (TcpWorker<Disconnected>, Receiver<UDPMessage>, Receiver<CanMessage>) {
+        let (udp_message_sender, udp_message_receiver) = std::sync::mpsc::channel();
+        let (can_message_sender, can_message_receiver) = std::sync::mpsc::channel();
+        let (_, tcp_message_receiver) = std::sync::mpsc::channel();
+        let worker = TcpWorker::new("127.0.0.1:0", udp_message_sender, can_message_sender, tcp_message_receiver, 128, 8090, Duration::from_millis(1000), DisconnectWait::Immediate, Vec::new(), Arc::new(CommandHistory::new(8)), Arc::new(Metrics::new()));
+        (worker.EnterDisconnected(), udp_message_receiver, can_message_receiver)
+    }
+
+    /**
+     * A connected worker along with the sender the udp thread would use to report recovery
+     */
+    fn connected_worker(disconnect_wait: DisconnectWait) -> (TcpWorker<Connected>, Sender<TcpMessage>, Receiver<UDPMessage>) {
+        let (udp_message_sender, udp_message_receiver) = std::sync::mpsc::channel();
+        let (can_message_sender, _) = std::sync::mpsc::channel();
+        let (tcp_message_sender, tcp_message_receiver) = std::sync::mpsc::channel();
+        let worker = TcpWorker::new("127.0.0.1:0", udp_message_sender, can_message_sender, tcp_message_receiver, 128, 8090, Duration::from_millis(1000), disconnect_wait, Vec::new(), Arc::new(CommandHistory::new(8)), Arc::new(Metrics::new()));
+        (worker.EnterConnected(), tcp_message_sender, udp_message_receiver)
+    }
+
+    /**
+     * Sends a request to the worker's listener. Returns the client side of the
+     * connection and the request once it has been read by a request thread
+     */
+    fn send_request<State>(worker: &TcpWorker<State>, request: &[u8]) -> (TcpStream, PendingRequest) {
+        let mut client = TcpStream::connect(worker.listener.local_addr().unwrap()).unwrap();
+        client.write_all(request).unwrap();
+        loop {
+            worker.accept_connection();
+            if let Ok(pending_request) = worker.request_receiver.try_recv() {
+                return (client, pending_request);
+            }
+            std::thread::yield_now();
+        }
+    }
+
+    fn read_response(mut client: TcpStream) -> String {
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn connect_is_refused_until_startup_completes() {
+        let (udp_message_sender, udp_message_receiver) = std::sync::mpsc::channel();
+        let (can_message_sender, _can_message_receiver) = std::sync::mpsc::channel();
+        let (tcp_message_sender, tcp_message_receiver) = std::sync::mpsc::channel();
+        let mut worker = TcpWorker::new("127.0.0.1:0", udp_message_sender, can_message_sender, tcp_message_receiver, 128, 8090, Duration::from_millis(1000), DisconnectWait::Immediate, Vec::new(), Arc::new(CommandHistory::new(8)), Arc::new(Metrics::new()));
+        let (client, PendingRequest { stream, request }) = send_request(&worker, b"CONNECT\r\n");
+        assert!(matches!(worker.handle_connection(stream, request), Err(Error::StartingUp)));
+        assert_eq!(read_response(client), "ERROR Starting up, retry shortly\r\n");
+        assert!(udp_message_receiver.try_recv().is_err());
+
+        let worker = match worker.main_loop() {
+            TcpWorkerState::Startup(worker) => worker,
+            _ => panic!("Expected the worker to wait for startup to complete")
+        };
+        tcp_message_sender.send(TcpMessage::StartupComplete).unwrap();
+        assert!(matches!(worker.main_loop(), TcpWorkerState::Disconnected(_)));
+    }
+
+    #[test]
+    fn ping_in_each_state() {
+        let (mut worker, _udp_message_receiver, _can_message_receiver) = test_worker();
+        let (client, PendingRequest { stream, request }) = send_request(&worker, b"PING\r\n");
+        assert!(matches!(worker.handle_connection(stream, request), Ok(RequestTypes::Heartbeat)));
+        assert_eq!(read_response(client), "PONG\r\n");
+
+        let mut worker = worker.EnterConnected();
+        let (client, PendingRequest { stream, request }) = send_request(&worker, b"PING\r\n");
+        assert!(matches!(worker.handle_connection(stream, request), Ok(RequestTypes::Heartbeat)));
+        assert_eq!(read_response(client), "PONG\r\n");
+
+        let mut worker = worker.EnterRecovery();
+        let (client, PendingRequest { stream, request }) = send_request(&worker, b"PING\r\n");
+        assert!(worker.handle_connection(stream, request).is_ok());
+        assert_eq!(read_response(client), "PONG\r\n");
+    }
+
+    #[test]
+    fn requests_from_a_peer_not_on_the_allowlist_are_refused() {
+        let (mut worker, udp_message_receiver, _can_message_receiver) = test_worker();
+        worker.connect_allowlist = vec!["192.168.0.20".parse().unwrap()];
+        let (client, pending_request) = send_request(&worker, b"CONNECT\r\n");
+        worker.queued_request = Some(pending_request);
+        let mut worker = match worker.main_loop() {
+            TcpWorkerState::Disconnected(worker) => worker,
+            _ => panic!("Expected the worker to stay disconnected")
+        };
+        assert!(udp_message_receiver.try_recv().is_err());
+        assert_eq!(read_response(client), "ERROR Unauthorized\r\n");
+
+        let (client, PendingRequest { stream, request }) = send_request(&worker, b"THROTTLE 40\r\n");
+        assert!(matches!(worker.handle_connection(stream, request), Err(Error::UnauthorizedPeer(_))));
+        assert_eq!(read_response(client), "ERROR Unauthorized\r\n");
+
+        // Nor can it lift maintenance to let itself in
+        worker.maintenance = true;
+        let (client, PendingRequest { stream, request }) = send_request(&worker, b"MAINTENANCE OFF\r\n");
+        assert!(matches!(worker.handle_connection(stream, request), Err(Error::UnauthorizedPeer(_))));
+        assert_eq!(read_response(client), "ERROR Unauthorized\r\n");
+        assert!(worker.maintenance);
+        worker.maintenance = false;
+
+        // Anyone may still stop the pod
+        let (client, PendingRequest { stream, request }) = send_request(&worker, b"ESTOP\r\n");
+        assert!(worker.handle_connection(stream, request).is_ok());
+        assert_eq!(read_response(client), "ESTOP ACK\r\n");
+
+        // The loopback test client is let through once it is on the list
+        let (mut worker, udp_message_receiver, _can_message_receiver) = test_worker();
+        worker.connect_allowlist = vec!["192.168.0.20".parse().unwrap(), "127.0.0.1".parse().unwrap()];
+        let (_client, PendingRequest { stream, request }) = send_request(&worker, b"CONNECT\r\n");
+        drop(udp_message_receiver); // Refuses the connection once it gets past the allowlist
+        assert!(matches!(worker.handle_connection(stream, request), Err(Error::UdpSocketError(_))));
+    }
+
+    #[test]
+    fn connect_is_refused_while_in_maintenance() {
+        let (mut worker, udp_message_receiver, can_message_receiver) = test_worker();
+        let (client, PendingRequest { stream, request }) = send_request(&worker, b"MAINTENANCE ON\r\n");
+        assert!(matches!(worker.handle_connection(stream, request), Ok(RequestTypes::Maintenance(Maintenance::On))));
+        assert_eq!(read_response(client), "MAINTENANCE ON\r\n");
+
+        let (client, PendingRequest { stream, request }) = send_request(&worker, b"CONNECT\r\n");
+        assert!(matches!(worker.handle_connection(stream, request), Err(Error::InMaintenance)));
+        assert_eq!(read_response(client), "ERROR In Maintenance\r\n");
+        assert!(udp_message_receiver.try_recv().is_err());
+        // ESTOP is still honoured
+        let (client, PendingRequest { stream, request }) = send_request(&worker, b"ESTOP\r\n");
+        assert!(matches!(worker.handle_connection(stream, request), Ok(RequestTypes::EmergencyStop)));
+        assert!(matches!(can_message_receiver.try_recv(), Ok(CanMessage::ChangeState(PodState::LowVoltage))));
+        assert_eq!(read_response(client), "ESTOP ACK\r\n");
+        // MAINTENANCE needs to be told ON or OFF
+        let (_client, PendingRequest { stream, request }) = send_request(&worker, b"MAINTENANCE\r\n");
+        assert!(worker.handle_connection(stream, request).is_err());
+
+        let (client, PendingRequest { stream, request }) = send_request(&worker, b"MAINTENANCE OFF\r\n");
+        assert!(matches!(worker.handle_connection(stream, request), Ok(RequestTypes::Maintenance(Maintenance::Off))));
+        assert_eq!(read_response(client), "MAINTENANCE OFF\r\n");
+
+        let udp_thread = std::thread::spawn(move || match udp_message_receiver.recv() {
+            Ok(UDPMessage::ConnectToDesktop(_, _, reply_sender)) => reply_sender.send(Ok(8081)).unwrap(),
+            _ => panic!("Expected the udp thread to be asked to connect")
+        });
+        let (client, PendingRequest { stream, request }) = send_request(&worker, b"CONNECT\r\n");
+        assert!(matches!(worker.handle_connection(stream, request), Ok(RequestTypes::Connect(_))));
+        udp_thread.join().unwrap();
+        assert!(read_response(client).starts_with("OK "));
+
+        // A connected controller is never locked in by maintenance
+        let mut worker = worker.EnterConnected();
+        let (client, PendingRequest { stream, request }) = send_request(&worker, b"MAINTENANCE ON\r\n");
+        assert!(worker.handle_connection(stream, request).is_ok());
+        assert_eq!(read_response(client), "ERROR POD Connected to Controller\r\n");
+        assert!(!worker.maintenance);
+    }
+
+    #[test]
+    fn estop_commands_low_voltage() {
+        let (worker, udp_message_receiver, can_message_receiver) = test_worker();
+        let mut worker = worker.EnterConnected();
+        let (client, PendingRequest { stream, request }) = send_request(&worker, b"ESTOP\r\n");
+        assert!(matches!(worker.handle_connection(stream, request), Ok(RequestTypes::EmergencyStop)));
+        assert!(matches!(can_message_receiver.try_recv(), Ok(CanMessage::ChangeState(PodState::LowVoltage))));
+        assert!(matches!(udp_message_receiver.try_recv(), Ok(UDPMessage::DisconnectFromHost)));
+        assert_eq!(read_response(client), "ESTOP ACK\r\n");
+
+        let mut worker = worker.EnterRecovery();
+        let (client, PendingRequest { stream, request }) = send_request(&worker, b"ESTOP\r\n");
+        assert!(worker.handle_connection(stream, request).is_ok());
+        assert!(matches!(can_message_receiver.try_recv(), Ok(CanMessage::ChangeState(PodState::LowVoltage))));
+        assert_eq!(read_response(client), "ESTOP ACK\r\n");
+    }
+
+    #[test]
+    fn throttle_is_only_set_in_autopilot() {
+        let (mut worker, _udp_message_receiver, can_message_receiver) = test_worker();
+        let (client, PendingRequest { stream, request }) = send_request(&worker, b"THROTTLE 40\r\n");
+        assert!(matches!(worker.handle_connection(stream, request), Ok(RequestTypes::Throttle(Some(40)))));
+        assert_eq!(read_response(client), "ERROR POD Not Connected to Controller\r\n");
+
+        let mut worker = worker.EnterConnected();
+        let (client, PendingRequest { stream, request }) = send_request(&worker, b"THROTTLE 40\r\n");
+        assert!(worker.handle_connection(stream, request).is_ok());
+        assert_eq!(read_response(client), "ERROR POD Not in AutoPilot\r\n");
+
+        worker.pod_state = PodState::AutoPilot;
+        let (client, PendingRequest { stream, request }) = send_request(&worker, b"THROTTLE 101\r\n");
+        assert!(matches!(worker.handle_connection(stream, request), Ok(RequestTypes::Throttle(None))));
+        assert_eq!(read_response(client), "ERROR Invalid Throttle\r\n");
+        assert!(can_message_receiver.try_recv().is_err());
+
+        let (client, PendingRequest { stream, request }) = send_request(&worker, b"THROTTLE 40\r\n");
+        assert!(worker.handle_connection(stream, request).is_ok());
+        assert_eq!(read_response(client), "THROTTLE 40\r\n");
+        assert!(matches!(can_message_receiver.try_recv(), Ok(CanMessage::SetThrottle(40))));
+    }
+
+    #[test]
+    fn history_lists_the_recorded_commands() {
+        let (mut worker, _udp_message_receiver, _can_message_receiver) = test_worker();
+        let time = chrono::NaiveDateTime::from_timestamp(1_600_000_000, 0);
+        worker.command_history.record(crate::can_extentions::prelude::CanCommand::PressureHigh(101.5), time);
+        worker.command_history.record(crate::can_extentions::prelude::CanCommand::PressureHigh(102.5), time);
+        let (client, PendingRequest { stream, request }) = send_request(&worker, b"HISTORY\r\n");
+        assert!(matches!(worker.handle_connection(stream, request), Ok(RequestTypes::History(DEFAULT_HISTORY_RESPONSE_LENGTH))));
+        assert_eq!(read_response(client), "1600000000000 PressureHigh(101.5)\r\n1600000000000 PressureHigh(102.5)\r\n");
+
+        let (client, PendingRequest { stream, request }) = send_request(&worker, b"HISTORY 1\r\n");
+        assert!(matches!(worker.handle_connection(stream, request), Ok(RequestTypes::History(1))));
+        assert_eq!(read_response(client), "1600000000000 PressureHigh(102.5)\r\n");
+
+        let (_client, PendingRequest { stream, request }) = send_request(&worker, b"HISTORY 0\r\n");
+        assert!(matches!(worker.handle_connection(stream, request), Err(Error::UnableToHandleTcpMessage)));
+    }
+
+    #[test]
+    fn metrics_in_each_state() {
+        let (mut worker, udp_message_receiver, can_message_receiver) = test_worker();
+        worker.metrics.can_frame_received();
+        let (client, PendingRequest { stream, request }) = send_request(&worker, b"METRICS\r\n");
+        assert!(matches!(worker.handle_connection(stream, request), Ok(RequestTypes::Metrics)));
+        let response = read_response(client);
+        for (name, _) in &worker.metrics.snapshot().counters() {
+            assert!(response.lines().any(|line| line.split(' ').next() == Some(*name)), "{} missing from {:?}", name, response);
+        }
+        assert!(response.contains("can_frames_received 1\r\n"));
+        assert!(response.contains("tcp_connections_accepted 1\r\n"));
+
+        let mut worker = worker.EnterConnected();
+        let (client, PendingRequest { stream, request }) = send_request(&worker, b"METRICS\r\n");
+        assert!(matches!(worker.handle_connection(stream, request), Ok(RequestTypes::Metrics)));
+        assert!(read_response(client).contains("tcp_connections_accepted 2\r\n"));
+
+        let mut worker = worker.EnterRecovery();
+        let (client, PendingRequest { stream, request }) = send_request(&worker, b"METRICS\r\n");
+        assert!(matches!(worker.handle_connection(stream, request), Ok(RequestTypes::Metrics)));
+        assert!(read_response(client).contains("tcp_connections_accepted 3\r\n"));
+
+        // Reading the counters never messages the other threads
+        assert!(udp_message_receiver.try_recv().is_err());
+        assert!(can_message_receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn dead_threads_do_not_take_down_the_tcp_thread() {
+        let (worker, udp_message_receiver, can_message_receiver) = test_worker();
+        drop(udp_message_receiver);
+        drop(can_message_receiver);
+        let mut worker = worker.EnterConnected();
+
+        let (client, PendingRequest { stream, request }) = send_request(&worker, b"ESTOP\r\n");
+        assert!(matches!(worker.handle_connection(stream, request), Err(Error::ThreadUnavailable("CAN"))));
+        assert_eq!(read_response(client), "ERROR POD CAN Thread is not running\r\n");
+
+        let (client, PendingRequest { stream, request }) = send_request(&worker, b"DISCONNECT\r\n");
+        assert!(matches!(worker.handle_connection(stream, request), Err(Error::ThreadUnavailable("UDP"))));
+        assert_eq!(read_response(client), "ERROR POD UDP Thread is not running\r\n");
+
+        let mut worker = worker.EnterDisconnected();
+        let (client, PendingRequest { stream, request }) = send_request(&worker, b"CONNECT\r\n");
+        assert!(matches!(worker.handle_connection(stream, request), Err(Error::UdpSocketError(_))));
+        assert_eq!(read_response(client), "ERROR POD Unable to bind UDP Socket\r\n");
+    }
+
+    #[test]
+    fn pipelined_commands_are_each_handled() {
+        let (mut worker, _udp_message_receiver, _can_message_receiver) = test_worker();
+        let (client, PendingRequest { stream, request }) = send_request(&worker, b"PING\r\nSTATUS\r\n");
+        assert!(matches!(worker.handle_connection(stream, request), Ok(RequestTypes::Heartbeat)));
+        let PendingRequest { stream, request } = worker.next_request().unwrap();
+        assert!(matches!(worker.handle_connection(stream, request), Ok(RequestTypes::Status)));
+        assert!(worker.next_request().is_none());
+        assert_eq!(read_response(client), "PONG\r\nSTATUS Disconnected LowVoltage\r\n");
+    }
+
+    #[test]
+    fn partial_command_waits_for_next_read() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut stream, _) = listener.accept().unwrap();
+
+        client.write_all(b"PING\r\nST").unwrap();
+        let writer = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            client.write_all(b"ATUS\r\n").unwrap();
+            client
+        });
+        let request = read_request(&mut stream, 128, Duration::from_millis(1000));
+        assert_eq!(request, b"PING\r\nSTATUS\r\n".to_vec());
+        drop(writer.join().unwrap());
+    }
+
+    #[test]
+    fn invalid_request_is_an_error_in_each_state() {
+        let (mut worker, _udp_message_receiver, _can_message_receiver) = test_worker();
+        let (_client, PendingRequest { stream, request }) = send_request(&worker, b"JUMP\r\n");
+        assert!(matches!(worker.handle_connection(stream, request), Err(Error::UnableToHandleTcpMessage)));
+
+        let mut worker = worker.EnterConnected();
+        let (_client, PendingRequest { stream, request }) = send_request(&worker, b"JUMP\r\n");
+        assert!(matches!(worker.handle_connection(stream, request), Err(Error::UnableToHandleTcpMessage)));
+
+        let mut worker = worker.EnterRecovery();
+        let (_client, PendingRequest { stream, request }) = send_request(&worker, b"JUMP\r\n");
+        assert!(matches!(worker.handle_connection(stream, request), Err(Error::UnableToHandleTcpMessage)));
+    }
+
+    #[test]
+    fn stalled_client_does_not_block_listener() {
+        let (worker, _udp_message_receiver, _can_message_receiver) = test_worker();
+        let _stalled_client = TcpStream::connect(worker.listener.local_addr().unwrap()).unwrap();
+        // The stalled connection is accepted, but its request thread never produces a request
+        let (client, PendingRequest { stream, request }) = send_request(&worker, b"PING\r\n");
+        assert!(request.starts_with(b"PING\r\n"));
+        drop(stream);
+        drop(client);
+    }
+
+    #[test]
+    fn request_larger_than_buffer_is_read_whole() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut stream, _) = listener.accept().unwrap();
+
+        client.write_all(b"CONNECT with some extra headers\r\n").unwrap();
+        let request = read_request(&mut stream, 8, Duration::from_millis(1000));
+        assert_eq!(request, b"CONNECT with some extra headers\r\n".to_vec());
+    }
+
+    #[test]
+    fn silent_client_times_out() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let _client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut stream, _) = listener.accept().unwrap();
+
+        let request = read_request(&mut stream, 128, Duration::from_millis(50));
+        assert_eq!(request, b"@@Failed@@\r\n".to_vec());
+    }
+
+    #[test]
+    fn disconnect_waits_for_recovery() {
+        let (mut worker, tcp_message_sender, udp_message_receiver) = connected_worker(DisconnectWait::Block(Duration::from_millis(1000)));
+        let (client, pending_request) = send_request(&worker, b"DISCONNECT\r\n");
+        worker.queued_request = Some(pending_request);
+        let mut worker = match worker.main_loop() {
+            TcpWorkerState::Recovery(worker) => worker,
+            _ => panic!("Expected the worker to wait in recovery")
+        };
+        assert!(matches!(udp_message_receiver.try_recv(), Ok(UDPMessage::DisconnectFromHost)));
+
+        // Other requests are still answered while the reply is held
+        let (ping_client, pending_request) = send_request(&worker, b"PING\r\n");
+        worker.queued_request = Some(pending_request);
+        let worker = match worker.main_loop() {
+            TcpWorkerState::Recovery(worker) => worker,
+            _ => panic!("Expected the worker to wait in recovery")
+        };
+        assert_eq!(read_response(ping_client), "PONG\r\n");
+        assert!(worker.disconnecting_stream.is_some());
+        tcp_message_sender.send(TcpMessage::RecoveryComplete).unwrap();
+        assert!(matches!(worker.main_loop(), TcpWorkerState::Disconnected(_)));
+        assert_eq!(read_response(client), "DISCONNECTED\r\n");
+
+        // Recovery which outlasts the wait is confirmed once it completes
+        let (mut worker, tcp_message_sender, _udp_message_receiver) = connected_worker(DisconnectWait::Block(Duration::from_millis(10)));
+        let (client, PendingRequest { stream, request }) = send_request(&worker, b"DISCONNECT\r\n");
+        assert!(matches!(worker.handle_connection(stream, request), Ok(RequestTypes::Disconnect(DisconnectMode::Recovery))));
+        let worker = worker.EnterRecovery();
+        std::thread::sleep(Duration::from_millis(20));
+        let worker = match worker.main_loop() {
+            TcpWorkerState::Recovery(worker) => worker,
+            _ => panic!("Expected the worker to wait in recovery")
+        };
+        tcp_message_sender.send(TcpMessage::RecoveryComplete).unwrap();
+        assert!(matches!(worker.main_loop(), TcpWorkerState::Disconnected(_)));
+        assert_eq!(read_response(client), "DISCONNECTING\r\nDISCONNECTED\r\n");
+    }
+
+    #[test]
+    fn fast_disconnect_does_not_wait_for_recovery() {
+        let (mut worker, _tcp_message_sender, udp_message_receiver) = connected_worker(DisconnectWait::Deferred);
+        let (client, pending_request) = send_request(&worker, b"DISCONNECT FAST\r\n");
+        worker.queued_request = Some(pending_request);
+        assert!(matches!(worker.main_loop(), TcpWorkerState::Disconnected(_)));
+        assert!(matches!(udp_message_receiver.try_recv(), Ok(UDPMessage::FastDisconnectFromHost)));
+        assert_eq!(read_response(client), "DISCONNECTED\r\n");
+
+        // Only FAST is understood
+        let (mut worker, _tcp_message_sender, udp_message_receiver) = connected_worker(DisconnectWait::Deferred);
+        let (_client, PendingRequest { stream, request }) = send_request(&worker, b"DISCONNECT SLOW\r\n");
+        assert!(worker.handle_connection(stream, request).is_err());
+        assert!(udp_message_receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn deferred_disconnect_is_confirmed_after_recovery() {
+        let (mut worker, tcp_message_sender, _udp_message_receiver) = connected_worker(DisconnectWait::Deferred);
+        let (mut client, pending_request) = send_request(&worker, b"DISCONNECT\r\n");
+        worker.queued_request = Some(pending_request);
+        let worker = match worker.main_loop() {
+            TcpWorkerState::Recovery(worker) => worker,
+            _ => panic!("Expected the worker to wait in recovery")
+        };
+        let mut response = [0u8; 15];
+        client.read_exact(&mut response).unwrap();
+        assert_eq!(&response, b"DISCONNECTING\r\n");
+
+        tcp_message_sender.send(TcpMessage::PodStateChanged(PodState::LowVoltage)).unwrap();
+        tcp_message_sender.send(TcpMessage::RecoveryComplete).unwrap();
+        assert!(matches!(worker.main_loop(), TcpWorkerState::Disconnected(_)));
+        assert_eq!(read_response(client), "DISCONNECTED\r\n");
+    }
+
+    /**
+     * Stands in for the udp thread, answering the next connect request with result
+     */
+    fn answer_connect(udp_message_receiver: Receiver<UDPMessage>, result: std::io::Result<u16>) -> std::thread::JoinHandle<Receiver<UDPMessage>> {
+        std::thread::spawn(move || {
+            match udp_message_receiver.recv() {
+                Ok(UDPMessage::ConnectToDesktop(_, _, reply_sender)) => reply_sender.send(result).unwrap(),
+                _ => panic!("Expected a ConnectToDesktop")
+            }
+            udp_message_receiver
+        })
+    }
+
+    #[test]
+    fn connect_fails_when_udp_socket_cannot_bind() {
+        let (mut worker, udp_message_receiver, _can_message_receiver) = test_worker();
+        let udp_thread = answer_connect(udp_message_receiver, Err(std::io::Error::from(std::io::ErrorKind::AddrInUse)));
+        let (client, PendingRequest { stream, request }) = send_request(&worker, b"CONNECT\r\n");
+        assert!(matches!(worker.handle_connection(stream, request), Err(Error::UdpSocketError(_))));
+        udp_thread.join().unwrap();
+        assert_eq!(read_response(client), "ERROR POD Unable to bind UDP Socket\r\n");
+    }
+
+    #[test]
+    fn connect_fails_when_the_udp_thread_does_not_answer() {
+        let (mut worker, _udp_message_receiver, _can_message_receiver) = test_worker();
+        worker.tcp_read_timeout = Duration::from_millis(50);
+        let (client, PendingRequest { stream, request }) = send_request(&worker, b"CONNECT\r\n");
+        match worker.handle_connection(stream, request) {
+            Err(Error::UdpSocketError(error)) => assert_eq!(error.kind(), std::io::ErrorKind::TimedOut),
+            _ => panic!("Expected the connect to time out")
+        }
+        assert_eq!(read_response(client), "ERROR POD Unable to bind UDP Socket\r\n");
+    }
+
+    #[test]
+    fn connect_sends_telemetry_to_the_requested_port() {
+        let (mut worker, udp_message_receiver, _can_message_receiver) = test_worker();
+        let (_client, PendingRequest { stream, request }) = send_request(&worker, b"CONNECT 0\r\n");
+        assert!(matches!(worker.handle_connection(stream, request), Err(Error::UnableToHandleTcpMessage)));
+
+        let udp_thread = std::thread::spawn(move || match udp_message_receiver.recv() {
+            Ok(UDPMessage::ConnectToDesktop(desktop_addr, fields, reply_sender)) => {
+                reply_sender.send(Ok(8081)).unwrap();
+                (desktop_addr, fields)
+            },
+            _ => panic!("Expected the udp thread to be asked to connect")
+        });
+        let (client, PendingRequest { stream, request }) = send_request(&worker, b"CONNECT 9999 fields=speed,pressure_high\r\n");
+        assert!(worker.handle_connection(stream, request).is_ok());
+        assert_eq!(udp_thread.join().unwrap(), ("127.0.0.1:9999".parse().unwrap(), TelemetryFields::parse("speed,pressure_high").unwrap()));
+        assert_eq!(read_response(client), "OK 9999 8081\r\n");
+    }
+
+    #[test]
+    fn connect_with_an_unknown_field_is_refused() {
+        let (mut worker, udp_message_receiver, _can_message_receiver) = test_worker();
+        let (client, PendingRequest { stream, request }) = send_request(&worker, b"CONNECT fields=speed,warp_factor\r\n");
+        assert!(matches!(worker.handle_connection(stream, request), Err(Error::UnknownTelemetryField)));
+        assert_eq!(read_response(client), "ERROR Unknown Telemetry Field\r\n");
+        assert!(udp_message_receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn concurrent_connect_requests() {
+        let (worker, udp_message_receiver, _can_message_receiver) = test_worker();
+        let (first_client, first) = send_request(&worker, b"CONNECT\r\n");
+        let (second_client, second) = send_request(&worker, b"CONNECT\r\n");
+
+        let udp_thread = answer_connect(udp_message_receiver, Ok(8080));
+        let mut worker = worker;
+        let mut worker = match worker.handle_connection(first.stream, first.request) {
+            Ok(RequestTypes::Connect(_)) => worker.EnterConnected(),
+            _ => panic!("Expected the first CONNECT to succeed")
+        };
+        let udp_message_receiver = udp_thread.join().unwrap();
+        assert!(matches!(worker.handle_connection(second.stream, second.request), Ok(RequestTypes::Connect(_))));
+        assert!(udp_message_receiver.try_recv().is_err());
+
+        assert_eq!(read_response(first_client), "OK 8090 8080\r\n");
+        assert_eq!(read_response(second_client), "ERROR POD Already Connected to Controller\r\n");
+    }
+}
+
+use crate::config::DisconnectWait;
+use crate::error::{ Error, SocketContext };
+use crate::metrics::Metrics;
+use crate::utils::command_history::CommandHistory;
+use log::{ debug, info, warn, error };
+use crate::pod_data::{ TelemetryFields, UnknownTelemetryField };
+use crate::pod_states::PodState;
 use crate::requests;
 use crate::stream_utils;
 
@@ -8,28 +534,128 @@ use super::super::main_loop::*;
 
 use std::io::prelude::*;
 use std::net::{
+    IpAddr,
+    SocketAddr,
     TcpListener,
     TcpStream
 };
+use std::sync::Arc;
 use std::sync::mpsc::{
+    channel,
     Sender,
     Receiver,
+    RecvTimeoutError,
 };
+use std::time::{ Duration, Instant };
 
 #[derive(Copy, Clone, Debug)]
 enum RequestTypes {
-    Connect,
-    Disconnect,
+    Connect(ConnectRequest),
+    Disconnect(DisconnectMode),
+    Status,
+    Heartbeat,
+    EmergencyStop,
+    Metrics,
+    History(usize), // Most recent commands to list
+    Maintenance(Maintenance),
+    Throttle(Option<u8>), // None if the percent given was not a whole number from 0 to 100
     Unknown
 }
 
+impl RequestTypes {
+    /**
+     * @brief is_restricted
+     * True for the requests only peers on the allowlist may make. ESTOP and DISCONNECT stay open to
+     * anyone, so the pod can always be brought down, as do STATUS, PING and METRICS, which only report counts
+     */
+    fn is_restricted(&self) -> bool {
+        matches!(self, RequestTypes::Connect(_) | RequestTypes::History(_) | RequestTypes::Maintenance(_) | RequestTypes::Throttle(_))
+    }
+}
+
+/**
+ * What a controller may ask for with CONNECT, e.g. CONNECT 9999 fields=speed,pressure_high
+ */
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct ConnectRequest {
+    desktop_port: Option<u16>, // Port the controller wants telemetry sent to, if it is not the configured udp port
+    fields: Option<TelemetryFields> // The telemetry fields to send. None if a field PodData does not have was asked for
+}
+
+impl Default for ConnectRequest {
+    fn default() -> ConnectRequest {
+        ConnectRequest { desktop_port: None, fields: Some(TelemetryFields::all()) }
+    }
+}
+
+/**
+ * MAINTENANCE ON locks the relay out for bench work. CONNECT is refused until MAINTENANCE OFF,
+ * so nobody can take control of the pod remotely. ESTOP is still honoured
+ */
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Maintenance {
+    On,
+    Off
+}
+
+/**
+ * How a connected controller's DISCONNECT brings the pod down. DISCONNECT FAST skips the recovery
+ * ramp and commands LowVoltage straight away, for bench testing
+ */
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum DisconnectMode {
+    Recovery,
+    Fast
+}
+
 trait CustomTcpStream {
     fn write_message(&mut self, buf: &[u8]) -> Result<usize, Error>;
 }
 
+/**
+ * @brief telemetry_address
+ * Points the controller's address at its telemetry port
+ */
+fn telemetry_address(mut addr: SocketAddr, udp_port: u16) -> SocketAddr {
+    addr.set_port(udp_port);
+    addr
+}
+
+/**
+ * @brief parse_connect_request
+ * Read the arguments to CONNECT, which may be given in either order. None if one is neither a udp port
+ * nor fields=<name>,<name>
+ */
+fn parse_connect_request(arguments: &[u8]) -> Option<ConnectRequest> {
+    let mut connect_request = ConnectRequest::default();
+    for argument in std::str::from_utf8(arguments).ok()?.split_ascii_whitespace() {
+        if let Some(fields) = argument.strip_prefix("fields=") {
+            connect_request.fields = match TelemetryFields::parse(fields) {
+                Ok(fields) => Some(fields),
+                Err(UnknownTelemetryField(field)) => {
+                    warn!("TCP THREAD: CONNECT asked for the unknown telemetry field {:?}", field);
+                    None
+                }
+            };
+        } else {
+            connect_request.desktop_port = Some(argument.parse::<u16>().ok().filter(|&port| port != 0)?);
+        }
+    }
+    Some(connect_request)
+}
+
+/**
+ * @brief connect_response
+ * The CONNECT response, advertising the port telemetry is sent to and the port the relay's udp socket
+ * is bound to. Both are taken from the sockets actually in use, so the response can never disagree with them
+ */
+fn connect_response(desktop_addr: SocketAddr, relay_udp_port: u16) -> String {
+    format!("OK {} {}\r\n", desktop_addr.port(), relay_udp_port)
+}
+
 impl CustomTcpStream for TcpStream {
     fn write_message(&mut self, buf: &[u8]) -> Result<usize, Error> {
-        self.write(buf).map_err(|e| Error::TcpSocketError(e))
+        self.write(buf).tcp_socket()
     }
 }
 
@@ -46,51 +672,174 @@ impl TcpWorkerRequestParser for requests::RequestParser<RequestTypes> {
         * Each Request Type will have a corresponding handler function which is ran
         * when the match occurs
         */
-        self.insert("CONNECT\r\n", RequestTypes::Connect);
-        self.insert("DISCONNECT\r\n", RequestTypes::Disconnect);
+        self.insert("CONNECT\r\n", RequestTypes::Connect(ConnectRequest::default())); // May take the controller's telemetry port and fields, see parse_request
+        self.insert("DISCONNECT\r\n", RequestTypes::Disconnect(DisconnectMode::Recovery));
+        self.insert("STATUS\r\n", RequestTypes::Status);
+        self.insert("PING\r\n", RequestTypes::Heartbeat);
+        self.insert("ESTOP\r\n", RequestTypes::EmergencyStop);
+        self.insert("METRICS\r\n", RequestTypes::Metrics);
+        self.insert("HISTORY\r\n", RequestTypes::History(DEFAULT_HISTORY_RESPONSE_LENGTH)); // May take how many commands to list, see parse_request
+        self.insert("MAINTENANCE\r\n", RequestTypes::Maintenance(Maintenance::On)); // Only valid with an argument, see parse_request
+        self.insert("THROTTLE\r\n", RequestTypes::Throttle(None)); // Only valid with an argument, see parse_request
         self.insert("@@Failed@@\r\n", RequestTypes::Unknown); // Special Message which is written into the request in the event of an error reading the message
         self
     }
 }
 
 #[repr(C)] // Required for type transmutations
-pub struct TcpWorker<State = Disconnected> {
+pub struct TcpWorker<State = Startup> {
     listener: TcpListener,
     request_parser: requests::RequestParser<RequestTypes>,
     udp_message_sender: Sender<UDPMessage>,
+    can_message_sender: Sender<CanMessage>,
     tcp_message_receiver: Receiver<TcpMessage>,
     tcp_message_buffer_size: usize,
+    udp_port: u16,
+    tcp_read_timeout: Duration,
+    pod_state: PodState, // Last pod state reported by the udp thread
+    request_sender: Sender<PendingRequest>,
+    request_receiver: Receiver<PendingRequest>,
+    queued_request: Option<PendingRequest>, // Commands left over from a request which held more than one
+    disconnect_wait: DisconnectWait,
+    disconnecting_stream: Option<TcpStream>, // Controller waiting for DISCONNECTED once recovery completes
+    disconnect_reply_deadline: Option<Instant>, // When the disconnecting controller is told DISCONNECTING if recovery has not completed, see DisconnectWait::Block
+    connect_allowlist: Vec<IpAddr>, // Peers allowed to make restricted requests, see RequestTypes::is_restricted. Empty allows any peer
+    command_history: Arc<CommandHistory>, // Recently decoded can commands, served with HISTORY
+    maintenance: bool, // Set by MAINTENANCE ON. CONNECT is refused until MAINTENANCE OFF
+    metrics: Arc<Metrics>,
+    shutdown_requested: bool,
     state: std::marker::PhantomData<State>
 }
 
+/**
+ * A request which has been read off of an accepted connection by a request thread,
+ * waiting to be handled by the TcpWorker
+ */
+struct PendingRequest {
+    stream: TcpStream,
+    request: Vec<u8>
+}
+
 pub type TcpWorkerState = WorkerState<TcpWorker<Startup>, TcpWorker<Recovery>, TcpWorker<Connected>, TcpWorker<Disconnected>>;
 
 impl TcpWorkerState {
-    pub fn new<A: std::net::ToSocketAddrs>(
+    pub fn new<A: std::net::ToSocketAddrs + std::fmt::Debug>(
         address: A,
         udp_message_sender: Sender<UDPMessage>,
+        can_message_sender: Sender<CanMessage>,
+        tcp_message_receiver: Receiver<TcpMessage>,
+        tcp_message_buffer_size: usize,
+        udp_port: u16,
+        tcp_read_timeout: Duration,
+        disconnect_wait: DisconnectWait,
+        connect_allowlist: Vec<IpAddr>,
+        command_history: Arc<CommandHistory>,
+        metrics: Arc<Metrics>
+    ) -> TcpWorkerState {
+        TcpWorkerState::Startup(TcpWorker::new(address, udp_message_sender, can_message_sender, tcp_message_receiver, tcp_message_buffer_size, udp_port, tcp_read_timeout, disconnect_wait, connect_allowlist, command_history, metrics))
+    }
+
+    pub fn with_listener(
+        listener: TcpListener,
+        udp_message_sender: Sender<UDPMessage>,
+        can_message_sender: Sender<CanMessage>,
         tcp_message_receiver: Receiver<TcpMessage>,
-        tcp_message_buffer_size: usize
+        tcp_message_buffer_size: usize,
+        udp_port: u16,
+        tcp_read_timeout: Duration,
+        disconnect_wait: DisconnectWait,
+        connect_allowlist: Vec<IpAddr>,
+        command_history: Arc<CommandHistory>,
+        metrics: Arc<Metrics>
     ) -> TcpWorkerState {
-        TcpWorkerState::Disconnected(TcpWorker::new(address, udp_message_sender, tcp_message_receiver, tcp_message_buffer_size))
+        TcpWorkerState::Startup(TcpWorker::with_listener(listener, udp_message_sender, can_message_sender, tcp_message_receiver, tcp_message_buffer_size, udp_port, tcp_read_timeout, disconnect_wait, connect_allowlist, command_history, metrics))
+    }
+
+    /**
+     * @brief is_shutdown
+     * True once the worker has received TcpMessage::Shutdown and should no longer be run
+     */
+    pub fn is_shutdown(&self) -> bool {
+        match self {
+            TcpWorkerState::Startup(worker) => worker.shutdown_requested,
+            TcpWorkerState::Disconnected(worker) => worker.shutdown_requested,
+            TcpWorkerState::Connected(worker) => worker.shutdown_requested,
+            TcpWorkerState::Recovery(worker) => worker.shutdown_requested,
+        }
+    }
+
+    /**
+     * @brief report_state
+     * Record which state the worker is in, so it can be reported by the health endpoint
+     */
+    pub fn report_state(&self) {
+        match self {
+            TcpWorkerState::Startup(worker) => worker.metrics.set_server_state(Startup::NAME),
+            TcpWorkerState::Disconnected(worker) => worker.metrics.set_server_state(Disconnected::NAME),
+            TcpWorkerState::Connected(worker) => worker.metrics.set_server_state(Connected::NAME),
+            TcpWorkerState::Recovery(worker) => worker.metrics.set_server_state(Recovery::NAME),
+        }
     }
 }
 
 impl TcpWorker {
-    pub fn new<A: std::net::ToSocketAddrs>(
+    pub fn new<A: std::net::ToSocketAddrs + std::fmt::Debug>(
         address: A,
         udp_message_sender: Sender<UDPMessage>,
+        can_message_sender: Sender<CanMessage>,
         tcp_message_receiver: Receiver<TcpMessage>,
-        tcp_message_buffer_size: usize
-    ) -> TcpWorker<Disconnected> {
-        let listener = TcpListener::bind(address).expect("Unable to Connect to Port");
+        tcp_message_buffer_size: usize,
+        udp_port: u16,
+        tcp_read_timeout: Duration,
+        disconnect_wait: DisconnectWait,
+        connect_allowlist: Vec<IpAddr>,
+        command_history: Arc<CommandHistory>,
+        metrics: Arc<Metrics>
+    ) -> TcpWorker<Startup> {
+        let listener = TcpListener::bind(&address).expect(&format!("Unable to Bind to TCP Socket on: {:?}", &address));
+        TcpWorker::with_listener(listener, udp_message_sender, can_message_sender, tcp_message_receiver, tcp_message_buffer_size, udp_port, tcp_read_timeout, disconnect_wait, connect_allowlist, command_history, metrics)
+    }
+
+    /**
+     * @brief with_listener
+     * Serve requests on a listener which has already been bound, such as one on an ephemeral port
+     */
+    pub fn with_listener(
+        listener: TcpListener,
+        udp_message_sender: Sender<UDPMessage>,
+        can_message_sender: Sender<CanMessage>,
+        tcp_message_receiver: Receiver<TcpMessage>,
+        tcp_message_buffer_size: usize,
+        udp_port: u16,
+        tcp_read_timeout: Duration,
+        disconnect_wait: DisconnectWait,
+        connect_allowlist: Vec<IpAddr>,
+        command_history: Arc<CommandHistory>,
+        metrics: Arc<Metrics>
+    ) -> TcpWorker<Startup> {
         listener.set_nonblocking(true).expect("Unable to set non blocking");
+        let (request_sender, request_receiver) = channel();
         TcpWorker {
             listener,
             request_parser: requests::RequestParser::new().init(),
             udp_message_sender,
+            can_message_sender,
             tcp_message_receiver,
             tcp_message_buffer_size,
+            udp_port,
+            tcp_read_timeout,
+            pod_state: PodState::LowVoltage,
+            request_sender,
+            request_receiver,
+            queued_request: None,
+            disconnect_wait,
+            disconnecting_stream: None,
+            disconnect_reply_deadline: None,
+            connect_allowlist,
+            command_history,
+            maintenance: false,
+            metrics,
+            shutdown_requested: false,
             state: std::marker::PhantomData
         }
     }
@@ -121,78 +870,371 @@ impl<State> TcpWorker<State> {
     }
 }
 
+/**
+ * How a state handles a request. Each state only handles the requests it treats differently,
+ * and passes the rest on to handle_shared_request
+ */
+trait HandleRequest {
+    fn handle_request(&mut self, request: RequestTypes, stream: &mut TcpStream, addr: SocketAddr) -> Result<(), Error>;
+}
+
+impl<State: StateName> TcpWorker<State> {
+    fn status_response(&self) -> String {
+        status_response::<State>(&self.pod_state)
+    }
+
+    /**
+     * @brief handle_shared_request
+     * Handle a request the way every state does unless it handles it itself, which is as if no controller were connected
+     */
+    fn handle_shared_request(&mut self, request: RequestTypes, stream: &mut TcpStream) -> Result<(), Error> {
+        match request {
+            RequestTypes::Connect(_) => {
+                stream.write_message(b"ERROR POD Already Connected to Controller\r\n")?;
+            },
+            RequestTypes::Disconnect(_) => {
+                info!("TCP HANDLER: Received a disconnect request while not connected");
+                stream.write_message(b"DISCONNECTED\r\n")?;
+            },
+            RequestTypes::Status => {
+                stream.write_message(self.status_response().as_bytes())?;
+            },
+            RequestTypes::Heartbeat => {
+                stream.write_message(b"PONG\r\n")?;
+            },
+            RequestTypes::EmergencyStop => {
+                self.emergency_stop(stream)?;
+            },
+            RequestTypes::Metrics => {
+                stream.write_message(self.metrics.snapshot().to_response().as_bytes())?;
+            },
+            RequestTypes::History(count) => {
+                stream.write_message(self.command_history.to_response(count).as_bytes())?;
+            },
+            RequestTypes::Maintenance(maintenance) => {
+                self.set_maintenance(maintenance, stream)?;
+            },
+            RequestTypes::Throttle(_) => {
+                stream.write_message(b"ERROR POD Not Connected to Controller\r\n")?;
+            },
+            RequestTypes::Unknown => {
+                warn!("Received a Malformed Input");
+            }
+        }
+        Ok(())
+    }
+
+    /**
+     * @brief handle_connection
+     * Handle the first command in a request, queueing any left over. Returns the command, or the error which stopped it
+     */
+    fn handle_connection(
+        &mut self,
+        mut stream: TcpStream,
+        request: Vec<u8>
+    ) -> Result<RequestTypes, Error> where Self: HandleRequest {
+        let addr = stream.peer_addr().tcp_socket()?;
+        info!("Connected to a new stream with addr: {}", addr);
+        debug!("Request: \n{}", String::from_utf8_lossy(&request));
+
+        match self.parse_request(request.as_slice()) {
+            requests::RequestParserResult::Success((value, remaining)) => {
+                let result = if value.is_restricted() && !self.is_on_allowlist(addr.ip()) {
+                    warn!("TCP HANDLER: Refused {:?} from {}, it is not on the allowlist", value, addr.ip());
+                    stream.write_message(b"ERROR Unauthorized\r\n").and_then(|_| Err(Error::UnauthorizedPeer(addr.ip())))
+                } else {
+                    self.handle_request(value, &mut stream, addr)
+                };
+                self.queue_remaining(stream, remaining);
+                return result.map(|_| value);
+            },
+            requests::RequestParserResult::InvalidRequest => {
+                warn!("Invalid Request Received");
+            },
+            _ => {}
+        }
+        Err(Error::UnableToHandleTcpMessage)
+    }
+}
+
+impl<State> TcpWorker<State> {
+    /**
+     * @brief accept_connection
+     * Accepts at most one new connection. Reading the request can block on a slow client,
+     * so it is done on its own thread and the result is queued for the worker to handle.
+     * This keeps a hung client from stopping the relay from accepting anyone else
+     */
+    fn accept_connection(&self) {
+        match self.listener.accept() {
+            Ok((stream, _addr)) => {
+                self.metrics.tcp_connection_accepted();
+                self.spawn_request_reader(stream)
+            },
+            Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {},
+            Err(e) => panic!("encountered IO error: {}", e),
+        }
+    }
+
+    fn spawn_request_reader(&self, mut stream: TcpStream) {
+        let request_sender = self.request_sender.clone();
+        let buffer_size = self.tcp_message_buffer_size;
+        let read_timeout = self.tcp_read_timeout;
+        std::thread::Builder::new().name("TCP Request Thread".to_string()).spawn(move || {
+            let request = read_request(&mut stream, buffer_size, read_timeout);
+            // The worker only goes away when the relay is shutting down, so the request can be dropped
+            let _ = request_sender.send(PendingRequest { stream, request });
+        }).expect("Should be able to create Thread");
+    }
+}
+
+impl<State> TcpWorker<State> {
+    /**
+     * @brief next_request
+     * Commands left over from the last request are handled before any newly read request,
+     * so that pipelined commands run in order and in the state the earlier ones left the worker in
+     */
+    fn next_request(&mut self) -> Option<PendingRequest> {
+        self.queued_request.take().or_else(|| self.request_receiver.try_recv().ok())
+    }
+
+    /**
+     * @brief parse_request
+     * Strip the next command off of a request. CONNECT may take the udp port telemetry should be sent to
+     * and the fields to send, DISCONNECT may take the argument FAST, MAINTENANCE must be given ON or OFF and THROTTLE a percent.
+     * Any other argument, or an argument to any other command, is an invalid request
+     */
+    fn parse_request<'request>(&self, request: &'request [u8]) -> requests::RequestParserResult<(RequestTypes, &'request [u8])> {
+        use requests::RequestParserResult::*;
+        match self.request_parser.strip_line_and_get_value_with_argument(request) {
+            Success((RequestTypes::Maintenance(_), None, _)) => InvalidRequest,
+            Success((RequestTypes::Throttle(_), None, _)) => InvalidRequest,
+            Success((&value, None, remaining)) => Success((value, remaining)),
+            Success((RequestTypes::Connect(_), Some(arguments), remaining)) => {
+                match parse_connect_request(arguments) {
+                    Some(connect_request) => Success((RequestTypes::Connect(connect_request), remaining)),
+                    None => InvalidRequest
+                }
+            },
+            Success((RequestTypes::History(_), Some(count), remaining)) => {
+                match std::str::from_utf8(count).ok().and_then(|count| count.parse::<usize>().ok()).filter(|&count| count > 0) {
+                    Some(count) => Success((RequestTypes::History(count), remaining)),
+                    None => InvalidRequest
+                }
+            },
+            Success((RequestTypes::Disconnect(_), Some(b"FAST"), remaining)) => Success((RequestTypes::Disconnect(DisconnectMode::Fast), remaining)),
+            Success((RequestTypes::Maintenance(_), Some(b"ON"), remaining)) => Success((RequestTypes::Maintenance(Maintenance::On), remaining)),
+            Success((RequestTypes::Maintenance(_), Some(b"OFF"), remaining)) => Success((RequestTypes::Maintenance(Maintenance::Off), remaining)),
+            Success((RequestTypes::Throttle(_), Some(percent), remaining)) => {
+                let percent = std::str::from_utf8(percent).ok()
+                    .and_then(|percent| percent.parse::<u8>().ok())
+                    .filter(|&percent| percent <= 100);
+                Success((RequestTypes::Throttle(percent), remaining))
+            },
+            Success(_) => InvalidRequest,
+            EmptyKey => EmptyKey,
+            InvalidKey => InvalidKey,
+            InvalidRequest => InvalidRequest
+        }
+    }
+
+    /**
+     * @brief is_on_allowlist
+     * True if the peer is on the allowlist, or there is no allowlist. IPv4 peers reached over an IPv6 listener are compared by their IPv4 address
+     */
+    fn is_on_allowlist(&self, peer: IpAddr) -> bool {
+        self.connect_allowlist.is_empty() || self.connect_allowlist.contains(&peer.to_canonical())
+    }
+
+    /**
+     * @brief queue_remaining
+     * Hold on to the stream if there are more commands in its request after the one just handled
+     */
+    fn queue_remaining(&mut self, stream: TcpStream, remaining: &[u8]) {
+        if !remaining.is_empty() {
+            self.queued_request = Some(PendingRequest { stream, request: remaining.to_vec() });
+        }
+    }
+
+    /**
+     * @brief emergency_stop
+     * Command the pod to LowVoltage, bypassing the udp thread's state machine, and acknowledge once it's queued
+     */
+    fn emergency_stop(&self, stream: &mut TcpStream) -> Result<(), Error> {
+        warn!("TCP THREAD: Emergency Stop Received");
+        if self.can_message_sender.send(CanMessage::ChangeState(PodState::LowVoltage)).is_err() {
+            // The supervisor commands LowVoltage on its own socket once it sees the can thread is gone
+            error!("TCP THREAD: CAN Thread is not running, the emergency stop could not be queued");
+            stream.write_message(b"ERROR POD CAN Thread is not running\r\n")?;
+            return Err(Error::ThreadUnavailable("CAN"));
+        }
+        stream.write_message(b"ESTOP ACK\r\n")?;
+        Ok(())
+    }
+
+    /**
+     * @brief set_maintenance
+     * Enter or leave maintenance, and confirm it to the requester
+     */
+    fn set_maintenance(&mut self, maintenance: Maintenance, stream: &mut TcpStream) -> Result<(), Error> {
+        match maintenance {
+            Maintenance::On => {
+                warn!("TCP THREAD: Entering maintenance, CONNECT is refused until MAINTENANCE OFF");
+                self.maintenance = true;
+                stream.write_message(b"MAINTENANCE ON\r\n")?;
+            },
+            Maintenance::Off => {
+                info!("TCP THREAD: Leaving maintenance");
+                self.maintenance = false;
+                stream.write_message(b"MAINTENANCE OFF\r\n")?;
+            }
+        }
+        Ok(())
+    }
+
+    /**
+     * @brief notify_udp
+     * Message the udp thread. Returns false, after logging, if it has exited. The tcp thread keeps
+     * serving requests until the supervisor shuts the relay down
+     */
+    fn notify_udp(&self, message: UDPMessage) -> bool {
+        match self.udp_message_sender.send(message) {
+            Ok(()) => true,
+            Err(err) => {
+                error!("TCP THREAD: UDP Thread is not running, dropped {:?}", err.0);
+                false
+            }
+        }
+    }
+}
+
+/**
+ * Commands listed by a HISTORY without a count. HISTORY <count> asks for more, up to the whole history
+ */
+const DEFAULT_HISTORY_RESPONSE_LENGTH: usize = 32;
+
+/**
+ * Largest request a client may send. Requests are read in buffer_size chunks until they are complete
+ */
+const MAX_REQUEST_SIZE: usize = 4096;
+
+/**
+ * @brief read_request
+ * Read a request off of a stream. A request may hold several \r\n terminated commands, and a
+ * partial trailing command is waited on until it completes. If the read fails, the request grows
+ * past MAX_REQUEST_SIZE, or the client does not send a complete command within read_timeout,
+ * the special @@Failed@@ request is returned
+ */
+fn read_request(stream: &mut TcpStream, buffer_size: usize, read_timeout: Duration) -> Vec<u8> {
+    // Accepted streams inherit non blocking mode from the listener on some platforms
+    if stream.set_nonblocking(false).and_then(|_| stream.set_read_timeout(Some(read_timeout))).is_err() {
+        return b"@@Failed@@\r\n".to_vec();
+    }
+    stream_utils::read_lines(stream, buffer_size, MAX_REQUEST_SIZE).unwrap_or(b"@@Failed@@\r\n".to_vec())
+}
+
+/**
+ * @brief status_response
+ * Response to a STATUS request. Of the form: STATUS <ServerState> <PodState>
+ */
+fn status_response<State: StateName>(pod_state: &PodState) -> String {
+    format!("STATUS {} {:?}\r\n", State::NAME, pod_state)
+}
+
 impl MainLoop<TcpWorkerState> for TcpWorker<Disconnected> {
     fn main_loop(mut self) -> TcpWorkerState {
         // Check for notifications from the other threads
         while let Ok(message) = self.tcp_message_receiver.try_recv() {
             match message {
-                TcpMessage::EnteringRecovery => return TcpWorkerState::Recovery(self.EnterRecovery()),
+                TcpMessage::StartupComplete => {}, // Only sent once, during startup
+                TcpMessage::EnteringRecovery | TcpMessage::UdpLinkLost => return TcpWorkerState::Recovery(self.EnterRecovery()),
                 TcpMessage::RecoveryComplete => return TcpWorkerState::Disconnected(self),
-                TcpMessage::UdpFailedToConnect => return TcpWorkerState::Disconnected(self)
+                TcpMessage::PodStateChanged(pod_state) => self.pod_state = pod_state,
+                TcpMessage::Shutdown => {
+                    self.shutdown_requested = true;
+                    return TcpWorkerState::Disconnected(self);
+                }
             }
         }
         // Check for incoming connections on TCP Socket
-        if let Some(stream) = self.listener.incoming().next() {
-            match stream {
-                Ok(s) => {
-                    // do something with the TcpStream
-                    match self.handle_connection(s) {
-                        Ok(result) => match result {
-                            RequestTypes::Connect => return TcpWorkerState::Connected(self.EnterConnected()),
-                            _ => return TcpWorkerState::Disconnected(self),
-                        },
-                        Err(err) => {
-                            println!("Error Occured While Processing TCP Stream: {:?}", err)
-                        }
-                    }
-                }
-                Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
-                    return TcpWorkerState::Disconnected(self);
+        self.accept_connection();
+
+        // Handle requests which have been read from their connections
+        if let Some(PendingRequest { stream, request }) = self.next_request() {
+            match self.handle_connection(stream, request) {
+                Ok(result) => match result {
+                    RequestTypes::Connect(_) => return TcpWorkerState::Connected(self.EnterConnected()),
+                    _ => return TcpWorkerState::Disconnected(self),
+                },
+                Err(err) => {
+                    error!("Error Occured While Processing TCP Stream: {:?}", err)
                 }
-                Err(e) => panic!("encountered IO error: {}", e),
             }
         }
 
-
         TcpWorkerState::Disconnected(self)
     }
 }
 
 impl TcpWorker<Disconnected> {
-    fn handle_connection(
-        &mut self,
-        mut stream: TcpStream
-    ) -> Result<RequestTypes, Error> {
-        let mut addr = stream.peer_addr().map_err(|e| Error::TcpSocketError(e))?;
-        println!("Connected to a new stream with addr: {}", addr);
-        let request = stream_utils::read_all(&mut stream, self.tcp_message_buffer_size).unwrap_or(b"@@Failed@@\r\n".to_vec());
-        println!("Request: \n{}", std::str::from_utf8(&request).unwrap());
-
-        match self.request_parser.strip_line_and_get_value(request.as_slice()) {
-            requests::RequestParserResult::Success((&value, _request)) => {
-                match value {
-                    RequestTypes::Connect => {
-                        println!("Connection Attempt received");
-                        addr.set_port(8090);
-                        self.udp_message_sender.send(UDPMessage::ConnectToDesktop(addr)).expect("Should be able to send Message to UDP Socket from TCP Socket");
-                        stream.write_message(b"OK 8090 8080")?;
-                    },
-                    RequestTypes::Disconnect => {
-                        println!("TCP HANDLER: Received a disconnect request while not connected");
-                        stream.write_message(b"DISCONNECTED")?;
-                    },
-                    RequestTypes::Unknown => {
-                        println!("Received a Malformed Input");
-                    }
-                }
-                return Ok(value);
-            },
-            requests::RequestParserResult::InvalidRequest => {
-                println!("Invalid Request Received");
-            },
-            _ => {}
+    /**
+     * @brief connect_udp
+     * Ask the udp thread to connect to the controller and wait for the port its socket is bound to.
+     * If the udp thread drops the request without answering, it was not in a state to connect. It is
+     * given as long as a client has to send its request, so a stuck udp thread can't hold up the tcp thread
+     */
+    fn connect_udp(&self, desktop_addr: SocketAddr, fields: TelemetryFields) -> std::io::Result<u16> {
+        let (reply_sender, reply_receiver) = channel();
+        if !self.notify_udp(UDPMessage::ConnectToDesktop(desktop_addr, fields, reply_sender)) {
+            return Err(std::io::Error::new(std::io::ErrorKind::NotConnected, "the udp thread is not running"));
+        }
+        match reply_receiver.recv_timeout(self.tcp_read_timeout) {
+            Ok(result) => result,
+            Err(RecvTimeoutError::Timeout) => Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "udp thread did not answer the connect request in time")),
+            Err(RecvTimeoutError::Disconnected) => Err(std::io::Error::new(std::io::ErrorKind::NotConnected, "udp thread did not answer the connect request"))
+        }
+    }
+
+    /**
+     * @brief connect
+     * Connect the udp thread to the controller, then tell the controller which ports telemetry is sent between
+     */
+    fn connect(&mut self, connect_request: ConnectRequest, stream: &mut TcpStream, addr: SocketAddr) -> Result<(), Error> {
+        info!("Connection Attempt received");
+        if self.maintenance {
+            warn!("TCP HANDLER: Refused a CONNECT from {}, the relay is in maintenance", addr.ip());
+            stream.write_message(b"ERROR In Maintenance\r\n")?;
+            return Err(Error::InMaintenance);
+        }
+        let fields = match connect_request.fields {
+            Some(fields) => fields,
+            None => {
+                stream.write_message(b"ERROR Unknown Telemetry Field\r\n")?;
+                return Err(Error::UnknownTelemetryField);
+            }
+        };
+        // The controller's ip is always the one it connected from, only its port may be given, e.g. from behind NAT
+        let desktop_addr = telemetry_address(addr, connect_request.desktop_port.unwrap_or(self.udp_port));
+        // Only tell the controller the connection succeeded once the udp socket is ready for it
+        let relay_udp_port = match self.connect_udp(desktop_addr, fields) {
+            Ok(relay_udp_port) => relay_udp_port,
+            Err(error) => {
+                error!("TCP HANDLER: UDP socket could not connect to the controller: {:?}", error);
+                stream.write_message(b"ERROR POD Unable to bind UDP Socket\r\n")?;
+                return Err(Error::UdpSocketError(error));
+            }
+        };
+        stream.write_message(connect_response(desktop_addr, relay_udp_port).as_bytes())?;
+        Ok(())
+    }
+}
+
+impl HandleRequest for TcpWorker<Disconnected> {
+    fn handle_request(&mut self, request: RequestTypes, stream: &mut TcpStream, addr: SocketAddr) -> Result<(), Error> {
+        match request {
+            RequestTypes::Connect(connect_request) => self.connect(connect_request, stream, addr),
+            // No controller is connected, so an ESTOP has no recovery for the udp thread to run
+            request => self.handle_shared_request(request, stream)
         }
-        Err(Error::UnableToHandleTcpMessage)
     }
 }
 
@@ -201,71 +1243,125 @@ impl MainLoop<TcpWorkerState> for TcpWorker<Connected> {
         // Check for notifications from the other threads
         while let Ok(message) = self.tcp_message_receiver.try_recv() {
             match message {
-                TcpMessage::EnteringRecovery => return TcpWorkerState::Recovery(self.EnterRecovery()),
+                TcpMessage::StartupComplete => {}, // Only sent once, during startup
+                TcpMessage::EnteringRecovery | TcpMessage::UdpLinkLost => return TcpWorkerState::Recovery(self.EnterRecovery()),
                 TcpMessage::RecoveryComplete => return TcpWorkerState::Disconnected(self.EnterDisconnected()),
-                TcpMessage::UdpFailedToConnect => return TcpWorkerState::Disconnected(self.EnterDisconnected())
+                TcpMessage::PodStateChanged(pod_state) => self.pod_state = pod_state,
+                TcpMessage::Shutdown => {
+                    self.shutdown_requested = true;
+                    return TcpWorkerState::Connected(self);
+                }
             }
         }
         // Check for incoming connections on TCP Socket
-        if let Some(stream) = self.listener.incoming().next() {
-            match stream {
-                Ok(s) => {
-                    // do something with the TcpStream
-                    match self.handle_connection(s) {
-                        Ok(result) => match result {
-                            RequestTypes::Disconnect => return TcpWorkerState::Disconnected(self.EnterDisconnected()),
-                            _ => {}
-                        },
-                        Err(err) => {
-                            println!("Error Occured While Processing TCP Stream {:?}", err)
-                        }
-                    }
-                }
-                Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
-                    return TcpWorkerState::Connected(self);
+        self.accept_connection();
+
+        // Handle requests which have been read from their connections
+        if let Some(PendingRequest { stream, request }) = self.next_request() {
+            match self.handle_connection(stream, request) {
+                Ok(result) => match result {
+                    // A controller still waiting on its DISCONNECTED is answered once recovery completes
+                    RequestTypes::Disconnect(_) if self.disconnecting_stream.is_some() => return TcpWorkerState::Recovery(self.EnterRecovery()),
+                    RequestTypes::Disconnect(_) => return TcpWorkerState::Disconnected(self.EnterDisconnected()),
+                    RequestTypes::EmergencyStop => return TcpWorkerState::Recovery(self.EnterRecovery()),
+                    _ => {}
+                },
+                Err(err) => {
+                    error!("Error Occured While Processing TCP Stream {:?}", err)
                 }
-                Err(e) => panic!("encountered IO error: {}", e),
             }
         }
 
-
         TcpWorkerState::Connected(self)
     }
 }
 
 impl TcpWorker<Connected> {
-    fn handle_connection(
-        &mut self,
-        mut stream: TcpStream
-    ) -> Result<RequestTypes, Error> {
-        let addr = stream.peer_addr().map_err(|e| Error::TcpSocketError(e))?;
-        println!("Connected to a new stream with addr: {}", addr);
-        let request = stream_utils::read_all(&mut stream, self.tcp_message_buffer_size).unwrap_or(b"@@Failed@@\r\n".to_vec());
-        println!("Request: \n{}", std::str::from_utf8(&request).unwrap());
-
-        match self.request_parser.strip_line_and_get_value(request.as_slice()) {
-            requests::RequestParserResult::Success((&value, _request)) => {
-                match value {
-                    RequestTypes::Connect => {
-                        stream.write_message(b"ERROR POD Already Connected to Controller")?;
-                    },
-                    RequestTypes::Disconnect => {
-                        println!("TCP THREAD: Disconnect Received");
-                        self.udp_message_sender.send(UDPMessage::DisconnectFromHost).expect("Should be able to send message to UDP socket");
-                        stream.write_message(b"DISCONNECTED")?;
-                    },
-                    RequestTypes::Unknown => {
-                        println!("Received a Malformed Input");
-                    }
+    /**
+     * @brief reply_to_disconnect
+     * Called once the udp thread has been told to disconnect. A controller which is not answered DISCONNECTED
+     * straight away is held on to, and confirmed once the Recovery state sees recovery complete
+     */
+    fn reply_to_disconnect(&mut self, stream: &mut TcpStream) -> Result<(), Error> {
+        match self.disconnect_wait {
+            DisconnectWait::Immediate => {
+                stream.write_message(b"DISCONNECTED\r\n")?;
+            },
+            DisconnectWait::Deferred => {
+                stream.write_message(b"DISCONNECTING\r\n")?;
+                self.disconnecting_stream = Some(stream.try_clone().tcp_socket()?);
+            },
+            DisconnectWait::Block(timeout) => {
+                // Nothing is sent until recovery completes or the timeout runs out, see TcpWorker<Recovery>::check_disconnect_timeout
+                self.disconnecting_stream = Some(stream.try_clone().tcp_socket()?);
+                self.disconnect_reply_deadline = Some(Instant::now() + timeout);
+            }
+        }
+        Ok(())
+    }
+
+    /**
+     * @brief set_throttle
+     * Change the throttle the roboteq is driven at, only while the pod is in AutoPilot.
+     * Replies with the accepted percent
+     */
+    fn set_throttle(&self, percent: Option<u8>, stream: &mut TcpStream) -> Result<(), Error> {
+        let percent = match percent {
+            Some(percent) => percent,
+            None => {
+                stream.write_message(b"ERROR Invalid Throttle\r\n")?;
+                return Ok(());
+            }
+        };
+        if self.pod_state != PodState::AutoPilot {
+            stream.write_message(b"ERROR POD Not in AutoPilot\r\n")?;
+            return Ok(());
+        }
+        if self.can_message_sender.send(CanMessage::SetThrottle(percent)).is_err() {
+            error!("TCP THREAD: CAN Thread is not running, the throttle could not be changed");
+            stream.write_message(b"ERROR POD CAN Thread is not running\r\n")?;
+            return Err(Error::ThreadUnavailable("CAN"));
+        }
+        info!("TCP THREAD: Throttle set to {}%", percent);
+        stream.write_message(format!("THROTTLE {}\r\n", percent).as_bytes())?;
+        Ok(())
+    }
+}
+
+impl HandleRequest for TcpWorker<Connected> {
+    fn handle_request(&mut self, request: RequestTypes, stream: &mut TcpStream, _addr: SocketAddr) -> Result<(), Error> {
+        match request {
+            RequestTypes::Disconnect(DisconnectMode::Fast) => {
+                warn!("TCP THREAD: Fast Disconnect Received, commanding LowVoltage without recovery");
+                if !self.notify_udp(UDPMessage::FastDisconnectFromHost) {
+                    stream.write_message(b"ERROR POD UDP Thread is not running\r\n")?;
+                    return Err(Error::ThreadUnavailable("UDP"));
                 }
-                return Ok(value);
+                stream.write_message(b"DISCONNECTED\r\n")?;
             },
-            requests::RequestParserResult::InvalidRequest => {
-                println!("Invalid Request Received");
+            RequestTypes::Disconnect(DisconnectMode::Recovery) => {
+                info!("TCP THREAD: Disconnect Received");
+                if !self.notify_udp(UDPMessage::DisconnectFromHost) {
+                    // Nothing is left to run recovery, so the controller must not be told it is safe to power off
+                    stream.write_message(b"ERROR POD UDP Thread is not running\r\n")?;
+                    return Err(Error::ThreadUnavailable("UDP"));
+                }
+                self.reply_to_disconnect(stream)?;
             },
-            _ => {}
+            RequestTypes::EmergencyStop => {
+                self.emergency_stop(stream)?;
+                self.notify_udp(UDPMessage::DisconnectFromHost);
+            },
+            RequestTypes::Maintenance(Maintenance::On) => {
+                // The connected controller has to disconnect first, maintenance never leaves a controller in charge
+                stream.write_message(b"ERROR POD Connected to Controller\r\n")?;
+            },
+            RequestTypes::Throttle(percent) => {
+                self.set_throttle(percent, stream)?;
+            },
+            request => self.handle_shared_request(request, stream)?
         }
-        Err(Error::UnableToHandleTcpMessage)
+        Ok(())
     }
 }
 
@@ -275,71 +1371,114 @@ impl MainLoop<TcpWorkerState> for TcpWorker<Recovery> {
         // Check for notifications from the other threads
         while let Ok(message) = self.tcp_message_receiver.try_recv() {
             match message {
-                TcpMessage::EnteringRecovery => return TcpWorkerState::Recovery(self.EnterRecovery()),
-                TcpMessage::RecoveryComplete => return TcpWorkerState::Disconnected(self.EnterDisconnected()),
-                TcpMessage::UdpFailedToConnect => {} // Continue in Recovery
-            }
-        }
-        // Check for incoming connections on TCP Socket
-        if let Some(stream) = self.listener.incoming().next() {
-            match stream {
-                Ok(s) => {
-                    // do something with the TcpStream
-                    match self.handle_connection(s) {
-                        Ok(_) => return TcpWorkerState::Recovery(self),
-                        Err(err) => {
-                            println!("Error Occured While Processing TCP Stream {:?}", err)
-                        }
-                    }
-                }
-                Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                TcpMessage::StartupComplete => {}, // Only sent once, during startup
+                TcpMessage::EnteringRecovery | TcpMessage::UdpLinkLost => return TcpWorkerState::Recovery(self.EnterRecovery()),
+                TcpMessage::RecoveryComplete => {
+                    self.confirm_disconnect();
+                    return TcpWorkerState::Disconnected(self.EnterDisconnected());
+                },
+                TcpMessage::PodStateChanged(pod_state) => self.pod_state = pod_state,
+                TcpMessage::Shutdown => {
+                    self.shutdown_requested = true;
                     return TcpWorkerState::Recovery(self);
                 }
-                Err(e) => panic!("encountered IO error: {}", e),
             }
         }
+        self.check_disconnect_timeout();
 
+        // Check for incoming connections on TCP Socket
+        self.accept_connection();
+
+        // Handle requests which have been read from their connections
+        if let Some(PendingRequest { stream, request }) = self.next_request() {
+            if let Err(err) = self.handle_connection(stream, request) {
+                error!("Error Occured While Processing TCP Stream {:?}", err)
+            }
+        }
 
         TcpWorkerState::Recovery(self)
     }
 }
 
 impl TcpWorker<Recovery> {
-    fn handle_connection(
-        &mut self,
-        mut stream: TcpStream
-    ) -> Result<(), Error> {
-        let addr = stream.peer_addr().map_err(|e| Error::TcpSocketError(e))?;
-        println!("Connected to a new stream with addr: {}", addr);
-        let request = stream_utils::read_all(&mut stream, self.tcp_message_buffer_size).unwrap_or(b"@@Failed@@\r\n".to_vec());
-        println!("Request: \n{}", std::str::from_utf8(&request).unwrap());
-
-        match self.request_parser.strip_line_and_get_value(request.as_slice()) {
-            requests::RequestParserResult::Success((&value, _request)) => {
-                match value {
-                    RequestTypes::Connect => {
-                        stream.write_message(b"ERROR POD Already Connected to Controller")?;
-                    },
-                    RequestTypes::Disconnect => {
-                        println!("TCP HANDLER: Received a disconnect request while not connected");
-                        stream.write_message(b"DISCONNECTED")?;
-                    },
-                    RequestTypes::Unknown => {
-                        println!("Received a Malformed Input");
-                    }
-                }
-            },
-            requests::RequestParserResult::InvalidRequest => {
-                println!("Invalid Request Received");
-            },
-            _ => {}
+    /**
+     * @brief confirm_disconnect
+     * Recovery has brought the pod down, so a controller which was told DISCONNECTING can now power off
+     */
+    fn confirm_disconnect(&mut self) {
+        self.disconnect_reply_deadline = None;
+        if let Some(mut stream) = self.disconnecting_stream.take() {
+            info!("TCP THREAD: Recovery complete, confirming the disconnect");
+            if let Err(err) = stream.write_message(b"DISCONNECTED\r\n") {
+                warn!("TCP THREAD: Controller left before its disconnect was confirmed: {:?}", err);
+            }
+        }
+    }
+
+    /**
+     * @brief check_disconnect_timeout
+     * Tell a controller whose DISCONNECT reply is being held DISCONNECTING once recovery has taken longer
+     * than the wait. It is still sent DISCONNECTED when recovery completes
+     */
+    fn check_disconnect_timeout(&mut self) {
+        match self.disconnect_reply_deadline {
+            Some(deadline) if Instant::now() >= deadline => self.disconnect_reply_deadline = None,
+            _ => return
+        }
+        warn!("TCP THREAD: Recovery did not complete within the disconnect wait, replying DISCONNECTING");
+        if let Some(mut stream) = self.disconnecting_stream.take() {
+            match stream.write_message(b"DISCONNECTING\r\n") {
+                Ok(_) => self.disconnecting_stream = Some(stream),
+                Err(err) => warn!("TCP THREAD: Controller left before its disconnect was answered: {:?}", err)
+            }
         }
-        Ok(())
+    }
+}
+
+impl HandleRequest for TcpWorker<Recovery> {
+    fn handle_request(&mut self, request: RequestTypes, stream: &mut TcpStream, _addr: SocketAddr) -> Result<(), Error> {
+        // The controller is still connected until recovery completes, but every request is handled as if it were not
+        self.handle_shared_request(request, stream)
     }
 }
 
 impl MainLoop<TcpWorkerState> for TcpWorker<Startup> {
-    fn main_loop(self) -> TcpWorkerState {
-        panic!("This function is here for completeness but it should never be called");
+    fn main_loop(mut self) -> TcpWorkerState {
+        // Wait for the udp thread to finish startup before a controller can connect
+        while let Ok(message) = self.tcp_message_receiver.try_recv() {
+            match message {
+                TcpMessage::StartupComplete => {
+                    info!("TCP THREAD: Startup complete, accepting CONNECT");
+                    return TcpWorkerState::Disconnected(self.EnterDisconnected());
+                },
+                TcpMessage::PodStateChanged(pod_state) => self.pod_state = pod_state,
+                TcpMessage::Shutdown => {
+                    self.shutdown_requested = true;
+                    return TcpWorkerState::Startup(self);
+                },
+                message => warn!("TCP THREAD: Received {:?} during Startup", message)
+            }
+        }
+        self.accept_connection();
+
+        if let Some(PendingRequest { stream, request }) = self.next_request() {
+            if let Err(err) = self.handle_connection(stream, request) {
+                error!("Error Occured While Processing TCP Stream: {:?}", err)
+            }
+        }
+        TcpWorkerState::Startup(self)
+    }
+}
+
+impl HandleRequest for TcpWorker<Startup> {
+    fn handle_request(&mut self, request: RequestTypes, stream: &mut TcpStream, addr: SocketAddr) -> Result<(), Error> {
+        match request {
+            RequestTypes::Connect(_) => {
+                info!("TCP HANDLER: Refused a CONNECT from {}, the udp thread has not finished startup", addr.ip());
+                stream.write_message(b"ERROR Starting up, retry shortly\r\n")?;
+                Err(Error::StartingUp)
+            },
+            request => self.handle_shared_request(request, stream)
+        }
     }
 }
@@ -0,0 +1,44 @@
+use std::sync::mpsc::Sender;
+use chrono::NaiveDateTime;
+use log::{ info };
+use super::worker::TelemetryWorker;
+use super::super::messages::*;
+use super::super::main_loop::WorkerStateTrait;
+use crate::pod_data::PodData;
+use crate::utils::command_history::CommandHistory;
+use crate::utils::sanity_ranges::SanityRanges;
+use crate::utils::bounded_channel::BoundedReceiver;
+
+pub struct WorkerManager {
+}
+
+impl WorkerManager {
+    pub fn run(
+        worker_message_receiver: BoundedReceiver<WorkerMessage>,
+        udp_message_sender: Sender<UDPMessage>,
+        can_message_sender: Sender<CanMessage>,
+        logger_sender: Sender<(PodData, NaiveDateTime)>,
+        telemetry_rate: u32,
+        telemetry_keepalive: Option<std::time::Duration>,
+        sanity_ranges: SanityRanges,
+        command_history: std::sync::Arc<CommandHistory>,
+        metrics: std::sync::Arc<crate::metrics::Metrics>,
+        #[cfg(feature = "websocket")]
+        websocket_message_sender: Sender<WebsocketMessage>
+    ) -> std::thread::JoinHandle<()> {
+        std::thread::Builder::new().name("Worker Thread".to_string()).spawn(move || {
+            let mut worker = TelemetryWorker::new(worker_message_receiver, udp_message_sender, can_message_sender, logger_sender, telemetry_rate, metrics)
+                .with_sanity_ranges(sanity_ranges)
+                .with_command_history(command_history)
+                .with_keepalive(telemetry_keepalive);
+            #[cfg(feature = "websocket")]
+            {
+                worker = worker.with_websocket(websocket_message_sender);
+            }
+            while !worker.is_finished() {
+                worker = worker.main_loop();
+            }
+            info!("Exiting");
+        }).expect("Should be able to create Thread")
+    }
+}
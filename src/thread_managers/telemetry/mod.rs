@@ -0,0 +1,4 @@
+mod worker;
+mod manager;
+
+pub use manager::WorkerManager;
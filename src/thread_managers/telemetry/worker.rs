@@ -0,0 +1,659 @@
+use std::sync::mpsc::{ RecvTimeoutError, Sender };
+use std::time::{ Duration, Instant };
+use chrono::NaiveDateTime;
+use log::{ debug, warn, error };
+use super::super::messages::*;
+use super::super::main_loop::WorkerStateTrait;
+use crate::can_extentions::prelude::*;
+use crate::device_watchdog::{
+    DeviceWatchdogMap,
+    DeviceWatchdogMapFuncs,
+    Device
+};
+use crate::metrics::Metrics;
+use crate::pod_data::PodData;
+use crate::utils::rpm_integrator::RpmIntegrator;
+use crate::utils::command_history::CommandHistory;
+use crate::utils::sanity_ranges::SanityRanges;
+use crate::utils::bounded_channel::BoundedReceiver;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use json::JsonValue;
+    use std::sync::Arc;
+    use std::sync::mpsc::channel;
+    use crate::utils::bounded_channel::{ bounded_channel, FullPolicy };
+    use crate::utils::sanity_ranges::OutOfRangeAction;
+
+    #[test]
+    fn frame_becomes_telemetry_for_udp_and_logger() {
+        let (worker_message_sender, worker_message_receiver) = bounded_channel(16, FullPolicy::Block);
+        let (udp_message_sender, udp_message_receiver) = channel();
+        let (can_message_sender, _can_message_receiver) = channel();
+        let (logger_sender, logger_receiver) = channel();
+        let command_history = Arc::new(CommandHistory::new(8));
+        let worker = TelemetryWorker::new(worker_message_receiver, udp_message_sender, can_message_sender, logger_sender, 20, Arc::new(Metrics::new()))
+            .with_command_history(Arc::clone(&command_history));
+
+        let frame = CANFrame::new(0x01F, &[0x00, 0x00, 0x2C, 0x41], false, false).unwrap(); // 10.75 little endian
+        let time = chrono::Utc::now().naive_local();
+        worker_message_sender.send(WorkerMessage::CanFrameAndTimeStamp(frame, time)).unwrap();
+        let worker = worker.main_loop();
+
+        match udp_message_receiver.try_recv() {
+            Ok(UDPMessage::TelemetryDataAvailable(pod_data, timestamp, _)) => {
+                assert_eq!(pod_data.speed, Some(10.75));
+                assert_eq!(timestamp, time);
+            },
+            _ => panic!("Expected telemetry to be sent to the udp thread")
+        }
+        assert!(logger_receiver.try_recv().is_ok());
+        assert_eq!(command_history.latest(usize::MAX), vec![(CanCommand::PodSpeed{ pod_speed: 10.75 }, time)]);
+        assert!(!worker.is_finished());
+
+        drop(worker_message_sender);
+        assert!(worker.main_loop().is_finished());
+    }
+
+    #[test]
+    fn telemetry_is_coalesced_to_the_rate() {
+        let (worker_message_sender, worker_message_receiver) = bounded_channel(16, FullPolicy::Block);
+        let (udp_message_sender, udp_message_receiver) = channel();
+        let (can_message_sender, _can_message_receiver) = channel();
+        let (logger_sender, _logger_receiver) = channel();
+        let mut worker = TelemetryWorker::new(worker_message_receiver, udp_message_sender, can_message_sender, logger_sender, 4, Arc::new(Metrics::new()));
+
+        let speed = |bytes: [u8; 4]| CANFrame::new(0x01F, &bytes, false, false).unwrap();
+        let time = chrono::Utc::now().naive_local();
+        worker_message_sender.send(WorkerMessage::CanFrameAndTimeStamp(speed([0x00, 0x00, 0x2C, 0x41]), time)).unwrap(); // 10.75
+        worker = worker.main_loop();
+        assert!(udp_message_receiver.try_recv().is_ok());
+
+        // Both arrive before the next tick, only the latest speed is sent
+        worker_message_sender.send(WorkerMessage::CanFrameAndTimeStamp(speed([0x00, 0x00, 0x48, 0x41]), time)).unwrap(); // 12.5
+        worker_message_sender.send(WorkerMessage::CanFrameAndTimeStamp(speed([0x00, 0x00, 0xF2, 0x41]), time)).unwrap(); // 30.25
+        worker = worker.main_loop().main_loop();
+        assert!(udp_message_receiver.try_recv().is_err());
+        worker = worker.main_loop();
+        match udp_message_receiver.try_recv() {
+            Ok(UDPMessage::TelemetryDataAvailable(pod_data, _, _)) => assert_eq!(pod_data.speed, Some(30.25)),
+            _ => panic!("Expected the coalesced telemetry on the tick")
+        }
+
+        // An update still waiting for its tick is sent when the can thread exits
+        worker_message_sender.send(WorkerMessage::CanFrameAndTimeStamp(speed([0x00, 0x00, 0x2C, 0x41]), time)).unwrap();
+        drop(worker_message_sender);
+        worker = worker.main_loop().main_loop();
+        assert!(worker.is_finished());
+        match udp_message_receiver.try_recv() {
+            Ok(UDPMessage::TelemetryDataAvailable(pod_data, _, _)) => assert_eq!(pod_data.speed, Some(10.75)),
+            _ => panic!("Expected the final telemetry to be sent")
+        }
+    }
+
+    #[test]
+    fn snapshot_sequence_is_strictly_increasing() {
+        let (worker_message_sender, worker_message_receiver) = bounded_channel(16, FullPolicy::Block);
+        let (udp_message_sender, udp_message_receiver) = channel();
+        let (can_message_sender, _can_message_receiver) = channel();
+        let (logger_sender, _logger_receiver) = channel();
+        let mut worker = TelemetryWorker::new(worker_message_receiver, udp_message_sender, can_message_sender, logger_sender, 1000, Arc::new(Metrics::new()));
+
+        let frame = CANFrame::new(0x01F, &[0x00, 0x00, 0x2C, 0x41], false, false).unwrap();
+        let mut sequences = Vec::new();
+        for _ in 0..5 {
+            worker_message_sender.send(WorkerMessage::CanFrameAndTimeStamp(frame, chrono::Utc::now().naive_local())).unwrap();
+            loop {
+                worker = worker.main_loop();
+                if let Ok(UDPMessage::TelemetryDataAvailable(_, _, sequence)) = udp_message_receiver.try_recv() {
+                    sequences.push(sequence);
+                    break;
+                }
+            }
+        }
+        assert_eq!(sequences[0], 0);
+        assert!(sequences.windows(2).all(|pair| pair[1] > pair[0]));
+    }
+
+    #[test]
+    fn last_snapshot_is_sent_again_as_a_keepalive() {
+        let (worker_message_sender, worker_message_receiver) = bounded_channel(16, FullPolicy::Block);
+        let (udp_message_sender, udp_message_receiver) = channel();
+        let (can_message_sender, _can_message_receiver) = channel();
+        let (logger_sender, _logger_receiver) = channel();
+        let mut worker = TelemetryWorker::new(worker_message_receiver, udp_message_sender, can_message_sender, logger_sender, 1000, Arc::new(Metrics::new()))
+            .with_keepalive(Some(Duration::from_millis(20)));
+
+        // Nothing has been sent yet, so there is nothing to keep alive
+        assert_eq!(worker.time_until_keepalive(), None);
+        let frame = CANFrame::new(0x01F, &[0x00, 0x00, 0x2C, 0x41], false, false).unwrap(); // 10.75
+        let time = chrono::Utc::now().naive_local();
+        worker_message_sender.send(WorkerMessage::CanFrameAndTimeStamp(frame, time)).unwrap();
+        worker = worker.main_loop();
+        assert!(matches!(udp_message_receiver.try_recv(), Ok(UDPMessage::TelemetryDataAvailable(_, _, 0))));
+
+        worker = worker.main_loop();
+        match udp_message_receiver.try_recv() {
+            Ok(UDPMessage::TelemetryKeepalive(pod_data, timestamp, sequence)) => {
+                assert_eq!(pod_data.speed, Some(10.75));
+                assert!(timestamp > time);
+                assert_eq!(sequence, 1);
+            },
+            _ => panic!("Expected a keepalive once nothing had changed for the interval")
+        }
+        assert!(!worker.is_finished());
+    }
+
+    #[test]
+    fn pod_speed_frame_reaches_telemetry() {
+        let frame = CANFrame::new(0x01F, &[0x00, 0x00, 0x2C, 0x41], false, false).unwrap(); // 10.75 little endian
+        let command = frame.get_command().unwrap();
+        let mut pod_data = PodData::new();
+        let time = chrono::Utc::now().naive_local();
+
+        assert!(update_pod_data(&mut pod_data, &command, &time, &SanityRanges::default()));
+        assert_eq!(command_source(&command), Some(Device::MC));
+        assert_eq!(pod_data.speed, Some(10.75));
+        assert!(pod_data.ok());
+        assert!(!pod_data.is_stale("speed", &time, Duration::from_millis(100)));
+        assert!(pod_data.is_stale("mc_pod_speed", &time, Duration::from_millis(100)));
+
+        let jv: JsonValue = pod_data.into();
+        assert_eq!(jv["speed"].as_f32(), Some(10.75));
+    }
+
+    #[test]
+    fn bms_health_check_reaches_telemetry() {
+        // 12.5 and 30.25 little endian
+        let frame = CANFrame::new(0x001, &[0x00, 0x00, 0x48, 0x41, 0x00, 0x00, 0xF2, 0x41], false, false).unwrap();
+        let command = frame.get_command().unwrap();
+        let mut pod_data = PodData::new();
+
+        assert!(update_pod_data(&mut pod_data, &command, &chrono::Utc::now().naive_local(), &SanityRanges::default()));
+        assert_eq!(command_source(&command), Some(Device::BMS));
+
+        let jv: JsonValue = pod_data.into();
+        assert_eq!(jv["battery_pack_current"].as_f32(), Some(12.5));
+        assert_eq!(jv["average_cell_temperature"].as_f32(), Some(30.25));
+    }
+
+    #[test]
+    fn motor_controller_health_check_reaches_telemetry() {
+        // 85.5 and 33.25 little endian
+        let frame = CANFrame::new(0x002, &[0x00, 0x00, 0xAB, 0x42, 0x00, 0x00, 0x05, 0x42], false, false).unwrap();
+        let command = frame.get_command().unwrap();
+        let mut pod_data = PodData::new();
+
+        assert!(update_pod_data(&mut pod_data, &command, &chrono::Utc::now().naive_local(), &SanityRanges::default()));
+        assert_eq!(command_source(&command), Some(Device::MC));
+        assert!(pod_data.ok());
+
+        let jv: JsonValue = pod_data.into();
+        assert_eq!(jv["igbt_temp"].as_f32(), Some(85.5));
+        assert_eq!(jv["motor_voltage"].as_f32(), Some(33.25));
+    }
+
+    #[test]
+    fn bms_data_frames_reach_telemetry() {
+        use crate::can_extentions::can_ids;
+        let frame = |id: u32, readings: &[f32]| {
+            let data: Vec<u8> = readings.iter().flat_map(|reading| reading.to_le_bytes()).collect();
+            CANFrame::new(id, &data, false, false).unwrap()
+        };
+        let frames = [frame(can_ids::BMS_DATA_1, &[48.25, 87.5]), frame(can_ids::BMS_DATA_2, &[31.0, 0.025]), frame(can_ids::BMS_DATA_3, &[47.75])];
+        let mut pod_data = PodData::new();
+        let time = chrono::Utc::now().naive_local();
+
+        for command in &frames.iter().map(|frame| frame.get_command().unwrap()).collect::<Vec<_>>() {
+            assert!(update_pod_data(&mut pod_data, command, &time, &SanityRanges::default()));
+            assert_eq!(command_source(command), Some(Device::BMS));
+        }
+        assert_eq!((pod_data.battery_pack_voltage, pod_data.state_of_charge), (Some(48.25), Some(87.5)));
+        assert_eq!((pod_data.buck_temperature, pod_data.bms_current), (Some(31.0), Some(0.025)));
+        assert_eq!(pod_data.link_cap_voltage, Some(47.75));
+        // Readings which are in range must not stop telemetry from being sent
+        assert!(pod_data.ok());
+        for field in &["battery_pack_voltage", "state_of_charge", "buck_temperature", "bms_current", "link_cap_voltage"] {
+            assert!(!pod_data.is_stale(field, &time, Duration::from_millis(100)), "{} was not marked updated", field);
+        }
+
+        let jv: JsonValue = pod_data.into();
+        assert_eq!(jv["state_of_charge"].as_f32(), Some(87.5));
+
+        let (worker_message_sender, worker_message_receiver) = bounded_channel(16, FullPolicy::Block);
+        let (udp_message_sender, udp_message_receiver) = channel();
+        let (can_message_sender, _can_message_receiver) = channel();
+        let (logger_sender, _logger_receiver) = channel();
+        let mut worker = TelemetryWorker::new(worker_message_receiver, udp_message_sender, can_message_sender, logger_sender, 1000, Arc::new(Metrics::new()));
+        for frame in &frames {
+            worker_message_sender.send(WorkerMessage::CanFrameAndTimeStamp(*frame, time)).unwrap();
+        }
+        let mut link_cap_voltage = None;
+        for _ in 0..100 {
+            worker = worker.main_loop();
+            if let Ok(UDPMessage::TelemetryDataAvailable(pod_data, _, _)) = udp_message_receiver.try_recv() {
+                link_cap_voltage = pod_data.link_cap_voltage;
+                if link_cap_voltage.is_some() {
+                    break;
+                }
+            }
+        }
+        assert_eq!(link_cap_voltage, Some(47.75), "Expected a snapshot with the BMS readings to be sent");
+    }
+
+    #[test]
+    fn roboteq_battery_amps_reach_their_motor() {
+        let mut pod_data = PodData::new();
+        let time = chrono::Utc::now().naive_local();
+        assert!(update_pod_data(&mut pod_data, &CanCommand::RoboteqBatteryAmpsResult{ motor_number: 1, amps: -12 }, &time, &SanityRanges::default()));
+        assert!(update_pod_data(&mut pod_data, &CanCommand::RoboteqBatteryAmpsResult{ motor_number: 2, amps: 7 }, &time, &SanityRanges::default()));
+        assert_eq!((pod_data.roboteq_motor_1_battery_amps, pod_data.roboteq_motor_2_battery_amps), (Some(-12), Some(7)));
+        assert!(!pod_data.is_stale("roboteq_motor_2_battery_amps", &time, Duration::from_millis(100)));
+    }
+
+    #[cfg(feature = "mock-can")]
+    #[test]
+    fn simulated_pod_telemetry_passes_the_checks() {
+        let pod = SimulatedPod::new();
+        let mut pod_data = PodData::new();
+        let time = chrono::Utc::now().naive_local();
+        for _ in 0..30 {
+            let command = pod.read_frame().unwrap().get_command().unwrap();
+            update_pod_data(&mut pod_data, &command, &time, &SanityRanges::default());
+        }
+        assert!(pod_data.ok(), "{:?}", pod_data);
+    }
+
+    #[test]
+    fn state_changes_are_not_telemetry() {
+        let frame = CANFrame::new(0x00B, &[0x00, 0x00], false, false).unwrap();
+        let command = frame.get_command().unwrap();
+        let mut pod_data = PodData::new();
+
+        assert!(!update_pod_data(&mut pod_data, &command, &chrono::Utc::now().naive_local(), &SanityRanges::default()));
+        assert_eq!(pod_data.updated, Default::default());
+        assert_eq!(command_source(&command), None);
+    }
+
+    #[test]
+    fn out_of_range_readings_are_dropped_unless_flagged() {
+        let mut sanity_ranges = SanityRanges::default();
+        let mut pod_data = PodData::new();
+        let time = chrono::Utc::now().naive_local();
+
+        let command = CanCommand::BmsHealthCheck{ battery_pack_current: 12.5, cell_temperature: -3000.0 };
+        assert!(update_pod_data(&mut pod_data, &command, &time, &sanity_ranges));
+        assert_eq!(pod_data.battery_pack_current, Some(12.5));
+        assert_eq!(pod_data.average_cell_temperature, None);
+        assert!(pod_data.is_stale("average_cell_temperature", &time, Duration::from_millis(100)));
+        assert!(!update_pod_data(&mut pod_data, &CanCommand::PressureHigh(-5.0), &time, &sanity_ranges));
+
+        sanity_ranges.set_action(OutOfRangeAction::Flag);
+        assert!(update_pod_data(&mut pod_data, &command, &time, &sanity_ranges));
+        assert_eq!(pod_data.average_cell_temperature, Some(-3000.0));
+    }
+}
+
+/**
+ * Turns the frames read by the can thread into telemetry. Keeps the latest value of every field,
+ * feeds the device watchdog, and passes each complete snapshot on to the logger. The udp thread is
+ * sent at most telemetry_rate snapshots per second, each carrying every update made since the last
+ */
+pub struct TelemetryWorker {
+    pod_data: PodData,
+    watchdog: DeviceWatchdogMap<CanMessage>,
+    worker_message_receiver: BoundedReceiver<WorkerMessage>,
+    udp_message_sender: Sender<UDPMessage>,
+    logger_sender: Sender<(PodData, NaiveDateTime)>,
+    #[cfg(feature = "websocket")]
+    websocket_message_sender: Option<Sender<WebsocketMessage>>, // Also sent every snapshot, for browser dashboards
+    telemetry_interval: Duration,
+    last_snapshot: Option<Instant>, // When a snapshot or keepalive was last sent to the udp thread
+    pending_snapshot: Option<NaiveDateTime>, // Timestamp of the newest update which has not been sent to the udp thread
+    next_sequence: u64, // Sequence number of the next snapshot. Starts from 0 each time the worker is created
+    keepalive_interval: Option<Duration>, // The last snapshot is sent again once nothing has been sent for this long. None sends no keepalives
+    sanity_ranges: SanityRanges,
+    command_history: Option<std::sync::Arc<CommandHistory>>, // Every decoded command is recorded here, if given
+    metrics: std::sync::Arc<Metrics>,
+    finished: bool
+}
+
+impl TelemetryWorker {
+    pub fn new(
+        worker_message_receiver: BoundedReceiver<WorkerMessage>,
+        udp_message_sender: Sender<UDPMessage>,
+        can_message_sender: Sender<CanMessage>,
+        logger_sender: Sender<(PodData, NaiveDateTime)>,
+        telemetry_rate: u32,
+        metrics: std::sync::Arc<Metrics>
+    ) -> TelemetryWorker {
+        TelemetryWorker {
+            pod_data: PodData::new(),
+            watchdog: DeviceWatchdogMap::with_all_devices(can_message_sender, CanMessage::DeviceLost, 400),
+            worker_message_receiver,
+            udp_message_sender,
+            logger_sender,
+            #[cfg(feature = "websocket")]
+            websocket_message_sender: None,
+            telemetry_interval: Duration::from_secs(1) / telemetry_rate.max(1),
+            last_snapshot: None,
+            pending_snapshot: None,
+            next_sequence: 0,
+            keepalive_interval: None,
+            sanity_ranges: SanityRanges::default(),
+            command_history: None,
+            metrics,
+            finished: false
+        }
+    }
+
+    /**
+     * @brief with_sanity_ranges
+     * Check readings against sanity_ranges rather than the defaults
+     */
+    pub fn with_sanity_ranges(mut self, sanity_ranges: SanityRanges) -> TelemetryWorker {
+        self.sanity_ranges = sanity_ranges;
+        self
+    }
+
+    /**
+     * @brief with_command_history
+     * Record every decoded command in command_history
+     */
+    pub fn with_command_history(mut self, command_history: std::sync::Arc<CommandHistory>) -> TelemetryWorker {
+        self.command_history = Some(command_history);
+        self
+    }
+
+    /**
+     * @brief with_keepalive
+     * Send the last snapshot again as a keepalive whenever nothing has been sent for keepalive_interval
+     */
+    pub fn with_keepalive(mut self, keepalive_interval: Option<Duration>) -> TelemetryWorker {
+        self.keepalive_interval = keepalive_interval;
+        self
+    }
+
+    /**
+     * @brief with_websocket
+     * Send every snapshot to the websocket thread as well as the udp thread
+     */
+    #[cfg(feature = "websocket")]
+    pub fn with_websocket(mut self, websocket_message_sender: Sender<WebsocketMessage>) -> TelemetryWorker {
+        self.websocket_message_sender = Some(websocket_message_sender);
+        self
+    }
+
+    /**
+     * @brief is_finished
+     * True once the can thread has exited and there are no more frames to handle
+     */
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    fn handle_frame(&mut self, frame: CANFrame, time: NaiveDateTime) {
+        let command = match frame.get_command() {
+            Ok(command) => command,
+            Err(_) => return, // The CAN thread reports and drops frames which fail to decode
+        };
+        if let Some(command_history) = &self.command_history {
+            command_history.record(command.clone(), time);
+        }
+        let new_data = update_pod_data(&mut self.pod_data, &command, &time, &self.sanity_ranges);
+        if let Some(device) = command_source(&command) {
+            self.watchdog.update_device_timestamp(device, crate::device_watchdog::get_now());
+        }
+        let devices = self.watchdog.check_devices();
+        for device in &devices {
+            warn!("DEBUG: WATCHDOG DETECTED DEVICE LOST: {:?}", device);
+        }
+        if new_data {
+            // println!("NEW DATA Parsed: {:?}", pod_data);
+            if self.pod_data.ok() {
+                self.logger_sender.send((self.pod_data.clone(), time)).unwrap();
+                self.pending_snapshot = Some(time);
+            } else {
+                //udp_message_sender.send(UDPMessage::SystemFault).expect("TO BE ABLE TO SEND MESSAGE");
+            }
+        }
+    }
+
+    fn time_until_next_snapshot(&self) -> Duration {
+        self.last_snapshot.map_or(Duration::ZERO, |last_snapshot| self.telemetry_interval.saturating_sub(last_snapshot.elapsed()))
+    }
+
+    /**
+     * None while a snapshot is pending, before the first snapshot has been sent, or without a keepalive interval
+     */
+    fn time_until_keepalive(&self) -> Option<Duration> {
+        if self.pending_snapshot.is_some() {
+            return None;
+        }
+        let (keepalive_interval, last_snapshot) = (self.keepalive_interval?, self.last_snapshot?);
+        Some(keepalive_interval.saturating_sub(last_snapshot.elapsed()))
+    }
+
+    /**
+     * Send the last snapshot to the udp thread again, stamped now and numbered like a snapshot.
+     * Not counted as telemetry sent, and not sent to the websocket thread
+     */
+    fn send_keepalive(&mut self) {
+        let sequence = self.next_sequence;
+        self.next_sequence = self.next_sequence.wrapping_add(1);
+        debug!("WORKER THREAD: No new telemetry, sending keepalive {}", sequence);
+        let message = UDPMessage::TelemetryKeepalive(self.pod_data.clone(), chrono::Utc::now().naive_local(), sequence);
+        self.udp_message_sender.send(message).expect("To be able to send telemetry data to udp from worker");
+        self.last_snapshot = Some(Instant::now());
+    }
+
+    /**
+     * Send the latest pod data to the udp thread, if it has changed since the last snapshot
+     */
+    fn send_snapshot(&mut self) {
+        if let Some((message, time)) = self.take_snapshot() {
+            #[cfg(feature = "websocket")]
+            self.send_to_websocket(&message);
+            self.udp_message_sender.send(message).expect("To be able to send telemetry data to udp from worker");
+            self.snapshot_sent(&time);
+            self.last_snapshot = Some(Instant::now());
+        }
+    }
+
+    /**
+     * The websocket thread is optional, if it has exited the udp thread is still sent telemetry
+     */
+    #[cfg(feature = "websocket")]
+    fn send_to_websocket(&mut self, message: &UDPMessage) {
+        if let (Some(websocket_message_sender), UDPMessage::TelemetryDataAvailable(pod_data, time, sequence)) = (&self.websocket_message_sender, message) {
+            if websocket_message_sender.send(WebsocketMessage::TelemetryDataAvailable(pod_data.clone(), *time, *sequence)).is_err() {
+                error!("WORKER THREAD: Websocket Thread is not running, no longer sending it telemetry");
+                self.websocket_message_sender = None;
+            }
+        }
+    }
+
+    /**
+     * Build the message for the pending snapshot and number it, along with the time it was taken. Wraps rather than overflowing,
+     * so the controller should compare sequence numbers with wrapping arithmetic
+     */
+    fn take_snapshot(&mut self) -> Option<(UDPMessage, chrono::NaiveDateTime)> {
+        let time = self.pending_snapshot.take()?;
+        let sequence = self.next_sequence;
+        self.next_sequence = self.next_sequence.wrapping_add(1);
+        Some((UDPMessage::TelemetryDataAvailable(self.pod_data.clone(), time, sequence), time))
+    }
+
+    /**
+     * Only counted once the udp thread has the snapshot, so the metrics never include one that was dropped
+     */
+    fn snapshot_sent(&self, time: &chrono::NaiveDateTime) {
+        self.metrics.telemetry_snapshot_sent();
+        self.metrics.telemetry_sent_at(time);
+    }
+}
+
+impl WorkerStateTrait for TelemetryWorker {
+    /**
+     * Handle one message from the can thread, or wait until the next snapshot is due if an update is pending,
+     * or until the next keepalive is due if not
+     */
+    fn main_loop(mut self) -> TelemetryWorker {
+        let message = match (self.pending_snapshot, self.time_until_keepalive()) {
+            (Some(_), _) => self.worker_message_receiver.recv_timeout(self.time_until_next_snapshot()),
+            (None, Some(time_until_keepalive)) => self.worker_message_receiver.recv_timeout(time_until_keepalive),
+            (None, None) => self.worker_message_receiver.recv().map_err(|_| RecvTimeoutError::Disconnected)
+        };
+        match message {
+            Ok(WorkerMessage::CanFrameAndTimeStamp(frame, time)) => self.handle_frame(frame, time),
+            Err(RecvTimeoutError::Timeout) => {},
+            Err(err) => {
+                // The can thread has exited and closed the channel. The last update is still sent,
+                // though the udp thread may already be gone
+                error!("Worker Receiver Error: {:?}", err);
+                if let Some((message, time)) = self.take_snapshot() {
+                    if self.udp_message_sender.send(message).is_ok() {
+                        self.snapshot_sent(&time);
+                    }
+                }
+                self.finished = true;
+                return self;
+            }
+        }
+        if self.time_until_next_snapshot().is_zero() {
+            self.send_snapshot();
+        }
+        if matches!(self.time_until_keepalive(), Some(time_until_keepalive) if time_until_keepalive.is_zero()) {
+            self.send_keepalive();
+        }
+        self
+    }
+}
+
+/**
+ * Set PodData fields and record when they were received, under the field's own name so the two cannot disagree.
+ * Readings the sanity ranges refuse leave their field untouched. True if any field was set
+ */
+macro_rules! update {
+    ($pod_data:ident, $time:ident, $sanity_ranges:ident, $($field:ident = $value:expr),+) => {{
+        let mut updated = false;
+        $(
+            let value = $value;
+            if $sanity_ranges.accept(stringify!($field), &value) {
+                $pod_data.$field = value;
+                $pod_data.mark_updated(stringify!($field), $time);
+                updated = true;
+            }
+        )+
+        updated
+    }};
+}
+
+/**
+ * @brief update_pod_data
+ * Store the telemetry carried by a decoded CAN command in pod_data, received at time.
+ * Returns false if the command did not carry any new telemetry, or every reading it carried was dropped
+ */
+fn update_pod_data(pod_data: &mut PodData, command: &CanCommand, time: &NaiveDateTime, sanity_ranges: &SanityRanges) -> bool {
+    match *command {
+        CanCommand::BmsHealthCheck{ battery_pack_current, cell_temperature } => {
+            update!(pod_data, time, sanity_ranges, battery_pack_current = Some(battery_pack_current), average_cell_temperature = Some(cell_temperature))
+        },
+        CanCommand::MotorControllerHealthCheck{ igbt_temp, motor_voltage } => {
+            update!(pod_data, time, sanity_ranges, motor_voltage = Some(motor_voltage), igbt_temp = Some(igbt_temp))
+        },
+        CanCommand::BmsData1{ battery_pack_voltage, state_of_charge } => {
+            update!(pod_data, time, sanity_ranges, battery_pack_voltage = Some(battery_pack_voltage), state_of_charge = Some(state_of_charge))
+        },
+        CanCommand::BmsData2{ buck_temperature, bms_current } => {
+            update!(pod_data, time, sanity_ranges, buck_temperature = Some(buck_temperature), bms_current = Some(bms_current))
+        },
+        CanCommand::BmsData3{ link_cap_voltage } => {
+            update!(pod_data, time, sanity_ranges, link_cap_voltage = Some(link_cap_voltage))
+        },
+        CanCommand::MotorControllerData1{ mc_pod_speed, motor_current } => {
+            update!(pod_data, time, sanity_ranges, mc_pod_speed = Some(mc_pod_speed), motor_current = Some(motor_current))
+        },
+        CanCommand::MotorControllerData2{ battery_current, battery_voltage } => {
+            update!(pod_data, time, sanity_ranges, battery_current = Some(battery_current), battery_voltage = Some(battery_voltage))
+        },
+        CanCommand::PodSpeed{ pod_speed } => {
+            update!(pod_data, time, sanity_ranges, speed = Some(pod_speed))
+        },
+        CanCommand::PressureHigh(pressure) => {
+            update!(pod_data, time, sanity_ranges, pressure_high = Some(pressure))
+        },
+        CanCommand::PressureLow1(pressure) => {
+            update!(pod_data, time, sanity_ranges, pressure_low_1 = Some(pressure))
+        },
+        CanCommand::PressureLow2(pressure) => {
+            update!(pod_data, time, sanity_ranges, pressure_low_2 = Some(pressure))
+        },
+        CanCommand::Current5V(current) => {
+            update!(pod_data, time, sanity_ranges, current_5v = Some(current))
+        },
+        CanCommand::Current12V(current) => {
+            update!(pod_data, time, sanity_ranges, current_12v = Some(current))
+        },
+        CanCommand::Current24V(current) => {
+            update!(pod_data, time, sanity_ranges, current_24v = Some(current))
+        },
+        CanCommand::Torchic1(data) => {
+            debug!("TORCHIC1 DATA: {:?}", data);
+            update!(pod_data, time, sanity_ranges, torchic_1 = data)
+        },
+        CanCommand::Torchic2(data) => {
+            update!(pod_data, time, sanity_ranges, torchic_2 = data)
+        },
+        CanCommand::RoboteqBatteryAmpsResult{ motor_number, amps } => {
+            match motor_number {
+                1 => update!(pod_data, time, sanity_ranges, roboteq_motor_1_battery_amps = Some(amps)),
+                2 => update!(pod_data, time, sanity_ranges, roboteq_motor_2_battery_amps = Some(amps)),
+                _ => false
+            }
+        },
+        CanCommand::RoboteqMotorEncoderResult{ motor_number, speed } => {
+            match motor_number {
+                1 => update!(pod_data, time, sanity_ranges, roboteq_motor_1_speed = Some(RpmIntegrator::calc_speed(speed))),
+                2 => update!(pod_data, time, sanity_ranges, roboteq_motor_2_speed = Some(RpmIntegrator::calc_speed(speed))),
+                _ => false
+            }
+        },
+        CanCommand::RoboteqTemperatureResult{ sub_index, temp } => {
+            match sub_index {
+                1 => update!(pod_data, time, sanity_ranges, roboteq_mcu_temp = Some(temp)),
+                2 => update!(pod_data, time, sanity_ranges, roboteq_sensor_1_temp = Some(temp)),
+                3 => update!(pod_data, time, sanity_ranges, roboteq_sensor_2_temp = Some(temp)),
+                _ => false
+            }
+        },
+        _ => false
+    }
+}
+
+/**
+ * @brief command_source
+ * The device which sends a telemetry command. Used to feed the device watchdog
+ */
+fn command_source(command: &CanCommand) -> Option<Device> {
+    match command {
+        CanCommand::BmsHealthCheck{ .. }
+        | CanCommand::BmsData1{ .. }
+        | CanCommand::BmsData2{ .. }
+        | CanCommand::BmsData3{ .. } => Some(Device::BMS),
+        CanCommand::MotorControllerHealthCheck{ .. }
+        | CanCommand::MotorControllerData1{ .. }
+        | CanCommand::MotorControllerData2{ .. }
+        | CanCommand::PodSpeed{ .. } => Some(Device::MC),
+        CanCommand::PressureHigh(_) => Some(Device::PRESSURE_HIGH),
+        CanCommand::PressureLow1(_) => Some(Device::PRESSURE_LOW_1),
+        CanCommand::PressureLow2(_) => Some(Device::PRESSURE_LOW_2),
+        CanCommand::Current5V(_)
+        | CanCommand::Current12V(_)
+        | CanCommand::Current24V(_) => Some(Device::ELEKID),
+        CanCommand::Torchic1(_) => Some(Device::TORCHIC_1),
+        CanCommand::Torchic2(_) => Some(Device::TORCHIC_2),
+        _ => None
+    }
+}
@@ -2,9 +2,34 @@ use std::sync::mpsc::{
     Sender,
     Receiver
 };
+use log::{ info };
 use std::time::Duration;
 use super::worker::UdpWorkerState;
 use super::super::messages::*;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::mpsc::channel;
+
+    #[test]
+    fn shutdown_joins_udp_thread() {
+        let (can_sender, _can_receiver) = channel();
+        let (tcp_sender, _tcp_receiver) = channel();
+        let (udp_sender, udp_receiver) = channel();
+        let handle = UdpManager::run(can_sender, tcp_sender, udp_receiver, 10, Duration::from_millis(50), Duration::from_millis(10), Duration::from_millis(100), "0.0.0.0:8080", std::sync::Arc::new(crate::metrics::Metrics::new()));
+
+        udp_sender.send(UDPMessage::StartupComplete).unwrap();
+        udp_sender.send(UDPMessage::Shutdown).unwrap();
+
+        let (joined_sender, joined_receiver) = channel();
+        std::thread::spawn(move || {
+            joined_sender.send(handle.join().is_ok()).unwrap();
+        });
+        assert_eq!(joined_receiver.recv_timeout(Duration::from_secs(2)), Ok(true));
+    }
+}
+
 pub struct UdpManager {
 }
 use super::super::main_loop::WorkerStateTrait;
@@ -16,14 +41,20 @@ impl UdpManager {
         udp_receiver: Receiver<UDPMessage>,
         udp_max_number_timeouts: u32,
         udp_socket_read_timeout: Duration,
-        udp_address: A
+        backoff_base: Duration,
+        backoff_cap: Duration,
+        udp_address: A,
+        metrics: std::sync::Arc<crate::metrics::Metrics>
     ) -> std::thread::JoinHandle<()> {
         std::thread::Builder::new().name("UDP Thread".to_string()).spawn(move || {
             // Setup
-            let mut udp_worker = UdpWorkerState::new(can_sender, tcp_sender, udp_receiver, udp_max_number_timeouts, udp_socket_read_timeout, udp_address);
-            loop {
+            let mut udp_worker = UdpWorkerState::new(can_sender, tcp_sender, udp_receiver, udp_max_number_timeouts, udp_socket_read_timeout, backoff_base, backoff_cap, udp_address, metrics);
+            while !udp_worker.is_shutdown() {
                 udp_worker = udp_worker.main_loop();
             }
+            // Dropping the worker closes the udp socket
+            drop(udp_worker);
+            info!("UDP THREAD: Shut down");
         }).expect("Should be able to create Thread")
     }
 }
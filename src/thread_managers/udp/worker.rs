@@ -1,13 +1,18 @@
 use std::net::{
+    SocketAddr,
     UdpSocket,
 };
+use log::{ info, warn, error };
 use chrono;
+use std::sync::Arc;
 use std::sync::mpsc::{
     Sender,
     Receiver
 };
 use std::time::Duration;
 use crate::{
+    can_extentions::fault_reports::FaultReport,
+    metrics::Metrics,
     pod_data,
     pod_states::{
         PodState
@@ -24,10 +29,130 @@ use crate::{
 use super::super::worker_states::*;
 use super::super::messages::*;
 use super::super::main_loop::*;
+use super::super::backoff::backoff_delay;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /**
+     * A worker on a loopback socket, with the channels the other threads would use to talk to it
+     */
+    fn test_worker() -> (UdpWorker<Startup>, Sender<UDPMessage>, Receiver<TcpMessage>, Receiver<CanMessage>) {
+        let (can_sender, can_receiver) = std::sync::mpsc::channel();
+        let (tcp_sender, tcp_receiver) = std::sync::mpsc::channel();
+        let (udp_sender, udp_receiver) = std::sync::mpsc::channel();
+        let worker = UdpWorker::<Startup>::new(can_sender, tcp_sender, udp_receiver, 10, Duration::from_millis(100), Duration::ZERO, Duration::ZERO, "127.0.0.1:0", Arc::new(Metrics::new()));
+        (worker, udp_sender, tcp_receiver, can_receiver)
+    }
+
+    #[test]
+    fn recovery_completes_once_low_voltage_is_acked() {
+        let (mut worker, udp_sender, tcp_receiver, can_receiver) = test_worker();
+        worker.current_pod_state = PodState::Armed;
+        worker.next_pod_state = PodState::Armed;
+
+        let worker = match worker.EnterRecovery().main_loop() {
+            UdpWorkerState::Recovery(worker) => worker,
+            _ => panic!("Recovery should wait for the pod to reach LowVoltage")
+        };
+        assert!(matches!(can_receiver.try_recv(), Ok(CanMessage::ChangeState(PodState::LowVoltage))));
+        assert!(tcp_receiver.try_recv().is_err());
+
+        // The can thread acks once every board has reported LowVoltage
+        udp_sender.send(UDPMessage::PodStateChangeAck).unwrap();
+        assert!(matches!(worker.main_loop(), UdpWorkerState::Disconnected(_)));
+        assert!(matches!(tcp_receiver.try_recv(), Ok(TcpMessage::PodStateChanged(PodState::LowVoltage))));
+        assert!(matches!(tcp_receiver.try_recv(), Ok(TcpMessage::RecoveryComplete)));
+    }
+
+    #[test]
+    fn connect_is_refused_during_recovery() {
+        let (mut worker, udp_sender, _tcp_receiver, _can_receiver) = test_worker();
+        worker.current_pod_state = PodState::Armed;
+        worker.next_pod_state = PodState::Armed;
+        let worker = worker.EnterRecovery();
+
+        let controller = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let (reply_sender, reply_receiver) = std::sync::mpsc::channel();
+        udp_sender.send(UDPMessage::ConnectToDesktop(controller.local_addr().unwrap(), pod_data::TelemetryFields::all(), reply_sender)).unwrap();
+        assert!(matches!(worker.main_loop(), UdpWorkerState::Recovery(_)));
+        assert!(reply_receiver.try_recv().unwrap().is_err());
+    }
+
+    #[test]
+    fn bus_recovery_clears_only_the_bus_fault() {
+        let (mut worker, _udp_sender, _tcp_receiver, _can_receiver) = test_worker();
+        worker.can_bus_degraded();
+        assert!(matches!(worker.errno, UdpErrno::CanBusFault));
+        worker.can_bus_recovered();
+        assert!(matches!(worker.errno, UdpErrno::NoError));
+
+        worker.can_bus_degraded();
+        worker.state_change_unacknowledged(PodState::Armed);
+        worker.can_bus_recovered();
+        assert!(matches!(worker.errno, UdpErrno::StateChangeTimeout));
+    }
+
+    #[test]
+    fn fast_disconnect_skips_recovery() {
+        let (mut worker, udp_sender, tcp_receiver, can_receiver) = test_worker();
+        worker.current_pod_state = PodState::AutoPilot;
+        worker.next_pod_state = PodState::AutoPilot;
+
+        udp_sender.send(UDPMessage::FastDisconnectFromHost).unwrap();
+        let worker = match worker.EnterConnected().main_loop() {
+            UdpWorkerState::Disconnected(worker) => worker,
+            _ => panic!("A fast disconnect should not go through recovery")
+        };
+        // Straight to LowVoltage rather than braking first
+        assert!(matches!(can_receiver.try_recv(), Ok(CanMessage::ChangeState(PodState::LowVoltage))));
+        assert!(can_receiver.try_recv().is_err());
+
+        udp_sender.send(UDPMessage::PodStateChangeAck).unwrap();
+        assert!(matches!(worker.main_loop(), UdpWorkerState::Disconnected(_)));
+        assert!(matches!(tcp_receiver.try_recv(), Ok(TcpMessage::PodStateChanged(PodState::LowVoltage))));
+    }
+
+    #[test]
+    fn socket_is_bound_to_the_configured_address() {
+        let (mut worker, _udp_sender, _tcp_receiver, _can_receiver) = test_worker();
+        let bound_addr = worker.udp_socket.local_addr().unwrap();
+        assert_eq!(bound_addr.ip(), std::net::Ipv4Addr::LOCALHOST);
+        assert_eq!(worker.udp_address, bound_addr);
+
+        // A re-bind after the link is lost stays on the same interface
+        worker.rebind_socket().unwrap();
+        assert_eq!(worker.udp_socket.local_addr().unwrap().ip(), std::net::Ipv4Addr::LOCALHOST);
+    }
+
+    #[test]
+    fn controller_can_reconnect_after_the_link_is_lost() {
+        let (worker, udp_sender, tcp_receiver, _can_receiver) = test_worker();
+        let bound_addr = worker.udp_socket.local_addr().unwrap();
+        let worker = worker.EnterDisconnected().EnterConnected().link_lost();
+        assert!(matches!(tcp_receiver.try_recv(), Ok(TcpMessage::UdpLinkLost)));
+
+        // Recovery ends once LowVoltage is acked, then the socket is re-bound to the same address for the next controller
+        udp_sender.send(UDPMessage::PodStateChangeAck).unwrap();
+        let worker = match worker.main_loop() {
+            UdpWorkerState::Disconnected(worker) => worker,
+            _ => panic!("Recovery should complete once LowVoltage is acked")
+        };
+        let controller = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let (reply_sender, reply_receiver) = std::sync::mpsc::channel();
+        udp_sender.send(UDPMessage::ConnectToDesktop(controller.local_addr().unwrap(), pod_data::TelemetryFields::all(), reply_sender)).unwrap();
+        assert!(matches!(worker.main_loop(), UdpWorkerState::Connected(_)));
+        assert_eq!(reply_receiver.try_recv().unwrap().unwrap(), bound_addr.port());
+    }
+}
+
+const MAX_PENDING_FAULTS: usize = 32;
 
 #[repr(C)] // Required for type transmutations
 pub struct UdpWorker<State = Startup> {
     udp_socket: UdpSocket,
+    udp_address: SocketAddr, // Local address the socket is bound to, and re-bound to after the link is lost
     current_pod_state: PodState,
     next_pod_state: PodState,
     errno: UdpErrno,
@@ -35,27 +160,39 @@ pub struct UdpWorker<State = Startup> {
     last_received_telemetry_timestamp: chrono::NaiveDateTime,
     current_pod_data: pod_data::PodData,
     current_telemetry_timestamp: chrono::NaiveDateTime,
+    current_telemetry_sequence: u64,
+    current_telemetry_keepalive: bool, // The current telemetry is a keepalive rather than a fresh snapshot
     tcp_sender: Sender<TcpMessage>,
     udp_message_receiver: Receiver<UDPMessage>,
     can_message_sender: Sender<CanMessage>,
     udp_max_number_timeouts: u32,
+    udp_socket_read_timeout: Duration,
+    backoff_base: Duration,
+    backoff_cap: Duration,
+    reconnect_attempts: u32, // Consecutive link losses without hearing from the controller. Drives the re-bind backoff
+    link_lost: bool, // The socket must be re-bound before connecting to a controller again
+    pending_faults: Vec<FaultReport>, // Fault reports waiting to be sent to the controller
+    telemetry_fields: pod_data::TelemetryFields, // The fields the connected controller asked for
+    metrics: Arc<Metrics>,
+    shutdown_requested: bool,
     state: std::marker::PhantomData<State>
 }
 
 impl UdpWorker<Connected> {
-    fn send_pod_state_message(&self) {
+    fn send_pod_state_message(&mut self) {
         // Send Message Back to Desktop
         let pod_state_message = if self.current_telemetry_timestamp.timestamp() > self.last_received_telemetry_timestamp.timestamp() {
-            PodStateMessage::new(self.current_pod_state, self.next_pod_state, self.errno, &self.current_pod_data, self.current_telemetry_timestamp, false)
+            PodStateMessage::new(self.current_pod_state, self.next_pod_state, self.errno, &self.current_pod_data, self.current_telemetry_timestamp, self.current_telemetry_sequence, false)
         } else {
             PodStateMessage::new_no_telemetry(self.current_pod_state, self.next_pod_state, self.errno, self.current_telemetry_timestamp, false)
-        };
+        }.with_faults(&self.pending_faults).with_fields(self.telemetry_fields).with_keepalive(self.current_telemetry_keepalive);
         match self.udp_socket.send_pod_state_message(&pod_state_message) {
             Ok(bytes_sent) => {
                 // println!("UDP THREAD: Sent {} to Desktop", bytes_sent);
+                self.pending_faults.clear();
             },
             Err(error) => {
-                println!("Error Sending Message on UDP Thread: {:?}", error);
+                error!("Error Sending Message on UDP Thread: {:?}", error);
             }
         }
     }
@@ -63,19 +200,20 @@ impl UdpWorker<Connected> {
 
 
 impl UdpWorker<Recovery> {
-    fn send_pod_state_message(&self) {
+    fn send_pod_state_message(&mut self) {
         // Send Message Back to Desktop
         let pod_state_message = if self.current_telemetry_timestamp.timestamp() > self.last_received_telemetry_timestamp.timestamp() {
-            PodStateMessage::new(self.current_pod_state, self.next_pod_state, self.errno, &self.current_pod_data, self.current_telemetry_timestamp, true)
+            PodStateMessage::new(self.current_pod_state, self.next_pod_state, self.errno, &self.current_pod_data, self.current_telemetry_timestamp, self.current_telemetry_sequence, true)
         } else {
             PodStateMessage::new_no_telemetry(self.current_pod_state, self.next_pod_state, self.errno, self.current_telemetry_timestamp, true)
-        };
+        }.with_faults(&self.pending_faults).with_fields(self.telemetry_fields).with_keepalive(self.current_telemetry_keepalive);
         match self.udp_socket.send_pod_state_message(&pod_state_message) {
             Ok(_bytes_sent) => {
                 // println!("UDP THREAD: Send {} to Desktop", bytes_sent);
+                self.pending_faults.clear();
             },
             Err(error) => {
-                println!("Error Sending Message on UDP Thread: {:?}", error);
+                error!("Error Sending Message on UDP Thread: {:?}", error);
             }
         }
     }
@@ -93,8 +231,58 @@ impl<State> UdpWorker<State> {
         }
     }
 
+    /**
+     * Message the tcp thread. If it has exited the message is logged and dropped, recovery must
+     * still run to completion. The dead thread is found by the relay's supervisor
+     */
+    fn notify_tcp(&self, message: TcpMessage) {
+        if let Err(err) = self.tcp_sender.send(message) {
+            error!("UDP THREAD: TCP Thread is not running, dropped {:?}", err.0);
+        }
+    }
+
+    /**
+     * Message the can thread. If it has exited, the supervisor commands the safe state on its own socket
+     */
+    fn command_can(&self, message: CanMessage) {
+        if let Err(err) = self.can_message_sender.send(message) {
+            error!("UDP THREAD: CAN Thread is not running, dropped {:?}", err.0);
+        }
+    }
+
     fn notify_recovery(&self) {
-        self.tcp_sender.send(TcpMessage::EnteringRecovery).expect("To be able to notify tcp thread that we are entering recovery mode");
+        self.notify_tcp(TcpMessage::EnteringRecovery);
+    }
+
+    /**
+     * The controller stopped responding. Let the tcp thread know and bring the pod down through recovery,
+     * which leaves the worker disconnected and waiting for the next ConnectToDesktop
+     */
+    fn link_lost(mut self) -> UdpWorker<Recovery> {
+        warn!("UDP THREAD: Lost the link to the controller after {} timeouts ({:?} without a message)", self.timeout_counter, self.udp_socket_read_timeout * self.timeout_counter);
+        self.notify_tcp(TcpMessage::UdpLinkLost);
+        self.errno = UdpErrno::ControllerTimeout;
+        self.timeout_counter = 0;
+        self.link_lost = true;
+        self.EnterRecovery()
+    }
+
+    /**
+     * Replace the socket with a freshly bound one. Waits out the backoff first so that a controller
+     * which keeps dropping off does not put the relay into a tight re-bind loop
+     */
+    fn rebind_socket(&mut self) -> std::io::Result<()> {
+        let delay = backoff_delay(self.backoff_base, self.backoff_cap, self.reconnect_attempts);
+        info!("UDP THREAD: Re-binding socket in {:?}", delay);
+        std::thread::sleep(delay);
+        self.reconnect_attempts = self.reconnect_attempts.saturating_add(1);
+        // The old socket still holds udp_address. Swap it for one on an ephemeral port so the address is free to bind again
+        self.udp_socket = UdpSocket::bind(SocketAddr::new(self.udp_address.ip(), 0))?;
+        let udp_socket = UdpSocket::bind(self.udp_address)?;
+        udp_socket.set_read_timeout(Some(self.udp_socket_read_timeout))?;
+        self.udp_socket = udp_socket;
+        self.link_lost = false;
+        Ok(())
     }
 
     fn invalid_transition_recognized(mut self) -> UdpWorker<Recovery> {
@@ -103,12 +291,67 @@ impl<State> UdpWorker<State> {
         self.EnterRecovery()
     }
 
+    /**
+     * Update the current pod state and let the tcp thread know so that it can report it
+     */
+    fn update_pod_state(&mut self, new_state: PodState) {
+        self.current_pod_state = new_state;
+        self.notify_tcp(TcpMessage::PodStateChanged(new_state));
+    }
+
+    /**
+     * A board refused the requested state. The pod stays where it is and the controller is told through errno
+     */
+    fn transition_rejected(&mut self, rejected_state: PodState) {
+        warn!("UDP THREAD: Transition to {:?} was rejected", rejected_state);
+        self.errno = UdpErrno::TransitionRejected;
+        self.next_pod_state = self.current_pod_state;
+    }
+
+    /**
+     * The boards never acknowledged the requested state. The can thread follows this with a SystemFault
+     * unless SystemFailure was itself the state, the controller is told why through errno
+     */
+    fn state_change_unacknowledged(&mut self, state: PodState) {
+        error!("UDP THREAD: The boards never acknowledged {:?}", state);
+        self.errno = UdpErrno::StateChangeTimeout;
+    }
+
+    /**
+     * The can thread can no longer read from or write to the bus and is trying to re-open its socket
+     */
+    fn can_bus_degraded(&mut self) {
+        error!("UDP THREAD: The CAN bus is degraded");
+        self.errno = UdpErrno::CanBusFault;
+    }
+
+    /**
+     * The can thread is reading from and writing to the bus again. Only the bus fault is cleared,
+     * any other errno set since is still for the controller to see
+     */
+    fn can_bus_recovered(&mut self) {
+        info!("UDP THREAD: The CAN bus has recovered");
+        if matches!(self.errno, UdpErrno::CanBusFault) {
+            self.errno = UdpErrno::NoError;
+        }
+    }
+
+    /**
+     * Hold a fault report until it can be sent. If no controller picks them up, only the most recent are kept
+     */
+    fn queue_fault(&mut self, fault: FaultReport) {
+        if self.pending_faults.len() == MAX_PENDING_FAULTS {
+            self.pending_faults.remove(0);
+        }
+        self.pending_faults.push(fault);
+    }
+
     fn handle_telemetry_timestamp(&mut self, timestamp: chrono::NaiveDateTime) {
         self.last_received_telemetry_timestamp = timestamp;
     }
 
     fn trigger_transition_to_new_state(&mut self, requested_state: PodState) {
-        self.can_message_sender.send(CanMessage::ChangeState(requested_state.clone())).expect("Should be able to Send a message to the Can thread from the UDP thread");
+        self.command_can(CanMessage::ChangeState(requested_state.clone()));
         self.next_pod_state = requested_state;
     }
 
@@ -118,13 +361,18 @@ impl<State> UdpWorker<State> {
         udp_receiver: Receiver<UDPMessage>,
         udp_max_number_timeouts: u32,
         udp_socket_read_timeout: Duration,
-        udp_address: A
+        backoff_base: Duration,
+        backoff_cap: Duration,
+        udp_address: A,
+        metrics: Arc<Metrics>
     ) -> UdpWorker<Startup> {
-        let udp_socket = UdpSocket::bind("0.0.0.0:8080").expect("Unable to Bind to UDP Socket on: 0.0.0.0:8080");
-        // let udp_socket = UdpSocket::bind(&udp_address).expect(&format!("Unable to Bind to UDP Socket on: {:?}", &udp_address));
+        let udp_socket = UdpSocket::bind(&udp_address).expect(&format!("Unable to Bind to UDP Socket on: {:?}", &udp_address));
+        let udp_address = udp_socket.local_addr().expect("Bound udp socket should have a local address");
+        info!("UDP THREAD: Bound to {}", udp_address);
         udp_socket.set_read_timeout(Some(udp_socket_read_timeout)).expect("Failed to set read timeout on udp_socket");
         UdpWorker {
             udp_socket,
+            udp_address,
             current_pod_state: PodState::LowVoltage, // *************  TODO Figure out what the initial Value for this should be
             next_pod_state: PodState::LowVoltage, // *************  TODO Figure out what the initial Value for this should be
             errno: UdpErrno::NoError,
@@ -132,10 +380,21 @@ impl<State> UdpWorker<State> {
             last_received_telemetry_timestamp: chrono::Utc::now().naive_local(),
             current_pod_data: pod_data::PodData::new(),
             current_telemetry_timestamp: chrono::Utc::now().naive_local(),
+            current_telemetry_sequence: 0,
+            current_telemetry_keepalive: false,
             tcp_sender: tcp_sender,
             udp_message_receiver: udp_receiver,
             can_message_sender: can_sender,
             udp_max_number_timeouts,
+            udp_socket_read_timeout,
+            backoff_base,
+            backoff_cap,
+            reconnect_attempts: 0,
+            link_lost: false,
+            pending_faults: Vec::new(),
+            telemetry_fields: pod_data::TelemetryFields::all(),
+            metrics,
+            shutdown_requested: false,
             state: std::marker::PhantomData
         }
     }
@@ -172,21 +431,46 @@ impl UdpWorkerState {
         udp_receiver: Receiver<UDPMessage>,
         udp_max_number_timeouts: u32,
         udp_socket_read_timeout: Duration,
+        backoff_base: Duration,
+        backoff_cap: Duration,
         udp_address: A,
+        metrics: Arc<Metrics>
     ) -> UdpWorkerState {
-        let worker: UdpWorker<Startup> = UdpWorker::<Startup>::new(can_sender, tcp_sender, udp_receiver, udp_max_number_timeouts, udp_socket_read_timeout, udp_address);
+        let worker: UdpWorker<Startup> = UdpWorker::<Startup>::new(can_sender, tcp_sender, udp_receiver, udp_max_number_timeouts, udp_socket_read_timeout, backoff_base, backoff_cap, udp_address, metrics);
         UdpWorkerState::Startup(worker)
     }
+
+    /**
+     * @brief is_shutdown
+     * True once the worker has received UDPMessage::Shutdown and should no longer be run
+     */
+    pub fn is_shutdown(&self) -> bool {
+        match self {
+            UdpWorkerState::Startup(worker) => worker.shutdown_requested,
+            UdpWorkerState::Disconnected(worker) => worker.shutdown_requested,
+            UdpWorkerState::Connected(worker) => worker.shutdown_requested,
+            UdpWorkerState::Recovery(worker) => worker.shutdown_requested,
+        }
+    }
 }
 
 impl MainLoop<UdpWorkerState> for UdpWorker<Startup> {
-    fn main_loop(self) ->  UdpWorkerState {
+    fn main_loop(mut self) ->  UdpWorkerState {
         match self.get_udp_receiver_message_or_panic() {
             UDPMessage::StartupComplete => {
+                self.notify_tcp(TcpMessage::StartupComplete);
                 UdpWorkerState::Disconnected(self.EnterDisconnected())
             },
+            UDPMessage::FaultReported(fault) => {
+                self.queue_fault(fault);
+                UdpWorkerState::Startup(self)
+            },
+            UDPMessage::Shutdown => {
+                self.shutdown_requested = true;
+                UdpWorkerState::Startup(self)
+            },
             message => {
-                println!("Received Message on UDP mpsc channel during Startup: {:?}", message);
+                warn!("Received Message on UDP mpsc channel during Startup: {:?}", message);
                 UdpWorkerState::Startup(self)
             }
         }
@@ -196,21 +480,54 @@ impl MainLoop<UdpWorkerState> for UdpWorker<Startup> {
 impl MainLoop<UdpWorkerState> for UdpWorker<Disconnected> {
     fn main_loop(mut self) -> UdpWorkerState {
         match self.get_udp_receiver_message_or_panic() {
-            UDPMessage::ConnectToDesktop(addr) => {
-                if let Ok(_) = self.udp_socket.connect(addr) {
-                    println!("UDP THREAD: Connected to addr: {:?}", addr);
-                    return UdpWorkerState::Connected(self.EnterConnected());
-                } else {
-                    println!("UDP THREAD: Unable to connect to {:?}", addr);
-                    self.tcp_sender.send(TcpMessage::UdpFailedToConnect).expect("To be able to message tcp thread");
+            UDPMessage::ConnectToDesktop(addr, telemetry_fields, reply_sender) => {
+                // The tcp thread only confirms the CONNECT once it hears back. It may have given up, so send failures are ignored
+                if self.link_lost {
+                    if let Err(error) = self.rebind_socket() {
+                        error!("UDP THREAD: Unable to re-bind socket: {:?}", error);
+                        reply_sender.send(Err(error)).ok();
+                        return UdpWorkerState::Disconnected(self);
+                    }
+                }
+                // The port is read back from the socket, so the CONNECT response can never disagree with it
+                match self.udp_socket.connect(addr).and_then(|_| self.udp_socket.local_addr()) {
+                    Ok(local_addr) => {
+                        info!("UDP THREAD: Connected to addr: {:?}", addr);
+                        self.telemetry_fields = telemetry_fields;
+                        reply_sender.send(Ok(local_addr.port())).ok();
+                        return UdpWorkerState::Connected(self.EnterConnected());
+                    },
+                    Err(error) => {
+                        error!("UDP THREAD: Unable to connect to {:?}: {:?}", addr, error);
+                        reply_sender.send(Err(error)).ok();
+                    }
                 }
             },
-            UDPMessage::TelemetryDataAvailable(_data, _time) => {},
+            UDPMessage::TelemetryDataAvailable(..) | UDPMessage::TelemetryKeepalive(..) => {},
+            UDPMessage::PodStateChangeAck => {
+                // A fast disconnect leaves LowVoltage to be acknowledged after the controller is gone
+                self.update_pod_state(self.next_pod_state);
+            },
             UDPMessage::SystemFault => {
-                self.current_pod_state = PodState::SystemFailure;
+                self.update_pod_state(PodState::SystemFailure);
+            }
+            UDPMessage::StateChangeUnacknowledged(state) => {
+                self.state_change_unacknowledged(state);
+            }
+            UDPMessage::CanBusDegraded => {
+                self.can_bus_degraded();
+            }
+            UDPMessage::CanBusRecovered => {
+                self.can_bus_recovered();
+            }
+            UDPMessage::FaultReported(fault) => {
+                self.queue_fault(fault);
+            }
+            UDPMessage::Shutdown => {
+                self.shutdown_requested = true;
             }
             message => {
-                println!("UDP THREAD: Received Message on UDP mpsc channel while Disconnected: {:?}", message);
+                warn!("UDP THREAD: Received Message on UDP mpsc channel while Disconnected: {:?}", message);
             }
         }
         UdpWorkerState::Disconnected(self)
@@ -224,7 +541,7 @@ impl MainLoop<UdpWorkerState> for UdpWorker<Connected> {
         if let Ok(message) = self.udp_message_receiver.try_recv() {
             match message {
                 UDPMessage::PodStateChangeAck => {
-                    self.current_pod_state = self.next_pod_state;
+                    self.update_pod_state(self.next_pod_state);
                     if self.current_pod_state == PodState::AutoPilot {
                         {
                             let can_sender = self.can_message_sender.clone();
@@ -235,17 +552,54 @@ impl MainLoop<UdpWorkerState> for UdpWorker<Connected> {
                         }
                     }
                 },
-                UDPMessage::TelemetryDataAvailable(new_data, timestamp) => {
+                UDPMessage::TelemetryDataAvailable(new_data, timestamp, sequence) => {
                     // println!("UDP Data Received: {}", timestamp);
                     self.current_pod_data = new_data;
                     self.current_telemetry_timestamp = timestamp;
+                    self.current_telemetry_sequence = sequence;
+                    self.current_telemetry_keepalive = false;
+                },
+                UDPMessage::TelemetryKeepalive(pod_data, timestamp, sequence) => {
+                    self.current_pod_data = pod_data;
+                    self.current_telemetry_timestamp = timestamp;
+                    self.current_telemetry_sequence = sequence;
+                    self.current_telemetry_keepalive = true;
                 },
                 UDPMessage::DisconnectFromHost => {
                     self.send_pod_state_message();
                     return UdpWorkerState::Recovery(self.EnterRecovery());
                 },
+                UDPMessage::FastDisconnectFromHost => {
+                    warn!("UDP THREAD: Disconnecting without recovery, commanding {:?}", PodState::LowVoltage);
+                    self.trigger_transition_to_new_state(PodState::LowVoltage);
+                    self.send_pod_state_message();
+                    return UdpWorkerState::Disconnected(self.EnterDisconnected());
+                },
                 UDPMessage::SystemFault => {
-                    self.current_pod_state = PodState::SystemFailure;
+                    self.update_pod_state(PodState::SystemFailure);
+                }
+                UDPMessage::StateTransitionRejected(rejected_state) => {
+                    self.transition_rejected(rejected_state);
+                }
+                UDPMessage::StateChangeUnacknowledged(state) => {
+                    self.state_change_unacknowledged(state);
+                }
+                UDPMessage::CanBusDegraded => {
+                    self.can_bus_degraded();
+                }
+                UDPMessage::CanBusRecovered => {
+                    self.can_bus_recovered();
+                }
+                UDPMessage::FaultReported(fault) => {
+                    self.queue_fault(fault);
+                }
+                UDPMessage::ConnectToDesktop(addr, _, reply_sender) => {
+                    warn!("UDP THREAD: Refused to connect to {:?}, a controller is already connected", addr);
+                    reply_sender.send(Err(std::io::Error::other("a controller is already connected"))).ok();
+                }
+                UDPMessage::Shutdown => {
+                    self.shutdown_requested = true;
+                    return UdpWorkerState::Connected(self);
                 }
                 unrecognized_message => {
                     panic!("UnExpected Message Received on UDP mpsc channel while in Connected State: {:?}", unrecognized_message);
@@ -282,48 +636,47 @@ impl MainLoop<UdpWorkerState> for UdpWorker<Connected> {
                                     self.handle_telemetry_timestamp(desktop_state_message.most_recent_timestamp);
                                 } else {
                                     if self.current_pod_state == self.next_pod_state {
-                                        println!("Case 4");
-                                        println!("Current State: {:?}", self.current_pod_state);
-                                        println!("NEXT State: {:?}", self.next_pod_state);
-                                        println!("requested State: {:?}", desktop_state_message.requested_state);
+                                        info!("Case 4");
+                                        info!("Current State: {:?}", self.current_pod_state);
+                                        info!("NEXT State: {:?}", self.next_pod_state);
+                                        info!("requested State: {:?}", desktop_state_message.requested_state);
                                         self.trigger_transition_to_new_state(desktop_state_message.requested_state);
                                         self.handle_telemetry_timestamp(desktop_state_message.most_recent_timestamp);
                                     } else {
-                                        println!("Case 5");
+                                        warn!("Case 5");
                                         return UdpWorkerState::Recovery(self.invalid_transition_recognized());
                                     }
                                 }
                             } else {
-                                println!("Case 6");
+                                warn!("Case 6");
                                 return UdpWorkerState::Recovery(self.invalid_transition_recognized());
                             }
                         }
                     } else {
                         // TODO: This needs to change once we've figured out how we want to handle an error
-                        println!("UDP THREAD: Unable to read: {:?}", std::str::from_utf8(&socket_buffer).expect("To be able to convert to utf"));
+                        error!("UDP THREAD: Unable to read: {:?}", std::str::from_utf8(&socket_buffer).expect("To be able to convert to utf"));
                         panic!("UDP THREAD: Failed to Read DesktopStateMessage in UDP Handler while in Connected State");
                     }
                     self.timeout_counter = 0;
+                    self.reconnect_attempts = 0;
                 } else {
                     // !! ERROR CASE
-                    println!("UDP ERROR STATE");
-                    self.can_message_sender.send(CanMessage::ChangeState(PodState::SystemFailure)).unwrap();
+                    error!("UDP ERROR STATE");
+                    self.command_can(CanMessage::ChangeState(PodState::SystemFailure));
                 }
             },
             Err(error) => {
                 match error.kind() {
                     std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock  => {
-                        println!("Udp Socket Timed Out while reading");
+                        warn!("Udp Socket Timed Out while reading");
+                        self.metrics.udp_timeout();
                         self.timeout_counter += 1; // Move this to the timeout  portion of the error handler
                         if self.timeout_counter >= self.udp_max_number_timeouts {
-                            // TODO Enter into Recovery. Assume Desktop Disconnected
-                            self.notify_recovery();
-                            self.errno = UdpErrno::ControllerTimeout;
-                            return UdpWorkerState::Recovery(self.EnterRecovery());
+                            return UdpWorkerState::Recovery(self.link_lost());
                         }
                     },
                     _ => {
-                        println!("Error: {:?}", error);
+                        error!("Error: {:?}", error);
                     }
                 }
 
@@ -340,28 +693,61 @@ impl MainLoop<UdpWorkerState> for UdpWorker<Recovery> {
         while let Ok(message) = self.udp_message_receiver.try_recv() {
             match message {
                 UDPMessage::PodStateChangeAck => {
-                    self.current_pod_state = self.next_pod_state;
+                    self.update_pod_state(self.next_pod_state);
                     if self.current_pod_state.is_error_state() {
                         self.errno = UdpErrno::GeneralPodFailure;
                     }
                 },
-                UDPMessage::TelemetryDataAvailable(new_data, timestamp) => {
+                UDPMessage::TelemetryDataAvailable(new_data, timestamp, sequence) => {
                     self.current_pod_data = new_data;
                     self.current_telemetry_timestamp = timestamp;
+                    self.current_telemetry_sequence = sequence;
+                    self.current_telemetry_keepalive = false;
                 },
-                UDPMessage::DisconnectFromHost => {
+                UDPMessage::TelemetryKeepalive(pod_data, timestamp, sequence) => {
+                    self.current_pod_data = pod_data;
+                    self.current_telemetry_timestamp = timestamp;
+                    self.current_telemetry_sequence = sequence;
+                    self.current_telemetry_keepalive = true;
+                },
+                UDPMessage::DisconnectFromHost | UDPMessage::FastDisconnectFromHost => {
+                    // Already recovering
                 },
                 UDPMessage::SystemFault => {
-                    self.current_pod_state = PodState::SystemFailure;
+                    self.update_pod_state(PodState::SystemFailure);
+                }
+                UDPMessage::StateTransitionRejected(rejected_state) => {
+                    self.transition_rejected(rejected_state);
+                }
+                UDPMessage::StateChangeUnacknowledged(state) => {
+                    self.state_change_unacknowledged(state);
+                }
+                UDPMessage::CanBusDegraded => {
+                    self.can_bus_degraded();
+                }
+                UDPMessage::CanBusRecovered => {
+                    self.can_bus_recovered();
+                }
+                UDPMessage::FaultReported(fault) => {
+                    self.queue_fault(fault);
+                }
+                UDPMessage::ConnectToDesktop(addr, _, reply_sender) => {
+                    // The pod has to be back in LowVoltage before the next controller can take over
+                    warn!("UDP THREAD: Refused to connect to {:?} while recovering", addr);
+                    reply_sender.send(Err(std::io::Error::other("the relay is recovering from the last controller"))).ok();
+                }
+                UDPMessage::Shutdown => {
+                    self.shutdown_requested = true;
+                    return UdpWorkerState::Recovery(self);
                 }
                 unrecognized_message => {
-                    panic!("UnExpected Message Received on UDP mpsc channel while in Connected State: {:?}", unrecognized_message);
+                    panic!("UnExpected Message Received on UDP mpsc channel while in Recovery State: {:?}", unrecognized_message);
                 }
             }
         }
         match self.current_pod_state {
             PodState::LowVoltage => {
-                self.tcp_sender.send(TcpMessage::RecoveryComplete).expect("To be able to send message");
+                self.notify_tcp(TcpMessage::RecoveryComplete);
                 return UdpWorkerState::Disconnected(self.EnterDisconnected());
             },
             PodState::Armed => {
@@ -384,7 +770,7 @@ impl MainLoop<UdpWorkerState> for UdpWorker<Recovery> {
             },
             PodState::SystemFailure => {},
             state => {
-                println!("Pod state mising in recovery procedure: {:?}", state);
+                warn!("Pod state mising in recovery procedure: {:?}", state);
             }
         }
         UdpWorkerState::Recovery(self)
@@ -0,0 +1,31 @@
+use super::worker::WebsocketWorker;
+use log::{ info };
+use std::net::TcpListener;
+use std::sync::mpsc::Receiver;
+use super::super::messages::WebsocketMessage;
+use super::super::main_loop::WorkerStateTrait;
+
+pub struct WebsocketManager {
+}
+
+impl WebsocketManager {
+    /**
+     * @brief run
+     * Serve telemetry to websocket clients on a listener which has already been bound, so that
+     * an address which is in use is found before any thread is started
+     */
+    pub fn run(
+        listener: TcpListener,
+        websocket_message_receiver: Receiver<WebsocketMessage>
+    ) -> std::thread::JoinHandle<()> {
+        std::thread::Builder::new().name("Websocket Thread".to_string()).spawn(move || {
+            let mut worker = WebsocketWorker::new(listener, websocket_message_receiver);
+            while !worker.is_finished() {
+                worker = worker.main_loop();
+            }
+            // Dropping the worker closes the listener
+            drop(worker);
+            info!("WEBSOCKET THREAD: Shut down");
+        }).expect("Should be able to create Thread")
+    }
+}
@@ -0,0 +1,167 @@
+use std::io::ErrorKind;
+use std::net::{ TcpListener, TcpStream };
+use std::sync::mpsc::{ Receiver, RecvTimeoutError };
+use std::time::Duration;
+use log::{ debug, info, warn };
+use tungstenite::{ Message, WebSocket };
+use super::super::messages::WebsocketMessage;
+use super::super::main_loop::WorkerStateTrait;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::pod_data::PodData;
+    use std::sync::mpsc::channel;
+
+    #[test]
+    fn telemetry_is_pushed_to_connected_clients() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (websocket_message_sender, websocket_message_receiver) = channel();
+        let mut worker = WebsocketWorker::new(listener, websocket_message_receiver);
+
+        let client = std::thread::spawn(move || {
+            let (mut client, _) = tungstenite::client(format!("ws://{}/", addr), TcpStream::connect(addr).unwrap()).unwrap();
+            client.read_message().unwrap()
+        });
+        for _ in 0..100 {
+            if !worker.clients.is_empty() {
+                break;
+            }
+            worker = worker.main_loop();
+        }
+        assert_eq!(worker.clients.len(), 1);
+
+        let mut pod_data = PodData::new();
+        pod_data.speed = Some(30.25);
+        let time = chrono::Utc::now().naive_local();
+        websocket_message_sender.send(WebsocketMessage::TelemetryDataAvailable(pod_data.clone(), time, 0)).unwrap();
+        worker = worker.main_loop();
+        match client.join().unwrap() {
+            Message::Text(json) => assert_eq!(json, pod_data.to_json(&time)),
+            message => panic!("Expected the telemetry as json, got {:?}", message)
+        }
+
+        websocket_message_sender.send(WebsocketMessage::Shutdown).unwrap();
+        assert!(worker.main_loop().is_finished());
+    }
+}
+
+/**
+ * Longest the worker waits for telemetry before checking for new clients
+ */
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(50);
+/**
+ * Amount of time a new client has to complete its handshake
+ */
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/**
+ * Serves telemetry to browser dashboards. Every snapshot sent to the udp thread is also sent here,
+ * and written to each connected client as the same json the logger writes. Clients only listen,
+ * one which has gone away or can't keep up with the snapshots is dropped
+ */
+pub struct WebsocketWorker {
+    listener: TcpListener,
+    clients: Vec<WebSocket<TcpStream>>,
+    websocket_message_receiver: Receiver<WebsocketMessage>,
+    finished: bool
+}
+
+impl WebsocketWorker {
+    pub fn new(listener: TcpListener, websocket_message_receiver: Receiver<WebsocketMessage>) -> WebsocketWorker {
+        listener.set_nonblocking(true).expect("Unable to set non blocking");
+        WebsocketWorker {
+            listener,
+            clients: Vec::new(),
+            websocket_message_receiver,
+            finished: false
+        }
+    }
+
+    /**
+     * @brief is_finished
+     * True once the worker has been told to shutdown and has closed its clients
+     */
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    fn accept_clients(&mut self) {
+        loop {
+            let (stream, addr) = match self.listener.accept() {
+                Ok(client) => client,
+                Err(err) if err.kind() == ErrorKind::WouldBlock => return,
+                Err(err) => {
+                    warn!("WEBSOCKET THREAD: Unable to accept a client: {:?}", err);
+                    return;
+                }
+            };
+            // The handshake is done blocking, then the client is made non blocking so that a slow one never holds up the rest
+            let handshake = stream.set_nonblocking(false)
+                .and_then(|_| stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT)))
+                .and_then(|_| stream.set_write_timeout(Some(HANDSHAKE_TIMEOUT)))
+                .map_err(|err| err.to_string())
+                .and_then(|_| tungstenite::accept(stream).map_err(|err| err.to_string()))
+                .and_then(|client| client.get_ref().set_nonblocking(true).map(|_| client).map_err(|err| err.to_string()));
+            match handshake {
+                Ok(client) => {
+                    info!("WEBSOCKET THREAD: {} connected", addr);
+                    self.clients.push(client);
+                },
+                Err(err) => warn!("WEBSOCKET THREAD: Handshake with {} failed: {}", addr, err)
+            }
+        }
+    }
+
+    /**
+     * Clients aren't expected to send anything, but reading answers their pings and notices when they close
+     */
+    fn read_clients(&mut self) {
+        self.clients.retain_mut(|client| loop {
+            match client.read_message() {
+                Ok(Message::Close(_)) => {
+                    info!("WEBSOCKET THREAD: A client disconnected");
+                    break false;
+                },
+                Ok(message) => debug!("WEBSOCKET THREAD: Ignoring {:?}", message),
+                Err(tungstenite::Error::Io(err)) if err.kind() == ErrorKind::WouldBlock => break true,
+                Err(err) => {
+                    info!("WEBSOCKET THREAD: Dropping a client: {}", err);
+                    break false;
+                }
+            }
+        });
+    }
+
+    fn broadcast(&mut self, json: String) {
+        self.clients.retain_mut(|client| match client.write_message(Message::Text(json.clone())) {
+            Ok(()) => true,
+            Err(err) => {
+                info!("WEBSOCKET THREAD: Dropping a client: {}", err);
+                false
+            }
+        });
+    }
+}
+
+impl WorkerStateTrait for WebsocketWorker {
+    fn main_loop(mut self) -> WebsocketWorker {
+        self.accept_clients();
+        self.read_clients();
+        match self.websocket_message_receiver.recv_timeout(ACCEPT_INTERVAL) {
+            Ok(WebsocketMessage::TelemetryDataAvailable(pod_data, timestamp, _)) => {
+                self.broadcast(pod_data.to_json(&timestamp));
+            },
+            Ok(WebsocketMessage::Shutdown) | Err(RecvTimeoutError::Disconnected) => {
+                for client in &mut self.clients {
+                    client.close(None).ok();
+                    client.write_pending().ok();
+                }
+                self.finished = true;
+            },
+            Err(RecvTimeoutError::Timeout) => {}
+        }
+        self
+    }
+}
@@ -0,0 +1,213 @@
+/*!
+ * @brief A channel holding at most a fixed number of messages, for telemetry which can arrive faster
+ * than it is consumed. std's sync_channel can only make the sender wait when it is full, this can
+ * also make room by dropping the oldest message, which suits telemetry where the newest reading is
+ * the one worth keeping. Commands should stay on unbounded channels, where nothing is ever dropped
+ */
+
+use std::collections::VecDeque;
+use std::sync::{ Arc, Condvar, Mutex };
+use std::sync::mpsc::{ RecvError, RecvTimeoutError, SendError, TryRecvError };
+use std::time::{ Duration, Instant };
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn full_channel_drops_the_oldest_or_blocks() {
+        let (sender, receiver) = bounded_channel(2, FullPolicy::DropOldest);
+        assert_eq!(sender.send(1).unwrap(), None);
+        assert_eq!(sender.send(2).unwrap(), None);
+        assert_eq!(sender.send(3).unwrap(), Some(1));
+        assert_eq!(receiver.try_recv(), Ok(2));
+        assert_eq!(receiver.recv(), Ok(3));
+        assert_eq!(receiver.recv_timeout(Duration::from_millis(10)), Err(RecvTimeoutError::Timeout));
+        drop(sender);
+        assert_eq!(receiver.recv(), Err(RecvError));
+
+        let (sender, receiver) = bounded_channel(1, FullPolicy::Block);
+        sender.send(1).unwrap();
+        let started = Instant::now();
+        assert_eq!(sender.send(2).unwrap(), Some(2)); // Nothing took a message, so the new one is dropped
+        assert!(started.elapsed() >= BLOCK_TIMEOUT);
+        let blocked_sender = sender.clone();
+        let blocked = std::thread::spawn(move || blocked_sender.send(2));
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(receiver.recv(), Ok(1)); // Makes room for the blocked send
+        assert_eq!(blocked.join().unwrap().unwrap(), None);
+        assert_eq!(receiver.recv(), Ok(2));
+        drop(receiver);
+        assert!(sender.send(3).is_err());
+    }
+}
+
+/**
+ * What a send does when the channel already holds as many messages as it can
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FullPolicy {
+    DropOldest, // Make room by dropping the oldest message, the sender never waits
+    Block // Wait up to BLOCK_TIMEOUT for the receiver to take a message, then drop the new message
+}
+
+/**
+ * The longest a Block send waits. The can thread is the sender, and it must not stop reading the bus
+ * or sending heartbeats for long just because the worker thread has stalled
+ */
+pub const BLOCK_TIMEOUT: Duration = Duration::from_millis(100);
+
+struct Queue<T> {
+    messages: VecDeque<T>,
+    senders: usize,
+    receiver_alive: bool
+}
+
+struct Shared<T> {
+    queue: Mutex<Queue<T>>,
+    bound: usize,
+    policy: FullPolicy,
+    message_available: Condvar, // Notified when a message is sent, or the last sender is dropped
+    space_available: Condvar // Notified when a message is taken, or the receiver is dropped
+}
+
+pub struct BoundedSender<T> {
+    shared: Arc<Shared<T>>
+}
+
+pub struct BoundedReceiver<T> {
+    shared: Arc<Shared<T>>
+}
+
+/**
+ * @brief bounded_channel
+ * A channel which holds at most bound messages, at least one, and does policy once it is full
+ */
+pub fn bounded_channel<T>(bound: usize, policy: FullPolicy) -> (BoundedSender<T>, BoundedReceiver<T>) {
+    let bound = bound.max(1);
+    let shared = Arc::new(Shared {
+        queue: Mutex::new(Queue { messages: VecDeque::with_capacity(bound), senders: 1, receiver_alive: true }),
+        bound,
+        policy,
+        message_available: Condvar::new(),
+        space_available: Condvar::new()
+    });
+    (BoundedSender { shared: Arc::clone(&shared) }, BoundedReceiver { shared })
+}
+
+impl<T> BoundedSender<T> {
+    /**
+     * @brief send
+     * Queue message, returning the message which was dropped if there was one. That is the oldest
+     * with DropOldest, or message itself if a Block send waited BLOCK_TIMEOUT without room being made.
+     * Fails, handing message back, once the receiver is gone
+     */
+    pub fn send(&self, message: T) -> Result<Option<T>, SendError<T>> {
+        let deadline = Instant::now() + BLOCK_TIMEOUT;
+        let mut queue = self.shared.queue.lock().unwrap();
+        loop {
+            if !queue.receiver_alive {
+                return Err(SendError(message));
+            }
+            if queue.messages.len() < self.shared.bound {
+                queue.messages.push_back(message);
+                self.shared.message_available.notify_one();
+                return Ok(None);
+            }
+            match self.shared.policy {
+                FullPolicy::DropOldest => {
+                    let oldest = queue.messages.pop_front();
+                    queue.messages.push_back(message);
+                    self.shared.message_available.notify_one();
+                    return Ok(oldest);
+                },
+                FullPolicy::Block => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Ok(Some(message));
+                    }
+                    queue = self.shared.space_available.wait_timeout(queue, deadline - now).unwrap().0;
+                }
+            }
+        }
+    }
+}
+
+impl<T> Clone for BoundedSender<T> {
+    fn clone(&self) -> BoundedSender<T> {
+        self.shared.queue.lock().unwrap().senders += 1;
+        BoundedSender { shared: Arc::clone(&self.shared) }
+    }
+}
+
+impl<T> Drop for BoundedSender<T> {
+    fn drop(&mut self) {
+        let mut queue = self.shared.queue.lock().unwrap();
+        queue.senders -= 1;
+        if queue.senders == 0 {
+            self.shared.message_available.notify_all();
+        }
+    }
+}
+
+impl<T> BoundedReceiver<T> {
+    /**
+     * Take the oldest message. Every message sent before the last sender was dropped is still received
+     */
+    fn take(&self, queue: &mut Queue<T>) -> Option<T> {
+        let message = queue.messages.pop_front();
+        if message.is_some() {
+            self.shared.space_available.notify_one();
+        }
+        message
+    }
+
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        let mut queue = self.shared.queue.lock().unwrap();
+        match self.take(&mut queue) {
+            Some(message) => Ok(message),
+            None if queue.senders == 0 => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty)
+        }
+    }
+
+    pub fn recv(&self) -> Result<T, RecvError> {
+        let mut queue = self.shared.queue.lock().unwrap();
+        loop {
+            if let Some(message) = self.take(&mut queue) {
+                return Ok(message);
+            }
+            if queue.senders == 0 {
+                return Err(RecvError);
+            }
+            queue = self.shared.message_available.wait(queue).unwrap();
+        }
+    }
+
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        let deadline = Instant::now() + timeout;
+        let mut queue = self.shared.queue.lock().unwrap();
+        loop {
+            if let Some(message) = self.take(&mut queue) {
+                return Ok(message);
+            }
+            if queue.senders == 0 {
+                return Err(RecvTimeoutError::Disconnected);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(RecvTimeoutError::Timeout);
+            }
+            queue = self.shared.message_available.wait_timeout(queue, deadline - now).unwrap().0;
+        }
+    }
+}
+
+impl<T> Drop for BoundedReceiver<T> {
+    fn drop(&mut self) {
+        let mut queue = self.shared.queue.lock().unwrap();
+        queue.receiver_alive = false;
+        queue.messages.clear();
+        self.shared.space_available.notify_all();
+    }
+}
@@ -0,0 +1,83 @@
+/*!
+ * @brief A flight recorder for the can bus. The worker thread records every command it decodes, and
+ * only the last few are kept, so the moments before an intermittent fault can be read back over TCP
+ * with HISTORY. The buffer is allocated once at its full length, recording is a push and at most one
+ * pop, so memory never grows past the configured length
+ */
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use chrono::NaiveDateTime;
+use crate::can_extentions::prelude::CanCommand;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn only_the_last_commands_are_kept() {
+        let history = CommandHistory::new(2);
+        let time = chrono::NaiveDateTime::from_timestamp(1_600_000_000, 0);
+        history.record(CanCommand::PressureHigh(1.0), time);
+        history.record(CanCommand::PressureHigh(2.0), time);
+        history.record(CanCommand::PodSpeed{ pod_speed: 10.75 }, time + chrono::Duration::milliseconds(5));
+
+        assert_eq!(history.latest(usize::MAX), vec![
+            (CanCommand::PressureHigh(2.0), time),
+            (CanCommand::PodSpeed{ pod_speed: 10.75 }, time + chrono::Duration::milliseconds(5))
+        ]);
+        assert_eq!(history.to_response(2), "1600000000000 PressureHigh(2.0)\r\n1600000000005 PodSpeed { pod_speed: 10.75 }\r\n");
+        assert_eq!(history.to_response(1), "1600000000005 PodSpeed { pod_speed: 10.75 }\r\n");
+
+        let disabled = CommandHistory::new(0);
+        disabled.record(CanCommand::PressureHigh(1.0), time);
+        assert!(disabled.latest(usize::MAX).is_empty());
+    }
+}
+
+pub struct CommandHistory {
+    entries: Mutex<VecDeque<(CanCommand, NaiveDateTime)>>, // Oldest first
+    length: usize // Most commands kept. Zero keeps none
+}
+
+impl CommandHistory {
+    pub fn new(length: usize) -> CommandHistory {
+        CommandHistory {
+            entries: Mutex::new(VecDeque::with_capacity(length)),
+            length
+        }
+    }
+
+    /**
+     * @brief record
+     * Keep command, decoded at time, dropping the oldest command once the history is full
+     */
+    pub fn record(&self, command: CanCommand, time: NaiveDateTime) {
+        if self.length == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() == self.length {
+            entries.pop_front();
+        }
+        entries.push_back((command, time));
+    }
+
+    /**
+     * @brief latest
+     * A copy of at most the last count commands in the history, oldest first. Only those are copied,
+     * so the worker thread is not held off recording by a long history
+     */
+    pub fn latest(&self, count: usize) -> Vec<(CanCommand, NaiveDateTime)> {
+        let entries = self.entries.lock().unwrap();
+        entries.iter().skip(entries.len().saturating_sub(count)).cloned().collect()
+    }
+
+    /**
+     * @brief to_response
+     * Response to a HISTORY request for the last count commands. One command per line, oldest first, of the form: <timestamp_ms> <command>\r\n
+     */
+    pub fn to_response(&self, count: usize) -> String {
+        self.latest(count).iter().map(|(command, time)| format!("{} {:?}\r\n", time.timestamp_millis(), command)).collect()
+    }
+}
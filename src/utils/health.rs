@@ -0,0 +1,149 @@
+/*!
+ * @brief A minimal HTTP server for monitoring the relay. GET /health is answered with json holding
+ * whether each thread is alive, the tcp thread's state, when telemetry was last sent and how many
+ * can reads in a row have timed out. The relay is healthy while every thread is alive, answered with
+ * 200, and 503 once one has died. The server runs on a thread of its own, so a slow monitor never
+ * holds up supervision. It answers from the liveness RelayHandles::supervise last published
+ */
+
+use std::io::Write;
+use std::net::{ TcpListener, TcpStream };
+use std::sync::{ Arc, Mutex };
+use std::time::Duration;
+use log::warn;
+use crate::metrics::{ Metrics, MetricsSnapshot };
+use crate::stream_utils;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Read;
+
+    fn get(addr: std::net::SocketAddr) -> (String, serde_json::Value) {
+        let mut client = TcpStream::connect(addr).unwrap();
+        client.write_all(b"GET /health HTTP/1.1\r\nHost: relay\r\n\r\n").unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        (head.lines().next().unwrap().to_string(), serde_json::from_str(body).unwrap())
+    }
+
+    #[test]
+    fn dead_thread_makes_the_relay_unhealthy() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let metrics = Arc::new(Metrics::new());
+        metrics.set_server_state("Connected");
+        metrics.set_can_timeouts(2);
+        let liveness = Arc::new(ThreadLiveness::default());
+        liveness.publish(vec![("TCP Thread", true), ("CAN Thread", true)]);
+        HealthServer::new(listener, Arc::clone(&metrics), Arc::clone(&liveness)).run();
+
+        let (status, health) = get(addr);
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        assert_eq!(health["healthy"], true);
+        assert_eq!(health["server_state"], "Connected");
+        assert_eq!(health["can_timeouts"], 2);
+        assert!(health["last_telemetry"].is_null());
+
+        let timestamp = chrono::NaiveDateTime::from_timestamp(1_600_000_000, 0);
+        metrics.telemetry_sent_at(&timestamp);
+        liveness.publish(vec![("TCP Thread", true), ("CAN Thread", false)]);
+        let (status, health) = get(addr);
+        assert_eq!(status, "HTTP/1.1 503 Service Unavailable");
+        assert_eq!(health["healthy"], false);
+        assert_eq!(health["threads"]["CAN Thread"], false);
+        assert_eq!(health["last_telemetry"], 1_600_000_000_000i64);
+    }
+}
+
+/**
+ * Amount of time a monitor has to send its request, and to read the response
+ */
+const REQUEST_TIMEOUT: Duration = Duration::from_millis(100);
+const MAX_REQUEST_SIZE: usize = 4096;
+
+/**
+ * Each thread's name and whether it is alive, as RelayHandles::supervise last saw them
+ */
+#[derive(Debug, Default)]
+pub struct ThreadLiveness {
+    threads: Mutex<Vec<(&'static str, bool)>>
+}
+
+impl ThreadLiveness {
+    pub fn publish(&self, threads: Vec<(&'static str, bool)>) {
+        *self.threads.lock().unwrap() = threads;
+    }
+
+    pub fn threads(&self) -> Vec<(&'static str, bool)> {
+        self.threads.lock().unwrap().clone()
+    }
+}
+
+pub struct HealthServer {
+    listener: TcpListener,
+    metrics: Arc<Metrics>,
+    liveness: Arc<ThreadLiveness>
+}
+
+impl HealthServer {
+    pub fn new(listener: TcpListener, metrics: Arc<Metrics>, liveness: Arc<ThreadLiveness>) -> HealthServer {
+        HealthServer {
+            listener,
+            metrics,
+            liveness
+        }
+    }
+
+    /**
+     * @brief run
+     * Answer requests one at a time on the Health Thread. It is never joined, like the signal thread
+     * it blocks on its listener until the relay exits, still answering 503 while the others shut down
+     */
+    pub fn run(self) -> std::thread::JoinHandle<()> {
+        std::thread::Builder::new().name("Health Thread".to_string()).spawn(move || {
+            for stream in self.listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        let addr = stream.peer_addr();
+                        if let Err(err) = self.respond(stream) {
+                            warn!("HEALTH: Unable to answer {:?}: {:?}", addr, err);
+                        }
+                    },
+                    Err(err) => warn!("HEALTH: Unable to accept a connection: {:?}", err)
+                }
+            }
+        }).expect("Should be able to create Thread")
+    }
+
+    fn respond(&self, mut stream: TcpStream) -> std::io::Result<()> {
+        stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+        stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+        let request = stream_utils::read_lines(&mut stream, 512, MAX_REQUEST_SIZE)?;
+        let request = String::from_utf8_lossy(&request);
+        let mut request_line = request.lines().next().unwrap_or_default().split_whitespace();
+        let response = match (request_line.next(), request_line.next()) {
+            (Some("GET"), Some("/health")) => health_response(&self.liveness.threads(), &self.metrics.snapshot()),
+            _ => http_response("404 Not Found", "")
+        };
+        stream.write_all(response.as_bytes())
+    }
+}
+
+fn health_response(threads: &[(&'static str, bool)], snapshot: &MetricsSnapshot) -> String {
+    let healthy = threads.iter().all(|&(_, alive)| alive);
+    let body = serde_json::json!({
+        "healthy": healthy,
+        "threads": threads.iter().map(|&(name, alive)| (name.to_string(), alive.into())).collect::<serde_json::Map<_, _>>(),
+        "server_state": snapshot.server_state,
+        "last_telemetry": snapshot.last_telemetry_at, // Milliseconds since the epoch, as in the telemetry json
+        "can_timeouts": snapshot.can_timeouts,
+        "counters": snapshot.counters().iter().map(|&(name, count)| (name.to_string(), count.into())).collect::<serde_json::Map<_, _>>()
+    });
+    http_response(if healthy { "200 OK" } else { "503 Service Unavailable" }, &body.to_string())
+}
+
+fn http_response(status: &str, body: &str) -> String {
+    format!("HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", status, body.len(), body)
+}
@@ -0,0 +1,155 @@
+/*!
+ * @brief Counters for a quick health read of a running relay, without parsing the logs.
+ * One Metrics is shared by every thread through an Arc. Counting is a single relaxed atomic add,
+ * so it is cheap enough for the can thread's read loop. A snapshot is served over TCP with METRICS
+ */
+
+use std::sync::atomic::{ AtomicI64, AtomicU32, AtomicU64, AtomicUsize, Ordering };
+use crate::thread_managers::worker_states::*;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn counts_from_every_thread_are_in_the_snapshot() {
+        let metrics = Arc::new(Metrics::new());
+        let threads: Vec<_> = (0..4).map(|_| {
+            let metrics = Arc::clone(&metrics);
+            std::thread::spawn(move || {
+                for _ in 0..100 {
+                    metrics.can_frame_received();
+                }
+                metrics.udp_timeout();
+            })
+        }).collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        metrics.telemetry_snapshot_sent();
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.can_frames_received, 400);
+        assert_eq!(snapshot.udp_timeouts, 4);
+        assert_eq!(snapshot.to_response(), "can_frames_received 400\r\ncan_frames_undecoded 0\r\nudp_timeouts 4\r\ntelemetry_snapshots_sent 1\r\ntcp_connections_accepted 0\r\nworker_frames_dropped 0\r\n");
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Metrics {
+    can_frames_received: AtomicU64,
+    can_frames_undecoded: AtomicU64, // Received frames with an unknown id or a truncated payload
+    udp_timeouts: AtomicU64,
+    telemetry_snapshots_sent: AtomicU64, // Snapshots handed from the worker thread to the udp thread
+    tcp_connections_accepted: AtomicU64,
+    worker_frames_dropped: AtomicU64, // Frames whose telemetry was dropped because the worker thread had fallen behind
+    // Gauges. Overwritten rather than counted, and left out of the METRICS response
+    server_state: AtomicUsize, // One more than the index in SERVER_STATES of the tcp thread's state, set each time it runs. Zero until the first
+    last_telemetry_at: AtomicI64, // Milliseconds since the epoch of the last snapshot sent, zero until the first
+    can_timeouts: AtomicU32 // Consecutive can reads which timed out, reset by the next frame received
+}
+
+/**
+ * Every state the tcp thread can report
+ */
+const SERVER_STATES: [&str; 4] = [Startup::NAME, Disconnected::NAME, Connected::NAME, Recovery::NAME];
+
+/**
+ * The counters at one point in time. Each counter is read on its own, so a snapshot taken
+ * while the relay is running may be a count or two apart between counters
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MetricsSnapshot {
+    pub can_frames_received: u64,
+    pub can_frames_undecoded: u64,
+    pub udp_timeouts: u64,
+    pub telemetry_snapshots_sent: u64,
+    pub tcp_connections_accepted: u64,
+    pub worker_frames_dropped: u64,
+    pub server_state: Option<&'static str>,
+    pub last_telemetry_at: Option<i64>,
+    pub can_timeouts: u32
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        Metrics::default()
+    }
+
+    pub fn can_frame_received(&self) {
+        self.can_frames_received.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn can_frame_undecoded(&self) {
+        self.can_frames_undecoded.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn udp_timeout(&self) {
+        self.udp_timeouts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn telemetry_snapshot_sent(&self) {
+        self.telemetry_snapshots_sent.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn tcp_connection_accepted(&self) {
+        self.tcp_connections_accepted.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn worker_frame_dropped(&self) {
+        self.worker_frames_dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn set_server_state(&self, server_state: &'static str) {
+        let index = SERVER_STATES.iter().position(|&state| state == server_state).map_or(0, |index| index + 1);
+        self.server_state.store(index, Ordering::Relaxed);
+    }
+
+    pub fn telemetry_sent_at(&self, timestamp: &chrono::NaiveDateTime) {
+        self.last_telemetry_at.store(timestamp.timestamp_millis(), Ordering::Relaxed);
+    }
+
+    pub fn set_can_timeouts(&self, can_timeouts: u32) {
+        self.can_timeouts.store(can_timeouts, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            can_frames_received: self.can_frames_received.load(Ordering::Relaxed),
+            can_frames_undecoded: self.can_frames_undecoded.load(Ordering::Relaxed),
+            udp_timeouts: self.udp_timeouts.load(Ordering::Relaxed),
+            telemetry_snapshots_sent: self.telemetry_snapshots_sent.load(Ordering::Relaxed),
+            tcp_connections_accepted: self.tcp_connections_accepted.load(Ordering::Relaxed),
+            worker_frames_dropped: self.worker_frames_dropped.load(Ordering::Relaxed),
+            server_state: self.server_state.load(Ordering::Relaxed).checked_sub(1).and_then(|index| SERVER_STATES.get(index).copied()),
+            last_telemetry_at: Some(self.last_telemetry_at.load(Ordering::Relaxed)).filter(|&millis| millis != 0),
+            can_timeouts: self.can_timeouts.load(Ordering::Relaxed)
+        }
+    }
+}
+
+impl MetricsSnapshot {
+    /**
+     * @brief counters
+     * Every counter with its name, in the order they are reported
+     */
+    pub fn counters(&self) -> [(&'static str, u64); 6] {
+        [
+            ("can_frames_received", self.can_frames_received),
+            ("can_frames_undecoded", self.can_frames_undecoded),
+            ("udp_timeouts", self.udp_timeouts),
+            ("telemetry_snapshots_sent", self.telemetry_snapshots_sent),
+            ("tcp_connections_accepted", self.tcp_connections_accepted),
+            ("worker_frames_dropped", self.worker_frames_dropped)
+        ]
+    }
+
+    /**
+     * @brief to_response
+     * Response to a METRICS request. One counter per line, of the form: <name> <count>\r\n
+     */
+    pub fn to_response(&self) -> String {
+        self.counters().iter().map(|(name, count)| format!("{} {}\r\n", name, count)).collect()
+    }
+}
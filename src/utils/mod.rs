@@ -2,3 +2,10 @@ pub mod requests;
 pub mod stream_utils;
 pub mod device_watchdog;
 pub mod rpm_integrator;
+pub mod signals;
+pub mod metrics;
+pub mod sanity_ranges;
+pub mod command_history;
+pub mod bounded_channel;
+#[cfg(feature = "health")]
+pub mod health;
@@ -170,6 +170,106 @@ mod test {
             panic!("Missing Value");
         }
     }
+
+    #[test]
+    fn offset_advances_past_each_command() {
+        let mut parser: RequestParser::<u32> = RequestParser::with_lenient_matching(true);
+        parser.insert("PING\r\n", 1).validate_result(Success(()));
+        parser.insert("STATUS\r\n", 2).validate_result(Success(()));
+
+        let request = b"PING\r\nstatus\nPING\r\nST";
+        let mut offset = 0;
+        let mut values = Vec::new();
+        while let Success((&value, length)) = parser.strip_line_and_get_offset(&request[offset..]) {
+            values.push(value);
+            offset += length;
+        }
+        assert_eq!(values, vec![1, 2, 1]);
+        assert_eq!(&request[offset..], b"ST");
+    }
+
+    #[test]
+    fn argument_is_captured_after_the_command() {
+        let mut parser: RequestParser::<u32> = RequestParser::new();
+        parser.insert("DISCONNECT\r\n", 1).validate_result(Success(()));
+        parser.insert("PING\r\n", 2).validate_result(Success(()));
+
+        let request = b"DISCONNECT FAST\r\nDISCONNECT\r\nPING\r\n";
+        let (value, argument, request) = match parser.strip_line_and_get_value_with_argument(&request[..]) {
+            Success(result) => result,
+            _ => panic!("Command followed by an argument should match")
+        };
+        assert_eq!((*value, argument, request), (1, Some(&b"FAST"[..]), &b"DISCONNECT\r\nPING\r\n"[..]));
+        let (value, argument, request) = match parser.strip_line_and_get_value_with_argument(request) {
+            Success(result) => result,
+            _ => panic!("Command without an argument should match")
+        };
+        assert_eq!((*value, argument, request), (1, None, &b"PING\r\n"[..]));
+        parser.strip_line_and_get_value_with_argument(b"DISCONNECT \r\n").validate_result(InvalidRequest);
+        parser.strip_line_and_get_value_with_argument(b"CONNECT FAST\r\n").validate_result(InvalidRequest);
+        parser.strip_line_and_get_value_with_argument(b"DISCONNECT FAST").validate_result(InvalidRequest);
+    }
+
+    #[test]
+    fn lenient_matching() {
+        let mut parser: RequestParser::<u32> = RequestParser::with_lenient_matching(true);
+        parser.insert("CONNECT\r\n", 1).validate_result(Success(()));
+        parser.insert("PING\r\n", 2).validate_result(Success(()));
+
+        let request = b"connect\nPING\r\n  Ping \r\nPONG\n";
+        let (value, request) = match parser.strip_line_and_get_value(&request[..]) {
+            Success(result) => result,
+            _ => panic!("LF only, lower case line should match")
+        };
+        assert_eq!((*value, request), (1, &b"PING\r\n  Ping \r\nPONG\n"[..]));
+        let (value, request) = match parser.strip_line_and_get_value(request) {
+            Success(result) => result,
+            _ => panic!("CRLF line should match")
+        };
+        assert_eq!((*value, request), (2, &b"  Ping \r\nPONG\n"[..]));
+        let (value, request) = match parser.strip_line_and_get_value(request) {
+            Success(result) => result,
+            _ => panic!("Padded line should match")
+        };
+        assert_eq!((*value, request), (2, &b"PONG\n"[..]));
+        parser.strip_line_and_get_value(request).validate_result(InvalidRequest);
+        parser.strip_line_and_get_value(b"PING").validate_result(InvalidRequest);
+        assert!(matches!(parser.get("Connect\n"), Success(&1)));
+
+        // Strict matching stays the default
+        let mut parser: RequestParser::<u32> = RequestParser::new();
+        parser.insert("CONNECT\r\n", 1).validate_result(Success(()));
+        assert!(matches!(parser.strip_line_and_get_value(b"CONNECT\r\n"), Success((&1, _))));
+        parser.strip_line_and_get_value(b"CONNECT\n").validate_result(InvalidRequest);
+        parser.strip_line_and_get_value(b"connect\r\n").validate_result(InvalidRequest);
+    }
+
+    #[test]
+    fn removed_commands_no_longer_parse() {
+        let mut parser: RequestParser::<u32> = RequestParser::new();
+        parser.insert("CONNECT\r\n", 1).validate_result(Success(()));
+        parser.insert("DISCONNECT\r\n", 2).validate_result(Success(()));
+        parser.insert("DISCONNECTED\r\n", 3).validate_result(Success(()));
+
+        assert!(parser.remove("DISCONNECT\r\n"));
+        assert!(!parser.remove("DISCONNECT\r\n"));
+        assert!(!parser.remove("DISC"));
+        assert!(!parser.remove(""));
+        parser.get("DISCONNECT\r\n").validate_result(InvalidKey);
+        parser.strip_line_and_get_value(b"DISCONNECT\r\n").validate_result(InvalidRequest);
+        // Commands sharing part of the key are left alone
+        assert!(matches!(parser.get("DISCONNECTED\r\n"), Success(&3)));
+        assert!(matches!(parser.strip_line_and_get_value(b"CONNECT\r\n"), Success((&1, _))));
+
+        // A removed command can be registered again
+        parser.insert("DISCONNECT\r\n", 4).validate_result(Success(()));
+        assert!(matches!(parser.get("DISCONNECT\r\n"), Success(&4)));
+
+        assert!(parser.clear());
+        assert!(!parser.clear());
+        parser.strip_line_and_get_value(b"CONNECT\r\n").validate_result(InvalidRequest);
+        parser.get("DISCONNECTED\r\n").validate_result(InvalidKey);
+    }
 }
 
 pub enum RequestParserResult<T> {
@@ -189,6 +289,7 @@ struct RequestParserNode<T> {
 
 pub struct RequestParser<T> {
     children: Vec::<RequestParserNode<T>>,
+    lenient: bool, // Ignore case, surrounding whitespace, and whether a line ends in \r\n or \n
 }
 
 impl<T> RequestParserNode<T> {
@@ -238,6 +339,15 @@ impl<T> RequestParserNode<T> {
         return self.get_child_mut().expect("Child Should Exist");
     }
 
+    /**
+     * True if this node, its children or its later siblings hold a value
+     */
+    fn has_values(&self) -> bool {
+        self.value.is_some()
+            || self.get_child().map_or(false, |child| child.has_values())
+            || self.get_sibling().map_or(false, |sibling| sibling.has_values())
+    }
+
     fn get_child(&self) -> Option<&RequestParserNode<T>> {
         if let Some(child) = &self.child {
             Some(child)
@@ -273,9 +383,37 @@ impl<T> RequestParserNode<T> {
 
 impl <T> RequestParser<T> {
     pub fn new() -> RequestParser<T> {
+        RequestParser::with_lenient_matching(false)
+    }
+
+    /**
+     * @brief with_lenient_matching
+     * @param lenient: bool
+     * A lenient parser matches keys without regard to ASCII case or surrounding spaces, and a key
+     * ending in \r\n also matches a line ending in just \n. Keys are normalized the same way on insert,
+     * so "CONNECT\r\n" matches "connect\n" and " Connect \r\n". A strict parser matches keys byte for byte
+     */
+    pub fn with_lenient_matching(lenient: bool) -> RequestParser<T> {
         RequestParser {
             children: Vec::<RequestParserNode<T>>::new(),
+            lenient
+        }
+    }
+
+    /**
+     * Key as it is stored in the tree. Lenient keys are upper cased, trimmed, and any line ending becomes \r\n
+     */
+    fn normalize_key(&self, key: &[u8]) -> Vec<u8> {
+        if !self.lenient {
+            return key.to_vec();
         }
+        let line = key.strip_suffix(b"\n").unwrap_or(key);
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        let mut normalized = line.trim_ascii().to_ascii_uppercase();
+        if key.ends_with(b"\n") {
+            normalized.extend_from_slice(b"\r\n");
+        }
+        normalized
     }
 
     fn insert_loop<'a>(&mut self, key: &'a [u8], value: T, root_index: usize) {
@@ -309,7 +447,8 @@ impl <T> RequestParser<T> {
      * this will reduce the time that it takes to parse the most often used commands
      */
     pub fn insert<'a>(&mut self, key: &'a str, value: T) -> RequestParserResult<()> {
-        let key = key.as_bytes();
+        let key = self.normalize_key(key.as_bytes());
+        let key = key.as_slice();
         let key_char = key.first();
 
         if let Some(&key_char) = key_char {
@@ -342,12 +481,16 @@ impl <T> RequestParser<T> {
         if key.len() == 0 {
             return EmptyKey
         }
-        let key = key.as_bytes();
+        let key = self.normalize_key(key.as_bytes());
+        if key.is_empty() {
+            return EmptyKey
+        }
+        let key = key.as_slice();
         let key_char = key.first().expect("Key is not empty");
 
         /* Find the index of the root which matches the first byte of the key */
         let root_index = self.get_root_index(*key_char);
-        if root_index == key.len() {
+        if root_index == self.children.len() {
             return InvalidKey
         }
 
@@ -393,6 +536,56 @@ impl <T> RequestParser<T> {
         InvalidKey
     }
 
+    /**
+     * @brief remove
+     * @param key &'a str
+     * Unregister the value at key. Returns false if there was no value at the key
+     *
+     * @NOTE The key's nodes are left in the tree without a value, so a later insert of the same key reuses them
+     */
+    pub fn remove<'a>(&mut self, key: &'a str) -> bool {
+        let key = self.normalize_key(key.as_bytes());
+        self.get_node_mut(&key).and_then(|node| node.value.take()).is_some()
+    }
+
+    /**
+     * @brief clear
+     * Unregister every value. Returns false if the parser was already empty
+     */
+    pub fn clear(&mut self) -> bool {
+        let removed = self.children.iter().any(|root| root.has_values());
+        self.children.clear();
+        removed
+    }
+
+    /**
+     * Node at the end of key, whether or not it holds a value
+     */
+    fn get_node(&self, key: &[u8]) -> Option<&RequestParserNode<T>> {
+        let (&first, rest) = key.split_first()?;
+        let mut runner = self.children.get(self.get_root_index(first))?;
+        for &key_char in rest {
+            runner = runner.get_child()?;
+            while runner.get_key_char() != key_char {
+                runner = runner.get_sibling()?;
+            }
+        }
+        Some(runner)
+    }
+
+    fn get_node_mut(&mut self, key: &[u8]) -> Option<&mut RequestParserNode<T>> {
+        let (&first, rest) = key.split_first()?;
+        let root_index = self.get_root_index(first);
+        let mut runner = self.children.get_mut(root_index)?;
+        for &key_char in rest {
+            runner = runner.get_child_mut()?;
+            while runner.get_key_char() != key_char {
+                runner = runner.get_sibling_mut()?;
+            }
+        }
+        Some(runner)
+    }
+
     fn get_root_index(&self, key_char: u8) -> usize {
         for (index, child) in self.children.iter().enumerate() {
             if child.get_key_char() == key_char {
@@ -410,8 +603,22 @@ impl <T> RequestParser<T> {
      * If value is None, returns InvalidRequest
      *
      * If the request is Invalid, then request will equal [b'\0']
+     *
+     * A lenient parser ends the line at the first \n, and normalizes it the same way keys are
      */
     pub fn strip_line_and_get_value<'request>(&self, request: &'request [u8]) -> RequestParserResult<(&T, &'request [u8])> {
+        if self.lenient {
+            let line_length = match request.iter().position(|&byte| byte == b'\n') {
+                Some(index) => index + 1,
+                None => return InvalidRequest
+            };
+            let (line, remaining) = request.split_at(line_length);
+            return match self.get_node(&self.normalize_key(line)).and_then(|node| node.get_value()) {
+                Some(value) => Success((value, remaining)),
+                None => InvalidRequest
+            };
+        }
+
         let  return_invalid = | request: std::slice::Iter<'_, u8> | -> RequestParserResult<(&T, &[u8])> {
             request.count();
             InvalidRequest
@@ -447,5 +654,54 @@ impl <T> RequestParser<T> {
 
         return_invalid(request)
     }
+
+    /**
+     * @brief strip_line_and_get_offset
+     * @param request: &[u8]
+     * Same as strip_line_and_get_value, but returns the number of bytes the matched line took up,
+     * including its line ending. The next command in the request starts at that offset
+     */
+    pub fn strip_line_and_get_offset(&self, request: &[u8]) -> RequestParserResult<(&T, usize)> {
+        match self.strip_line_and_get_value(request) {
+            Success((value, remaining)) => Success((value, request.len() - remaining.len())),
+            EmptyKey => EmptyKey,
+            InvalidKey => InvalidKey,
+            InvalidRequest => InvalidRequest
+        }
+    }
+
+    /**
+     * @brief strip_line_and_get_value_with_argument
+     * @param request: &[u8]
+     * Same as strip_line_and_get_value, but a line which does not match a key as a whole may be a key
+     * followed by an argument. The line is split at its first space, and the text after it, up to the
+     * line ending and without surrounding spaces, is returned as the argument. "DISCONNECT FAST\r\n"
+     * matches the key "DISCONNECT\r\n" with the argument "FAST". A line which matches as a whole has no argument
+     */
+    pub fn strip_line_and_get_value_with_argument<'request>(&self, request: &'request [u8]) -> RequestParserResult<(&T, Option<&'request [u8]>, &'request [u8])> {
+        if let Success((value, remaining)) = self.strip_line_and_get_value(request) {
+            return Success((value, None, remaining));
+        }
+        let line_length = match request.iter().position(|&byte| byte == b'\n') {
+            Some(index) => index + 1,
+            None => return InvalidRequest
+        };
+        let (line, remaining) = request.split_at(line_length);
+        let line_ending = if line.ends_with(b"\r\n") { line.len() - 2 } else { line.len() - 1 };
+        let (line, ending) = line.split_at(line_ending);
+        let line = if self.lenient { line.trim_ascii_start() } else { line };
+        let (command, argument) = match line.iter().position(|&byte| byte == b' ') {
+            Some(index) => (&line[..index], line[index + 1..].trim_ascii()),
+            None => return InvalidRequest
+        };
+        if argument.is_empty() {
+            return InvalidRequest;
+        }
+        let key = [command, ending].concat();
+        match self.get_node(&self.normalize_key(&key)).and_then(|node| node.get_value()) {
+            Some(value) => Success((value, Some(argument), remaining)),
+            None => InvalidRequest
+        }
+    }
 }
 
@@ -0,0 +1,151 @@
+/*!
+ * @brief The range of readings each PodData field can plausibly hold. A corrupted frame can still be
+ * a full 8 bytes and decode into something like -3000°C, which would raise a false alarm on the
+ * controller. Readings outside of their field's range are logged, and dropped unless the ranges are
+ * only flagging them. A dropped reading leaves the field's last good value in place, and the field
+ * goes stale if nothing good replaces it
+ */
+
+use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
+use log::warn;
+use crate::pod_data::PodData;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn readings_outside_of_their_range_are_dropped_or_flagged() {
+        let mut ranges = SanityRanges::default();
+        assert!(ranges.accept("igbt_temp", &Some(85.0f32)));
+        assert!(!ranges.accept("igbt_temp", &Some(-3000.0f32)));
+        assert!(!ranges.accept("pressure_high", &Some(f32::NAN)));
+        assert!(!ranges.accept("torchic_1", &[Some(21.5f32), Some(900.0)]));
+        assert!(ranges.accept("torchic_1", &[Some(21.5f32), None]));
+        assert!(!ranges.accept("roboteq_mcu_temp", &Some(-128i8)));
+        // Fields without a range are always accepted
+        assert!(ranges.accept("speed", &Some(-3000.0f32)));
+
+        ranges.set_range("speed", 0.0, 44.0);
+        assert!(!ranges.accept("speed", &Some(-3000.0f32)));
+        ranges.set_action(OutOfRangeAction::Flag);
+        assert!(ranges.accept("speed", &Some(-3000.0f32)));
+
+        assert!(is_pod_data_field("pressure_low_2"));
+        assert!(!is_pod_data_field("updated"));
+        assert!(!is_pod_data_field("pressure"));
+    }
+}
+
+/**
+ * What is done with a reading outside of its field's range
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutOfRangeAction {
+    Flag, // Log the reading and forward it anyway
+    Drop // Log the reading and keep the field's last good value
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SanityRanges {
+    ranges: BTreeMap<String, RangeInclusive<f64>>, // Keyed by the field's name in the telemetry. Fields without a range are never checked
+    action: OutOfRangeAction
+}
+
+/**
+ * Wider than any of the thermistors on the pod can read
+ */
+const TEMPERATURE_RANGE: RangeInclusive<f64> = -40.0..=200.0;
+const TEMPERATURE_FIELDS: [&str; 8] = ["average_cell_temperature", "igbt_temp", "buck_temperature", "torchic_1", "torchic_2", "roboteq_mcu_temp", "roboteq_sensor_1_temp", "roboteq_sensor_2_temp"];
+/**
+ * Pressures can't be negative, and the high pressure line is well below the top of this range
+ */
+const PRESSURE_RANGE: RangeInclusive<f64> = 0.0..=10000.0;
+const PRESSURE_FIELDS: [&str; 3] = ["pressure_high", "pressure_low_1", "pressure_low_2"];
+
+impl Default for SanityRanges {
+    /**
+     * Temperatures and pressures are checked, and out of range readings are dropped
+     */
+    fn default() -> SanityRanges {
+        let temperatures = TEMPERATURE_FIELDS.iter().map(|field| (field.to_string(), TEMPERATURE_RANGE));
+        let pressures = PRESSURE_FIELDS.iter().map(|field| (field.to_string(), PRESSURE_RANGE));
+        SanityRanges {
+            ranges: temperatures.chain(pressures).collect(),
+            action: OutOfRangeAction::Drop
+        }
+    }
+}
+
+impl SanityRanges {
+    /**
+     * @brief set_range
+     * Check field against min..=max, replacing any range it already had
+     */
+    pub fn set_range(&mut self, field: &str, min: f64, max: f64) {
+        self.ranges.insert(field.to_string(), min..=max);
+    }
+
+    pub fn set_action(&mut self, action: OutOfRangeAction) {
+        self.action = action;
+    }
+
+    pub fn range(&self, field: &str) -> Option<&RangeInclusive<f64>> {
+        self.ranges.get(field)
+    }
+
+    /**
+     * @brief accept
+     * True if the value should be stored in field. Every reading outside of the field's range is logged,
+     * and the value is only refused if the ranges are dropping them
+     */
+    pub fn accept<R: Readings>(&self, field: &str, value: &R) -> bool {
+        let range = match self.ranges.get(field) {
+            Some(range) => range,
+            None => return true
+        };
+        // NaN is never contained by a range, so a reading which decoded to NaN is caught as well
+        let out_of_range: Vec<f64> = value.readings().into_iter().filter(|reading| !range.contains(reading)).collect();
+        if out_of_range.is_empty() {
+            return true;
+        }
+        match self.action {
+            OutOfRangeAction::Flag => {
+                warn!("WORKER THREAD: {} reading {:?} is outside of {:?}, forwarding it anyway", field, out_of_range, range);
+                true
+            },
+            OutOfRangeAction::Drop => {
+                warn!("WORKER THREAD: Dropped {} reading {:?}, it is outside of {:?}", field, out_of_range, range);
+                false
+            }
+        }
+    }
+}
+
+/**
+ * A PodData field's value as the readings it holds. Most fields hold one, the torchics hold two
+ */
+pub trait Readings {
+    fn readings(&self) -> Vec<f64>;
+}
+
+impl<T: Copy + Into<f64>> Readings for Option<T> {
+    fn readings(&self) -> Vec<f64> {
+        self.iter().map(|&reading| reading.into()).collect()
+    }
+}
+
+impl Readings for [Option<f32>; 2] {
+    fn readings(&self) -> Vec<f64> {
+        self.iter().flatten().map(|&reading| reading.into()).collect()
+    }
+}
+
+/**
+ * @brief is_pod_data_field
+ * True if field is the name of a reading in the telemetry
+ */
+pub fn is_pod_data_field(field: &str) -> bool {
+    field != "updated" && serde_json::to_value(PodData::new()).map_or(false, |telemetry| telemetry.get(field).is_some())
+}
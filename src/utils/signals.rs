@@ -0,0 +1,78 @@
+use std::sync::atomic::{ AtomicBool, Ordering };
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sigterm_requests_shutdown() {
+        install_shutdown_handler().unwrap();
+        assert!(!shutdown_requested());
+        unsafe { libc::raise(libc::SIGTERM) };
+        assert!(shutdown_requested());
+    }
+}
+
+/**
+ * Set from the signal handler. Handlers are only allowed to do a very small amount of work,
+ * so the handler only records the request and the relay polls it from a regular thread
+ */
+static SHUTDOWN_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/**
+ * @brief install_shutdown_handler
+ * Hook SIGINT and SIGTERM on unix, or Ctrl-C and console close events on windows,
+ * so that they request a shutdown instead of killing the process outright
+ */
+pub fn install_shutdown_handler() -> std::io::Result<()> {
+    platform::install()
+}
+
+/**
+ * @brief shutdown_requested
+ * True once the process has received a shutdown signal
+ */
+pub fn shutdown_requested() -> bool {
+    SHUTDOWN_REQUESTED.load(Ordering::SeqCst)
+}
+
+#[cfg(unix)]
+mod platform {
+    use super::*;
+
+    extern "C" fn handle_signal(_signal: libc::c_int) {
+        SHUTDOWN_REQUESTED.store(true, Ordering::SeqCst);
+    }
+
+    pub fn install() -> std::io::Result<()> {
+        for &signal in &[libc::SIGINT, libc::SIGTERM] {
+            let handler = handle_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            if unsafe { libc::signal(signal, handler) } == libc::SIG_ERR {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use super::*;
+
+    // kernel32 is always linked by std on windows, so no extra dependency is needed for this
+    extern "system" {
+        fn SetConsoleCtrlHandler(handler: Option<unsafe extern "system" fn(u32) -> i32>, add: i32) -> i32;
+    }
+
+    unsafe extern "system" fn handle_ctrl_event(_ctrl_type: u32) -> i32 {
+        SHUTDOWN_REQUESTED.store(true, Ordering::SeqCst);
+        1 // Handled, don't run the default handler which terminates the process
+    }
+
+    pub fn install() -> std::io::Result<()> {
+        if unsafe { SetConsoleCtrlHandler(Some(handle_ctrl_event), 1) } == 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
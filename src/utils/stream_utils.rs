@@ -1,18 +1,121 @@
 use std::io::Read;
 
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::VecDeque;
+
+    /**
+     * Hands out one scripted piece per read, then reports the end of the stream
+     */
+    struct SplitReader {
+        pieces: VecDeque<&'static [u8]>
+    }
+
+    impl SplitReader {
+        fn new(pieces: &[&'static [u8]]) -> SplitReader {
+            SplitReader { pieces: pieces.iter().copied().collect() }
+        }
+    }
+
+    impl Read for SplitReader {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            match self.pieces.pop_front() {
+                Some(piece) => {
+                    buf[..piece.len()].copy_from_slice(piece);
+                    Ok(piece.len())
+                },
+                None => Ok(0)
+            }
+        }
+    }
+
+    #[test]
+    fn message_split_across_two_reads() {
+        let mut stream = SplitReader::new(&[b"CONNECT X-Header: ", b"value\r\n"]);
+        assert_eq!(read_all(&mut stream, 32, 1024).unwrap(), b"CONNECT X-Header: value\r\n".to_vec());
+
+        let mut stream = SplitReader::new(&[b"PING\r\nST", b"ATUS\r\n"]);
+        assert_eq!(read_lines(&mut stream, 32, 1024).unwrap(), b"PING\r\nSTATUS\r\n".to_vec());
+    }
+
+    #[test]
+    fn message_larger_than_the_cap_is_rejected() {
+        let mut stream = SplitReader::new(&[b"0123456789", b"0123456789", b"\r\n"]);
+        assert_eq!(read_all(&mut stream, 16, 16).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+
+        let mut stream = SplitReader::new(&[b"0123456789", b"0123456789", b"\r\n"]);
+        assert_eq!(read_lines(&mut stream, 16, 16).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    }
+}
+
 /**
  * @func read_all
- * Read all of the bytes out of a socket and return the result as a vector
+ * Read a message out of a socket and return the result as a vector. Reads until the message
+ * ends with \r\n or the stream closes, so a message larger than chunk_size is not truncated.
+ * A message which grows past max_size is an InvalidData error, so a client can not make us
+ * hold an unbounded amount of memory
+ */
+pub fn read_all<T>(stream: &mut T, chunk_size: usize, max_size: usize) -> std::io::Result<Vec::<u8>>
+where T: Read {
+    let mut out_buf = Vec::<u8>::new();
+    let mut buffer = vec![0; chunk_size];
+
+    while !out_buf.ends_with(b"\r\n") {
+        let bytes_read = stream.read(&mut buffer)?;
+        if bytes_read == 0 {
+            break;
+        }
+        out_buf.extend_from_slice(&buffer[..bytes_read]);
+        check_size(&out_buf, max_size)?;
+    }
+    Ok(out_buf)
+}
+
+/**
+ * @func read_lines
+ * Read from a stream until everything received ends with a complete \r\n terminated line.
+ * A partial trailing line is held and completed by the following reads. If the stream fails or closes
+ * once at least one complete line has been read, the complete lines are returned and the partial line is dropped.
+ * Reading more than max_size bytes is an InvalidData error, the same as in read_all
  */
-pub fn read_all<T>(stream: &mut T, chunk_size: usize) -> std::io::Result<Vec::<u8>>
+pub fn read_lines<T>(stream: &mut T, chunk_size: usize, max_size: usize) -> std::io::Result<Vec::<u8>>
 where T: Read {
     let mut out_buf = Vec::<u8>::new();
-    let mut bytes_read = chunk_size;
+    let mut buffer = vec![0; chunk_size];
 
-    while bytes_read == chunk_size {
-        let mut buffer = vec![0; chunk_size];
-        bytes_read = stream.read(&mut buffer)?;
-        out_buf.append(&mut buffer);
+    while !out_buf.ends_with(b"\r\n") {
+        let result = match stream.read(&mut buffer) {
+            Ok(0) => Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof)),
+            result => result
+        };
+        match result {
+            Ok(bytes_read) => {
+                out_buf.extend_from_slice(&buffer[..bytes_read]);
+                check_size(&out_buf, max_size)?;
+            },
+            Err(error) => {
+                let complete_length = complete_lines_length(&out_buf);
+                if complete_length == 0 {
+                    return Err(error);
+                }
+                out_buf.truncate(complete_length);
+            }
+        }
     }
     Ok(out_buf)
 }
+
+/**
+ * Number of bytes at the start of the buffer which make up complete \r\n terminated lines
+ */
+fn complete_lines_length(buf: &[u8]) -> usize {
+    buf.windows(2).rposition(|window| window == b"\r\n").map_or(0, |index| index + 2)
+}
+
+fn check_size(buf: &[u8], max_size: usize) -> std::io::Result<()> {
+    if buf.len() > max_size {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("message is larger than {} bytes", max_size)));
+    }
+    Ok(())
+}
@@ -0,0 +1,150 @@
+#![cfg(feature = "mock-can")]
+/**
+ *  @test Simulated pod test
+ *  @brief Runs the tcp, udp and can threads against a simulated pod, and drives them as the controller would.
+ *  Checks the whole loop from the controller to the boards and back without any hardware:
+ *  - CONNECT starts the udp link to the controller
+ *  - Each state the controller requests is written on the bus, acked by the boards and reported back
+ *  - The roboteq is only throttled once AutoPilot has been acked, and the pod is left in LowVoltage on shutdown
+ *  Only built with the mock-can feature: cargo test --features mock-can
+ * */
+use relay::can_extentions::prelude::{ SimulatedPod, Board };
+use relay::config::{ DisconnectWait, RoboteqConfig };
+use relay::metrics::Metrics;
+use relay::pod_states::PodState;
+use relay::utils::bounded_channel::{ bounded_channel, FullPolicy };
+use relay::utils::command_history::CommandHistory;
+use relay::thread_managers::{ CanManager, CanWorkerInitializer, TcpManager, UdpManager };
+use relay::thread_managers::messages::{ CanMessage, TcpMessage, UDPMessage };
+use std::io::{ Read, Write };
+use std::net::{ SocketAddr, TcpListener, TcpStream, UdpSocket };
+use std::sync::Arc;
+use std::sync::mpsc::channel;
+use std::time::{ Duration, Instant };
+
+const ROBOTEQ_NODE: u32 = 1;
+const THROTTLE_PERCENT: u32 = 30;
+
+/**
+ * The current state in a message from the relay's udp thread
+ */
+fn current_state(message: &[u8]) -> PodState {
+  let message = json::parse(std::str::from_utf8(message).unwrap()).unwrap();
+  PodState::from(message["current_state"].as_u8().unwrap())
+}
+
+fn request_state(controller: &UdpSocket, state: PodState) {
+  let request = json::object!{ "requested_state": state.to_byte(), "most_recent_timestamp": chrono::Utc::now().timestamp() };
+  controller.send(request.dump().as_bytes()).unwrap();
+}
+
+#[test]
+fn autopilot_throttles_the_roboteq_once_the_boards_ack() {
+  let pod = SimulatedPod::new();
+
+  let metrics = Arc::new(Metrics::new());
+  let (tcp_message_sender, tcp_message_receiver) = channel();
+  let (udp_message_sender, udp_message_receiver) = channel();
+  let (can_message_sender, can_message_receiver) = channel();
+  let (worker_message_sender, _worker_message_receiver) = bounded_channel(1024, FullPolicy::DropOldest);
+
+  let can_handle = CanManager::run_with_socket(Box::new(pod.clone()), CanWorkerInitializer {
+    can_interface: String::from("simulated"),
+    udp_message_sender: udp_message_sender.clone(),
+    worker_message_sender,
+    can_message_receiver,
+    can_socket_read_timeout: Duration::from_millis(10),
+    can_max_number_timeouts: 100,
+    roboteq: RoboteqConfig::new(ROBOTEQ_NODE, vec![1, 2], THROTTLE_PERCENT).unwrap(),
+    log_path: None,
+    dry_run: false,
+    state_change_timeout: Duration::from_millis(1000),
+    state_change_retries: 3,
+    metrics: Arc::clone(&metrics)
+  });
+  let udp_handle = UdpManager::run(
+    can_message_sender.clone(),
+    tcp_message_sender.clone(),
+    udp_message_receiver,
+    10,
+    Duration::from_millis(500),
+    Duration::from_millis(100),
+    Duration::from_millis(1000),
+    "127.0.0.1:0",
+    Arc::clone(&metrics)
+  );
+  udp_message_sender.send(UDPMessage::StartupComplete).unwrap();
+
+  let controller = UdpSocket::bind("127.0.0.1:0").unwrap();
+  controller.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+  let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+  let relay_addr = listener.local_addr().unwrap();
+  let tcp_handle = TcpManager::run_with_listener(
+    listener,
+    udp_message_sender.clone(),
+    can_message_sender.clone(),
+    tcp_message_receiver,
+    128,
+    controller.local_addr().unwrap().port(),
+    Duration::from_millis(1000),
+    DisconnectWait::Immediate,
+    Vec::new(),
+    Arc::new(CommandHistory::new(8)),
+    Arc::clone(&metrics)
+  );
+
+  // The tcp thread refuses CONNECT until the udp thread has told it startup is complete, so retry as the controller would
+  let mut response = String::new();
+  for _ in 0..50 {
+    let mut connection = TcpStream::connect(relay_addr).unwrap();
+    connection.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    connection.write_all(b"CONNECT\r\n").unwrap();
+    response.clear();
+    connection.read_to_string(&mut response).unwrap();
+    if !response.starts_with("ERROR Starting up") {
+      break;
+    }
+    std::thread::sleep(Duration::from_millis(10));
+  }
+  let relay_udp_port = response.split_whitespace().nth(2).and_then(|port| port.parse::<u16>().ok()).unwrap_or_else(|| panic!("Unexpected CONNECT response {:?}", response));
+  controller.connect(SocketAddr::from(([127, 0, 0, 1], relay_udp_port))).unwrap();
+
+  // Answer every message from the relay, stepping up to AutoPilot as each state is reached
+  let deadline = Instant::now() + Duration::from_secs(20);
+  let mut buffer = [0u8; 4096];
+  loop {
+    assert!(Instant::now() < deadline, "The roboteq was never throttled, the pod is commanded {:?}", pod.commanded_state());
+    let bytes_received = controller.recv(&mut buffer).unwrap();
+    let state = current_state(&buffer[..bytes_received]);
+    match state {
+      PodState::LowVoltage => request_state(&controller, PodState::Armed),
+      PodState::Armed => {
+        // This message may be older than the bus, but a throttle must follow AutoPilot on it
+        if pod.throttle(ROBOTEQ_NODE, 1).is_some() {
+          assert_eq!(pod.commanded_state(), Some(PodState::AutoPilot));
+        }
+        request_state(&controller, PodState::AutoPilot);
+      },
+      PodState::AutoPilot => {
+        request_state(&controller, PodState::AutoPilot);
+        if pod.throttle(ROBOTEQ_NODE, 1).is_some() && pod.throttle(ROBOTEQ_NODE, 2).is_some() {
+          break;
+        }
+      },
+      state => panic!("The pod should never be in {:?}", state)
+    }
+  }
+  assert_eq!(pod.throttle(ROBOTEQ_NODE, 1), Some(THROTTLE_PERCENT));
+  assert_eq!(pod.throttle(ROBOTEQ_NODE, 2), Some(THROTTLE_PERCENT));
+  for board in [Board::Bms, Board::MotorController, Board::Pressure].iter() {
+    assert_eq!(pod.board_state(*board), PodState::AutoPilot);
+  }
+
+  tcp_message_sender.send(TcpMessage::Shutdown).unwrap();
+  udp_message_sender.send(UDPMessage::Shutdown).unwrap();
+  can_message_sender.send(CanMessage::Shutdown).unwrap();
+  tcp_handle.join().unwrap();
+  udp_handle.join().unwrap();
+  can_handle.join().unwrap();
+  assert_eq!(pod.commanded_state(), Some(PodState::LowVoltage));
+}
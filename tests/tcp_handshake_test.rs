@@ -0,0 +1,66 @@
+/**
+ *  @test TCP handshake test
+ *  @brief Runs the TCP thread on an ephemeral port and connects to it as the controller would.
+ *  The udp thread is stood in for by the test, so this checks the handshake end to end:
+ *  - CONNECT is only accepted once the udp thread has finished startup
+ *  - CONNECT asks the udp thread to connect to the controller's address on the forwarded udp port
+ *  - The controller is only answered once the udp thread has connected, with the port the udp thread is bound to
+ * */
+use relay::config::DisconnectWait;
+use relay::metrics::Metrics;
+use relay::utils::command_history::CommandHistory;
+use relay::thread_managers::TcpManager;
+use relay::thread_managers::messages::{ TcpMessage, UDPMessage };
+use std::io::{ Read, Write };
+use std::net::{ SocketAddr, TcpListener, TcpStream };
+use std::sync::Arc;
+use std::sync::mpsc::channel;
+use std::time::Duration;
+
+const UDP_PORT: u16 = 8888;
+const RELAY_UDP_PORT: u16 = 8081;
+
+#[test]
+fn connect_is_forwarded_to_the_udp_thread() {
+  let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+  let relay_addr = listener.local_addr().unwrap();
+  let (udp_message_sender, udp_message_receiver) = channel();
+  let (can_message_sender, _can_message_receiver) = channel();
+  let (tcp_message_sender, tcp_message_receiver) = channel();
+  let tcp_handle = TcpManager::run_with_listener(
+    listener,
+    udp_message_sender,
+    can_message_sender,
+    tcp_message_receiver,
+    128,
+    UDP_PORT,
+    Duration::from_millis(1000),
+    DisconnectWait::Immediate,
+    Vec::new(),
+    Arc::new(CommandHistory::new(8)),
+    Arc::new(Metrics::new())
+  );
+
+  // Stand in for the udp thread finishing startup
+  tcp_message_sender.send(TcpMessage::StartupComplete).unwrap();
+
+  let mut controller = TcpStream::connect(relay_addr).unwrap();
+  controller.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+  controller.write_all(b"CONNECT\r\n").unwrap();
+
+  match udp_message_receiver.recv_timeout(Duration::from_secs(5)) {
+    Ok(UDPMessage::ConnectToDesktop(desktop_addr, _, reply_sender)) => {
+      let expected_addr: SocketAddr = ([127, 0, 0, 1], UDP_PORT).into();
+      assert_eq!(desktop_addr, expected_addr);
+      reply_sender.send(Ok(RELAY_UDP_PORT)).unwrap();
+    },
+    other => panic!("Expected a ConnectToDesktop, got {:?}", other.map(|_| ()))
+  }
+
+  let mut response = String::new();
+  controller.read_to_string(&mut response).unwrap();
+  assert_eq!(response, format!("OK {} {}\r\n", UDP_PORT, RELAY_UDP_PORT));
+
+  tcp_message_sender.send(TcpMessage::Shutdown).unwrap();
+  tcp_handle.join().unwrap();
+}